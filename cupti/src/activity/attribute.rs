@@ -0,0 +1,484 @@
+use std::ffi::c_void;
+
+use cupti_sys::*;
+
+use super::ActivityAttribute;
+use crate::*;
+
+/// The value of an [`ActivityAttribute`].
+///
+/// Each attribute has a fixed value type, given by
+/// [`ActivityAttribute::value_type`]. Attributes that CUPTI stores as a
+/// `uint8_t` are all on/off flags and so are represented as a `bool`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AttributeValue {
+    /// A `size_t` value.
+    Size(usize),
+    /// A `uint8_t` flag.
+    Bool(bool),
+}
+
+/// The type of the value stored by an [`ActivityAttribute`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AttributeValueType {
+    /// The attribute is a `size_t`.
+    Size,
+    /// The attribute is a `uint8_t` flag.
+    Bool,
+}
+
+impl AttributeValue {
+    /// The type of this value.
+    pub fn value_type(&self) -> AttributeValueType {
+        match self {
+            Self::Size(_) => AttributeValueType::Size,
+            Self::Bool(_) => AttributeValueType::Bool,
+        }
+    }
+
+    /// Get the value if it is a [`Size`](Self::Size).
+    pub fn as_size(&self) -> Option<usize> {
+        match *self {
+            Self::Size(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value if it is a [`Bool`](Self::Bool).
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<usize> for AttributeValue {
+    fn from(value: usize) -> Self {
+        Self::Size(value)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl ActivityAttribute {
+    /// The type of value stored by this attribute.
+    ///
+    /// Returns `None` if this attribute is not known to this crate.
+    pub fn value_type(self) -> Option<AttributeValueType> {
+        Some(match self {
+            Self::DeviceBufferSize
+            | Self::DeviceBufferSizeCDP
+            | Self::DeviceBufferPoolLimit
+            | Self::ProfilingSemaphorePoolSize
+            | Self::ProfilingSemaphorePoolLimit
+            | Self::DeviceBufferPreAllocateValue
+            | Self::ProfilingSemaphorePreAllocateValue
+            | Self::DeviceBufferSizeDeviceGraphs => AttributeValueType::Size,
+            Self::ZeroedOutActivityBuffer
+            | Self::MemAllocationTypeHostPinned
+            | Self::PerThreadActivityBuffer => AttributeValueType::Bool,
+            _ => return None,
+        })
+    }
+}
+
+/// Read an activity API attribute.
+///
+/// This wraps `cuptiActivityGetAttribute`. The returned value always has the
+/// type given by [`ActivityAttribute::value_type`].
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidParameter`] if `attr` is not a known activity attribute
+/// - [`Error::NotSupported`] if `attr` is no longer supported by CUPTI
+pub fn get_attribute(attr: ActivityAttribute) -> Result<AttributeValue> {
+    match attr.value_type() {
+        Some(AttributeValueType::Size) => {
+            let mut value = 0usize;
+            unsafe { get_raw_attribute(attr, &mut value) }.map(|_| AttributeValue::Size(value))
+        }
+        Some(AttributeValueType::Bool) => {
+            let mut value = 0u8;
+            unsafe { get_raw_attribute(attr, &mut value) }.map(|_| AttributeValue::Bool(value != 0))
+        }
+        None => Err(Error::InvalidParameter),
+    }
+}
+
+/// Write an activity API attribute.
+///
+/// This wraps `cuptiActivitySetAttribute`. Most attributes only take effect
+/// for buffers allocated after they are set, so they should be set before
+/// initializing CUDA or enabling any activity kinds. See the documentation of
+/// each [`ActivityAttribute`] for details.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidParameter`] if `attr` is not a known activity attribute,
+///   or if `value` does not have the type given by
+///   [`ActivityAttribute::value_type`]
+/// - [`Error::NotSupported`] if `attr` is no longer supported by CUPTI or the
+///   value is not supported on this system
+pub fn set_attribute(attr: ActivityAttribute, value: impl Into<AttributeValue>) -> Result<()> {
+    let value = value.into();
+    if attr.value_type() != Some(value.value_type()) {
+        return Err(Error::InvalidParameter);
+    }
+
+    match value {
+        AttributeValue::Size(mut value) => unsafe { set_raw_attribute(attr, &mut value) },
+        AttributeValue::Bool(value) => unsafe { set_raw_attribute(attr, &mut u8::from(value)) },
+    }
+}
+
+/// # Safety
+/// `T` must be the type CUPTI uses for `attr`.
+unsafe fn get_raw_attribute<T>(attr: ActivityAttribute, value: &mut T) -> Result<()> {
+    let mut size = std::mem::size_of::<T>();
+    let code = unsafe {
+        cuptiActivityGetAttribute(attr.into(), &mut size, value as *mut T as *mut c_void)
+    };
+
+    Error::result(code)
+}
+
+/// # Safety
+/// `T` must be the type CUPTI uses for `attr`.
+unsafe fn set_raw_attribute<T>(attr: ActivityAttribute, value: &mut T) -> Result<()> {
+    let mut size = std::mem::size_of::<T>();
+    let code = unsafe {
+        cuptiActivitySetAttribute(attr.into(), &mut size, value as *mut T as *mut c_void)
+    };
+
+    Error::result(code)
+}
+
+/// A set of activity attributes to apply together.
+///
+/// Fields that are `None` leave the corresponding attribute unchanged. Most of
+/// these attributes only take effect for buffers allocated after they are set,
+/// and [`per_thread_activity_buffer`] must be set before
+/// [`register_callbacks`] is called, so the configuration should be applied
+/// before CUDA is initialized and before tracing starts.
+///
+/// [`per_thread_activity_buffer`]: Self::per_thread_activity_buffer
+/// [`register_callbacks`]: super::register_callbacks
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ActivityConfig {
+    /// See [`ActivityAttribute::DeviceBufferSize`].
+    pub device_buffer_size: Option<usize>,
+    /// See [`ActivityAttribute::DeviceBufferSizeCDP`].
+    pub device_buffer_size_cdp: Option<usize>,
+    /// See [`ActivityAttribute::DeviceBufferSizeDeviceGraphs`].
+    pub device_buffer_size_device_graphs: Option<usize>,
+    /// See [`ActivityAttribute::DeviceBufferPoolLimit`].
+    pub device_buffer_pool_limit: Option<usize>,
+    /// See [`ActivityAttribute::DeviceBufferPreAllocateValue`].
+    pub device_buffer_pre_allocate_value: Option<usize>,
+    /// See [`ActivityAttribute::ProfilingSemaphorePoolSize`].
+    pub profiling_semaphore_pool_size: Option<usize>,
+    /// See [`ActivityAttribute::ProfilingSemaphorePoolLimit`].
+    pub profiling_semaphore_pool_limit: Option<usize>,
+    /// See [`ActivityAttribute::ProfilingSemaphorePreAllocateValue`].
+    pub profiling_semaphore_pre_allocate_value: Option<usize>,
+    /// See [`ActivityAttribute::ZeroedOutActivityBuffer`].
+    pub zeroed_out_activity_buffer: Option<bool>,
+    /// See [`ActivityAttribute::MemAllocationTypeHostPinned`].
+    pub mem_allocation_type_host_pinned: Option<bool>,
+    /// See [`ActivityAttribute::PerThreadActivityBuffer`].
+    pub per_thread_activity_buffer: Option<bool>,
+}
+
+impl ActivityConfig {
+    /// Create a configuration that leaves all attributes unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The attributes set by this configuration, along with their values.
+    pub fn attributes(&self) -> Vec<(ActivityAttribute, AttributeValue)> {
+        let sizes = [
+            (ActivityAttribute::DeviceBufferSize, self.device_buffer_size),
+            (
+                ActivityAttribute::DeviceBufferSizeCDP,
+                self.device_buffer_size_cdp,
+            ),
+            (
+                ActivityAttribute::DeviceBufferSizeDeviceGraphs,
+                self.device_buffer_size_device_graphs,
+            ),
+            (
+                ActivityAttribute::DeviceBufferPoolLimit,
+                self.device_buffer_pool_limit,
+            ),
+            (
+                ActivityAttribute::DeviceBufferPreAllocateValue,
+                self.device_buffer_pre_allocate_value,
+            ),
+            (
+                ActivityAttribute::ProfilingSemaphorePoolSize,
+                self.profiling_semaphore_pool_size,
+            ),
+            (
+                ActivityAttribute::ProfilingSemaphorePoolLimit,
+                self.profiling_semaphore_pool_limit,
+            ),
+            (
+                ActivityAttribute::ProfilingSemaphorePreAllocateValue,
+                self.profiling_semaphore_pre_allocate_value,
+            ),
+        ];
+        let flags = [
+            (
+                ActivityAttribute::ZeroedOutActivityBuffer,
+                self.zeroed_out_activity_buffer,
+            ),
+            (
+                ActivityAttribute::MemAllocationTypeHostPinned,
+                self.mem_allocation_type_host_pinned,
+            ),
+            (
+                ActivityAttribute::PerThreadActivityBuffer,
+                self.per_thread_activity_buffer,
+            ),
+        ];
+
+        let sizes = sizes
+            .into_iter()
+            .filter_map(|(attr, value)| Some((attr, AttributeValue::Size(value?))));
+        let flags = flags
+            .into_iter()
+            .filter_map(|(attr, value)| Some((attr, AttributeValue::Bool(value?))));
+
+        sizes.chain(flags).collect()
+    }
+
+    /// Check that this configuration is consistent without applying it.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidParameter`] if a buffer or pool size is 0, or if a
+    ///   pre-allocate value is not less than the corresponding pool limit
+    pub fn validate(&self) -> Result<()> {
+        let sizes = [
+            self.device_buffer_size,
+            self.device_buffer_size_cdp,
+            self.device_buffer_size_device_graphs,
+            self.device_buffer_pool_limit,
+            self.profiling_semaphore_pool_size,
+            self.profiling_semaphore_pool_limit,
+        ];
+        if sizes.contains(&Some(0)) {
+            return Err(Error::InvalidParameter);
+        }
+
+        let pools = [
+            (
+                self.device_buffer_pre_allocate_value,
+                self.device_buffer_pool_limit,
+            ),
+            (
+                self.profiling_semaphore_pre_allocate_value,
+                self.profiling_semaphore_pool_limit,
+            ),
+        ];
+        for (pre_allocate, limit) in pools {
+            if let (Some(pre_allocate), Some(limit)) = (pre_allocate, limit)
+                && pre_allocate >= limit
+            {
+                return Err(Error::InvalidParameter);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply this configuration.
+    ///
+    /// The configuration is validated before any attribute is changed. If
+    /// CUPTI rejects one of the attributes then those that were already set
+    /// are restored to their previous values before the error is returned, so
+    /// either the whole configuration is applied or none of it is.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotInitialized`]
+    /// - [`Error::InvalidParameter`] if [`validate`](Self::validate) fails or
+    ///   CUPTI rejects one of the values
+    /// - [`Error::NotSupported`] if one of the attributes is no longer
+    ///   supported by CUPTI or a value is not supported on this system
+    pub fn apply(&self) -> Result<()> {
+        self.validate()?;
+
+        let attributes = self.attributes();
+        let previous = attributes
+            .iter()
+            .map(|&(attr, _)| get_attribute(attr).map(|value| (attr, value)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (index, &(attr, value)) in attributes.iter().enumerate() {
+            if let Err(e) = set_attribute(attr, value) {
+                for &(attr, value) in previous[..index].iter().rev() {
+                    let _ = set_attribute(attr, value);
+                }
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the current value of every supported attribute into a
+    /// configuration.
+    ///
+    /// Attributes that are no longer supported by CUPTI are left as `None`.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotInitialized`]
+    pub fn current() -> Result<Self> {
+        fn size(attr: ActivityAttribute) -> Result<Option<usize>> {
+            match get_attribute(attr) {
+                Ok(value) => Ok(value.as_size()),
+                Err(Error::NotSupported) => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        fn flag(attr: ActivityAttribute) -> Result<Option<bool>> {
+            match get_attribute(attr) {
+                Ok(value) => Ok(value.as_bool()),
+                Err(Error::NotSupported) => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        Ok(Self {
+            device_buffer_size: size(ActivityAttribute::DeviceBufferSize)?,
+            device_buffer_size_cdp: size(ActivityAttribute::DeviceBufferSizeCDP)?,
+            device_buffer_size_device_graphs: size(
+                ActivityAttribute::DeviceBufferSizeDeviceGraphs,
+            )?,
+            device_buffer_pool_limit: size(ActivityAttribute::DeviceBufferPoolLimit)?,
+            device_buffer_pre_allocate_value: size(
+                ActivityAttribute::DeviceBufferPreAllocateValue,
+            )?,
+            profiling_semaphore_pool_size: size(ActivityAttribute::ProfilingSemaphorePoolSize)?,
+            profiling_semaphore_pool_limit: size(ActivityAttribute::ProfilingSemaphorePoolLimit)?,
+            profiling_semaphore_pre_allocate_value: size(
+                ActivityAttribute::ProfilingSemaphorePreAllocateValue,
+            )?,
+            zeroed_out_activity_buffer: flag(ActivityAttribute::ZeroedOutActivityBuffer)?,
+            mem_allocation_type_host_pinned: flag(ActivityAttribute::MemAllocationTypeHostPinned)?,
+            per_thread_activity_buffer: flag(ActivityAttribute::PerThreadActivityBuffer)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_sizes_are_rejected() {
+        assert_eq!(ActivityConfig::new().validate(), Ok(()));
+
+        let config = ActivityConfig {
+            device_buffer_size: Some(0),
+            ..ActivityConfig::new()
+        };
+        assert_eq!(config.validate(), Err(Error::InvalidParameter));
+
+        let config = ActivityConfig {
+            profiling_semaphore_pool_limit: Some(0),
+            ..ActivityConfig::new()
+        };
+        assert_eq!(config.validate(), Err(Error::InvalidParameter));
+    }
+
+    #[test]
+    fn pre_allocate_must_be_below_limit() {
+        let config = |pre_allocate, limit| ActivityConfig {
+            device_buffer_pre_allocate_value: Some(pre_allocate),
+            device_buffer_pool_limit: Some(limit),
+            ..ActivityConfig::new()
+        };
+
+        assert_eq!(config(3, 4).validate(), Ok(()));
+        assert_eq!(config(4, 4).validate(), Err(Error::InvalidParameter));
+        assert_eq!(config(5, 4).validate(), Err(Error::InvalidParameter));
+
+        let config = ActivityConfig {
+            profiling_semaphore_pre_allocate_value: Some(8),
+            profiling_semaphore_pool_limit: Some(8),
+            ..ActivityConfig::new()
+        };
+        assert_eq!(config.validate(), Err(Error::InvalidParameter));
+
+        // A pre-allocate value without a limit is not checked.
+        let config = ActivityConfig {
+            device_buffer_pre_allocate_value: Some(100),
+            ..ActivityConfig::new()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn attributes_only_include_set_fields() {
+        assert_eq!(ActivityConfig::new().attributes(), []);
+
+        let config = ActivityConfig {
+            device_buffer_size: Some(8 << 20),
+            profiling_semaphore_pool_size: Some(1024),
+            per_thread_activity_buffer: Some(true),
+            zeroed_out_activity_buffer: Some(false),
+            ..ActivityConfig::new()
+        };
+
+        assert_eq!(
+            config.attributes(),
+            [
+                (
+                    ActivityAttribute::DeviceBufferSize,
+                    AttributeValue::Size(8 << 20)
+                ),
+                (
+                    ActivityAttribute::ProfilingSemaphorePoolSize,
+                    AttributeValue::Size(1024)
+                ),
+                (
+                    ActivityAttribute::ZeroedOutActivityBuffer,
+                    AttributeValue::Bool(false)
+                ),
+                (
+                    ActivityAttribute::PerThreadActivityBuffer,
+                    AttributeValue::Bool(true)
+                ),
+            ]
+        );
+        for (attr, value) in config.attributes() {
+            assert_eq!(attr.value_type(), Some(value.value_type()));
+        }
+    }
+
+    #[test]
+    fn mismatched_value_types_are_rejected() {
+        // These fail before CUPTI is called.
+        assert_eq!(
+            set_attribute(ActivityAttribute::DeviceBufferSize, true),
+            Err(Error::InvalidParameter)
+        );
+        assert_eq!(
+            set_attribute(ActivityAttribute::PerThreadActivityBuffer, 1usize),
+            Err(Error::InvalidParameter)
+        );
+    }
+}
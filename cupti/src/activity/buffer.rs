@@ -0,0 +1,226 @@
+use std::alloc::Layout;
+use std::sync::Arc;
+
+use cupti_sys::*;
+
+use super::ActivityRecords;
+use crate::util::NonPoisonMutex;
+use crate::*;
+
+/// The alignment CUPTI requires for activity buffers.
+pub const ACTIVITY_BUFFER_ALIGNMENT: usize = 8;
+
+/// The buffer size used by the default implementation of
+/// [`ActivityBufferHandler::buffer_requested`].
+pub const DEFAULT_ACTIVITY_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+static HANDLER: NonPoisonMutex<Option<Arc<dyn ActivityBufferHandler>>> = NonPoisonMutex::new(None);
+
+/// A request for a new activity buffer returned from
+/// [`ActivityBufferHandler::buffer_requested`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BufferRequest {
+    /// The size of the buffer to allocate, in bytes.
+    ///
+    /// A size of 0 declines the request. CUPTI may drop activity records if no
+    /// buffer is provided.
+    pub size: usize,
+
+    /// The maximum number of records that should be placed in the buffer.
+    ///
+    /// If 0 then the buffer is filled with as many records as possible.
+    pub max_records: usize,
+}
+
+impl BufferRequest {
+    /// Request a buffer of `size` bytes that CUPTI will fill with as many
+    /// records as possible.
+    pub const fn new(size: usize) -> Self {
+        Self {
+            size,
+            max_records: 0,
+        }
+    }
+
+    /// Decline the buffer request.
+    pub const fn decline() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Default for BufferRequest {
+    fn default() -> Self {
+        Self::new(DEFAULT_ACTIVITY_BUFFER_SIZE)
+    }
+}
+
+/// A buffer of activity records that has been completed by CUPTI.
+///
+/// The underlying memory is owned by this crate and is released once
+/// [`ActivityBufferHandler::buffer_completed`] returns, so any data that needs
+/// to outlive the callback must be copied out.
+pub struct ActivityBuffer<'a> {
+    context: Option<&'a Context>,
+    stream_id: u32,
+    data: &'a [u8],
+}
+
+impl<'a> ActivityBuffer<'a> {
+    /// Create an `ActivityBuffer` from its raw parts.
+    ///
+    /// # Safety
+    /// `data` must contain activity records written by CUPTI, and any strings
+    /// referenced by those records must remain valid for `'a`.
+    pub unsafe fn from_raw_parts(
+        context: Option<&'a Context>,
+        stream_id: u32,
+        data: &'a [u8],
+    ) -> Self {
+        Self {
+            context,
+            stream_id,
+            data,
+        }
+    }
+
+    /// The context this buffer is associated with.
+    ///
+    /// Since CUDA 6.0 all buffers are global buffers so this will always be
+    /// `None`.
+    pub fn context(&self) -> Option<&'a Context> {
+        self.context
+    }
+
+    /// The stream ID this buffer is associated with.
+    ///
+    /// Since CUDA 6.0 all buffers are global buffers so this will always be 0.
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    /// The valid bytes within the buffer.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The number of valid bytes within the buffer.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether this buffer contains no records.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Iterate over the activity records within this buffer.
+    pub fn records(&self) -> ActivityRecords<'a> {
+        ActivityRecords::new(self.data)
+    }
+}
+
+/// Callbacks used by CUPTI to request and return activity buffers.
+///
+/// Install a handler with [`register_callbacks`].
+///
+/// # Notes
+///
+/// - These methods are called from CUPTI's internal threads as well as
+///   application threads that call into CUDA, so they should avoid blocking
+///   where possible.
+/// - Panicking within either method will abort the process.
+pub trait ActivityBufferHandler: Send + Sync + 'static {
+    /// Called when CUPTI needs an empty buffer to store activity records in.
+    ///
+    /// The buffer itself is allocated (with the alignment required by CUPTI)
+    /// by this crate. The default implementation requests a buffer of
+    /// [`DEFAULT_ACTIVITY_BUFFER_SIZE`] bytes.
+    fn buffer_requested(&self) -> BufferRequest {
+        BufferRequest::default()
+    }
+
+    /// Called when CUPTI has finished filling a buffer with activity records.
+    fn buffer_completed(&self, buffer: ActivityBuffer<'_>);
+}
+
+/// Register a handler for CUPTI activity buffers.
+///
+/// This wraps `cuptiActivityRegisterCallbacks`. Once registered, activity
+/// record buffers are requested from and returned to `handler` asynchronously.
+/// Registering a new handler replaces any previously registered one. Buffers
+/// that are still owned by CUPTI will be returned to the new handler.
+///
+/// If registration fails then the previously registered handler, if any, is
+/// left in place.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidParameter`]
+pub fn register_callbacks<H: ActivityBufferHandler>(handler: H) -> Result<()> {
+    let previous = HANDLER.lock().replace(Arc::new(handler));
+
+    let code =
+        unsafe { cuptiActivityRegisterCallbacks(Some(buffer_requested), Some(buffer_completed)) };
+
+    if let Err(e) = Error::result(code) {
+        *HANDLER.lock() = previous;
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+fn current_handler() -> Option<Arc<dyn ActivityBufferHandler>> {
+    HANDLER.lock().clone()
+}
+
+fn buffer_layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size, ACTIVITY_BUFFER_ALIGNMENT).ok()
+}
+
+unsafe extern "C" fn buffer_requested(
+    buffer: *mut *mut u8,
+    size: *mut usize,
+    max_num_records: *mut usize,
+) {
+    let request = match current_handler() {
+        Some(handler) => handler.buffer_requested(),
+        None => BufferRequest::decline(),
+    };
+
+    let data = match buffer_layout(request.size) {
+        Some(layout) if layout.size() != 0 => unsafe { std::alloc::alloc(layout) },
+        _ => std::ptr::null_mut(),
+    };
+
+    unsafe {
+        *buffer = data;
+        *size = if data.is_null() { 0 } else { request.size };
+        *max_num_records = request.max_records;
+    }
+}
+
+unsafe extern "C" fn buffer_completed(
+    context: CUcontext,
+    stream_id: u32,
+    buffer: *mut u8,
+    size: usize,
+    valid_size: usize,
+) {
+    if buffer.is_null() {
+        return;
+    }
+
+    if let Some(handler) = current_handler() {
+        let data = unsafe { std::slice::from_raw_parts(buffer, valid_size.min(size)) };
+        let context = unsafe { Context::from_ptr(context) };
+
+        handler
+            .buffer_completed(unsafe { ActivityBuffer::from_raw_parts(context, stream_id, data) });
+    }
+
+    if let Some(layout) = buffer_layout(size) {
+        unsafe { std::alloc::dealloc(buffer, layout) };
+    }
+}
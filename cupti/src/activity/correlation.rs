@@ -0,0 +1,293 @@
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+
+use cupti_sys::*;
+
+use super::ExternalCorrelationKind;
+use crate::*;
+
+/// An external correlation ID along with the kind of external API it belongs
+/// to.
+///
+/// This is the ID pushed by [`external_correlation`], and is also available on
+/// decoded [`ActivityExternalCorrelation`] records through
+/// [`ActivityExternalCorrelation::external`], so it can be used directly as a
+/// key to join GPU activity back to the external work that caused it.
+///
+/// [`ActivityExternalCorrelation`]: super::ActivityExternalCorrelation
+/// [`ActivityExternalCorrelation::external`]: super::ActivityExternalCorrelation::external
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExternalCorrelationId {
+    /// The kind of external API.
+    pub kind: ExternalCorrelationKind,
+    /// The ID assigned by the external API.
+    pub id: u64,
+}
+
+impl ExternalCorrelationId {
+    /// Create a new external correlation ID.
+    pub const fn new(kind: ExternalCorrelationKind, id: u64) -> Self {
+        Self { kind, id }
+    }
+}
+
+/// Push an external correlation ID for the calling thread.
+///
+/// This wraps `cuptiActivityPushExternalCorrelationId`. While the ID is pushed,
+/// CUPTI precedes each API activity record created on this thread with an
+/// [`ActivityExternalCorrelation`] record for `kind`, provided that
+/// [`ActivityKind::ExternalCorrelation`] is enabled.
+///
+/// Prefer [`external_correlation`], which pops the ID again automatically.
+///
+/// # Errors
+///
+/// - [`Error::InvalidParameter`] if `kind` is invalid
+///
+/// [`ActivityExternalCorrelation`]: super::ActivityExternalCorrelation
+/// [`ActivityKind::ExternalCorrelation`]: super::ActivityKind::ExternalCorrelation
+pub fn push_external_correlation_id(kind: ExternalCorrelationKind, id: u64) -> Result<()> {
+    Error::result(unsafe { cuptiActivityPushExternalCorrelationId(kind.into(), id) })
+}
+
+/// Pop the most recently pushed external correlation ID of `kind` for the
+/// calling thread, returning it.
+///
+/// This wraps `cuptiActivityPopExternalCorrelationId`.
+///
+/// # Errors
+///
+/// - [`Error::InvalidParameter`] if `kind` is invalid
+/// - [`Error::QueueEmpty`] if no ID of `kind` is currently pushed
+pub fn pop_external_correlation_id(kind: ExternalCorrelationKind) -> Result<u64> {
+    let mut id = 0;
+    let code = unsafe { cuptiActivityPopExternalCorrelationId(kind.into(), &mut id) };
+
+    Error::result(code).map(|_| id)
+}
+
+struct Entry {
+    token: u64,
+    kind: ExternalCorrelationKind,
+    released: bool,
+}
+
+thread_local! {
+    static STACK: RefCell<Vec<Entry>> = const { RefCell::new(Vec::new()) };
+    static NEXT_TOKEN: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Push an external correlation ID for the calling thread, returning a guard
+/// that pops it again when dropped.
+///
+/// See [`push_external_correlation_id`] for details.
+///
+/// Guards for the same `kind` on a thread must be released in the reverse
+/// order that they were created in. If a guard is released while a guard
+/// created after it is still alive then [`ExternalCorrelationGuard::pop`]
+/// returns [`Error::InvalidOperation`] and the ID is popped once all of the
+/// guards created after it have been released.
+///
+/// # Errors
+///
+/// - [`Error::InvalidParameter`] if `kind` is invalid
+pub fn external_correlation(
+    kind: ExternalCorrelationKind,
+    id: u64,
+) -> Result<ExternalCorrelationGuard> {
+    push_external_correlation_id(kind, id)?;
+
+    let token = NEXT_TOKEN.with(|next| {
+        let token = next.get();
+        next.set(token + 1);
+        token
+    });
+
+    STACK.with_borrow_mut(|stack| {
+        stack.push(Entry {
+            token,
+            kind,
+            released: false,
+        })
+    });
+
+    Ok(ExternalCorrelationGuard {
+        token,
+        id: ExternalCorrelationId::new(kind, id),
+        popped: false,
+        _marker: PhantomData,
+    })
+}
+
+/// A guard for an external correlation ID pushed by [`external_correlation`].
+///
+/// The ID is popped when the guard is dropped, or explicitly with
+/// [`pop`](Self::pop). Guards are tied to the thread that created them.
+#[must_use = "the external correlation ID is popped immediately if the guard is not kept"]
+pub struct ExternalCorrelationGuard {
+    token: u64,
+    id: ExternalCorrelationId,
+    popped: bool,
+    // Correlation IDs are pushed per-thread so the guard must not be sent to
+    // another thread.
+    _marker: PhantomData<*const ()>,
+}
+
+impl ExternalCorrelationGuard {
+    /// The external correlation ID that was pushed.
+    pub fn id(&self) -> ExternalCorrelationId {
+        self.id
+    }
+
+    /// Pop the external correlation ID, returning the ID reported by CUPTI.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidOperation`] if a guard of the same kind that was
+    ///   created after this one is still alive. The ID will be popped once that
+    ///   guard is released.
+    /// - [`Error::QueueEmpty`] if the ID was already popped by a direct call to
+    ///   [`pop_external_correlation_id`]
+    pub fn pop(mut self) -> Result<u64> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<u64> {
+        if std::mem::replace(&mut self.popped, true) {
+            return Ok(self.id.id);
+        }
+
+        let kind = self.id.kind;
+        let token = self.token;
+
+        // The entry is only missing if the stack was tampered with, in which
+        // case there is nothing left to pop.
+        STACK
+            .with_borrow_mut(|stack| release_entry(stack, token, kind, pop_external_correlation_id))
+            .unwrap_or(Ok(self.id.id))
+    }
+}
+
+/// Remove the entry for `token` from `stack`, calling `pop` to pop its ID and
+/// those of any entries beneath it that were released out of order.
+///
+/// Returns `None` if there is no entry for `token`.
+fn release_entry(
+    stack: &mut Vec<Entry>,
+    token: u64,
+    kind: ExternalCorrelationKind,
+    mut pop: impl FnMut(ExternalCorrelationKind) -> Result<u64>,
+) -> Option<Result<u64>> {
+    let index = stack.iter().position(|entry| entry.token == token)?;
+
+    if stack[index + 1..].iter().any(|entry| entry.kind == kind) {
+        stack[index].released = true;
+        return Some(Err(Error::InvalidOperation));
+    }
+
+    stack.remove(index);
+    let id = pop(kind);
+
+    // Pop any guards beneath this one that were released out of order.
+    while let Some(index) = stack.iter().rposition(|entry| entry.kind == kind) {
+        if !stack[index].released {
+            break;
+        }
+
+        stack.remove(index);
+        let _ = pop(kind);
+    }
+
+    Some(id)
+}
+
+impl Drop for ExternalCorrelationGuard {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: ExternalCorrelationKind = ExternalCorrelationKind::Custom0;
+    const B: ExternalCorrelationKind = ExternalCorrelationKind::Custom1;
+
+    fn stack(kinds: &[ExternalCorrelationKind]) -> Vec<Entry> {
+        kinds
+            .iter()
+            .enumerate()
+            .map(|(token, &kind)| Entry {
+                token: token as u64,
+                kind,
+                released: false,
+            })
+            .collect()
+    }
+
+    /// Release `token`, returning the result and the kinds that were popped.
+    fn release_token(
+        stack: &mut Vec<Entry>,
+        token: u64,
+    ) -> (Option<Result<u64>>, Vec<ExternalCorrelationKind>) {
+        let kind = stack
+            .iter()
+            .find(|entry| entry.token == token)
+            .map_or(A, |entry| entry.kind);
+
+        let mut popped = Vec::new();
+        let result = release_entry(stack, token, kind, |kind| {
+            popped.push(kind);
+            Ok(popped.len() as u64)
+        });
+        (result, popped)
+    }
+
+    #[test]
+    fn releases_in_reverse_order() {
+        let mut stack = stack(&[A, A, A]);
+
+        assert_eq!(release_token(&mut stack, 2), (Some(Ok(1)), vec![A]));
+        assert_eq!(release_token(&mut stack, 1), (Some(Ok(1)), vec![A]));
+        assert_eq!(release_token(&mut stack, 0), (Some(Ok(1)), vec![A]));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn out_of_order_release_is_deferred() {
+        let mut stack = stack(&[A, A, A]);
+
+        assert_eq!(
+            release_token(&mut stack, 1),
+            (Some(Err(Error::InvalidOperation)), vec![])
+        );
+        assert_eq!(stack.len(), 3);
+
+        // Releasing the innermost guard also pops the one released early.
+        assert_eq!(release_token(&mut stack, 2), (Some(Ok(1)), vec![A, A]));
+        assert_eq!(stack.len(), 1);
+        assert_eq!(release_token(&mut stack, 0), (Some(Ok(1)), vec![A]));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn kinds_do_not_block_each_other() {
+        let mut stack = stack(&[A, B, A, B]);
+
+        assert_eq!(release_token(&mut stack, 1).1, []);
+        assert_eq!(release_token(&mut stack, 2), (Some(Ok(1)), vec![A]));
+        assert_eq!(release_token(&mut stack, 0), (Some(Ok(1)), vec![A]));
+        assert_eq!(release_token(&mut stack, 3), (Some(Ok(1)), vec![B, B]));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn missing_entries_are_ignored() {
+        let mut stack = stack(&[A]);
+
+        assert_eq!(release_token(&mut stack, 7), (None, vec![]));
+        assert_eq!(stack.len(), 1);
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::{Arc, Condvar};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cupti_sys::*;
+
+use super::ActivityFlag;
+use crate::util::NonPoisonMutex;
+use crate::*;
+
+fn flush_flag(forced: bool) -> u32 {
+    if forced {
+        ActivityFlag::FLUSH_FORCED.bits()
+    } else {
+        ActivityFlag::NONE.bits()
+    }
+}
+
+/// Request that CUPTI deliver activity buffers for a context or stream.
+///
+/// This wraps `cuptiActivityFlush`, which is deprecated: CUPTI ignores
+/// `context` and `stream_id` and behaves like [`flush_all`].
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidOperation`] if not preceded by a successful call to
+///   [`register_callbacks`]
+/// - [`Error::Unknown`] if an internal error occurred
+///
+/// [`register_callbacks`]: super::register_callbacks
+pub fn flush(context: Option<&Context>, stream_id: u32, forced: bool) -> Result<()> {
+    let context = context.map(|c| c.as_raw()).unwrap_or(std::ptr::null_mut());
+
+    Error::result(unsafe { cuptiActivityFlush(context, stream_id, flush_flag(forced)) })
+}
+
+/// Request that CUPTI deliver all activity buffers that are ready.
+///
+/// This wraps `cuptiActivityFlushAll`. Buffers are delivered to the handler
+/// installed with [`register_callbacks`] before this function returns.
+///
+/// - A default flush (`forced == false`) delivers every buffer whose records
+///   have all completed, even if the buffer is not full.
+/// - A forced flush (`forced == true`) delivers every buffer, including those
+///   with incomplete records. This should be done before the end of the
+///   profiling session so that no records are lost.
+///
+/// This function does not synchronize with the device. Synchronize any
+/// outstanding CUDA work first if complete records are required.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidOperation`] if not preceded by a successful call to
+///   [`register_callbacks`]
+/// - [`Error::Unknown`] if an internal error occurred
+///
+/// [`register_callbacks`]: super::register_callbacks
+pub fn flush_all(forced: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityFlushAll(flush_flag(forced)) })
+}
+
+/// Set the flush period of the CUPTI worker thread.
+///
+/// This wraps `cuptiActivityFlushPeriod`. The period overrides the heuristics
+/// CUPTI uses to decide when to deliver buffers. A periodic flush only
+/// delivers buffers that are full and have all of their records completed; use
+/// [`Flusher`] to also deliver partially filled buffers.
+///
+/// The period is rounded up to a whole number of milliseconds. Passing `None`
+/// or a zero duration disables the periodic flush and restores the default
+/// behavior.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidParameter`] if `period` does not fit in a `u32` number of
+///   milliseconds
+pub fn set_flush_period(period: Option<Duration>) -> Result<()> {
+    let millis = period_millis(period)?;
+
+    Error::result(unsafe { cuptiActivityFlushPeriod(millis) })
+}
+
+fn period_millis(period: Option<Duration>) -> Result<u32> {
+    match period {
+        Some(period) => period
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .try_into()
+            .map_err(|_| Error::InvalidParameter),
+        None => Ok(0),
+    }
+}
+
+/// The shortest interval between the flushes of a [`Flusher`].
+const MIN_FLUSH_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Default)]
+struct FlusherState {
+    shutdown: NonPoisonMutex<bool>,
+    wakeup: Condvar,
+}
+
+/// A background thread that periodically forces CUPTI to deliver activity
+/// buffers.
+///
+/// CUPTI only delivers buffers once they are full, which can take a long time
+/// in a service that does little GPU work. A `Flusher` calls
+/// [`flush_all`] with a forced flush at a fixed interval so that records
+/// reach the [`ActivityBufferHandler`] within a bounded amount of time.
+///
+/// When the flusher is dropped (or [`stop`](Self::stop) is called) the
+/// background thread exits and a final forced flush is performed, so that
+/// every record collected so far is delivered.
+///
+/// [`ActivityBufferHandler`]: super::ActivityBufferHandler
+pub struct Flusher {
+    state: Arc<FlusherState>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Flusher {
+    /// Start a thread that performs a forced flush every `interval`.
+    ///
+    /// Intervals shorter than 1 ms, including zero, are rounded up to 1 ms so
+    /// that the thread does not flush in a busy loop.
+    ///
+    /// Errors from the periodic flushes are ignored. The buffer handler should
+    /// be registered with [`register_callbacks`] before the flusher is
+    /// started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the background thread could not be spawned.
+    ///
+    /// [`register_callbacks`]: super::register_callbacks
+    pub fn new(interval: Duration) -> Self {
+        let interval = interval.max(MIN_FLUSH_INTERVAL);
+        let state = Arc::new(FlusherState::default());
+        let thread = std::thread::Builder::new()
+            .name("cupti-flusher".into())
+            .spawn({
+                let state = state.clone();
+                move || Self::run(&state, interval)
+            })
+            .expect("failed to spawn the CUPTI flusher thread");
+
+        Self {
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(state: &FlusherState, interval: Duration) {
+        let mut deadline = Instant::now() + interval;
+        let mut shutdown = state.shutdown.lock();
+
+        while !*shutdown {
+            let now = Instant::now();
+            if now < deadline {
+                shutdown = match state.wakeup.wait_timeout(shutdown, deadline - now) {
+                    Ok((guard, _)) => guard,
+                    Err(e) => e.into_inner().0,
+                };
+                continue;
+            }
+
+            // Don't hold the lock while CUPTI calls into the buffer handler.
+            drop(shutdown);
+            let _ = flush_all(true);
+            deadline = Instant::now() + interval;
+            shutdown = state.shutdown.lock();
+        }
+    }
+
+    /// Stop the background thread and perform a final forced flush.
+    ///
+    /// This is the same as dropping the flusher, except that the result of the
+    /// final flush is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the final call to [`flush_all`].
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+
+        *self.state.shutdown.lock() = true;
+        self.state.wakeup.notify_all();
+        let _ = thread.join();
+
+        flush_all(true)
+    }
+}
+
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush_period_is_rounded_up_to_millis() {
+        assert_eq!(period_millis(None), Ok(0));
+        assert_eq!(period_millis(Some(Duration::ZERO)), Ok(0));
+        assert_eq!(period_millis(Some(Duration::from_nanos(1))), Ok(1));
+        assert_eq!(period_millis(Some(Duration::from_micros(1_500))), Ok(2));
+        assert_eq!(period_millis(Some(Duration::from_secs(2))), Ok(2_000));
+        assert_eq!(
+            period_millis(Some(Duration::MAX)),
+            Err(Error::InvalidParameter)
+        );
+        assert_eq!(
+            set_flush_period(Some(Duration::MAX)),
+            Err(Error::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn flusher_stops_promptly_on_drop() {
+        let flusher = Flusher::new(Duration::from_secs(3600));
+
+        let start = Instant::now();
+        drop(flusher);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,259 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A string referenced by an activity record.
+///
+/// Records decoded from an activity buffer borrow their strings directly from
+/// the buffer or from memory owned by CUPTI. Converting a record with
+/// [`ActivityRecord::into_owned`] replaces these with shared strings from an
+/// [`Interner`], so that the many records referencing the same kernel or
+/// function name share a single allocation.
+///
+/// [`ActivityRecord::into_owned`]: super::ActivityRecord::into_owned
+#[derive(Clone)]
+pub enum RecordStr<'a> {
+    /// A string borrowed from an activity buffer or from CUPTI.
+    Borrowed(&'a CStr),
+    /// A string that is owned by the record.
+    Shared(Arc<CStr>),
+}
+
+impl RecordStr<'_> {
+    /// Get the underlying C string.
+    pub fn as_c_str(&self) -> &CStr {
+        match self {
+            Self::Borrowed(s) => s,
+            Self::Shared(s) => s,
+        }
+    }
+
+    /// Convert this string to UTF-8, replacing any invalid sequences with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        self.as_c_str().to_string_lossy()
+    }
+
+    /// Convert this string into one that is not tied to the activity buffer,
+    /// deduplicating it against other strings in `interner`.
+    pub fn into_owned(self, interner: &mut Interner) -> RecordStr<'static> {
+        match self {
+            Self::Borrowed(s) => RecordStr::Shared(interner.intern(s)),
+            Self::Shared(s) => RecordStr::Shared(s),
+        }
+    }
+}
+
+impl Deref for RecordStr<'_> {
+    type Target = CStr;
+
+    fn deref(&self) -> &CStr {
+        self.as_c_str()
+    }
+}
+
+impl AsRef<CStr> for RecordStr<'_> {
+    fn as_ref(&self) -> &CStr {
+        self.as_c_str()
+    }
+}
+
+impl<'a> From<&'a CStr> for RecordStr<'a> {
+    fn from(value: &'a CStr) -> Self {
+        Self::Borrowed(value)
+    }
+}
+
+impl From<Arc<CStr>> for RecordStr<'_> {
+    fn from(value: Arc<CStr>) -> Self {
+        Self::Shared(value)
+    }
+}
+
+impl PartialEq for RecordStr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_c_str() == other.as_c_str()
+    }
+}
+
+impl Eq for RecordStr<'_> {}
+
+impl std::hash::Hash for RecordStr<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_c_str().hash(state)
+    }
+}
+
+impl PartialOrd for RecordStr<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RecordStr<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_c_str().cmp(other.as_c_str())
+    }
+}
+
+impl fmt::Debug for RecordStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_c_str().fmt(f)
+    }
+}
+
+impl fmt::Display for RecordStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+/// Strings are serialized as strings where the format is human-readable and
+/// they are valid UTF-8, and as bytes otherwise, so that names that are not
+/// UTF-8 survive a round trip.
+#[cfg(feature = "serde")]
+impl serde::Serialize for RecordStr<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let bytes = self.to_bytes();
+        match std::str::from_utf8(bytes) {
+            Ok(s) if serializer.is_human_readable() => serializer.serialize_str(s),
+            _ => serializer.serialize_bytes(bytes),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RecordStr<'_> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{self, Error};
+
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = std::ffi::CString;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string or byte array without interior nul bytes")
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                self.visit_bytes(v.as_bytes())
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                std::ffi::CString::new(v).map_err(E::custom)
+            }
+
+            fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                std::ffi::CString::new(v).map_err(E::custom)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                self.visit_byte_buf(bytes)
+            }
+        }
+
+        let value = if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Visitor)?
+        } else {
+            deserializer.deserialize_bytes(Visitor)?
+        };
+
+        Ok(Self::Shared(value.into()))
+    }
+}
+
+/// A set of deduplicated strings used when converting activity records into
+/// owned records.
+///
+/// See [`ActivityRecord::into_owned`].
+///
+/// [`ActivityRecord::into_owned`]: super::ActivityRecord::into_owned
+#[derive(Clone, Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Arc<CStr>>,
+}
+
+impl Interner {
+    /// Create a new, empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the shared copy of `value`, adding it to the interner if it is not
+    /// already present.
+    pub fn intern(&mut self, value: &CStr) -> Arc<CStr> {
+        if let Some(existing) = self.strings.get(value) {
+            return existing.clone();
+        }
+
+        let value: Arc<CStr> = value.into();
+        self.strings.insert(value.clone());
+        value
+    }
+
+    /// The number of distinct strings in the interner.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Whether the interner contains no strings.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Remove all strings from the interner.
+    ///
+    /// Strings that are still referenced by records remain valid.
+    pub fn clear(&mut self) {
+        self.strings.clear();
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    fn shared(bytes: &[u8]) -> RecordStr<'static> {
+        RecordStr::Shared(std::ffi::CString::new(bytes).unwrap().into())
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for value in [shared(b"_Z6vecAddPKfS0_Pfi"), shared(b"bad \xff\xfe name")] {
+            let json = serde_json::to_string(&value).unwrap();
+            let decoded: RecordStr<'static> = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, value);
+        }
+
+        assert_eq!(
+            serde_json::to_string(&shared(b"kernel")).unwrap(),
+            "\"kernel\""
+        );
+    }
+
+    #[test]
+    fn postcard_round_trip() {
+        for value in [shared(b"_Z6vecAddPKfS0_Pfi"), shared(b"bad \xff\xfe name")] {
+            let bytes = postcard::to_stdvec(&value).unwrap();
+            let decoded: RecordStr<'static> = postcard::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, value);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::mem::size_of;
+use std::sync::OnceLock;
+
+use cupti_sys::*;
+
+use super::ActivityKind;
+use crate::*;
+
+/// A group of activity record structures that are revisions of each other.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) enum Family {
+    Kernel,
+    Memcpy,
+    MemcpyPtoP,
+    Memset,
+    Device,
+    Context,
+    Marker,
+    MarkerData,
+    Overhead,
+    UnifiedMemoryCounter,
+    CudaEvent,
+    Synchronization,
+    NvLink,
+    Memory,
+    MemoryPool,
+    GraphTrace,
+    Jit,
+}
+
+impl Family {
+    const COUNT: usize = Self::Jit as usize + 1;
+
+    fn of(kind: ActivityKind) -> Option<Self> {
+        Some(match kind {
+            ActivityKind::Kernel | ActivityKind::ConcurrentKernel => Self::Kernel,
+            ActivityKind::Memcpy => Self::Memcpy,
+            ActivityKind::Memcpy2 => Self::MemcpyPtoP,
+            ActivityKind::Memset => Self::Memset,
+            ActivityKind::Device => Self::Device,
+            ActivityKind::Context => Self::Context,
+            ActivityKind::Marker => Self::Marker,
+            ActivityKind::MarkerData => Self::MarkerData,
+            ActivityKind::Overhead => Self::Overhead,
+            ActivityKind::UnifiedMemoryCounter => Self::UnifiedMemoryCounter,
+            ActivityKind::CudaEvent => Self::CudaEvent,
+            ActivityKind::Synchronization => Self::Synchronization,
+            ActivityKind::NvLink => Self::NvLink,
+            ActivityKind::Memory2 => Self::Memory,
+            ActivityKind::MemoryPool => Self::MemoryPool,
+            ActivityKind::GraphTrace => Self::GraphTrace,
+            ActivityKind::Jit => Self::Jit,
+            _ => return None,
+        })
+    }
+
+    /// All known revisions of this family, oldest first.
+    fn revisions(self) -> &'static [Revision] {
+        match self {
+            Self::Kernel => KERNEL,
+            Self::Memcpy => MEMCPY,
+            Self::MemcpyPtoP => MEMCPY_PTOP,
+            Self::Memset => MEMSET,
+            Self::Device => DEVICE,
+            Self::Context => CONTEXT,
+            Self::Marker => MARKER,
+            Self::MarkerData => MARKER_DATA,
+            Self::Overhead => OVERHEAD,
+            Self::UnifiedMemoryCounter => UNIFIED_MEMORY_COUNTER,
+            Self::CudaEvent => CUDA_EVENT,
+            Self::Synchronization => SYNCHRONIZATION,
+            Self::NvLink => NVLINK,
+            Self::Memory => MEMORY,
+            Self::MemoryPool => MEMORY_POOL,
+            Self::GraphTrace => GRAPH_TRACE,
+            Self::Jit => JIT,
+        }
+    }
+
+    const ALL: [Self; Self::COUNT] = [
+        Self::Kernel,
+        Self::Memcpy,
+        Self::MemcpyPtoP,
+        Self::Memset,
+        Self::Device,
+        Self::Context,
+        Self::Marker,
+        Self::MarkerData,
+        Self::Overhead,
+        Self::UnifiedMemoryCounter,
+        Self::CudaEvent,
+        Self::Synchronization,
+        Self::NvLink,
+        Self::Memory,
+        Self::MemoryPool,
+        Self::GraphTrace,
+        Self::Jit,
+    ];
+}
+
+/// A single revision of an activity record structure.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) struct Revision {
+    /// The revision number, matching the suffix of the CUPTI struct name.
+    pub(super) revision: u8,
+    /// The size of the structure, in bytes.
+    pub(super) size: usize,
+    /// The first CUDA release (major, minor) to produce this revision.
+    since: (u32, u32),
+}
+
+const fn rev<T>(revision: u8, since: (u32, u32)) -> Revision {
+    Revision {
+        revision,
+        size: size_of::<T>(),
+        since,
+    }
+}
+
+// Where the release that introduced a revision is not documented by CUPTI we
+// err on the side of a later release. Decoding a newer record with an older
+// layout only loses the trailing fields, while the reverse would read past the
+// end of the record.
+//
+// Revisions 3 and 4 of the NvLink record share a layout.
+const KERNEL: &[Revision] = &[
+    rev::<CUpti_ActivityKernel>(1, (0, 0)),
+    rev::<CUpti_ActivityKernel2>(2, (5, 5)),
+    rev::<CUpti_ActivityKernel3>(3, (6, 5)),
+    rev::<CUpti_ActivityKernel4>(4, (9, 0)),
+    rev::<CUpti_ActivityKernel5>(5, (11, 0)),
+    rev::<CUpti_ActivityKernel6>(6, (11, 2)),
+    rev::<CUpti_ActivityKernel7>(7, (11, 6)),
+    rev::<CUpti_ActivityKernel8>(8, (11, 8)),
+    rev::<CUpti_ActivityKernel9>(9, (12, 0)),
+    rev::<CUpti_ActivityKernel10>(10, (13, 0)),
+];
+const MEMCPY: &[Revision] = &[
+    rev::<CUpti_ActivityMemcpy>(1, (0, 0)),
+    rev::<CUpti_ActivityMemcpy3>(3, (11, 0)),
+    rev::<CUpti_ActivityMemcpy4>(4, (11, 1)),
+    rev::<CUpti_ActivityMemcpy5>(5, (11, 6)),
+    rev::<CUpti_ActivityMemcpy6>(6, (12, 8)),
+];
+const MEMCPY_PTOP: &[Revision] = &[
+    rev::<CUpti_ActivityMemcpyPtoP>(1, (0, 0)),
+    rev::<CUpti_ActivityMemcpyPtoP2>(2, (11, 0)),
+    rev::<CUpti_ActivityMemcpyPtoP3>(3, (11, 1)),
+    rev::<CUpti_ActivityMemcpyPtoP4>(4, (11, 6)),
+];
+const MEMSET: &[Revision] = &[
+    rev::<CUpti_ActivityMemset>(1, (0, 0)),
+    rev::<CUpti_ActivityMemset2>(2, (11, 0)),
+    rev::<CUpti_ActivityMemset3>(3, (11, 1)),
+    rev::<CUpti_ActivityMemset4>(4, (11, 6)),
+];
+const DEVICE: &[Revision] = &[
+    rev::<CUpti_ActivityDevice>(1, (0, 0)),
+    rev::<CUpti_ActivityDevice2>(2, (5, 5)),
+    rev::<CUpti_ActivityDevice3>(3, (7, 0)),
+    rev::<CUpti_ActivityDevice4>(4, (11, 6)),
+    rev::<CUpti_ActivityDevice5>(5, (12, 6)),
+];
+const CONTEXT: &[Revision] = &[
+    rev::<CUpti_ActivityContext>(1, (0, 0)),
+    rev::<CUpti_ActivityContext2>(2, (12, 4)),
+    rev::<CUpti_ActivityContext3>(3, (12, 8)),
+];
+const MARKER: &[Revision] = &[
+    rev::<CUpti_ActivityMarker>(1, (0, 0)),
+    rev::<CUpti_ActivityMarker2>(2, (8, 0)),
+];
+const MARKER_DATA: &[Revision] = &[
+    rev::<CUpti_ActivityMarkerData>(1, (0, 0)),
+    rev::<CUpti_ActivityMarkerData2>(2, (13, 1)),
+];
+const OVERHEAD: &[Revision] = &[
+    rev::<CUpti_ActivityOverhead>(1, (0, 0)),
+    rev::<CUpti_ActivityOverhead2>(2, (12, 2)),
+    rev::<CUpti_ActivityOverhead3>(3, (12, 8)),
+];
+const UNIFIED_MEMORY_COUNTER: &[Revision] = &[
+    rev::<CUpti_ActivityUnifiedMemoryCounter>(1, (0, 0)),
+    rev::<CUpti_ActivityUnifiedMemoryCounter2>(2, (7, 0)),
+    rev::<CUpti_ActivityUnifiedMemoryCounter3>(3, (12, 8)),
+];
+const CUDA_EVENT: &[Revision] = &[
+    rev::<CUpti_ActivityCudaEvent>(1, (0, 0)),
+    rev::<CUpti_ActivityCudaEvent2>(2, (12, 8)),
+];
+const SYNCHRONIZATION: &[Revision] = &[
+    rev::<CUpti_ActivitySynchronization>(1, (0, 0)),
+    rev::<CUpti_ActivitySynchronization2>(2, (12, 8)),
+];
+const NVLINK: &[Revision] = &[
+    rev::<CUpti_ActivityNvLink>(1, (0, 0)),
+    rev::<CUpti_ActivityNvLink2>(2, (9, 0)),
+    rev::<CUpti_ActivityNvLink3>(3, (10, 0)),
+    rev::<CUpti_ActivityNvLink4>(4, (12, 0)),
+];
+const MEMORY: &[Revision] = &[
+    rev::<CUpti_ActivityMemory2>(2, (0, 0)),
+    rev::<CUpti_ActivityMemory3>(3, (11, 6)),
+    rev::<CUpti_ActivityMemory4>(4, (12, 8)),
+];
+const MEMORY_POOL: &[Revision] = &[
+    rev::<CUpti_ActivityMemoryPool>(1, (0, 0)),
+    rev::<CUpti_ActivityMemoryPool2>(2, (11, 6)),
+    rev::<CUpti_ActivityMemoryPool3>(3, (13, 0)),
+];
+const GRAPH_TRACE: &[Revision] = &[
+    rev::<CUpti_ActivityGraphTrace>(1, (0, 0)),
+    rev::<CUpti_ActivityGraphTrace2>(2, (12, 8)),
+];
+const JIT: &[Revision] = &[
+    rev::<CUpti_ActivityJit>(1, (0, 0)),
+    rev::<CUpti_ActivityJit2>(2, (12, 8)),
+];
+
+/// The CUDA release (major, minor) corresponding to a CUPTI API version.
+fn cuda_release(version: u32) -> (u32, u32) {
+    if version >= 10000 {
+        return (version / 10000, version / 100 % 100);
+    }
+
+    match version {
+        0..=1 => (4, 0),
+        2 => (4, 1),
+        3 => (5, 0),
+        4 => (5, 5),
+        5 => (6, 0),
+        6 | 7 => (6, 5),
+        8 => (7, 0),
+        9 => (8, 0),
+        10 => (9, 0),
+        11 => (9, 1),
+        12 => (10, 0),
+        13 => (11, 0),
+        14 => (11, 1),
+        15 => (11, 2),
+        16 => (11, 5),
+        17 => (11, 6),
+        18 => (11, 8),
+        19 => (12, 0),
+        v => (12, v - 18),
+    }
+}
+
+/// The layout of the activity records produced by a particular version of
+/// CUPTI.
+///
+/// CUPTI has revised most of its activity record structures over time (e.g.
+/// `CUpti_ActivityKernel` through `CUpti_ActivityKernel10`) and the revision
+/// that is produced depends on the version of CUPTI loaded at run time, not the
+/// version this crate was built against. An `ActivityLayout` records which
+/// revision to expect for each activity kind so that records can be decoded
+/// into the same Rust types regardless of the CUPTI version. Fields that are
+/// not present in older revisions are decoded as `None`.
+///
+/// When iterating over an [`ActivityBuffer`] the layout is additionally checked
+/// against the actual size of each record, so an inaccurate layout will be
+/// corrected as records are decoded.
+///
+/// [`ActivityBuffer`]: super::ActivityBuffer
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ActivityLayout {
+    version: u32,
+    revisions: [u8; Family::COUNT],
+}
+
+impl ActivityLayout {
+    /// The layout produced by the version of CUPTI this crate was built
+    /// against.
+    pub fn latest() -> Self {
+        Self::for_version(CUPTI_API_VERSION)
+    }
+
+    /// The layout produced by the given CUPTI API version, as returned by
+    /// [`get_version`].
+    pub fn for_version(version: u32) -> Self {
+        let release = cuda_release(version);
+        let revisions = Family::ALL.map(|family| {
+            let revisions = family.revisions();
+
+            revisions
+                .iter()
+                .rev()
+                .find(|rev| rev.since <= release)
+                .unwrap_or(&revisions[0])
+                .revision
+        });
+
+        Self { version, revisions }
+    }
+
+    /// The layout produced by the version of CUPTI loaded at run time.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from [`get_version`].
+    pub fn current() -> Result<Self> {
+        get_version().map(Self::for_version)
+    }
+
+    /// The CUPTI API version this layout was selected for.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The size of an activity record of the given kind, in bytes.
+    ///
+    /// Returns `None` for activity kinds that cannot be decoded by this crate.
+    pub fn record_size(&self, kind: ActivityKind) -> Option<usize> {
+        if let Some(family) = Family::of(kind) {
+            return Some(self.revision(family).size);
+        }
+
+        Some(match kind {
+            ActivityKind::Driver | ActivityKind::Runtime | ActivityKind::InternalLaunchApi => {
+                size_of::<CUpti_ActivityAPI>()
+            }
+            ActivityKind::Name => size_of::<CUpti_ActivityName>(),
+            ActivityKind::CdpKernel => size_of::<CUpti_ActivityCdpKernel>(),
+            ActivityKind::Preemption => size_of::<CUpti_ActivityPreemption>(),
+            ActivityKind::Environment => size_of::<CUpti_ActivityEnvironment>(),
+            ActivityKind::Function => size_of::<CUpti_ActivityFunction>(),
+            ActivityKind::Module => size_of::<CUpti_ActivityModule>(),
+            ActivityKind::DeviceAttribute => size_of::<CUpti_ActivityDeviceAttribute>(),
+            ActivityKind::OpenaccData => size_of::<CUpti_ActivityOpenAccData>(),
+            ActivityKind::OpenaccLaunch => size_of::<CUpti_ActivityOpenAccLaunch>(),
+            ActivityKind::OpenaccOther => size_of::<CUpti_ActivityOpenAccOther>(),
+            ActivityKind::Stream => size_of::<CUpti_ActivityStream>(),
+            ActivityKind::ExternalCorrelation => size_of::<CUpti_ActivityExternalCorrelation>(),
+            ActivityKind::Memory => size_of::<CUpti_ActivityMemory>(),
+            ActivityKind::Pcie => size_of::<CUpti_ActivityPcie>(),
+            ActivityKind::Openmp => size_of::<CUpti_ActivityOpenMp>(),
+            ActivityKind::DeviceGraphTrace => size_of::<CUpti_ActivityDeviceGraphTrace>(),
+            ActivityKind::MemDecompress => size_of::<CUpti_ActivityMemDecompress>(),
+            ActivityKind::ConfidentialComputeRotation => {
+                size_of::<CUpti_ActivityConfidentialComputeRotation>()
+            }
+            _ => return None,
+        })
+    }
+
+    pub(super) fn revision(&self, family: Family) -> Revision {
+        let revision = self.revisions[family as usize];
+
+        family
+            .revisions()
+            .iter()
+            .copied()
+            .find(|rev| rev.revision == revision)
+            .expect("layout should only contain known revisions")
+    }
+
+    /// Adjust this layout so that records of `kind` are expected to be `size`
+    /// bytes long, if there is a known revision of that size.
+    pub(super) fn with_record_size(mut self, kind: ActivityKind, size: usize) -> Self {
+        let Some(family) = Family::of(kind) else {
+            return self;
+        };
+
+        if self.revision(family).size == size {
+            return self;
+        }
+
+        if let Some(rev) = family.revisions().iter().find(|rev| rev.size == size) {
+            self.revisions[family as usize] = rev.revision;
+        }
+
+        self
+    }
+
+    /// The layout for the version of CUPTI loaded at run time, falling back to
+    /// the latest layout if the version cannot be determined.
+    pub(super) fn detected() -> Self {
+        static LAYOUT: OnceLock<ActivityLayout> = OnceLock::new();
+
+        *LAYOUT.get_or_init(|| Self::current().unwrap_or_else(|_| Self::latest()))
+    }
+}
+
+impl Default for ActivityLayout {
+    fn default() -> Self {
+        Self::latest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuda_releases_match_documented_versions() {
+        for (version, release) in [
+            (1, (4, 0)),
+            (2, (4, 1)),
+            (3, (5, 0)),
+            (4, (5, 5)),
+            (5, (6, 0)),
+            (6, (6, 5)),
+            (7, (6, 5)),
+            (8, (7, 0)),
+            (9, (8, 0)),
+            (10, (9, 0)),
+            (11, (9, 1)),
+            (12, (10, 0)),
+            (13, (11, 0)),
+            (14, (11, 1)),
+            (15, (11, 2)),
+            (16, (11, 5)),
+            (17, (11, 6)),
+            (18, (11, 8)),
+            (19, (12, 0)),
+            (20, (12, 2)),
+            (21, (12, 3)),
+            (22, (12, 4)),
+            (23, (12, 5)),
+            (24, (12, 6)),
+            (120800, (12, 8)),
+            (120900, (12, 9)),
+            (130000, (13, 0)),
+            (130001, (13, 0)),
+        ] {
+            assert_eq!(cuda_release(version), release, "version {version}");
+        }
+    }
+
+    #[test]
+    fn selects_newest_revision_for_release() {
+        for version in [1, 4, 8, 12, 13, 17, 18, 19, 24, 120800, 130001] {
+            let release = cuda_release(version);
+            let layout = ActivityLayout::for_version(version);
+
+            for family in Family::ALL {
+                let selected = layout.revision(family);
+                let revisions = family.revisions();
+                let index = revisions.iter().position(|rev| *rev == selected).unwrap();
+
+                assert!(selected.since <= release, "{family:?} for {version}");
+                if let Some(next) = revisions.get(index + 1) {
+                    assert!(next.since > release, "{family:?} for {version}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn selects_known_revisions() {
+        let revision = |version, family| ActivityLayout::for_version(version).revision(family);
+
+        assert_eq!(revision(12, Family::Kernel).revision, 4);
+        assert_eq!(revision(12, Family::NvLink).revision, 3);
+        assert_eq!(revision(18, Family::Kernel).revision, 8);
+        assert_eq!(revision(18, Family::Memcpy).revision, 5);
+        assert_eq!(revision(120800, Family::Kernel).revision, 9);
+        assert_eq!(revision(120800, Family::Memory).revision, 4);
+        assert_eq!(revision(130001, Family::Kernel).revision, 10);
+        assert_eq!(revision(130001, Family::MemoryPool).revision, 3);
+        assert_eq!(revision(130001, Family::MarkerData).revision, 1);
+    }
+
+    #[test]
+    fn record_size_selects_matching_revision() {
+        let layout = ActivityLayout::for_version(130001).with_record_size(
+            ActivityKind::ConcurrentKernel,
+            size_of::<CUpti_ActivityKernel8>(),
+        );
+        assert_eq!(layout.revision(Family::Kernel).revision, 8);
+
+        // Sizes that do not match a known revision leave the layout unchanged.
+        let unchanged = layout.with_record_size(ActivityKind::ConcurrentKernel, 3);
+        assert_eq!(unchanged, layout);
+    }
+}
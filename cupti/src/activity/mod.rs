@@ -1,9 +1,58 @@
-use c_enum::c_enum;
 use cupti_sys::*;
 
 use crate::*;
 
-c_enum! {
+mod attribute;
+mod buffer;
+mod correlation;
+mod flush;
+mod intern;
+mod layout;
+mod options;
+mod record;
+mod timestamp;
+mod unified_memory;
+
+pub use self::attribute::{
+    ActivityConfig, AttributeValue, AttributeValueType, get_attribute, set_attribute,
+};
+pub use self::buffer::{
+    ACTIVITY_BUFFER_ALIGNMENT, ActivityBuffer, ActivityBufferHandler, BufferRequest,
+    DEFAULT_ACTIVITY_BUFFER_SIZE, register_callbacks,
+};
+pub use self::correlation::{
+    ExternalCorrelationGuard, ExternalCorrelationId, external_correlation,
+    pop_external_correlation_id, push_external_correlation_id,
+};
+pub use self::flush::{Flusher, flush, flush_all, set_flush_period};
+pub use self::intern::{Interner, RecordStr};
+pub use self::layout::ActivityLayout;
+pub use self::options::{
+    ActivityOption, ActivityOptions, enable_all_sync_records, enable_allocation_source,
+    enable_cuda_event_device_timestamps, enable_device_graph, enable_driver_api, enable_hw_trace,
+    enable_latency_timestamps, enable_launch_attributes, enable_runtime_api,
+};
+pub use self::record::{
+    ActivityApi, ActivityCdpKernel, ActivityConfidentialComputeRotation, ActivityContext,
+    ActivityCudaEvent, ActivityDevice, ActivityDeviceAttribute, ActivityDeviceGraphTrace,
+    ActivityEnvironment, ActivityExternalCorrelation, ActivityFunction, ActivityGraphTrace,
+    ActivityJit, ActivityKernel, ActivityMarker, ActivityMarkerData, ActivityMemDecompress,
+    ActivityMemcpy, ActivityMemcpyPtoP, ActivityMemory, ActivityMemory2, ActivityMemoryPool,
+    ActivityMemoryPoolConfig, ActivityMemset, ActivityModule, ActivityName, ActivityNvLink,
+    ActivityObjectId, ActivityOpenAccCommon, ActivityOpenAccData, ActivityOpenAccLaunch,
+    ActivityOpenAccOther, ActivityOpenMp, ActivityOverhead, ActivityPcie, ActivityPreemption,
+    ActivityRecord, ActivityRecords, ActivityStream, ActivitySynchronization,
+    ActivityUnifiedMemoryCounter, DeviceAttributeKind, DeviceAttributeValue, EnvironmentData,
+    MarkerPayload, NvLinkDevice, PcieDevice,
+};
+#[cfg(target_os = "linux")]
+pub use self::timestamp::monotonic_raw_timestamp;
+pub use self::timestamp::{ClockDomain, realtime_timestamp, register_timestamp_callback};
+pub use self::unified_memory::{
+    UnifiedMemoryCounterConfig, UnifiedMemoryCounters, configure_unified_memory_counters,
+};
+
+serde_c_enum! {
     /// The kinds of activity objects.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityObjectKind : CUpti_ActivityObjectKind {
@@ -22,7 +71,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kinds of activity records.
     ///
     /// Each activity record kind represents information about a GPU or an
@@ -297,7 +346,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kinds of activity overhead.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityOverheadKind : CUpti_ActivityOverheadKind {
@@ -326,7 +375,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kind of a compute API.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityComputeApiKind : CUpti_ActivityComputeApiKind {
@@ -344,7 +393,8 @@ bitflags::bitflags! {
     ///
     /// Activity record flags. Flags can be combined by bitwise OR to associate multiple flags with
     /// an activity record. Each flag is specific to a certain activity kind.
-    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct ActivityFlag : CUpti_ActivityFlag {
         /// The activity record has no flags.
         const NONE = CUPTI_ACTIVITY_FLAG_NONE;
@@ -454,7 +504,7 @@ bitflags::bitflags! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The stall reason for PC sampling activity.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityPCSamplingStallReason : CUpti_ActivityPCSamplingStallReason {
@@ -489,7 +539,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Sampling period for PC sampling method.
     ///
     /// Sampling period can be set using `cuptiActivityConfigurePCSampling`.
@@ -510,7 +560,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kind of a memory copy, indicating the source and destination targets of the copy.
     ///
     /// Each kind represents the source and destination targets of a memory copy. Targets are
@@ -542,7 +592,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kinds of memory accessed by a memory operation/copy.
     ///
     /// Each kind represents the type of the memory accessed by a memory operation/copy.
@@ -567,7 +617,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kind of a preemption activity.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityPreemptionKind : CUpti_ActivityPreemptionKind {
@@ -580,7 +630,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kind of environment data.
     ///
     /// Used to indicate what type of data is being reported by an environment activity record.
@@ -603,7 +653,8 @@ bitflags::bitflags! {
     /// Reasons for clock throttling.
     ///
     /// There could be more than one reason that is clock is being throttled so this is a bitfield.
-    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct EnvironmentClocksThrottleReason : CUpti_EnvironmentClocksThrottleReason {
         /// Nothing is running on the GPU and the clocks are dropping to idle state.
         const GPU_IDLE = CUPTI_CLOCKS_THROTTLE_REASON_GPU_IDLE;
@@ -633,7 +684,7 @@ bitflags::bitflags! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Scope of the unified memory counter (deprecated in CUDA 7.0).
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityUnifiedMemoryCounterScope : CUpti_ActivityUnifiedMemoryCounterScope {
@@ -646,7 +697,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Kind of the Unified Memory counter.
     ///
     /// Many activities are associated with Unified Memory mechanism; among them
@@ -709,7 +760,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Memory access type for unified memory page faults.
     ///
     /// This is valid for [`ActivityUnifiedMemoryCounterKind::GpuPageFault`] and
@@ -729,7 +780,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Migration cause of the Unified Memory counter.
     ///
     /// This is valid for [`ActivityUnifiedMemoryCounterKind::BytesTransferHtoD`] and
@@ -761,7 +812,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Remote memory map cause of the Unified Memory counter.
     ///
     /// This is valid for [`ActivityUnifiedMemoryCounterKind::RemoteMap`].
@@ -788,7 +839,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// SASS instruction classification.
     ///
     /// The SASS instructions are broadly divided into different classes. Each enum represents a classification.
@@ -839,7 +890,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Partitioned global caching option.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityPartitionedGlobalCacheConfig : CUpti_ActivityPartitionedGlobalCacheConfig {
@@ -854,7 +905,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Synchronization type.
     ///
     /// The types of synchronization to be used with CUpti_ActivitySynchronization2.
@@ -873,7 +924,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Stream type.
     ///
     /// The types of stream to be used with CUpti_ActivityStream.
@@ -896,7 +947,8 @@ bitflags::bitflags! {
     /// Link flags.
     ///
     /// Describes link properties, to be used with NvLink activities.
-    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct LinkFlag : CUpti_LinkFlag {
         /// The flag is invalid.
         const INVALID = CUPTI_LINK_FLAG_INVALID;
@@ -911,7 +963,7 @@ bitflags::bitflags! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Memory operation types.
     ///
     /// Describes the type of memory operation, to be used with `CUpti_ActivityMemory4`.
@@ -926,7 +978,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Memory pool types.
     ///
     /// Describes the type of memory pool, to be used with `CUpti_ActivityMemory4`.
@@ -941,7 +993,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Memory pool operation types.
     ///
     /// Describes the type of memory pool operation, to be used with `CUpti_ActivityMemoryPool2`.
@@ -958,7 +1010,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ChannelType : CUpti_ChannelType {
         Invalid = CUPTI_CHANNEL_TYPE_INVALID,
@@ -976,7 +1028,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// CIG (CUDA in Graphics) modes.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ContextCigMode : CUpti_ContextCigMode {
@@ -991,7 +1043,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum NvtxExtPayloadType : CUpti_NvtxExtPayloadType {
         /// The payload type is not known.
@@ -1005,7 +1057,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The type of the CUDA kernel launch.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityLaunchType : CUpti_ActivityLaunchType {
@@ -1022,7 +1074,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The shared memory limit per block config for a kernel.
     ///
     /// This should be used to set the `cudaOccFuncShmemConfig` field in the occupancy
@@ -1039,7 +1091,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The kind of external APIs supported for correlation.
     ///
     /// Custom correlation kinds are reserved for usage in external tools.
@@ -1060,7 +1112,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Field to differentiate whether PCIE Activity record is of a GPU or a PCI Bridge.
     #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum PcieDeviceType: CUpti_PcieDeviceType {
@@ -1071,7 +1123,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// PCIE Generation.
     ///
     /// Enumeration of PCIE Generation for pcie activity attribute pcieGeneration.
@@ -1092,7 +1144,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Confidential Computing Rotation Events.
     ///
     /// Event types for confidential compute tracing.
@@ -1111,8 +1163,9 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The types of JIT entry.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityJitEntryType: CUpti_ActivityJitEntryType {
         /// Invalid JIT entry type.
         Invalid = CUPTI_ACTIVITY_JIT_ENTRY_INVALID,
@@ -1123,8 +1176,9 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The types of JIT compilation operations.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityJitOperationType: CUpti_ActivityJitOperationType {
         /// Invalid JIT operation type.
         Invalid = CUPTI_ACTIVITY_JIT_OPERATION_INVALID,
@@ -1137,8 +1191,9 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// The launch mode for device graph execution.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum DeviceGraphLaunchMode: CUpti_DeviceGraphLaunchMode {
         Invalid = CUPTI_DEVICE_GRAPH_LAUNCH_MODE_INVALID,
         FireAndForget = CUPTI_DEVICE_GRAPH_LAUNCH_MODE_FIRE_AND_FORGET,
@@ -1147,10 +1202,86 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
+    /// The OpenACC event kind for OpenACC activity records.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    pub enum OpenAccEventKind : CUpti_OpenAccEventKind {
+        Invalid = CUPTI_OPENACC_EVENT_KIND_INVALID,
+        DeviceInit = CUPTI_OPENACC_EVENT_KIND_DEVICE_INIT,
+        DeviceShutdown = CUPTI_OPENACC_EVENT_KIND_DEVICE_SHUTDOWN,
+        RuntimeShutdown = CUPTI_OPENACC_EVENT_KIND_RUNTIME_SHUTDOWN,
+        EnqueueLaunch = CUPTI_OPENACC_EVENT_KIND_ENQUEUE_LAUNCH,
+        EnqueueUpload = CUPTI_OPENACC_EVENT_KIND_ENQUEUE_UPLOAD,
+        EnqueueDownload = CUPTI_OPENACC_EVENT_KIND_ENQUEUE_DOWNLOAD,
+        Wait = CUPTI_OPENACC_EVENT_KIND_WAIT,
+        ImplicitWait = CUPTI_OPENACC_EVENT_KIND_IMPLICIT_WAIT,
+        ComputeConstruct = CUPTI_OPENACC_EVENT_KIND_COMPUTE_CONSTRUCT,
+        Update = CUPTI_OPENACC_EVENT_KIND_UPDATE,
+        EnterData = CUPTI_OPENACC_EVENT_KIND_ENTER_DATA,
+        ExitData = CUPTI_OPENACC_EVENT_KIND_EXIT_DATA,
+        Create = CUPTI_OPENACC_EVENT_KIND_CREATE,
+        Delete = CUPTI_OPENACC_EVENT_KIND_DELETE,
+        Alloc = CUPTI_OPENACC_EVENT_KIND_ALLOC,
+        Free = CUPTI_OPENACC_EVENT_KIND_FREE,
+    }
+}
+
+serde_c_enum! {
+    /// The OpenACC parent construct kind for OpenACC activity records.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    pub enum OpenAccConstructKind : CUpti_OpenAccConstructKind {
+        Unknown = CUPTI_OPENACC_CONSTRUCT_KIND_UNKNOWN,
+        Parallel = CUPTI_OPENACC_CONSTRUCT_KIND_PARALLEL,
+        Kernels = CUPTI_OPENACC_CONSTRUCT_KIND_KERNELS,
+        Loop = CUPTI_OPENACC_CONSTRUCT_KIND_LOOP,
+        Data = CUPTI_OPENACC_CONSTRUCT_KIND_DATA,
+        EnterData = CUPTI_OPENACC_CONSTRUCT_KIND_ENTER_DATA,
+        ExitData = CUPTI_OPENACC_CONSTRUCT_KIND_EXIT_DATA,
+        HostData = CUPTI_OPENACC_CONSTRUCT_KIND_HOST_DATA,
+        Atomic = CUPTI_OPENACC_CONSTRUCT_KIND_ATOMIC,
+        Declare = CUPTI_OPENACC_CONSTRUCT_KIND_DECLARE,
+        Init = CUPTI_OPENACC_CONSTRUCT_KIND_INIT,
+        Shutdown = CUPTI_OPENACC_CONSTRUCT_KIND_SHUTDOWN,
+        Set = CUPTI_OPENACC_CONSTRUCT_KIND_SET,
+        Update = CUPTI_OPENACC_CONSTRUCT_KIND_UPDATE,
+        Routine = CUPTI_OPENACC_CONSTRUCT_KIND_ROUTINE,
+        Wait = CUPTI_OPENACC_CONSTRUCT_KIND_WAIT,
+        RuntimeApi = CUPTI_OPENACC_CONSTRUCT_KIND_RUNTIME_API,
+    }
+}
+
+serde_c_enum! {
+    /// The OpenMP event kind for OpenMP activity records.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    pub enum OpenMpEventKind : CUpti_OpenMpEventKind {
+        Invalid = CUPTI_OPENMP_EVENT_KIND_INVALID,
+        Parallel = CUPTI_OPENMP_EVENT_KIND_PARALLEL,
+        Task = CUPTI_OPENMP_EVENT_KIND_TASK,
+        Thread = CUPTI_OPENMP_EVENT_KIND_THREAD,
+        Idle = CUPTI_OPENMP_EVENT_KIND_IDLE,
+        WaitBarrier = CUPTI_OPENMP_EVENT_KIND_WAIT_BARRIER,
+        WaitTaskwait = CUPTI_OPENMP_EVENT_KIND_WAIT_TASKWAIT,
+    }
+}
+
+serde_c_enum! {
+    /// The device type for a device connected to NVLink.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    pub enum DevType : CUpti_DevType {
+        /// Invalid device type.
+        Invalid = CUPTI_DEV_TYPE_INVALID,
+        /// The device type is GPU.
+        Gpu = CUPTI_DEV_TYPE_GPU,
+        /// The device type is NVLink processing unit in CPU.
+        Npu = CUPTI_DEV_TYPE_NPU,
+    }
+}
+
+serde_c_enum! {
     /// Activity attributes.
     ///
     /// These attributes are used to control the behavior of the activity API.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityAttribute: CUpti_ActivityAttribute {
         /// The device memory size (in bytes) reserved for storing profiling data for concurrent
         /// kernels (activity kind CUPTI_ACTIVITY_KIND_CONCURRENT_KERNEL), memcopies and memsets
@@ -1375,12 +1506,13 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Thread-Id types.
     ///
     /// CUPTI uses different methods to obtain the thread-id depending on the
     /// support and the underlying platform. This enum documents these methods
     /// for each type.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ActivityThreadIdType: CUpti_ActivityThreadIdType {
         /// Default type.
         ///
@@ -1459,7 +1591,7 @@ pub fn enable(kind: ActivityKind) -> Result<()> {
 /// late attaches CUPTI.
 ///
 /// Before calling this function, the user must register buffer callbacks to get
-/// the activity records by creating a [`Subscriber`]. If the user does not
+/// the activity records by calling [`register_callbacks`]. If the user does not
 /// register the buffers and calls this function, then CUPTI will enable the
 /// activity kind but not provide any records for that activity kind.
 ///
@@ -1475,7 +1607,6 @@ pub fn enable(kind: ActivityKind) -> Result<()> {
 /// - [`Error::InvalidKind`] if the activity kind is not supported
 ///
 /// [`enable`]: enable
-/// [`Subscriber`]: crate::Subscriber
 pub fn enable_and_dump(kind: ActivityKind) -> Result<()> {
     Error::result(unsafe { cuptiActivityEnableAndDump(kind.0) })
 }
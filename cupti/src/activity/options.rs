@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use cupti_sys::*;
+
+use crate::callbacks::CallbackId;
+use crate::*;
+
+/// Control the collection of queued and submitted timestamps for kernels.
+///
+/// This wraps `cuptiActivityEnableLatencyTimestamps`. These timestamps are
+/// reported in [`ActivityKernel::queued`] and [`ActivityKernel::submitted`].
+/// They are not collected by default, and are not supported when HW trace is
+/// enabled with [`enable_hw_trace`].
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKernel::queued`]: super::ActivityKernel::queued
+/// [`ActivityKernel::submitted`]: super::ActivityKernel::submitted
+pub fn enable_latency_timestamps(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableLatencyTimestamps(enable.into()) })
+}
+
+/// Control the collection of launch attributes for kernels.
+///
+/// This wraps `cuptiActivityEnableLaunchAttributes`. Launch attributes are
+/// collected for kernels whose activity kind is
+/// [`ActivityKind::ConcurrentKernel`].
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::ConcurrentKernel`]: super::ActivityKind::ConcurrentKernel
+pub fn enable_launch_attributes(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableLaunchAttributes(enable.into()) })
+}
+
+/// Control the collection of records for device-launched graphs.
+///
+/// This wraps `cuptiActivityEnableDeviceGraph`. When enabled, device-launched
+/// graphs produce [`ActivityKind::DeviceGraphTrace`] records.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::DeviceGraphTrace`]: super::ActivityKind::DeviceGraphTrace
+pub fn enable_device_graph(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableDeviceGraph(enable.into()) })
+}
+
+/// Control the collection of records for a single driver API.
+///
+/// This wraps `cuptiActivityEnableDriverApi`. To collect only a small set of
+/// driver APIs, enable [`ActivityKind::Driver`] and then disable the APIs that
+/// are not needed. To collect everything except a small set, do the opposite.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::Driver`]: super::ActivityKind::Driver
+pub fn enable_driver_api(cbid: DriverApiTraceCbid, enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableDriverApi(cbid.into(), enable.into()) })
+}
+
+/// Control the collection of records for a single runtime API.
+///
+/// This wraps `cuptiActivityEnableRuntimeApi`. `cbid` is a
+/// `CUpti_runtime_api_trace_cbid` value. See [`enable_driver_api`] for how
+/// this interacts with [`ActivityKind::Runtime`].
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::Runtime`]: super::ActivityKind::Runtime
+pub fn enable_runtime_api(cbid: CallbackId, enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableRuntimeApi(cbid, enable.into()) })
+}
+
+/// Control the collection of kernel timestamps through the hardware event
+/// system instead of through instrumentation.
+///
+/// This wraps `cuptiActivityEnableHWTrace`. It must be called after the CUDA
+/// driver has been initialized.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`] if CUPTI or the CUDA driver is not initialized
+/// - [`Error::NotSupported`] if HW trace is not supported on this platform
+/// - [`Error::VirtualizedDeviceNotSupported`],
+///   [`Error::ConfidentialComputingNotSupported`],
+///   [`Error::CmpDeviceNotSupported`], [`Error::MigDeviceNotSupported`],
+///   [`Error::SliDeviceNotSupported`] or [`Error::WslDeviceNotSupported`] if HW
+///   trace is not supported on this device
+pub fn enable_hw_trace(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableHWTrace(enable.into()) })
+}
+
+/// Control whether the source library of memory allocations is recorded.
+///
+/// This wraps `cuptiActivityEnableAllocationSource`. The source is reported in
+/// [`ActivityKind::Memory2`] records.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::Memory2`]: super::ActivityKind::Memory2
+pub fn enable_allocation_source(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableAllocationSource(enable.into()) })
+}
+
+/// Control the collection of all synchronization records.
+///
+/// This wraps `cuptiActivityEnableAllSyncRecords`. By default CUPTI only
+/// records CUDA event queries and stream queries that report completion. When
+/// enabled, every query produces an [`ActivityKind::Synchronization`] record.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::Synchronization`]: super::ActivityKind::Synchronization
+pub fn enable_all_sync_records(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableAllSyncRecords(enable.into()) })
+}
+
+/// Control the collection of device timestamps for CUDA events.
+///
+/// This wraps `cuptiActivityEnableCudaEventDeviceTimestamps`. The timestamps
+/// are reported in [`ActivityKind::CudaEvent`] records.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+///
+/// [`ActivityKind::CudaEvent`]: super::ActivityKind::CudaEvent
+pub fn enable_cuda_event_device_timestamps(enable: bool) -> Result<()> {
+    Error::result(unsafe { cuptiActivityEnableCudaEventDeviceTimestamps(enable.into()) })
+}
+
+/// An option that can be set by [`ActivityOptions`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ActivityOption {
+    /// See [`enable_latency_timestamps`].
+    LatencyTimestamps,
+    /// See [`enable_launch_attributes`].
+    LaunchAttributes,
+    /// See [`enable_device_graph`].
+    DeviceGraph,
+    /// See [`enable_driver_api`].
+    DriverApi(DriverApiTraceCbid),
+    /// See [`enable_runtime_api`].
+    RuntimeApi(CallbackId),
+    /// See [`enable_hw_trace`].
+    HwTrace,
+    /// See [`enable_allocation_source`].
+    AllocationSource,
+    /// See [`enable_all_sync_records`].
+    AllSyncRecords,
+    /// See [`enable_cuda_event_device_timestamps`].
+    CudaEventDeviceTimestamps,
+}
+
+impl ActivityOption {
+    /// Enable or disable this option.
+    ///
+    /// # Errors
+    ///
+    /// See the function that corresponds to this option.
+    pub fn set(self, enable: bool) -> Result<()> {
+        match self {
+            Self::LatencyTimestamps => enable_latency_timestamps(enable),
+            Self::LaunchAttributes => enable_launch_attributes(enable),
+            Self::DeviceGraph => enable_device_graph(enable),
+            Self::DriverApi(cbid) => enable_driver_api(cbid, enable),
+            Self::RuntimeApi(cbid) => enable_runtime_api(cbid, enable),
+            Self::HwTrace => enable_hw_trace(enable),
+            Self::AllocationSource => enable_allocation_source(enable),
+            Self::AllSyncRecords => enable_all_sync_records(enable),
+            Self::CudaEventDeviceTimestamps => enable_cuda_event_device_timestamps(enable),
+        }
+    }
+}
+
+/// A set of activity options to apply together.
+///
+/// Options that are not set are left unchanged. Options are applied in the
+/// order they were first set.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ActivityOptions {
+    options: Vec<(ActivityOption, bool)>,
+    /// The index of each option in `options`.
+    index: HashMap<ActivityOption, usize>,
+}
+
+impl ActivityOptions {
+    /// Create an empty set of options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable `option`, replacing any previous value for it.
+    pub fn option(mut self, option: ActivityOption, enable: bool) -> Self {
+        match self.index.get(&option) {
+            Some(&index) => self.options[index].1 = enable,
+            None => {
+                self.index.insert(option, self.options.len());
+                self.options.push((option, enable));
+            }
+        }
+
+        self
+    }
+
+    /// See [`enable_latency_timestamps`].
+    pub fn latency_timestamps(self, enable: bool) -> Self {
+        self.option(ActivityOption::LatencyTimestamps, enable)
+    }
+
+    /// See [`enable_launch_attributes`].
+    pub fn launch_attributes(self, enable: bool) -> Self {
+        self.option(ActivityOption::LaunchAttributes, enable)
+    }
+
+    /// See [`enable_device_graph`].
+    pub fn device_graph(self, enable: bool) -> Self {
+        self.option(ActivityOption::DeviceGraph, enable)
+    }
+
+    /// See [`enable_driver_api`].
+    pub fn driver_api(self, cbid: DriverApiTraceCbid, enable: bool) -> Self {
+        self.option(ActivityOption::DriverApi(cbid), enable)
+    }
+
+    /// Enable or disable each of the driver APIs in `cbids`.
+    ///
+    /// See [`enable_driver_api`].
+    pub fn driver_apis<I>(self, cbids: I, enable: bool) -> Self
+    where
+        I: IntoIterator<Item = DriverApiTraceCbid>,
+    {
+        cbids
+            .into_iter()
+            .fold(self, |options, cbid| options.driver_api(cbid, enable))
+    }
+
+    /// See [`enable_runtime_api`].
+    pub fn runtime_api(self, cbid: CallbackId, enable: bool) -> Self {
+        self.option(ActivityOption::RuntimeApi(cbid), enable)
+    }
+
+    /// See [`enable_hw_trace`].
+    pub fn hw_trace(self, enable: bool) -> Self {
+        self.option(ActivityOption::HwTrace, enable)
+    }
+
+    /// See [`enable_allocation_source`].
+    pub fn allocation_source(self, enable: bool) -> Self {
+        self.option(ActivityOption::AllocationSource, enable)
+    }
+
+    /// See [`enable_all_sync_records`].
+    pub fn all_sync_records(self, enable: bool) -> Self {
+        self.option(ActivityOption::AllSyncRecords, enable)
+    }
+
+    /// See [`enable_cuda_event_device_timestamps`].
+    pub fn cuda_event_device_timestamps(self, enable: bool) -> Self {
+        self.option(ActivityOption::CudaEventDeviceTimestamps, enable)
+    }
+
+    /// The options that have been set, along with whether they are enabled.
+    pub fn options(&self) -> &[(ActivityOption, bool)] {
+        &self.options
+    }
+
+    /// Apply all of the options.
+    ///
+    /// Options that the running version of CUPTI, or the current platform,
+    /// does not support are skipped. They are returned along with the error
+    /// CUPTI reported for them.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error, other than one indicating that an option is
+    /// not supported, reported by CUPTI. Options before it will have already
+    /// been applied.
+    pub fn apply(&self) -> Result<Vec<(ActivityOption, Error)>> {
+        let mut unsupported = Vec::new();
+
+        for &(option, enable) in &self.options {
+            match option.set(enable) {
+                Ok(()) => (),
+                Err(e) if is_not_supported(e) => unsupported.push((option, e)),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(unsupported)
+    }
+}
+
+fn is_not_supported(error: Error) -> bool {
+    matches!(
+        error,
+        Error::NotSupported
+            | Error::ApiNotImplemented
+            | Error::VirtualizedDeviceNotSupported
+            | Error::ConfidentialComputingNotSupported
+            | Error::CmpDeviceNotSupported
+            | Error::MigDeviceNotSupported
+            | Error::SliDeviceNotSupported
+            | Error::WslDeviceNotSupported
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_keep_first_set_order() {
+        let options = ActivityOptions::new()
+            .latency_timestamps(true)
+            .hw_trace(true)
+            .runtime_api(211, false)
+            .latency_timestamps(false)
+            .runtime_api(211, true)
+            .all_sync_records(true);
+
+        assert_eq!(
+            options.options(),
+            [
+                (ActivityOption::LatencyTimestamps, false),
+                (ActivityOption::HwTrace, true),
+                (ActivityOption::RuntimeApi(211), true),
+                (ActivityOption::AllSyncRecords, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn driver_apis_are_deduplicated() {
+        let options = ActivityOptions::new()
+            .driver_api(DriverApiTraceCbid::cuInit, true)
+            .driver_apis(
+                [
+                    DriverApiTraceCbid::cuLaunchKernel,
+                    DriverApiTraceCbid::cuInit,
+                    DriverApiTraceCbid::cuLaunchKernel,
+                ],
+                false,
+            );
+
+        assert_eq!(
+            options.options(),
+            [
+                (ActivityOption::DriverApi(DriverApiTraceCbid::cuInit), false),
+                (
+                    ActivityOption::DriverApi(DriverApiTraceCbid::cuLaunchKernel),
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            options,
+            options
+                .clone()
+                .driver_api(DriverApiTraceCbid::cuInit, false)
+        );
+    }
+
+    #[test]
+    fn classifies_unsupported_errors() {
+        for error in [
+            Error::NotSupported,
+            Error::ApiNotImplemented,
+            Error::MigDeviceNotSupported,
+            Error::WslDeviceNotSupported,
+        ] {
+            assert!(is_not_supported(error), "{error:?}");
+        }
+        for error in [
+            Error::NotInitialized,
+            Error::InvalidParameter,
+            Error::Unknown,
+        ] {
+            assert!(!is_not_supported(error), "{error:?}");
+        }
+    }
+}
@@ -0,0 +1,3445 @@
+use std::borrow::Cow;
+use std::ffi::{CStr, c_char};
+
+use cupti_sys::*;
+
+use super::layout::Family;
+use super::*;
+
+/// A decoded activity record.
+///
+/// Each variant corresponds to an [`ActivityKind`]. Kinds that share a record
+/// layout in CUPTI (e.g. [`ActivityKind::Kernel`] and
+/// [`ActivityKind::ConcurrentKernel`]) share a record type here as well.
+///
+/// Strings within a record borrow from memory owned by CUPTI. They remain
+/// valid for at least as long as the activity buffer the record was decoded
+/// from. Use [`into_owned`](Self::into_owned) to keep a record around for
+/// longer than that.
+///
+/// Fields that were added to a record in later versions of CUPTI are `Option`s
+/// and are `None` when decoded from an older revision of the record. See
+/// [`ActivityLayout`] for details.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ActivityRecord<'a> {
+    Memcpy(ActivityMemcpy),
+    Memset(ActivityMemset),
+    Kernel(ActivityKernel<'a>),
+    Driver(ActivityApi),
+    Runtime(ActivityApi),
+    Device(ActivityDevice<'a>),
+    Context(ActivityContext),
+    ConcurrentKernel(ActivityKernel<'a>),
+    Name(ActivityName<'a>),
+    Marker(ActivityMarker<'a>),
+    MarkerData(ActivityMarkerData),
+    Overhead(ActivityOverhead),
+    CdpKernel(ActivityCdpKernel<'a>),
+    Preemption(ActivityPreemption),
+    Environment(ActivityEnvironment),
+    Memcpy2(ActivityMemcpyPtoP),
+    UnifiedMemoryCounter(ActivityUnifiedMemoryCounter),
+    Function(ActivityFunction<'a>),
+    Module(ActivityModule<'a>),
+    DeviceAttribute(ActivityDeviceAttribute),
+    OpenaccData(ActivityOpenAccData<'a>),
+    OpenaccLaunch(ActivityOpenAccLaunch<'a>),
+    OpenaccOther(ActivityOpenAccOther<'a>),
+    CudaEvent(ActivityCudaEvent),
+    Stream(ActivityStream),
+    Synchronization(ActivitySynchronization),
+    ExternalCorrelation(ActivityExternalCorrelation),
+    NvLink(ActivityNvLink),
+    Memory(ActivityMemory<'a>),
+    Pcie(ActivityPcie),
+    Openmp(ActivityOpenMp),
+    InternalLaunchApi(ActivityApi),
+    Memory2(ActivityMemory2<'a>),
+    MemoryPool(ActivityMemoryPool),
+    GraphTrace(ActivityGraphTrace),
+    Jit(ActivityJit<'a>),
+    DeviceGraphTrace(ActivityDeviceGraphTrace),
+    MemDecompress(ActivityMemDecompress),
+    ConfidentialComputeRotation(ActivityConfidentialComputeRotation),
+
+    /// A record of a kind that is no longer supported by CUPTI or is unknown
+    /// to this crate.
+    ///
+    /// Only the kind of these records can be decoded.
+    Unsupported(ActivityKind),
+}
+
+impl<'a> ActivityRecord<'a> {
+    /// Decode a single activity record from the start of `bytes`.
+    ///
+    /// `layout` determines which revision of the CUPTI record structure is
+    /// expected for each activity kind. Use [`ActivityLayout::current`] for
+    /// records produced by the CUPTI library loaded in this process.
+    ///
+    /// `bytes` may extend past the end of the record. Any trailing data is
+    /// ignored.
+    ///
+    /// # Safety
+    ///
+    /// Any string or data pointers within the record must either be null or
+    /// point to valid data that lives for at least `'a`. This is always the
+    /// case for records within a buffer completed by CUPTI.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::ParameterSizeNotSufficient`] if `bytes` is too short to
+    ///   contain a record of the kind indicated by its header.
+    pub unsafe fn from_bytes(bytes: &'a [u8], layout: &ActivityLayout) -> Result<Self> {
+        let kind = record_kind(bytes).ok_or(Error::ParameterSizeNotSufficient)?;
+
+        unsafe {
+            Ok(match kind {
+                ActivityKind::Memcpy => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Memcpy)?;
+                    Self::Memcpy(ActivityMemcpy::from_raw(&raw, revision))
+                }
+                ActivityKind::Memset => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Memset)?;
+                    Self::Memset(ActivityMemset::from_raw(&raw, revision))
+                }
+                ActivityKind::Kernel => {
+                    let (raw, revision) = read_kernel(bytes, layout)?;
+                    Self::Kernel(ActivityKernel::from_raw(&raw, revision))
+                }
+                ActivityKind::ConcurrentKernel => {
+                    let (raw, revision) = read_kernel(bytes, layout)?;
+                    Self::ConcurrentKernel(ActivityKernel::from_raw(&raw, revision))
+                }
+                ActivityKind::Driver => Self::Driver(ActivityApi::from_raw(&read(bytes)?)),
+                ActivityKind::Runtime => Self::Runtime(ActivityApi::from_raw(&read(bytes)?)),
+                ActivityKind::InternalLaunchApi => {
+                    Self::InternalLaunchApi(ActivityApi::from_raw(&read(bytes)?))
+                }
+                ActivityKind::Device => {
+                    let (raw, revision) = read_device(bytes, layout)?;
+                    Self::Device(ActivityDevice::from_raw(&raw, revision))
+                }
+                ActivityKind::Context => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Context)?;
+                    Self::Context(ActivityContext::from_raw(&raw, revision))
+                }
+                ActivityKind::Name => Self::Name(ActivityName::from_raw(&read(bytes)?)),
+                ActivityKind::Marker => {
+                    let (raw, _) = read_revision(bytes, layout, Family::Marker)?;
+                    Self::Marker(ActivityMarker::from_raw(&raw))
+                }
+                ActivityKind::MarkerData => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::MarkerData)?;
+                    Self::MarkerData(ActivityMarkerData::from_raw(&raw, revision))
+                }
+                ActivityKind::Overhead => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Overhead)?;
+                    Self::Overhead(ActivityOverhead::from_raw(&raw, revision))
+                }
+                ActivityKind::CdpKernel => {
+                    Self::CdpKernel(ActivityCdpKernel::from_raw(&read(bytes)?))
+                }
+                ActivityKind::Preemption => {
+                    Self::Preemption(ActivityPreemption::from_raw(&read(bytes)?))
+                }
+                ActivityKind::Environment => {
+                    Self::Environment(ActivityEnvironment::from_raw(&read(bytes)?))
+                }
+                ActivityKind::Memcpy2 => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::MemcpyPtoP)?;
+                    Self::Memcpy2(ActivityMemcpyPtoP::from_raw(&raw, revision))
+                }
+                ActivityKind::UnifiedMemoryCounter => {
+                    // The first revision of this record was deprecated in CUDA 7.0
+                    // and uses an incompatible layout.
+                    if layout.revision(Family::UnifiedMemoryCounter).revision == 1 {
+                        return Ok(Self::Unsupported(kind));
+                    }
+
+                    let (raw, revision) =
+                        read_revision(bytes, layout, Family::UnifiedMemoryCounter)?;
+                    Self::UnifiedMemoryCounter(ActivityUnifiedMemoryCounter::from_raw(
+                        &raw, revision,
+                    ))
+                }
+                ActivityKind::Function => Self::Function(ActivityFunction::from_raw(&read(bytes)?)),
+                ActivityKind::Module => Self::Module(ActivityModule::from_raw(&read(bytes)?)),
+                ActivityKind::DeviceAttribute => {
+                    Self::DeviceAttribute(ActivityDeviceAttribute::from_raw(&read(bytes)?))
+                }
+                ActivityKind::OpenaccData => {
+                    Self::OpenaccData(ActivityOpenAccData::from_raw(&read(bytes)?))
+                }
+                ActivityKind::OpenaccLaunch => {
+                    Self::OpenaccLaunch(ActivityOpenAccLaunch::from_raw(&read(bytes)?))
+                }
+                ActivityKind::OpenaccOther => {
+                    Self::OpenaccOther(ActivityOpenAccOther::from_raw(&read(bytes)?))
+                }
+                ActivityKind::CudaEvent => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::CudaEvent)?;
+                    Self::CudaEvent(ActivityCudaEvent::from_raw(&raw, revision))
+                }
+                ActivityKind::Stream => Self::Stream(ActivityStream::from_raw(&read(bytes)?)),
+                ActivityKind::Synchronization => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Synchronization)?;
+                    Self::Synchronization(ActivitySynchronization::from_raw(&raw, revision))
+                }
+                ActivityKind::ExternalCorrelation => {
+                    Self::ExternalCorrelation(ActivityExternalCorrelation::from_raw(&read(bytes)?))
+                }
+                ActivityKind::NvLink => {
+                    let (raw, revision) = read_nvlink(bytes, layout)?;
+                    Self::NvLink(ActivityNvLink::from_raw(&raw, revision))
+                }
+                ActivityKind::Memory => Self::Memory(ActivityMemory::from_raw(&read(bytes)?)),
+                ActivityKind::Pcie => Self::Pcie(ActivityPcie::from_raw(&read(bytes)?)),
+                ActivityKind::Openmp => Self::Openmp(ActivityOpenMp::from_raw(&read(bytes)?)),
+                ActivityKind::Memory2 => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Memory)?;
+                    Self::Memory2(ActivityMemory2::from_raw(&raw, revision))
+                }
+                ActivityKind::MemoryPool => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::MemoryPool)?;
+                    Self::MemoryPool(ActivityMemoryPool::from_raw(&raw, revision))
+                }
+                ActivityKind::GraphTrace => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::GraphTrace)?;
+                    Self::GraphTrace(ActivityGraphTrace::from_raw(&raw, revision))
+                }
+                ActivityKind::Jit => {
+                    let (raw, revision) = read_revision(bytes, layout, Family::Jit)?;
+                    Self::Jit(ActivityJit::from_raw(&raw, revision))
+                }
+                ActivityKind::DeviceGraphTrace => {
+                    Self::DeviceGraphTrace(ActivityDeviceGraphTrace::from_raw(&read(bytes)?))
+                }
+                ActivityKind::MemDecompress => {
+                    Self::MemDecompress(ActivityMemDecompress::from_raw(&read(bytes)?))
+                }
+                ActivityKind::ConfidentialComputeRotation => Self::ConfidentialComputeRotation(
+                    ActivityConfidentialComputeRotation::from_raw(&read(bytes)?),
+                ),
+                kind => Self::Unsupported(kind),
+            })
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer it was decoded from.
+    ///
+    /// Strings within the record are deduplicated using `interner`, so
+    /// converting many records that share the same names only allocates each
+    /// name once.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityRecord<'static> {
+        match self {
+            Self::Memcpy(r) => ActivityRecord::Memcpy(r),
+            Self::Memset(r) => ActivityRecord::Memset(r),
+            Self::Kernel(r) => ActivityRecord::Kernel(r.into_owned(interner)),
+            Self::Driver(r) => ActivityRecord::Driver(r),
+            Self::Runtime(r) => ActivityRecord::Runtime(r),
+            Self::Device(r) => ActivityRecord::Device(r.into_owned(interner)),
+            Self::Context(r) => ActivityRecord::Context(r),
+            Self::ConcurrentKernel(r) => ActivityRecord::ConcurrentKernel(r.into_owned(interner)),
+            Self::Name(r) => ActivityRecord::Name(r.into_owned(interner)),
+            Self::Marker(r) => ActivityRecord::Marker(r.into_owned(interner)),
+            Self::MarkerData(r) => ActivityRecord::MarkerData(r),
+            Self::Overhead(r) => ActivityRecord::Overhead(r),
+            Self::CdpKernel(r) => ActivityRecord::CdpKernel(r.into_owned(interner)),
+            Self::Preemption(r) => ActivityRecord::Preemption(r),
+            Self::Environment(r) => ActivityRecord::Environment(r),
+            Self::Memcpy2(r) => ActivityRecord::Memcpy2(r),
+            Self::UnifiedMemoryCounter(r) => ActivityRecord::UnifiedMemoryCounter(r),
+            Self::Function(r) => ActivityRecord::Function(r.into_owned(interner)),
+            Self::Module(r) => ActivityRecord::Module(r.into_owned(interner)),
+            Self::DeviceAttribute(r) => ActivityRecord::DeviceAttribute(r),
+            Self::OpenaccData(r) => ActivityRecord::OpenaccData(r.into_owned(interner)),
+            Self::OpenaccLaunch(r) => ActivityRecord::OpenaccLaunch(r.into_owned(interner)),
+            Self::OpenaccOther(r) => ActivityRecord::OpenaccOther(r.into_owned(interner)),
+            Self::CudaEvent(r) => ActivityRecord::CudaEvent(r),
+            Self::Stream(r) => ActivityRecord::Stream(r),
+            Self::Synchronization(r) => ActivityRecord::Synchronization(r),
+            Self::ExternalCorrelation(r) => ActivityRecord::ExternalCorrelation(r),
+            Self::NvLink(r) => ActivityRecord::NvLink(r),
+            Self::Memory(r) => ActivityRecord::Memory(r.into_owned(interner)),
+            Self::Pcie(r) => ActivityRecord::Pcie(r),
+            Self::Openmp(r) => ActivityRecord::Openmp(r),
+            Self::InternalLaunchApi(r) => ActivityRecord::InternalLaunchApi(r),
+            Self::Memory2(r) => ActivityRecord::Memory2(r.into_owned(interner)),
+            Self::MemoryPool(r) => ActivityRecord::MemoryPool(r),
+            Self::GraphTrace(r) => ActivityRecord::GraphTrace(r),
+            Self::Jit(r) => ActivityRecord::Jit(r.into_owned(interner)),
+            Self::DeviceGraphTrace(r) => ActivityRecord::DeviceGraphTrace(r),
+            Self::MemDecompress(r) => ActivityRecord::MemDecompress(r),
+            Self::ConfidentialComputeRotation(r) => ActivityRecord::ConfidentialComputeRotation(r),
+            Self::Unsupported(kind) => ActivityRecord::Unsupported(kind),
+        }
+    }
+
+    /// The kind of this activity record.
+    pub fn kind(&self) -> ActivityKind {
+        match self {
+            Self::Memcpy(_) => ActivityKind::Memcpy,
+            Self::Memset(_) => ActivityKind::Memset,
+            Self::Kernel(_) => ActivityKind::Kernel,
+            Self::Driver(_) => ActivityKind::Driver,
+            Self::Runtime(_) => ActivityKind::Runtime,
+            Self::Device(_) => ActivityKind::Device,
+            Self::Context(_) => ActivityKind::Context,
+            Self::ConcurrentKernel(_) => ActivityKind::ConcurrentKernel,
+            Self::Name(_) => ActivityKind::Name,
+            Self::Marker(_) => ActivityKind::Marker,
+            Self::MarkerData(_) => ActivityKind::MarkerData,
+            Self::Overhead(_) => ActivityKind::Overhead,
+            Self::CdpKernel(_) => ActivityKind::CdpKernel,
+            Self::Preemption(_) => ActivityKind::Preemption,
+            Self::Environment(_) => ActivityKind::Environment,
+            Self::Memcpy2(_) => ActivityKind::Memcpy2,
+            Self::UnifiedMemoryCounter(_) => ActivityKind::UnifiedMemoryCounter,
+            Self::Function(_) => ActivityKind::Function,
+            Self::Module(_) => ActivityKind::Module,
+            Self::DeviceAttribute(_) => ActivityKind::DeviceAttribute,
+            Self::OpenaccData(_) => ActivityKind::OpenaccData,
+            Self::OpenaccLaunch(_) => ActivityKind::OpenaccLaunch,
+            Self::OpenaccOther(_) => ActivityKind::OpenaccOther,
+            Self::CudaEvent(_) => ActivityKind::CudaEvent,
+            Self::Stream(_) => ActivityKind::Stream,
+            Self::Synchronization(_) => ActivityKind::Synchronization,
+            Self::ExternalCorrelation(_) => ActivityKind::ExternalCorrelation,
+            Self::NvLink(_) => ActivityKind::NvLink,
+            Self::Memory(_) => ActivityKind::Memory,
+            Self::Pcie(_) => ActivityKind::Pcie,
+            Self::Openmp(_) => ActivityKind::Openmp,
+            Self::InternalLaunchApi(_) => ActivityKind::InternalLaunchApi,
+            Self::Memory2(_) => ActivityKind::Memory2,
+            Self::MemoryPool(_) => ActivityKind::MemoryPool,
+            Self::GraphTrace(_) => ActivityKind::GraphTrace,
+            Self::Jit(_) => ActivityKind::Jit,
+            Self::DeviceGraphTrace(_) => ActivityKind::DeviceGraphTrace,
+            Self::MemDecompress(_) => ActivityKind::MemDecompress,
+            Self::ConfidentialComputeRotation(_) => ActivityKind::ConfidentialComputeRotation,
+            Self::Unsupported(kind) => *kind,
+        }
+    }
+}
+
+/// An iterator over the records within an [`ActivityBuffer`].
+///
+/// This is created by [`ActivityBuffer::records`]. Records are decoded using
+/// the [`ActivityLayout`] of the CUPTI library loaded in this process. The
+/// layout is also checked against the size of each record within the buffer
+/// and corrected as needed.
+///
+/// A record that fails to decode is returned as an error and iteration
+/// continues with the next record.
+pub struct ActivityRecords<'a> {
+    data: &'a [u8],
+    layout: ActivityLayout,
+    record: *mut CUpti_Activity,
+    lookahead: Option<Result<usize>>,
+    done: bool,
+}
+
+impl<'a> ActivityRecords<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            layout: ActivityLayout::detected(),
+            record: std::ptr::null_mut(),
+            lookahead: None,
+            done: false,
+        }
+    }
+
+    /// Decode records using `layout` instead of the detected layout.
+    pub fn with_layout(mut self, layout: ActivityLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// The layout currently being used to decode records.
+    pub fn layout(&self) -> ActivityLayout {
+        self.layout
+    }
+
+    /// Advance to the next record, returning its offset within the buffer.
+    fn advance(&mut self) -> Option<Result<usize>> {
+        if self.done {
+            return None;
+        }
+
+        // CUPTI never writes to the buffer here, it only needs a mutable pointer
+        // to satisfy the C signature.
+        let code = unsafe {
+            cuptiActivityGetNextRecord(
+                self.data.as_ptr() as *mut u8,
+                self.data.len(),
+                &mut self.record,
+            )
+        };
+
+        if let Err(e) = Error::result(code) {
+            self.done = true;
+
+            return match e {
+                Error::MaxLimitReached => None,
+                e => Some(Err(e)),
+            };
+        }
+
+        let offset = (self.record as usize).wrapping_sub(self.data.as_ptr() as usize);
+        if offset >= self.data.len() {
+            self.done = true;
+            return Some(Err(Error::InvalidParameter));
+        }
+
+        Some(Ok(offset))
+    }
+}
+
+impl<'a> Iterator for ActivityRecords<'a> {
+    type Item = Result<ActivityRecord<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = match self.lookahead.take() {
+            Some(offset) => offset,
+            None => self.advance()?,
+        };
+        let offset = match offset {
+            Ok(offset) => offset,
+            Err(e) => return Some(Err(e)),
+        };
+
+        // Look at where the next record starts so that we know the exact size
+        // of this one.
+        self.lookahead = self.advance();
+        let bytes = match self.lookahead {
+            Some(Ok(next)) if next > offset => {
+                let bytes = &self.data[offset..next];
+                if let Some(kind) = record_kind(bytes) {
+                    self.layout = self.layout.with_record_size(kind, bytes.len());
+                }
+
+                bytes
+            }
+            _ => &self.data[offset..],
+        };
+
+        Some(unsafe { ActivityRecord::from_bytes(bytes, &self.layout) })
+    }
+}
+
+impl std::iter::FusedIterator for ActivityRecords<'_> {}
+
+/// Read the kind from the header of an activity record.
+fn record_kind(bytes: &[u8]) -> Option<ActivityKind> {
+    let header = bytes.first_chunk::<4>()?;
+
+    Some(ActivityKind::from(u32::from_ne_bytes(*header)))
+}
+
+/// Read the first `size` bytes of `bytes` into a zero-initialized `T`.
+///
+/// # Safety
+/// `T` must be valid for any bit pattern.
+unsafe fn read_prefix<T: Copy>(bytes: &[u8], size: usize) -> Result<T> {
+    let size = size.min(std::mem::size_of::<T>());
+    if bytes.len() < size {
+        return Err(Error::ParameterSizeNotSufficient);
+    }
+
+    let mut value = std::mem::MaybeUninit::<T>::zeroed();
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), value.as_mut_ptr() as *mut u8, size);
+        Ok(value.assume_init())
+    }
+}
+
+/// Read a record whose revisions only ever append fields to the previous
+/// revision.
+///
+/// Older revisions are zero-extended to `T`, which must be the latest revision
+/// of `family`. The revision that was read is returned alongside the record so
+/// that fields which were not present can be discarded.
+///
+/// # Safety
+/// `T` must be valid for any bit pattern.
+unsafe fn read_revision<T: Copy>(
+    bytes: &[u8],
+    layout: &ActivityLayout,
+    family: Family,
+) -> Result<(T, u8)> {
+    let revision = layout.revision(family);
+    let raw = unsafe { read_prefix(bytes, revision.size)? };
+
+    Ok((raw, revision.revision))
+}
+
+/// Read a kernel record, converting the first two revisions to the latest
+/// layout.
+///
+/// # Safety
+/// See [`read_revision`].
+unsafe fn read_kernel(
+    bytes: &[u8],
+    layout: &ActivityLayout,
+) -> Result<(CUpti_ActivityKernel10, u8)> {
+    let revision = layout.revision(Family::Kernel).revision;
+    if revision > 2 {
+        return unsafe { read_revision(bytes, layout, Family::Kernel) };
+    }
+
+    let mut raw: CUpti_ActivityKernel10 = unsafe { std::mem::zeroed() };
+    if revision == 1 {
+        let old: CUpti_ActivityKernel = unsafe { read(bytes)? };
+
+        raw.kind = old.kind;
+        raw.cacheConfig.both = (old.cacheConfigRequested & 0xF) | (old.cacheConfigExecuted << 4);
+        raw.registersPerThread = old.registersPerThread;
+        raw.start = old.start;
+        raw.end = old.end;
+        raw.deviceId = old.deviceId;
+        raw.contextId = old.contextId;
+        raw.streamId = old.streamId;
+        raw.gridX = old.gridX;
+        raw.gridY = old.gridY;
+        raw.gridZ = old.gridZ;
+        raw.blockX = old.blockX;
+        raw.blockY = old.blockY;
+        raw.blockZ = old.blockZ;
+        raw.staticSharedMemory = old.staticSharedMemory;
+        raw.dynamicSharedMemory = old.dynamicSharedMemory;
+        raw.localMemoryPerThread = old.localMemoryPerThread;
+        raw.localMemoryTotal = old.localMemoryTotal;
+        raw.correlationId = old.correlationId;
+        raw.name = old.name;
+    } else {
+        let old: CUpti_ActivityKernel2 = unsafe { read(bytes)? };
+
+        raw.kind = old.kind;
+        raw.cacheConfig.both = unsafe { old.cacheConfig.both };
+        raw.sharedMemoryConfig = old.sharedMemoryConfig;
+        raw.registersPerThread = old.registersPerThread;
+        raw.start = old.start;
+        raw.end = old.end;
+        raw.completed = old.completed;
+        raw.deviceId = old.deviceId;
+        raw.contextId = old.contextId;
+        raw.streamId = old.streamId;
+        raw.gridX = old.gridX;
+        raw.gridY = old.gridY;
+        raw.gridZ = old.gridZ;
+        raw.blockX = old.blockX;
+        raw.blockY = old.blockY;
+        raw.blockZ = old.blockZ;
+        raw.staticSharedMemory = old.staticSharedMemory;
+        raw.dynamicSharedMemory = old.dynamicSharedMemory;
+        raw.localMemoryPerThread = old.localMemoryPerThread;
+        raw.localMemoryTotal = old.localMemoryTotal;
+        raw.correlationId = old.correlationId;
+        raw.gridId = old.gridId;
+        raw.name = old.name;
+    }
+
+    Ok((raw, revision))
+}
+
+/// Read a device record, converting the first revision to the latest layout.
+///
+/// # Safety
+/// See [`read_revision`].
+unsafe fn read_device(
+    bytes: &[u8],
+    layout: &ActivityLayout,
+) -> Result<(CUpti_ActivityDevice5, u8)> {
+    let revision = layout.revision(Family::Device).revision;
+    if revision > 1 {
+        return unsafe { read_revision(bytes, layout, Family::Device) };
+    }
+
+    let old: CUpti_ActivityDevice = unsafe { read(bytes)? };
+    let mut raw: CUpti_ActivityDevice5 = unsafe { std::mem::zeroed() };
+
+    raw.kind = old.kind;
+    raw.flags = old.flags;
+    raw.globalMemoryBandwidth = old.globalMemoryBandwidth;
+    raw.globalMemorySize = old.globalMemorySize;
+    raw.constantMemorySize = old.constantMemorySize;
+    raw.l2CacheSize = old.l2CacheSize;
+    raw.numThreadsPerWarp = old.numThreadsPerWarp;
+    raw.coreClockRate = old.coreClockRate;
+    raw.numMemcpyEngines = old.numMemcpyEngines;
+    raw.numMultiprocessors = old.numMultiprocessors;
+    raw.maxIPC = old.maxIPC;
+    raw.maxWarpsPerMultiprocessor = old.maxWarpsPerMultiprocessor;
+    raw.maxBlocksPerMultiprocessor = old.maxBlocksPerMultiprocessor;
+    raw.maxRegistersPerBlock = old.maxRegistersPerBlock;
+    raw.maxSharedMemoryPerBlock = old.maxSharedMemoryPerBlock;
+    raw.maxThreadsPerBlock = old.maxThreadsPerBlock;
+    raw.maxBlockDimX = old.maxBlockDimX;
+    raw.maxBlockDimY = old.maxBlockDimY;
+    raw.maxBlockDimZ = old.maxBlockDimZ;
+    raw.maxGridDimX = old.maxGridDimX;
+    raw.maxGridDimY = old.maxGridDimY;
+    raw.maxGridDimZ = old.maxGridDimZ;
+    raw.computeCapabilityMajor = old.computeCapabilityMajor;
+    raw.computeCapabilityMinor = old.computeCapabilityMinor;
+    raw.id = old.id;
+    raw.name = old.name;
+
+    Ok((raw, revision))
+}
+
+/// Read an NVLink record, converting the first revision to the latest layout.
+///
+/// # Safety
+/// See [`read_revision`].
+unsafe fn read_nvlink(
+    bytes: &[u8],
+    layout: &ActivityLayout,
+) -> Result<(CUpti_ActivityNvLink4, u8)> {
+    let revision = layout.revision(Family::NvLink).revision;
+    if revision > 1 {
+        return unsafe { read_revision(bytes, layout, Family::NvLink) };
+    }
+
+    // Everything before the port numbers shares the same layout. The first
+    // revision only has room for 4 ports per device.
+    let old: CUpti_ActivityNvLink = unsafe { read(bytes)? };
+    let mut raw: CUpti_ActivityNvLink4 =
+        unsafe { read_prefix(bytes, std::mem::offset_of!(CUpti_ActivityNvLink, portDev0))? };
+
+    raw.portDev0 = [-1; 32];
+    raw.portDev1 = [-1; 32];
+    raw.portDev0[..4].copy_from_slice(&old.portDev0);
+    raw.portDev1[..4].copy_from_slice(&old.portDev1);
+    raw.bandwidth = old.bandwidth;
+
+    Ok((raw, revision))
+}
+
+/// Read a `T` from the start of `bytes`.
+///
+/// # Safety
+/// `T` must be valid for any bit pattern.
+unsafe fn read<T: Copy>(bytes: &[u8]) -> Result<T> {
+    if bytes.len() < std::mem::size_of::<T>() {
+        return Err(Error::ParameterSizeNotSufficient);
+    }
+
+    Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// # Safety
+/// `ptr` must be null or point to a nul-terminated string that lives for `'a`.
+unsafe fn cstr<'a>(ptr: *const c_char) -> Option<RecordStr<'a>> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(RecordStr::Borrowed(unsafe { CStr::from_ptr(ptr) }))
+    }
+}
+
+fn uuid(uuid: CUuuid) -> [u8; 16] {
+    uuid.bytes.map(|b| b as u8)
+}
+
+/// The identifier of the object an activity record refers to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ActivityObjectId {
+    /// A process or thread.
+    ///
+    /// `thread_id` is only meaningful for [`ActivityObjectKind::Thread`].
+    Process { process_id: u32, thread_id: u32 },
+
+    /// A device, context or stream.
+    ///
+    /// `context_id` is only meaningful for [`ActivityObjectKind::Context`] and
+    /// [`ActivityObjectKind::Stream`], and `stream_id` is only meaningful for
+    /// [`ActivityObjectKind::Stream`].
+    Device {
+        device_id: u32,
+        context_id: u32,
+        stream_id: u32,
+    },
+
+    /// The object kind was not recognized.
+    Unknown,
+}
+
+impl ActivityObjectId {
+    unsafe fn from_raw(kind: ActivityObjectKind, id: CUpti_ActivityObjectKindId) -> Self {
+        unsafe {
+            match kind {
+                ActivityObjectKind::Process | ActivityObjectKind::Thread => Self::Process {
+                    process_id: id.pt.processId,
+                    thread_id: id.pt.threadId,
+                },
+                ActivityObjectKind::Device
+                | ActivityObjectKind::Context
+                | ActivityObjectKind::Stream => Self::Device {
+                    device_id: id.dcs.deviceId,
+                    context_id: id.dcs.contextId,
+                    stream_id: id.dcs.streamId,
+                },
+                _ => Self::Unknown,
+            }
+        }
+    }
+}
+
+/// A memory copy.
+///
+/// Produced for [`ActivityKind::Memcpy`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemcpy {
+    /// The kind of the memory copy.
+    pub copy_kind: ActivityMemcpyKind,
+    /// The source memory kind read by the memory copy.
+    pub src_kind: ActivityMemoryKind,
+    /// The destination memory kind read by the memory copy.
+    pub dst_kind: ActivityMemoryKind,
+    /// The flags associated with the memory copy.
+    pub flags: ActivityFlag,
+    /// The number of bytes transferred by the memory copy.
+    pub bytes: u64,
+    /// The start timestamp for the memory copy, in ns.
+    pub start: u64,
+    /// The end timestamp for the memory copy, in ns.
+    pub end: u64,
+    /// The ID of the device where the memory copy is occurring.
+    pub device_id: u32,
+    /// The ID of the context where the memory copy is occurring.
+    pub context_id: u32,
+    /// The ID of the stream where the memory copy is occurring.
+    pub stream_id: u32,
+    /// The correlation ID of the memory copy.
+    pub correlation_id: u32,
+    /// The runtime correlation ID of the memory copy.
+    pub runtime_correlation_id: u32,
+    /// The unique ID of the graph node that executed this memcpy through graph
+    /// launch, or 0 if it was not executed through a graph launch.
+    pub graph_node_id: Option<u64>,
+    /// The ID of the graph that executed this memcpy through graph launch, or 0
+    /// if it was not executed through a graph launch.
+    pub graph_id: Option<u32>,
+    /// The ID of the HW channel on which the memory copy is occurring.
+    pub channel_id: Option<u32>,
+    /// The type of the channel.
+    pub channel_type: Option<ChannelType>,
+    /// Whether the memory copy was launched from the device.
+    pub is_device_launched: Option<bool>,
+    /// The number of memory copies in a batched memory copy, or 1 otherwise.
+    pub copy_count: Option<u64>,
+}
+
+impl ActivityMemcpy {
+    fn from_raw(raw: &CUpti_ActivityMemcpy6, revision: u8) -> Self {
+        Self {
+            copy_kind: ActivityMemcpyKind::from(raw.copyKind as u32),
+            src_kind: ActivityMemoryKind::from(raw.srcKind as u32),
+            dst_kind: ActivityMemoryKind::from(raw.dstKind as u32),
+            flags: ActivityFlag::from_bits_retain(raw.flags as u32),
+            bytes: raw.bytes,
+            start: raw.start,
+            end: raw.end,
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            correlation_id: raw.correlationId,
+            runtime_correlation_id: raw.runtimeCorrelationId,
+            graph_node_id: (revision >= 3).then_some(raw.graphNodeId),
+            graph_id: (revision >= 4).then_some(raw.graphId),
+            channel_id: (revision >= 5).then_some(raw.channelID),
+            channel_type: (revision >= 5).then_some(ChannelType::from(raw.channelType)),
+            is_device_launched: (revision >= 6).then_some(raw.isDeviceLaunched != 0),
+            copy_count: (revision >= 6).then_some(raw.copyCount),
+        }
+    }
+}
+
+/// A peer-to-peer memory copy.
+///
+/// Produced for [`ActivityKind::Memcpy2`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemcpyPtoP {
+    /// The kind of the memory copy.
+    pub copy_kind: ActivityMemcpyKind,
+    /// The source memory kind read by the memory copy.
+    pub src_kind: ActivityMemoryKind,
+    /// The destination memory kind read by the memory copy.
+    pub dst_kind: ActivityMemoryKind,
+    /// The flags associated with the memory copy.
+    pub flags: ActivityFlag,
+    /// The number of bytes transferred by the memory copy.
+    pub bytes: u64,
+    /// The start timestamp for the memory copy, in ns.
+    pub start: u64,
+    /// The end timestamp for the memory copy, in ns.
+    pub end: u64,
+    /// The ID of the device where the memory copy is occurring.
+    pub device_id: u32,
+    /// The ID of the context where the memory copy is occurring.
+    pub context_id: u32,
+    /// The ID of the stream where the memory copy is occurring.
+    pub stream_id: u32,
+    /// The ID of the device where memory is being copied from.
+    pub src_device_id: u32,
+    /// The ID of the context owning the memory being copied from.
+    pub src_context_id: u32,
+    /// The ID of the device where memory is being copied to.
+    pub dst_device_id: u32,
+    /// The ID of the context owning the memory being copied to.
+    pub dst_context_id: u32,
+    /// The correlation ID of the memory copy.
+    pub correlation_id: u32,
+    /// The unique ID of the graph node that executed this memcpy through graph
+    /// launch, or 0 if it was not executed through a graph launch.
+    pub graph_node_id: Option<u64>,
+    /// The ID of the graph that executed this memcpy through graph launch, or 0
+    /// if it was not executed through a graph launch.
+    pub graph_id: Option<u32>,
+    /// The ID of the HW channel on which the memory copy is occurring.
+    pub channel_id: Option<u32>,
+    /// The type of the channel.
+    pub channel_type: Option<ChannelType>,
+}
+
+impl ActivityMemcpyPtoP {
+    fn from_raw(raw: &CUpti_ActivityMemcpyPtoP4, revision: u8) -> Self {
+        Self {
+            copy_kind: ActivityMemcpyKind::from(raw.copyKind as u32),
+            src_kind: ActivityMemoryKind::from(raw.srcKind as u32),
+            dst_kind: ActivityMemoryKind::from(raw.dstKind as u32),
+            flags: ActivityFlag::from_bits_retain(raw.flags as u32),
+            bytes: raw.bytes,
+            start: raw.start,
+            end: raw.end,
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            src_device_id: raw.srcDeviceId,
+            src_context_id: raw.srcContextId,
+            dst_device_id: raw.dstDeviceId,
+            dst_context_id: raw.dstContextId,
+            correlation_id: raw.correlationId,
+            graph_node_id: (revision >= 2).then_some(raw.graphNodeId),
+            graph_id: (revision >= 3).then_some(raw.graphId),
+            channel_id: (revision >= 4).then_some(raw.channelID),
+            channel_type: (revision >= 4).then_some(ChannelType::from(raw.channelType)),
+        }
+    }
+}
+
+/// A memset operation.
+///
+/// Produced for [`ActivityKind::Memset`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemset {
+    /// The value being assigned to memory by the memory set.
+    pub value: u32,
+    /// The number of bytes being set by the memory set.
+    pub bytes: u64,
+    /// The start timestamp for the memory set, in ns.
+    pub start: u64,
+    /// The end timestamp for the memory set, in ns.
+    pub end: u64,
+    /// The ID of the device where the memory set is occurring.
+    pub device_id: u32,
+    /// The ID of the context where the memory set is occurring.
+    pub context_id: u32,
+    /// The ID of the stream where the memory set is occurring.
+    pub stream_id: u32,
+    /// The correlation ID of the memory set.
+    pub correlation_id: u32,
+    /// The flags associated with the memory set.
+    pub flags: ActivityFlag,
+    /// The memory kind of the memory set.
+    pub memory_kind: ActivityMemoryKind,
+    /// The unique ID of the graph node that executed this memset through graph
+    /// launch, or 0 if it was not executed through a graph launch.
+    pub graph_node_id: Option<u64>,
+    /// The ID of the graph that executed this memset through graph launch, or 0
+    /// if it was not executed through a graph launch.
+    pub graph_id: Option<u32>,
+    /// The ID of the HW channel on which the memory set is occurring.
+    pub channel_id: Option<u32>,
+    /// The type of the channel.
+    pub channel_type: Option<ChannelType>,
+    /// Whether the memory set was launched from the device.
+    pub is_device_launched: Option<bool>,
+}
+
+impl ActivityMemset {
+    fn from_raw(raw: &CUpti_ActivityMemset4, revision: u8) -> Self {
+        Self {
+            value: raw.value,
+            bytes: raw.bytes,
+            start: raw.start,
+            end: raw.end,
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            correlation_id: raw.correlationId,
+            flags: ActivityFlag::from_bits_retain(raw.flags as u32),
+            memory_kind: ActivityMemoryKind::from(raw.memoryKind as u32),
+            graph_node_id: (revision >= 2).then_some(raw.graphNodeId),
+            graph_id: (revision >= 3).then_some(raw.graphId),
+            channel_id: (revision >= 4).then_some(raw.channelID),
+            channel_type: (revision >= 4).then_some(ChannelType::from(raw.channelType)),
+            is_device_launched: (revision >= 4).then_some(raw.isDeviceLaunched != 0),
+        }
+    }
+}
+
+/// A kernel execution.
+///
+/// Produced for [`ActivityKind::Kernel`] and
+/// [`ActivityKind::ConcurrentKernel`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityKernel<'a> {
+    /// The cache configuration requested by the kernel.
+    pub cache_config_requested: u8,
+    /// The cache configuration used for the kernel.
+    pub cache_config_executed: u8,
+    /// The shared memory configuration used for the kernel.
+    pub shared_memory_config: Option<u8>,
+    /// The number of registers required for each thread executing the kernel.
+    pub registers_per_thread: u16,
+    /// The partitioned global caching requested for the kernel.
+    pub partitioned_global_cache_requested: Option<ActivityPartitionedGlobalCacheConfig>,
+    /// The partitioned global caching executed for the kernel.
+    pub partitioned_global_cache_executed: Option<ActivityPartitionedGlobalCacheConfig>,
+    /// The start timestamp for the kernel execution, in ns.
+    pub start: u64,
+    /// The end timestamp for the kernel execution, in ns.
+    pub end: u64,
+    /// The completed timestamp for the kernel execution, in ns.
+    ///
+    /// This is `CUPTI_TIMESTAMP_UNKNOWN` if the completion time is unknown.
+    pub completed: Option<u64>,
+    /// The ID of the device where the kernel is executing.
+    pub device_id: u32,
+    /// The ID of the context where the kernel is executing.
+    pub context_id: u32,
+    /// The ID of the stream where the kernel is executing.
+    pub stream_id: u32,
+    /// The X-dimension grid size for the kernel.
+    pub grid_x: i32,
+    /// The Y-dimension grid size for the kernel.
+    pub grid_y: i32,
+    /// The Z-dimension grid size for the kernel.
+    pub grid_z: i32,
+    /// The X-dimension block size for the kernel.
+    pub block_x: i32,
+    /// The Y-dimension block size for the kernel.
+    pub block_y: i32,
+    /// The Z-dimension block size for the kernel.
+    pub block_z: i32,
+    /// The static shared memory allocated for the kernel, in bytes.
+    pub static_shared_memory: i32,
+    /// The dynamic shared memory reserved for the kernel, in bytes.
+    pub dynamic_shared_memory: i32,
+    /// The amount of local memory reserved for each thread, in bytes.
+    pub local_memory_per_thread: u32,
+    /// The total amount of local memory reserved for the kernel, in bytes.
+    pub local_memory_total: u64,
+    /// The correlation ID of the kernel.
+    pub correlation_id: u32,
+    /// The grid ID of the kernel.
+    pub grid_id: Option<i64>,
+    /// The name of the kernel.
+    pub name: Option<RecordStr<'a>>,
+    /// The timestamp when the kernel is queued up in the command buffer, in ns.
+    pub queued: Option<u64>,
+    /// The timestamp when the command buffer containing the kernel launch is
+    /// submitted to the GPU, in ns.
+    pub submitted: Option<u64>,
+    /// The indicates if the kernel was executed via a regular launch or via a
+    /// single/multi device cooperative launch.
+    pub launch_type: Option<ActivityLaunchType>,
+    /// Whether the shared memory carveout was requested for the kernel.
+    pub is_shared_memory_carveout_requested: Option<bool>,
+    /// The shared memory carveout requested for the kernel, as a percentage.
+    pub shared_memory_carveout_requested: Option<u8>,
+    /// The shared memory size set by the driver, in bytes.
+    pub shared_memory_executed: Option<u32>,
+    /// The unique ID of the graph node that launched this kernel through graph
+    /// launch, or 0 if it was not launched through a graph launch.
+    pub graph_node_id: Option<u64>,
+    /// The shared memory limit config for the kernel.
+    pub shmem_limit_config: Option<FuncShmemLimitConfig>,
+    /// The ID of the graph that launched this kernel through graph launch, or 0
+    /// if it was not launched through a graph launch.
+    pub graph_id: Option<u32>,
+    /// The ID of the HW channel on which the kernel is launched.
+    pub channel_id: Option<u32>,
+    /// The type of the channel.
+    pub channel_type: Option<ChannelType>,
+    /// The X-dimension cluster size for the kernel.
+    pub cluster_x: Option<u32>,
+    /// The Y-dimension cluster size for the kernel.
+    pub cluster_y: Option<u32>,
+    /// The Z-dimension cluster size for the kernel.
+    pub cluster_z: Option<u32>,
+    /// The cluster scheduling policy for the kernel.
+    pub cluster_scheduling_policy: Option<u32>,
+    /// The maximum cluster size for the kernel.
+    pub max_potential_cluster_size: Option<u32>,
+    /// The maximum clusters that could co-exist on the target device for the
+    /// kernel.
+    pub max_active_clusters: Option<u32>,
+    /// Whether the kernel was launched from the device.
+    pub is_device_launched: Option<bool>,
+}
+
+impl<'a> ActivityKernel<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityKernel10, revision: u8) -> Self {
+        let cache_config = unsafe { raw.cacheConfig.both };
+
+        Self {
+            cache_config_requested: cache_config & 0xF,
+            cache_config_executed: cache_config >> 4,
+            shared_memory_config: (revision >= 2).then_some(raw.sharedMemoryConfig),
+            registers_per_thread: raw.registersPerThread,
+            partitioned_global_cache_requested: (revision >= 3)
+                .then_some(raw.partitionedGlobalCacheRequested.into()),
+            partitioned_global_cache_executed: (revision >= 3)
+                .then_some(raw.partitionedGlobalCacheExecuted.into()),
+            start: raw.start,
+            end: raw.end,
+            completed: (revision >= 2).then_some(raw.completed),
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            grid_x: raw.gridX,
+            grid_y: raw.gridY,
+            grid_z: raw.gridZ,
+            block_x: raw.blockX,
+            block_y: raw.blockY,
+            block_z: raw.blockZ,
+            static_shared_memory: raw.staticSharedMemory,
+            dynamic_shared_memory: raw.dynamicSharedMemory,
+            local_memory_per_thread: raw.localMemoryPerThread,
+            local_memory_total: match revision {
+                8.. => raw.localMemoryTotal_v2,
+                _ => raw.localMemoryTotal as u64,
+            },
+            correlation_id: raw.correlationId,
+            grid_id: (revision >= 2).then_some(raw.gridId),
+            name: unsafe { cstr(raw.name) },
+            queued: (revision >= 4).then_some(raw.queued),
+            submitted: (revision >= 4).then_some(raw.submitted),
+            launch_type: (revision >= 4).then_some(ActivityLaunchType::from(raw.launchType as u32)),
+            is_shared_memory_carveout_requested: (revision >= 4)
+                .then_some(raw.isSharedMemoryCarveoutRequested != 0),
+            shared_memory_carveout_requested: (revision >= 4)
+                .then_some(raw.sharedMemoryCarveoutRequested),
+            shared_memory_executed: (revision >= 4).then_some(raw.sharedMemoryExecuted),
+            graph_node_id: (revision >= 5).then_some(raw.graphNodeId),
+            shmem_limit_config: (revision >= 5).then_some(raw.shmemLimitConfig.into()),
+            graph_id: (revision >= 5).then_some(raw.graphId),
+            channel_id: (revision >= 7).then_some(raw.channelID),
+            channel_type: (revision >= 7).then_some(raw.channelType.into()),
+            cluster_x: (revision >= 8).then_some(raw.clusterX),
+            cluster_y: (revision >= 8).then_some(raw.clusterY),
+            cluster_z: (revision >= 8).then_some(raw.clusterZ),
+            cluster_scheduling_policy: (revision >= 8).then_some(raw.clusterSchedulingPolicy),
+            max_potential_cluster_size: (revision >= 9).then_some(raw.maxPotentialClusterSize),
+            max_active_clusters: (revision >= 9).then_some(raw.maxActiveClusters),
+            is_device_launched: (revision >= 10).then_some(raw.isDeviceLaunched != 0),
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityKernel<'static> {
+        ActivityKernel {
+            cache_config_requested: self.cache_config_requested,
+            cache_config_executed: self.cache_config_executed,
+            shared_memory_config: self.shared_memory_config,
+            registers_per_thread: self.registers_per_thread,
+            partitioned_global_cache_requested: self.partitioned_global_cache_requested,
+            partitioned_global_cache_executed: self.partitioned_global_cache_executed,
+            start: self.start,
+            end: self.end,
+            completed: self.completed,
+            device_id: self.device_id,
+            context_id: self.context_id,
+            stream_id: self.stream_id,
+            grid_x: self.grid_x,
+            grid_y: self.grid_y,
+            grid_z: self.grid_z,
+            block_x: self.block_x,
+            block_y: self.block_y,
+            block_z: self.block_z,
+            static_shared_memory: self.static_shared_memory,
+            dynamic_shared_memory: self.dynamic_shared_memory,
+            local_memory_per_thread: self.local_memory_per_thread,
+            local_memory_total: self.local_memory_total,
+            correlation_id: self.correlation_id,
+            grid_id: self.grid_id,
+            name: self.name.map(|s| s.into_owned(interner)),
+            queued: self.queued,
+            submitted: self.submitted,
+            launch_type: self.launch_type,
+            is_shared_memory_carveout_requested: self.is_shared_memory_carveout_requested,
+            shared_memory_carveout_requested: self.shared_memory_carveout_requested,
+            shared_memory_executed: self.shared_memory_executed,
+            graph_node_id: self.graph_node_id,
+            shmem_limit_config: self.shmem_limit_config,
+            graph_id: self.graph_id,
+            channel_id: self.channel_id,
+            channel_type: self.channel_type,
+            cluster_x: self.cluster_x,
+            cluster_y: self.cluster_y,
+            cluster_z: self.cluster_z,
+            cluster_scheduling_policy: self.cluster_scheduling_policy,
+            max_potential_cluster_size: self.max_potential_cluster_size,
+            max_active_clusters: self.max_active_clusters,
+            is_device_launched: self.is_device_launched,
+        }
+    }
+
+    /// The demangled name of the kernel, using a cache local to the calling
+    /// thread.
+    ///
+    /// See [`demangle`](crate::demangle::demangle).
+    #[cfg(feature = "demangle")]
+    pub fn demangled_name(&self) -> Option<std::sync::Arc<str>> {
+        let name = self.name.as_ref()?;
+        Some(crate::demangle::demangle(&name.to_string_lossy()))
+    }
+}
+
+/// A CDP (CUDA Dynamic Parallelism) kernel execution.
+///
+/// Produced for [`ActivityKind::CdpKernel`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityCdpKernel<'a> {
+    /// The cache configuration requested by the kernel.
+    pub cache_config_requested: u8,
+    /// The cache configuration used for the kernel.
+    pub cache_config_executed: u8,
+    /// The shared memory configuration used for the kernel.
+    pub shared_memory_config: u8,
+    /// The number of registers required for each thread executing the kernel.
+    pub registers_per_thread: u16,
+    /// The start timestamp for the kernel execution, in ns.
+    pub start: u64,
+    /// The end timestamp for the kernel execution, in ns.
+    pub end: u64,
+    /// The ID of the device where the kernel is executing.
+    pub device_id: u32,
+    /// The ID of the context where the kernel is executing.
+    pub context_id: u32,
+    /// The ID of the stream where the kernel is executing.
+    pub stream_id: u32,
+    /// The X-dimension grid size for the kernel.
+    pub grid_x: i32,
+    /// The Y-dimension grid size for the kernel.
+    pub grid_y: i32,
+    /// The Z-dimension grid size for the kernel.
+    pub grid_z: i32,
+    /// The X-dimension block size for the kernel.
+    pub block_x: i32,
+    /// The Y-dimension block size for the kernel.
+    pub block_y: i32,
+    /// The Z-dimension block size for the kernel.
+    pub block_z: i32,
+    /// The static shared memory allocated for the kernel, in bytes.
+    pub static_shared_memory: i32,
+    /// The dynamic shared memory reserved for the kernel, in bytes.
+    pub dynamic_shared_memory: i32,
+    /// The amount of local memory reserved for each thread, in bytes.
+    pub local_memory_per_thread: u32,
+    /// The total amount of local memory reserved for the kernel, in bytes.
+    pub local_memory_total: u32,
+    /// The correlation ID of the kernel.
+    pub correlation_id: u32,
+    /// The grid ID of the kernel.
+    pub grid_id: i64,
+    /// The grid ID of the parent kernel.
+    pub parent_grid_id: i64,
+    /// The timestamp when the kernel is queued up, in ns.
+    pub queued: u64,
+    /// The timestamp when the kernel is submitted to the GPU, in ns.
+    pub submitted: u64,
+    /// The timestamp when the kernel is marked as completed, in ns.
+    pub completed: u64,
+    /// The X-dimension of the parent block.
+    pub parent_block_x: u32,
+    /// The Y-dimension of the parent block.
+    pub parent_block_y: u32,
+    /// The Z-dimension of the parent block.
+    pub parent_block_z: u32,
+    /// The name of the kernel.
+    pub name: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityCdpKernel<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityCdpKernel) -> Self {
+        let cache_config = unsafe { raw.cacheConfig.both };
+
+        Self {
+            cache_config_requested: cache_config & 0xF,
+            cache_config_executed: cache_config >> 4,
+            shared_memory_config: raw.sharedMemoryConfig,
+            registers_per_thread: raw.registersPerThread,
+            start: raw.start,
+            end: raw.end,
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            grid_x: raw.gridX,
+            grid_y: raw.gridY,
+            grid_z: raw.gridZ,
+            block_x: raw.blockX,
+            block_y: raw.blockY,
+            block_z: raw.blockZ,
+            static_shared_memory: raw.staticSharedMemory,
+            dynamic_shared_memory: raw.dynamicSharedMemory,
+            local_memory_per_thread: raw.localMemoryPerThread,
+            local_memory_total: raw.localMemoryTotal,
+            correlation_id: raw.correlationId,
+            grid_id: raw.gridId,
+            parent_grid_id: raw.parentGridId,
+            queued: raw.queued,
+            submitted: raw.submitted,
+            completed: raw.completed,
+            parent_block_x: raw.parentBlockX,
+            parent_block_y: raw.parentBlockY,
+            parent_block_z: raw.parentBlockZ,
+            name: unsafe { cstr(raw.name) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityCdpKernel<'static> {
+        ActivityCdpKernel {
+            cache_config_requested: self.cache_config_requested,
+            cache_config_executed: self.cache_config_executed,
+            shared_memory_config: self.shared_memory_config,
+            registers_per_thread: self.registers_per_thread,
+            start: self.start,
+            end: self.end,
+            device_id: self.device_id,
+            context_id: self.context_id,
+            stream_id: self.stream_id,
+            grid_x: self.grid_x,
+            grid_y: self.grid_y,
+            grid_z: self.grid_z,
+            block_x: self.block_x,
+            block_y: self.block_y,
+            block_z: self.block_z,
+            static_shared_memory: self.static_shared_memory,
+            dynamic_shared_memory: self.dynamic_shared_memory,
+            local_memory_per_thread: self.local_memory_per_thread,
+            local_memory_total: self.local_memory_total,
+            correlation_id: self.correlation_id,
+            grid_id: self.grid_id,
+            parent_grid_id: self.parent_grid_id,
+            queued: self.queued,
+            submitted: self.submitted,
+            completed: self.completed,
+            parent_block_x: self.parent_block_x,
+            parent_block_y: self.parent_block_y,
+            parent_block_z: self.parent_block_z,
+            name: self.name.map(|s| s.into_owned(interner)),
+        }
+    }
+
+    /// The demangled name of the kernel, using a cache local to the calling
+    /// thread.
+    ///
+    /// See [`demangle`](crate::demangle::demangle).
+    #[cfg(feature = "demangle")]
+    pub fn demangled_name(&self) -> Option<std::sync::Arc<str>> {
+        let name = self.name.as_ref()?;
+        Some(crate::demangle::demangle(&name.to_string_lossy()))
+    }
+}
+
+/// A driver or runtime API invocation.
+///
+/// Produced for [`ActivityKind::Driver`], [`ActivityKind::Runtime`] and
+/// [`ActivityKind::InternalLaunchApi`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityApi {
+    /// The ID of the driver or runtime function.
+    ///
+    /// For driver API records this can be converted into a
+    /// [`DriverApiTraceCbid`]. It is not used for internal launch records.
+    pub cbid: u32,
+    /// The start timestamp for the function, in ns.
+    pub start: u64,
+    /// The end timestamp for the function, in ns.
+    pub end: u64,
+    /// The ID of the process where the driver or runtime CUDA function is
+    /// executing.
+    pub process_id: u32,
+    /// The ID of the thread where the driver or runtime CUDA function is
+    /// executing.
+    pub thread_id: u32,
+    /// The correlation ID of the driver or runtime CUDA function.
+    pub correlation_id: u32,
+    /// The return value for the function.
+    ///
+    /// For a CUDA driver function this is a `CUresult` value, and for a CUDA
+    /// runtime function this is a `cudaError_t` value.
+    pub return_value: u32,
+}
+
+impl ActivityApi {
+    fn from_raw(raw: &CUpti_ActivityAPI) -> Self {
+        Self {
+            cbid: raw.cbid,
+            start: raw.start,
+            end: raw.end,
+            process_id: raw.processId,
+            thread_id: raw.threadId,
+            correlation_id: raw.correlationId,
+            return_value: raw.returnValue,
+        }
+    }
+}
+
+/// Information about a device.
+///
+/// Produced for [`ActivityKind::Device`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityDevice<'a> {
+    /// The flags associated with the device.
+    pub flags: ActivityFlag,
+    /// The global memory bandwidth available on the device, in kBytes/sec.
+    pub global_memory_bandwidth: u64,
+    /// The amount of global memory on the device, in bytes.
+    pub global_memory_size: u64,
+    /// The amount of constant memory on the device, in bytes.
+    pub constant_memory_size: u32,
+    /// The size of the L2 cache on the device, in bytes.
+    pub l2_cache_size: u32,
+    /// The number of threads per warp on the device.
+    pub num_threads_per_warp: u32,
+    /// The core clock rate of the device, in kHz.
+    pub core_clock_rate: u32,
+    /// Number of memory copy engines on the device.
+    pub num_memcpy_engines: u32,
+    /// Number of multiprocessors on the device.
+    pub num_multiprocessors: u32,
+    /// The maximum "instructions per cycle" possible on each device
+    /// multiprocessor.
+    pub max_ipc: u32,
+    /// Maximum number of warps that can be present on a multiprocessor at any
+    /// given time.
+    pub max_warps_per_multiprocessor: u32,
+    /// Maximum number of blocks that can be present on a multiprocessor at any
+    /// given time.
+    pub max_blocks_per_multiprocessor: u32,
+    /// Maximum amount of shared memory available per multiprocessor, in bytes.
+    pub max_shared_memory_per_multiprocessor: Option<u32>,
+    /// Maximum number of 32-bit registers available per multiprocessor.
+    pub max_registers_per_multiprocessor: Option<u32>,
+    /// Maximum number of registers that can be allocated to a block.
+    pub max_registers_per_block: u32,
+    /// Maximum amount of shared memory that can be assigned to a block, in
+    /// bytes.
+    pub max_shared_memory_per_block: u32,
+    /// Maximum number of threads allowed in a block.
+    pub max_threads_per_block: u32,
+    /// Maximum allowed X dimension for a block.
+    pub max_block_dim_x: u32,
+    /// Maximum allowed Y dimension for a block.
+    pub max_block_dim_y: u32,
+    /// Maximum allowed Z dimension for a block.
+    pub max_block_dim_z: u32,
+    /// Maximum allowed X dimension for a grid.
+    pub max_grid_dim_x: u32,
+    /// Maximum allowed Y dimension for a grid.
+    pub max_grid_dim_y: u32,
+    /// Maximum allowed Z dimension for a grid.
+    pub max_grid_dim_z: u32,
+    /// Compute capability for the device, major number.
+    pub compute_capability_major: u32,
+    /// Compute capability for the device, minor number.
+    pub compute_capability_minor: u32,
+    /// The device ID.
+    pub id: u32,
+    /// ECC enabled flag for device.
+    pub ecc_enabled: Option<u32>,
+    /// The device UUID.
+    pub uuid: Option<[u8; 16]>,
+    /// The device name.
+    pub name: Option<RecordStr<'a>>,
+    /// Whether the CUDA driver can access this device.
+    pub is_cuda_visible: Option<bool>,
+    /// Whether MIG is enabled on this device.
+    pub is_mig_enabled: Option<bool>,
+    /// The GPU instance ID, if MIG is enabled.
+    pub gpu_instance_id: Option<u32>,
+    /// The compute instance ID, if MIG is enabled.
+    pub compute_instance_id: Option<u32>,
+    /// The MIG UUID, if MIG is enabled.
+    pub mig_uuid: Option<[u8; 16]>,
+    /// Whether this device is a NUMA node.
+    pub is_numa_node: Option<bool>,
+    /// The NUMA ID of this device.
+    pub numa_id: Option<u32>,
+}
+
+impl<'a> ActivityDevice<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityDevice5, revision: u8) -> Self {
+        Self {
+            flags: ActivityFlag::from_bits_retain(raw.flags),
+            global_memory_bandwidth: raw.globalMemoryBandwidth,
+            global_memory_size: raw.globalMemorySize,
+            constant_memory_size: raw.constantMemorySize,
+            l2_cache_size: raw.l2CacheSize,
+            num_threads_per_warp: raw.numThreadsPerWarp,
+            core_clock_rate: raw.coreClockRate,
+            num_memcpy_engines: raw.numMemcpyEngines,
+            num_multiprocessors: raw.numMultiprocessors,
+            max_ipc: raw.maxIPC,
+            max_warps_per_multiprocessor: raw.maxWarpsPerMultiprocessor,
+            max_blocks_per_multiprocessor: raw.maxBlocksPerMultiprocessor,
+            max_shared_memory_per_multiprocessor: (revision >= 2)
+                .then_some(raw.maxSharedMemoryPerMultiprocessor),
+            max_registers_per_multiprocessor: (revision >= 2)
+                .then_some(raw.maxRegistersPerMultiprocessor),
+            max_registers_per_block: raw.maxRegistersPerBlock,
+            max_shared_memory_per_block: raw.maxSharedMemoryPerBlock,
+            max_threads_per_block: raw.maxThreadsPerBlock,
+            max_block_dim_x: raw.maxBlockDimX,
+            max_block_dim_y: raw.maxBlockDimY,
+            max_block_dim_z: raw.maxBlockDimZ,
+            max_grid_dim_x: raw.maxGridDimX,
+            max_grid_dim_y: raw.maxGridDimY,
+            max_grid_dim_z: raw.maxGridDimZ,
+            compute_capability_major: raw.computeCapabilityMajor,
+            compute_capability_minor: raw.computeCapabilityMinor,
+            id: raw.id,
+            ecc_enabled: (revision >= 2).then_some(raw.eccEnabled),
+            uuid: (revision >= 2).then_some(uuid(raw.uuid)),
+            name: unsafe { cstr(raw.name) },
+            is_cuda_visible: (revision >= 3).then_some(raw.isCudaVisible != 0),
+            is_mig_enabled: (revision >= 4).then_some(raw.isMigEnabled != 0),
+            gpu_instance_id: (revision >= 4).then_some(raw.gpuInstanceId),
+            compute_instance_id: (revision >= 4).then_some(raw.computeInstanceId),
+            mig_uuid: (revision >= 4).then_some(uuid(raw.migUuid)),
+            is_numa_node: (revision >= 5).then_some(raw.isNumaNode != 0),
+            numa_id: (revision >= 5).then_some(raw.numaId),
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityDevice<'static> {
+        ActivityDevice {
+            flags: self.flags,
+            global_memory_bandwidth: self.global_memory_bandwidth,
+            global_memory_size: self.global_memory_size,
+            constant_memory_size: self.constant_memory_size,
+            l2_cache_size: self.l2_cache_size,
+            num_threads_per_warp: self.num_threads_per_warp,
+            core_clock_rate: self.core_clock_rate,
+            num_memcpy_engines: self.num_memcpy_engines,
+            num_multiprocessors: self.num_multiprocessors,
+            max_ipc: self.max_ipc,
+            max_warps_per_multiprocessor: self.max_warps_per_multiprocessor,
+            max_blocks_per_multiprocessor: self.max_blocks_per_multiprocessor,
+            max_shared_memory_per_multiprocessor: self.max_shared_memory_per_multiprocessor,
+            max_registers_per_multiprocessor: self.max_registers_per_multiprocessor,
+            max_registers_per_block: self.max_registers_per_block,
+            max_shared_memory_per_block: self.max_shared_memory_per_block,
+            max_threads_per_block: self.max_threads_per_block,
+            max_block_dim_x: self.max_block_dim_x,
+            max_block_dim_y: self.max_block_dim_y,
+            max_block_dim_z: self.max_block_dim_z,
+            max_grid_dim_x: self.max_grid_dim_x,
+            max_grid_dim_y: self.max_grid_dim_y,
+            max_grid_dim_z: self.max_grid_dim_z,
+            compute_capability_major: self.compute_capability_major,
+            compute_capability_minor: self.compute_capability_minor,
+            id: self.id,
+            ecc_enabled: self.ecc_enabled,
+            uuid: self.uuid,
+            name: self.name.map(|s| s.into_owned(interner)),
+            is_cuda_visible: self.is_cuda_visible,
+            is_mig_enabled: self.is_mig_enabled,
+            gpu_instance_id: self.gpu_instance_id,
+            compute_instance_id: self.compute_instance_id,
+            mig_uuid: self.mig_uuid,
+            is_numa_node: self.is_numa_node,
+            numa_id: self.numa_id,
+        }
+    }
+}
+
+/// The identifier of a device attribute.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceAttributeKind {
+    /// A `CUdevice_attribute` value.
+    Cuda(u32),
+    /// A `CUpti_DeviceAttribute` value.
+    Cupti(u32),
+}
+
+/// The value of a device attribute.
+///
+/// The type of the value depends on the attribute, so this stores the raw bits
+/// of the value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceAttributeValue(pub u64);
+
+impl DeviceAttributeValue {
+    /// Interpret the value as an `f64`.
+    pub fn as_f64(self) -> f64 {
+        f64::from_bits(self.0)
+    }
+
+    /// Interpret the value as a `u32`.
+    pub fn as_u32(self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Interpret the value as a `u64`.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Interpret the value as an `i32`.
+    pub fn as_i32(self) -> i32 {
+        self.0 as u32 as i32
+    }
+
+    /// Interpret the value as an `i64`.
+    pub fn as_i64(self) -> i64 {
+        self.0 as i64
+    }
+}
+
+/// A device attribute value.
+///
+/// Produced for [`ActivityKind::DeviceAttribute`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityDeviceAttribute {
+    /// The flags associated with the device.
+    pub flags: ActivityFlag,
+    /// The ID of the device that this attribute applies to.
+    pub device_id: u32,
+    /// The attribute.
+    pub attribute: DeviceAttributeKind,
+    /// The value for the attribute.
+    pub value: DeviceAttributeValue,
+}
+
+impl ActivityDeviceAttribute {
+    unsafe fn from_raw(raw: &CUpti_ActivityDeviceAttribute) -> Self {
+        let flags = ActivityFlag::from_bits_retain(raw.flags);
+        let attribute = unsafe { raw.attribute.cupti };
+
+        Self {
+            flags,
+            device_id: raw.deviceId,
+            attribute: if flags.contains(ActivityFlag::DEVICE_ATTRIBUTE_CUDEVICE) {
+                DeviceAttributeKind::Cuda(attribute)
+            } else {
+                DeviceAttributeKind::Cupti(attribute)
+            },
+            value: DeviceAttributeValue(unsafe { raw.value.vUint64 }),
+        }
+    }
+}
+
+/// Information about a context.
+///
+/// Produced for [`ActivityKind::Context`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityContext {
+    /// The context ID.
+    pub context_id: u32,
+    /// The device ID.
+    pub device_id: u32,
+    /// The compute API kind.
+    pub compute_api_kind: ActivityComputeApiKind,
+    /// The ID for the NULL stream in this context.
+    pub null_stream_id: u16,
+    /// The ID of the parent context, for green contexts.
+    pub parent_context_id: Option<u32>,
+    /// Whether this is a green context.
+    pub is_green_context: Option<bool>,
+    /// The number of multiprocessors assigned to a green context.
+    pub num_multiprocessors: Option<u16>,
+    /// The CIG mode of the context.
+    pub cig_mode: Option<ContextCigMode>,
+}
+
+impl ActivityContext {
+    fn from_raw(raw: &CUpti_ActivityContext3, revision: u8) -> Self {
+        Self {
+            context_id: raw.contextId,
+            device_id: raw.deviceId,
+            compute_api_kind: ActivityComputeApiKind::from(raw.computeApiKind as u32),
+            null_stream_id: raw.nullStreamId,
+            parent_context_id: (revision >= 2).then_some(raw.parentContextId),
+            is_green_context: (revision >= 2).then_some(raw.isGreenContext != 0),
+            num_multiprocessors: (revision >= 2).then_some(raw.numMultiprocessors),
+            cig_mode: (revision >= 3).then_some(raw.cigMode.into()),
+        }
+    }
+}
+
+/// A name assigned to an object via NVTX.
+///
+/// Produced for [`ActivityKind::Name`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityName<'a> {
+    /// The kind of activity object being named.
+    pub object_kind: ActivityObjectKind,
+    /// The identifier for the activity object.
+    pub object_id: ActivityObjectId,
+    /// The name.
+    pub name: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityName<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityName) -> Self {
+        let object_kind = ActivityObjectKind::from(raw.objectKind);
+
+        Self {
+            object_kind,
+            object_id: unsafe { ActivityObjectId::from_raw(object_kind, raw.objectId) },
+            name: unsafe { cstr(raw.name) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityName<'static> {
+        ActivityName {
+            object_kind: self.object_kind,
+            object_id: self.object_id,
+            name: self.name.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+/// An NVTX marker.
+///
+/// Produced for [`ActivityKind::Marker`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMarker<'a> {
+    /// The flags associated with the marker.
+    pub flags: ActivityFlag,
+    /// The timestamp for the marker, in ns.
+    pub timestamp: u64,
+    /// The marker ID.
+    pub id: u32,
+    /// The kind of activity object associated with this marker.
+    pub object_kind: ActivityObjectKind,
+    /// The identifier for the activity object associated with this marker.
+    pub object_id: ActivityObjectId,
+    /// The marker name for an instantaneous or start marker.
+    ///
+    /// This will be `None` for an end marker.
+    pub name: Option<RecordStr<'a>>,
+    /// The name of the domain to which this marker belongs to.
+    ///
+    /// This will be `None` for the default domain.
+    pub domain: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityMarker<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityMarker2) -> Self {
+        let object_kind = ActivityObjectKind::from(raw.objectKind);
+
+        Self {
+            flags: ActivityFlag::from_bits_retain(raw.flags),
+            timestamp: raw.timestamp,
+            id: raw.id,
+            object_kind,
+            object_id: unsafe { ActivityObjectId::from_raw(object_kind, raw.objectId) },
+            name: unsafe { cstr(raw.name) },
+            domain: unsafe { cstr(raw.domain) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityMarker<'static> {
+        ActivityMarker {
+            flags: self.flags,
+            timestamp: self.timestamp,
+            id: self.id,
+            object_kind: self.object_kind,
+            object_id: self.object_id,
+            name: self.name.map(|s| s.into_owned(interner)),
+            domain: self.domain.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+/// The payload attached to an NVTX marker.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MarkerPayload {
+    Double(f64),
+    Uint64(u64),
+    Percent(f64),
+    Throughput(u64),
+    Int64(i64),
+    UtilizationLevel(u32),
+    /// A pointer to an NVTX extended payload.
+    NvtxExtendedPayload(u64),
+    /// The payload kind was not recognized. The raw bits are provided.
+    Unknown {
+        kind: u32,
+        bits: u64,
+    },
+}
+
+impl MarkerPayload {
+    unsafe fn from_raw(kind: CUpti_MetricValueKind, value: CUpti_MetricValue) -> Self {
+        unsafe {
+            match kind {
+                CUPTI_METRIC_VALUE_KIND_DOUBLE => Self::Double(value.metricValueDouble),
+                CUPTI_METRIC_VALUE_KIND_UINT64 => Self::Uint64(value.metricValueUint64),
+                CUPTI_METRIC_VALUE_KIND_PERCENT => Self::Percent(value.metricValuePercent),
+                CUPTI_METRIC_VALUE_KIND_THROUGHPUT => Self::Throughput(value.metricValueThroughput),
+                CUPTI_METRIC_VALUE_KIND_INT64 => Self::Int64(value.metricValueInt64),
+                CUPTI_METRIC_VALUE_KIND_UTILIZATION_LEVEL => {
+                    Self::UtilizationLevel(value.metricValueUtilizationLevel)
+                }
+                CUPTI_METRIC_VALUE_KIND_NVTX_EXTENDED_PAYLOAD => {
+                    Self::NvtxExtendedPayload(value.metricValueNvtxExtendedPayload)
+                }
+                kind => Self::Unknown {
+                    kind,
+                    bits: value.metricValueUint64,
+                },
+            }
+        }
+    }
+}
+
+/// Additional data attached to an NVTX marker.
+///
+/// Produced for [`ActivityKind::MarkerData`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMarkerData {
+    /// The flags associated with the marker.
+    pub flags: ActivityFlag,
+    /// The marker ID.
+    pub id: u32,
+    /// The payload value.
+    pub payload: MarkerPayload,
+    /// The color for the marker.
+    pub color: u32,
+    /// The category for the marker.
+    pub category: u32,
+    /// The ID of the domain the marker belongs to, as assigned by CUPTI.
+    pub domain_id: Option<u32>,
+}
+
+impl ActivityMarkerData {
+    unsafe fn from_raw(raw: &CUpti_ActivityMarkerData2, revision: u8) -> Self {
+        Self {
+            flags: ActivityFlag::from_bits_retain(raw.flags),
+            id: raw.id,
+            payload: unsafe { MarkerPayload::from_raw(raw.payloadKind, raw.payload) },
+            color: raw.color,
+            category: raw.category,
+            domain_id: (revision >= 2).then_some(raw.cuptiDomainId),
+        }
+    }
+}
+
+/// CUPTI or driver overhead.
+///
+/// Produced for [`ActivityKind::Overhead`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityOverhead {
+    /// The kind of overhead.
+    pub overhead_kind: ActivityOverheadKind,
+    /// The kind of activity object that the overhead is associated with.
+    pub object_kind: ActivityObjectKind,
+    /// The identifier for the activity object.
+    pub object_id: ActivityObjectId,
+    /// The start timestamp for the overhead, in ns.
+    pub start: u64,
+    /// The end timestamp for the overhead, in ns.
+    pub end: u64,
+    /// The correlation ID of the overhead operation.
+    pub correlation_id: Option<u32>,
+}
+
+impl ActivityOverhead {
+    unsafe fn from_raw(raw: &CUpti_ActivityOverhead3, revision: u8) -> Self {
+        let object_kind = ActivityObjectKind::from(raw.objectKind);
+
+        Self {
+            overhead_kind: raw.overheadKind.into(),
+            object_kind,
+            object_id: unsafe { ActivityObjectId::from_raw(object_kind, raw.objectId) },
+            start: raw.start,
+            end: raw.end,
+            correlation_id: (revision >= 2).then_some(raw.correlationId),
+        }
+    }
+}
+
+/// A kernel preemption event.
+///
+/// Produced for [`ActivityKind::Preemption`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityPreemption {
+    /// The kind of preemption.
+    pub preemption_kind: ActivityPreemptionKind,
+    /// The timestamp of the preemption, in ns.
+    pub timestamp: u64,
+    /// The grid ID of the kernel that is being preempted.
+    pub grid_id: i64,
+    /// The X-dimension of the block that is being preempted.
+    pub block_x: u32,
+    /// The Y-dimension of the block that is being preempted.
+    pub block_y: u32,
+    /// The Z-dimension of the block that is being preempted.
+    pub block_z: u32,
+}
+
+impl ActivityPreemption {
+    fn from_raw(raw: &CUpti_ActivityPreemption) -> Self {
+        Self {
+            preemption_kind: raw.preemptionKind.into(),
+            timestamp: raw.timestamp,
+            grid_id: raw.gridId,
+            block_x: raw.blockX,
+            block_y: raw.blockY,
+            block_z: raw.blockZ,
+        }
+    }
+}
+
+/// The data associated with an environment record.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EnvironmentData {
+    /// Data for [`ActivityEnvironmentKind::Speed`].
+    Speed {
+        /// The SM frequency, in MHz.
+        sm_clock: u32,
+        /// The memory frequency, in MHz.
+        memory_clock: u32,
+        /// The PCIe link generation.
+        pcie_link_gen: u32,
+        /// The PCIe link width.
+        pcie_link_width: u32,
+        /// The clocks throttle reasons.
+        clocks_throttle_reasons: EnvironmentClocksThrottleReason,
+    },
+
+    /// Data for [`ActivityEnvironmentKind::Temperature`].
+    Temperature {
+        /// The GPU temperature, in degrees C.
+        gpu_temperature: u32,
+    },
+
+    /// Data for [`ActivityEnvironmentKind::Power`].
+    Power {
+        /// The power in milliwatts consumed by the GPU and associated
+        /// circuitry.
+        power: u32,
+        /// The power in milliwatts that will trigger power management.
+        power_limit: u32,
+    },
+
+    /// Data for [`ActivityEnvironmentKind::Cooling`].
+    Cooling {
+        /// The fan speed as a percentage of maximum.
+        fan_speed: u32,
+    },
+
+    /// The environment kind was not recognized.
+    Unknown,
+}
+
+/// A sample of the GPU environment.
+///
+/// Produced for [`ActivityKind::Environment`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityEnvironment {
+    /// The ID of the device.
+    pub device_id: u32,
+    /// The timestamp when this sample was retrieved, in ns.
+    pub timestamp: u64,
+    /// The kind of data reported in this record.
+    pub environment_kind: ActivityEnvironmentKind,
+    /// The data reported in this record.
+    pub data: EnvironmentData,
+}
+
+impl ActivityEnvironment {
+    unsafe fn from_raw(raw: &CUpti_ActivityEnvironment) -> Self {
+        let environment_kind = ActivityEnvironmentKind::from(raw.environmentKind);
+        let data = unsafe {
+            match environment_kind {
+                ActivityEnvironmentKind::Speed => EnvironmentData::Speed {
+                    sm_clock: raw.data.speed.smClock,
+                    memory_clock: raw.data.speed.memoryClock,
+                    pcie_link_gen: raw.data.speed.pcieLinkGen,
+                    pcie_link_width: raw.data.speed.pcieLinkWidth,
+                    clocks_throttle_reasons: EnvironmentClocksThrottleReason::from_bits_retain(
+                        raw.data.speed.clocksThrottleReasons,
+                    ),
+                },
+                ActivityEnvironmentKind::Temperature => EnvironmentData::Temperature {
+                    gpu_temperature: raw.data.temperature.gpuTemperature,
+                },
+                ActivityEnvironmentKind::Power => EnvironmentData::Power {
+                    power: raw.data.power.power,
+                    power_limit: raw.data.power.powerLimit,
+                },
+                ActivityEnvironmentKind::Cooling => EnvironmentData::Cooling {
+                    fan_speed: raw.data.cooling.fanSpeed,
+                },
+                _ => EnvironmentData::Unknown,
+            }
+        };
+
+        Self {
+            device_id: raw.deviceId,
+            timestamp: raw.timestamp,
+            environment_kind,
+            data,
+        }
+    }
+}
+
+/// A unified memory counter.
+///
+/// Produced for [`ActivityKind::UnifiedMemoryCounter`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityUnifiedMemoryCounter {
+    /// The unified memory counter kind.
+    pub counter_kind: ActivityUnifiedMemoryCounterKind,
+    /// The value of the counter.
+    ///
+    /// For counters that record data transfers this is the number of bytes
+    /// transferred. For page faults this is the number of faults.
+    pub value: u64,
+    /// The start timestamp of the counter, in ns.
+    pub start: u64,
+    /// The end timestamp of the counter, in ns.
+    pub end: u64,
+    /// The virtual base address of the page(s) being transferred or faulted.
+    pub address: u64,
+    /// The ID of the source CPU/device involved in the memory transfer or page
+    /// fault.
+    pub src_id: u32,
+    /// The ID of the destination CPU/device involved in the memory transfer or
+    /// remote map.
+    pub dst_id: u32,
+    /// The ID of the stream causing the transfer.
+    pub stream_id: u32,
+    /// The ID of the process to which this record belongs to.
+    pub process_id: u32,
+    /// The raw flags associated with this record.
+    pub flags: u32,
+    /// The bitmask of processors involved in a thrashing or throttling event.
+    pub processors: Option<[u64; 5]>,
+    /// The type of memory access that caused a page fault.
+    ///
+    /// Only present for [`ActivityUnifiedMemoryCounterKind::GpuPageFault`].
+    pub access_type: Option<ActivityUnifiedMemoryAccessType>,
+    /// The cause of a migration.
+    ///
+    /// Only present for [`ActivityUnifiedMemoryCounterKind::BytesTransferHtoD`]
+    /// and [`ActivityUnifiedMemoryCounterKind::BytesTransferDtoH`].
+    pub migration_cause: Option<ActivityUnifiedMemoryMigrationCause>,
+    /// The cause of a remote map.
+    ///
+    /// Only present for [`ActivityUnifiedMemoryCounterKind::RemoteMap`].
+    pub remote_map_cause: Option<ActivityUnifiedMemoryRemoteMapCause>,
+}
+
+impl ActivityUnifiedMemoryCounter {
+    fn from_raw(raw: &CUpti_ActivityUnifiedMemoryCounter3, revision: u8) -> Self {
+        use ActivityUnifiedMemoryCounterKind as Kind;
+
+        let counter_kind: Kind = raw.counterKind.into();
+
+        Self {
+            counter_kind,
+            value: raw.value,
+            start: raw.start,
+            end: raw.end,
+            address: raw.address,
+            src_id: raw.srcId,
+            dst_id: raw.dstId,
+            stream_id: raw.streamId,
+            process_id: raw.processId,
+            flags: raw.flags,
+            processors: (revision >= 3).then_some(raw.processors),
+            access_type: matches!(counter_kind, Kind::GpuPageFault).then(|| raw.flags.into()),
+            migration_cause: matches!(
+                counter_kind,
+                Kind::BytesTransferHtoD | Kind::BytesTransferDtoH
+            )
+            .then(|| raw.flags.into()),
+            remote_map_cause: matches!(counter_kind, Kind::RemoteMap).then(|| raw.flags.into()),
+        }
+    }
+}
+
+/// A global function executing on the device.
+///
+/// Produced for [`ActivityKind::Function`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityFunction<'a> {
+    /// The ID of the function.
+    pub id: u32,
+    /// The ID of the context where the function is launched.
+    pub context_id: u32,
+    /// The module ID that the function belongs to.
+    pub module_id: u32,
+    /// The index of the function in the module.
+    pub function_index: u32,
+    /// The name of the function.
+    pub name: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityFunction<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityFunction) -> Self {
+        Self {
+            id: raw.id,
+            context_id: raw.contextId,
+            module_id: raw.moduleId,
+            function_index: raw.functionIndex,
+            name: unsafe { cstr(raw.name) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityFunction<'static> {
+        ActivityFunction {
+            id: self.id,
+            context_id: self.context_id,
+            module_id: self.module_id,
+            function_index: self.function_index,
+            name: self.name.map(|s| s.into_owned(interner)),
+        }
+    }
+
+    /// The demangled name of the function, using a cache local to the calling
+    /// thread.
+    ///
+    /// See [`demangle`](crate::demangle::demangle).
+    #[cfg(feature = "demangle")]
+    pub fn demangled_name(&self) -> Option<std::sync::Arc<str>> {
+        let name = self.name.as_ref()?;
+        Some(crate::demangle::demangle(&name.to_string_lossy()))
+    }
+}
+
+/// A CUDA module.
+///
+/// Produced for [`ActivityKind::Module`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityModule<'a> {
+    /// The ID of the context where the module is loaded.
+    pub context_id: u32,
+    /// The module ID.
+    pub id: u32,
+    /// The cubin data for the module.
+    pub cubin: Cow<'a, [u8]>,
+}
+
+impl<'a> ActivityModule<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityModule) -> Self {
+        let cubin = if raw.cubin.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(raw.cubin as *const u8, raw.cubinSize as usize) }
+        };
+
+        Self {
+            context_id: raw.contextId,
+            id: raw.id,
+            cubin: Cow::Borrowed(cubin),
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, _interner: &mut Interner) -> ActivityModule<'static> {
+        ActivityModule {
+            context_id: self.context_id,
+            id: self.id,
+            cubin: Cow::Owned(self.cubin.into_owned()),
+        }
+    }
+}
+
+/// Fields common to all OpenACC activity records.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityOpenAccCommon<'a> {
+    /// The OpenACC event kind.
+    pub event_kind: OpenAccEventKind,
+    /// The kind of OpenACC parent construct.
+    pub parent_construct: OpenAccConstructKind,
+    /// The version number.
+    pub version: u32,
+    /// Whether this event was generated implicitly.
+    pub implicit: bool,
+    /// The device type.
+    pub device_type: u32,
+    /// The device number.
+    pub device_number: u32,
+    /// The ID of the thread that generated the event.
+    pub thread_id: u32,
+    /// The value of the async clause.
+    pub async_: u64,
+    /// The internal asynchronous queue number used.
+    pub async_map: u64,
+    /// The line number of the directive or program construct.
+    pub line_no: u32,
+    /// The line number of the end of the directive or program construct.
+    pub end_line_no: u32,
+    /// The line number of the first line of the function.
+    pub func_line_no: u32,
+    /// The line number of the last line of the function.
+    pub func_end_line_no: u32,
+    /// The CUPTI start timestamp, in ns.
+    pub start: u64,
+    /// The CUPTI end timestamp, in ns.
+    pub end: u64,
+    /// The CUDA device ID.
+    pub cu_device_id: u32,
+    /// The CUDA context ID.
+    pub cu_context_id: u32,
+    /// The CUDA stream ID.
+    pub cu_stream_id: u32,
+    /// The ID of the process where the OpenACC activity is executing.
+    pub cu_process_id: u32,
+    /// The ID of the thread where the OpenACC activity is executing.
+    pub cu_thread_id: u32,
+    /// The OpenACC correlation ID.
+    pub external_id: u32,
+    /// The name of the source file, if known.
+    pub src_file: Option<RecordStr<'a>>,
+    /// The name of the function, if known.
+    pub func_name: Option<RecordStr<'a>>,
+}
+
+impl ActivityOpenAccCommon<'_> {
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityOpenAccCommon<'static> {
+        ActivityOpenAccCommon {
+            event_kind: self.event_kind,
+            parent_construct: self.parent_construct,
+            version: self.version,
+            implicit: self.implicit,
+            device_type: self.device_type,
+            device_number: self.device_number,
+            thread_id: self.thread_id,
+            async_: self.async_,
+            async_map: self.async_map,
+            line_no: self.line_no,
+            end_line_no: self.end_line_no,
+            func_line_no: self.func_line_no,
+            func_end_line_no: self.func_end_line_no,
+            start: self.start,
+            end: self.end,
+            cu_device_id: self.cu_device_id,
+            cu_context_id: self.cu_context_id,
+            cu_stream_id: self.cu_stream_id,
+            cu_process_id: self.cu_process_id,
+            cu_thread_id: self.cu_thread_id,
+            external_id: self.external_id,
+            src_file: self.src_file.map(|s| s.into_owned(interner)),
+            func_name: self.func_name.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+macro_rules! openacc_common {
+    ($raw:expr) => {{
+        let raw = $raw;
+
+        ActivityOpenAccCommon {
+            event_kind: raw.eventKind.into(),
+            parent_construct: raw.parentConstruct.into(),
+            version: raw.version,
+            implicit: raw.implicit != 0,
+            device_type: raw.deviceType,
+            device_number: raw.deviceNumber,
+            thread_id: raw.threadId,
+            async_: raw.async_,
+            async_map: raw.asyncMap,
+            line_no: raw.lineNo,
+            end_line_no: raw.endLineNo,
+            func_line_no: raw.funcLineNo,
+            func_end_line_no: raw.funcEndLineNo,
+            start: raw.start,
+            end: raw.end,
+            cu_device_id: raw.cuDeviceId,
+            cu_context_id: raw.cuContextId,
+            cu_stream_id: raw.cuStreamId,
+            cu_process_id: raw.cuProcessId,
+            cu_thread_id: raw.cuThreadId,
+            external_id: raw.externalId,
+            src_file: unsafe { cstr(raw.srcFile) },
+            func_name: unsafe { cstr(raw.funcName) },
+        }
+    }};
+}
+
+/// An OpenACC data event.
+///
+/// Produced for [`ActivityKind::OpenaccData`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityOpenAccData<'a> {
+    /// Fields common to all OpenACC records.
+    pub common: ActivityOpenAccCommon<'a>,
+    /// The number of bytes.
+    pub bytes: u64,
+    /// The host pointer if available, or 0.
+    pub host_ptr: u64,
+    /// The device pointer if available, or 0.
+    pub device_ptr: u64,
+    /// The variable name, if available.
+    pub var_name: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityOpenAccData<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityOpenAccData) -> Self {
+        Self {
+            common: openacc_common!(raw),
+            bytes: raw.bytes,
+            host_ptr: raw.hostPtr,
+            device_ptr: raw.devicePtr,
+            var_name: unsafe { cstr(raw.varName) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityOpenAccData<'static> {
+        ActivityOpenAccData {
+            common: self.common.into_owned(interner),
+            bytes: self.bytes,
+            host_ptr: self.host_ptr,
+            device_ptr: self.device_ptr,
+            var_name: self.var_name.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+/// An OpenACC launch event.
+///
+/// Produced for [`ActivityKind::OpenaccLaunch`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityOpenAccLaunch<'a> {
+    /// Fields common to all OpenACC records.
+    pub common: ActivityOpenAccCommon<'a>,
+    /// The number of gangs created for this kernel launch.
+    pub num_gangs: u64,
+    /// The number of workers created for this kernel launch.
+    pub num_workers: u64,
+    /// The number of vector lanes created for this kernel launch.
+    pub vector_length: u64,
+    /// The name of the kernel, if available.
+    pub kernel_name: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityOpenAccLaunch<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityOpenAccLaunch) -> Self {
+        Self {
+            common: openacc_common!(raw),
+            num_gangs: raw.numGangs,
+            num_workers: raw.numWorkers,
+            vector_length: raw.vectorLength,
+            kernel_name: unsafe { cstr(raw.kernelName) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityOpenAccLaunch<'static> {
+        ActivityOpenAccLaunch {
+            common: self.common.into_owned(interner),
+            num_gangs: self.num_gangs,
+            num_workers: self.num_workers,
+            vector_length: self.vector_length,
+            kernel_name: self.kernel_name.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+/// Any other OpenACC event.
+///
+/// Produced for [`ActivityKind::OpenaccOther`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityOpenAccOther<'a> {
+    /// Fields common to all OpenACC records.
+    pub common: ActivityOpenAccCommon<'a>,
+}
+
+impl<'a> ActivityOpenAccOther<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityOpenAccOther) -> Self {
+        Self {
+            common: openacc_common!(raw),
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityOpenAccOther<'static> {
+        ActivityOpenAccOther {
+            common: self.common.into_owned(interner),
+        }
+    }
+}
+
+/// An OpenMP event.
+///
+/// Produced for [`ActivityKind::Openmp`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityOpenMp {
+    /// The OpenMP event kind.
+    pub event_kind: OpenMpEventKind,
+    /// The version number.
+    pub version: u32,
+    /// The ID of the OpenMP thread.
+    pub thread_id: u32,
+    /// The start timestamp, in ns.
+    pub start: u64,
+    /// The end timestamp, in ns.
+    pub end: u64,
+    /// The ID of the process where the OpenMP activity is executing.
+    pub cu_process_id: u32,
+    /// The ID of the thread where the OpenMP activity is executing.
+    pub cu_thread_id: u32,
+}
+
+impl ActivityOpenMp {
+    fn from_raw(raw: &CUpti_ActivityOpenMp) -> Self {
+        Self {
+            event_kind: raw.eventKind.into(),
+            version: raw.version,
+            thread_id: raw.threadId,
+            start: raw.start,
+            end: raw.end,
+            cu_process_id: raw.cuProcessId,
+            cu_thread_id: raw.cuThreadId,
+        }
+    }
+}
+
+/// A CUDA event record.
+///
+/// Produced for [`ActivityKind::CudaEvent`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityCudaEvent {
+    /// The correlation ID of the `cudaEventRecord` call.
+    pub correlation_id: u32,
+    /// The ID of the context where the event was recorded.
+    pub context_id: u32,
+    /// The compute stream where the event was recorded.
+    pub stream_id: u32,
+    /// A unique event ID to identify the event record.
+    pub event_id: u32,
+    /// The ID of the device where the event was recorded.
+    pub device_id: Option<u32>,
+    /// The device-side timestamp of the event, in ns.
+    pub device_timestamp: Option<u64>,
+    /// A unique ID used to match the event record with the corresponding
+    /// synchronization record.
+    pub cuda_event_sync_id: Option<u64>,
+}
+
+impl ActivityCudaEvent {
+    fn from_raw(raw: &CUpti_ActivityCudaEvent2, revision: u8) -> Self {
+        Self {
+            correlation_id: raw.correlationId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            event_id: raw.eventId,
+            device_id: (revision >= 2).then_some(raw.deviceId),
+            device_timestamp: (revision >= 2).then_some(raw.deviceTimestamp),
+            cuda_event_sync_id: (revision >= 2).then_some(raw.cudaEventSyncId),
+        }
+    }
+}
+
+/// Information about a stream.
+///
+/// Produced for [`ActivityKind::Stream`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityStream {
+    /// The ID of the context where the stream was created.
+    pub context_id: u32,
+    /// A unique stream ID to identify the stream.
+    pub stream_id: u32,
+    /// The clamped priority for the stream.
+    pub priority: u32,
+    /// The flags passed when the stream was created.
+    pub flag: ActivityStreamFlag,
+    /// The correlation ID of the API to which this result is associated.
+    pub correlation_id: u32,
+}
+
+impl ActivityStream {
+    fn from_raw(raw: &CUpti_ActivityStream) -> Self {
+        Self {
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            priority: raw.priority,
+            flag: raw.flag.into(),
+            correlation_id: raw.correlationId,
+        }
+    }
+}
+
+/// A synchronization operation.
+///
+/// Produced for [`ActivityKind::Synchronization`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivitySynchronization {
+    /// The type of record.
+    pub type_: ActivitySynchronizationType,
+    /// The start timestamp for the function, in ns.
+    pub start: u64,
+    /// The end timestamp for the function, in ns.
+    pub end: u64,
+    /// The correlation ID of the API to which this result is associated.
+    pub correlation_id: u32,
+    /// The ID of the context for which the synchronization API is called.
+    pub context_id: u32,
+    /// The compute stream for which the synchronization API is called.
+    pub stream_id: u32,
+    /// The event ID for which the synchronization API is called.
+    pub cuda_event_id: u32,
+    /// A unique ID used to match the synchronization record with the
+    /// corresponding CUDA event record.
+    pub cuda_event_sync_id: Option<u64>,
+    /// The return value of the synchronization API.
+    pub return_value: Option<u32>,
+}
+
+impl ActivitySynchronization {
+    fn from_raw(raw: &CUpti_ActivitySynchronization2, revision: u8) -> Self {
+        Self {
+            type_: raw.type_.into(),
+            start: raw.start,
+            end: raw.end,
+            correlation_id: raw.correlationId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            cuda_event_id: raw.cudaEventId,
+            cuda_event_sync_id: (revision >= 2).then_some(raw.cudaEventSyncId),
+            return_value: (revision >= 2).then_some(raw.returnValue),
+        }
+    }
+}
+
+/// A correlation between a CUPTI correlation ID and an external ID.
+///
+/// Produced for [`ActivityKind::ExternalCorrelation`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityExternalCorrelation {
+    /// The kind of external API this record correlates to.
+    pub external_kind: ExternalCorrelationKind,
+    /// The correlation ID of the associated non-CUDA API record.
+    pub external_id: u64,
+    /// The correlation ID of the associated CUDA driver or runtime API record.
+    pub correlation_id: u32,
+}
+
+impl ActivityExternalCorrelation {
+    fn from_raw(raw: &CUpti_ActivityExternalCorrelation) -> Self {
+        Self {
+            external_kind: raw.externalKind.into(),
+            external_id: raw.externalId,
+            correlation_id: raw.correlationId,
+        }
+    }
+
+    /// The external correlation ID this record refers to.
+    ///
+    /// This is the ID that was pushed with [`external_correlation`] when the
+    /// associated CUDA API was called.
+    pub fn external(&self) -> ExternalCorrelationId {
+        ExternalCorrelationId::new(self.external_kind, self.external_id)
+    }
+}
+
+/// A device connected by NVLink.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NvLinkDevice {
+    /// A GPU, identified by its UUID.
+    Gpu { uuid: [u8; 16] },
+    /// An NVLink processing unit in a CPU.
+    Npu { index: u32, domain_id: u32 },
+    /// The device type was not recognized.
+    Unknown,
+}
+
+/// An NVLink connection between two devices.
+///
+/// Produced for [`ActivityKind::NvLink`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityNvLink {
+    /// The NVLink version.
+    pub nvlink_version: u32,
+    /// The type of the first device.
+    pub type_dev0: DevType,
+    /// The type of the second device.
+    pub type_dev1: DevType,
+    /// The first device.
+    pub id_dev0: NvLinkDevice,
+    /// The second device.
+    pub id_dev1: NvLinkDevice,
+    /// The capabilities of the link.
+    pub flag: LinkFlag,
+    /// The number of physical NVLinks present between the two devices.
+    pub physical_nvlink_count: u32,
+    /// The port numbers for the links connected to the first device.
+    ///
+    /// Entries of -1 are invalid.
+    pub port_dev0: [i8; 32],
+    /// The port numbers for the links connected to the second device.
+    ///
+    /// Entries of -1 are invalid.
+    pub port_dev1: [i8; 32],
+    /// The bandwidth of the NVLink, in kbytes/sec.
+    pub bandwidth: u64,
+    /// Whether an NVSwitch is connected between the devices.
+    pub nvswitch_connected: Option<bool>,
+}
+
+impl ActivityNvLink {
+    unsafe fn from_raw(raw: &CUpti_ActivityNvLink4, revision: u8) -> Self {
+        let type_dev0 = DevType::from(raw.typeDev0);
+        let type_dev1 = DevType::from(raw.typeDev1);
+
+        let id_dev0 = unsafe {
+            match type_dev0 {
+                DevType::Gpu => NvLinkDevice::Gpu {
+                    uuid: uuid(raw.idDev0.uuidDev),
+                },
+                DevType::Npu => NvLinkDevice::Npu {
+                    index: raw.idDev0.npu.index,
+                    domain_id: raw.idDev0.npu.domainId,
+                },
+                _ => NvLinkDevice::Unknown,
+            }
+        };
+        let id_dev1 = unsafe {
+            match type_dev1 {
+                DevType::Gpu => NvLinkDevice::Gpu {
+                    uuid: uuid(raw.idDev1.uuidDev),
+                },
+                DevType::Npu => NvLinkDevice::Npu {
+                    index: raw.idDev1.npu.index,
+                    domain_id: raw.idDev1.npu.domainId,
+                },
+                _ => NvLinkDevice::Unknown,
+            }
+        };
+
+        Self {
+            nvlink_version: raw.nvlinkVersion,
+            type_dev0,
+            type_dev1,
+            id_dev0,
+            id_dev1,
+            flag: LinkFlag::from_bits_retain(raw.flag),
+            physical_nvlink_count: raw.physicalNvLinkCount,
+            port_dev0: raw.portDev0,
+            port_dev1: raw.portDev1,
+            bandwidth: raw.bandwidth,
+            nvswitch_connected: (revision >= 3).then_some(raw.nvswitchConnected != 0),
+        }
+    }
+}
+
+/// A PCIe device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PcieDevice {
+    /// A GPU.
+    Gpu {
+        /// The GPU device ID.
+        device_id: i32,
+        /// The UUID of the GPU.
+        uuid: [u8; 16],
+        /// The devices that this GPU has peer access to.
+        peer_devices: [i32; 32],
+    },
+
+    /// A PCIe bridge.
+    Bridge {
+        /// A unique identifier for the bridge.
+        bridge_id: u32,
+        /// The bus ID of the bridge's secondary interface.
+        secondary_bus: u16,
+        /// The device ID of the bridge.
+        device_id: u16,
+        /// The vendor ID of the bridge.
+        vendor_id: u16,
+    },
+
+    /// The device type was not recognized.
+    Unknown,
+}
+
+/// PCIe topology information.
+///
+/// Produced for [`ActivityKind::Pcie`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityPcie {
+    /// The type of the PCIe device.
+    pub type_: PcieDeviceType,
+    /// The PCIe device.
+    pub device: PcieDevice,
+    /// The domain of the PCIe device.
+    pub domain: u32,
+    /// The PCIe generation.
+    pub pcie_generation: PcieGen,
+    /// The link rate of the device, in Mbps.
+    pub link_rate: u16,
+    /// The link width of the device.
+    pub link_width: u16,
+    /// The upstream bus ID for the device.
+    pub upstream_bus: u16,
+}
+
+impl ActivityPcie {
+    unsafe fn from_raw(raw: &CUpti_ActivityPcie) -> Self {
+        let type_ = PcieDeviceType::from(raw.type_);
+        let device = unsafe {
+            match type_ {
+                PcieDeviceType::Gpu => PcieDevice::Gpu {
+                    device_id: raw.id.devId,
+                    uuid: uuid(raw.attr.gpuAttr.uuidDev),
+                    peer_devices: raw.attr.gpuAttr.peerDev,
+                },
+                PcieDeviceType::Bridge => PcieDevice::Bridge {
+                    bridge_id: raw.id.bridgeId,
+                    secondary_bus: raw.attr.bridgeAttr.secondaryBus,
+                    device_id: raw.attr.bridgeAttr.deviceId,
+                    vendor_id: raw.attr.bridgeAttr.vendorId,
+                },
+                _ => PcieDevice::Unknown,
+            }
+        };
+
+        Self {
+            type_,
+            device,
+            domain: raw.domain,
+            pcie_generation: PcieGen::from(raw.pcieGeneration as u32),
+            link_rate: raw.linkRate,
+            link_width: raw.linkWidth,
+            upstream_bus: raw.upstreamBus,
+        }
+    }
+}
+
+/// A memory allocation and free.
+///
+/// Produced for [`ActivityKind::Memory`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemory<'a> {
+    /// The memory kind requested by the user.
+    pub memory_kind: ActivityMemoryKind,
+    /// The virtual address of the allocation.
+    pub address: u64,
+    /// The number of bytes of memory allocated.
+    pub bytes: u64,
+    /// The start timestamp for the memory operation, in ns.
+    pub start: u64,
+    /// The end timestamp for the memory operation, in ns.
+    pub end: u64,
+    /// The program counter of the allocation of memory.
+    pub alloc_pc: u64,
+    /// The program counter of the freeing of memory.
+    pub free_pc: u64,
+    /// The ID of the process to which this record belongs to.
+    pub process_id: u32,
+    /// The ID of the device where the memory allocation is taking place.
+    pub device_id: u32,
+    /// The ID of the context.
+    pub context_id: u32,
+    /// The variable name of the memory, if available.
+    pub name: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityMemory<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityMemory) -> Self {
+        Self {
+            memory_kind: raw.memoryKind.into(),
+            address: raw.address,
+            bytes: raw.bytes,
+            start: raw.start,
+            end: raw.end,
+            alloc_pc: raw.allocPC,
+            free_pc: raw.freePC,
+            process_id: raw.processId,
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            name: unsafe { cstr(raw.name) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityMemory<'static> {
+        ActivityMemory {
+            memory_kind: self.memory_kind,
+            address: self.address,
+            bytes: self.bytes,
+            start: self.start,
+            end: self.end,
+            alloc_pc: self.alloc_pc,
+            free_pc: self.free_pc,
+            process_id: self.process_id,
+            device_id: self.device_id,
+            context_id: self.context_id,
+            name: self.name.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+/// The memory pool configuration used for a memory operation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemoryPoolConfig {
+    /// The type of the memory pool.
+    pub memory_pool_type: ActivityMemoryPoolType,
+    /// The base address of the memory pool.
+    pub address: u64,
+    /// The release threshold of the memory pool, in bytes.
+    pub release_threshold: u64,
+    /// The size of the memory pool, for local memory pools.
+    pub size: Option<u64>,
+    /// The ID of the process from which the pool was imported, for imported
+    /// memory pools.
+    pub process_id: Option<u64>,
+    /// The amount of memory from the pool that is in use, in bytes.
+    pub utilized_size: Option<u64>,
+}
+
+/// A memory allocation or free operation.
+///
+/// Produced for [`ActivityKind::Memory2`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemory2<'a> {
+    /// The memory operation requested by the user.
+    pub memory_operation_type: ActivityMemoryOperationType,
+    /// The memory kind requested by the user.
+    pub memory_kind: ActivityMemoryKind,
+    /// The correlation ID of the memory operation.
+    pub correlation_id: u32,
+    /// The virtual address of the allocation.
+    pub address: u64,
+    /// The number of bytes of memory allocated.
+    pub bytes: u64,
+    /// The start timestamp for the memory operation, in ns.
+    pub timestamp: u64,
+    /// The program counter of the memory operation.
+    pub pc: u64,
+    /// The ID of the process to which this record belongs to.
+    pub process_id: u32,
+    /// The ID of the device where the memory operation is taking place.
+    pub device_id: u32,
+    /// The ID of the context.
+    pub context_id: u32,
+    /// The ID of the stream, for asynchronous memory operations.
+    pub stream_id: u32,
+    /// The variable name of the memory, if available.
+    pub name: Option<RecordStr<'a>>,
+    /// Whether the memory operation happened through an async memory API.
+    pub is_async: bool,
+    /// The memory pool configuration, if the memory was allocated from a pool.
+    pub memory_pool_config: Option<ActivityMemoryPoolConfig>,
+    /// The source of the allocation, if allocation source tracking is enabled.
+    pub source: Option<RecordStr<'a>>,
+}
+
+impl<'a> ActivityMemory2<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityMemory4, revision: u8) -> Self {
+        let pool = &raw.memoryPoolConfig;
+        let memory_pool_type = ActivityMemoryPoolType::from(pool.memoryPoolType);
+        let memory_pool_config = match memory_pool_type {
+            ActivityMemoryPoolType::Invalid => None,
+            _ => Some(ActivityMemoryPoolConfig {
+                memory_pool_type,
+                address: pool.address,
+                release_threshold: pool.releaseThreshold,
+                size: match memory_pool_type {
+                    ActivityMemoryPoolType::Local => Some(unsafe { pool.pool.size }),
+                    _ => None,
+                },
+                process_id: match memory_pool_type {
+                    ActivityMemoryPoolType::Imported => Some(unsafe { pool.pool.processId }),
+                    _ => None,
+                },
+                utilized_size: (revision >= 3).then_some(pool.utilizedSize),
+            }),
+        };
+
+        Self {
+            memory_operation_type: raw.memoryOperationType.into(),
+            memory_kind: raw.memoryKind.into(),
+            correlation_id: raw.correlationId,
+            address: raw.address,
+            bytes: raw.bytes,
+            timestamp: raw.timestamp,
+            pc: raw.PC,
+            process_id: raw.processId,
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            name: unsafe { cstr(raw.name) },
+            is_async: raw.isAsync != 0,
+            memory_pool_config,
+            source: unsafe { cstr(raw.source) },
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityMemory2<'static> {
+        ActivityMemory2 {
+            memory_operation_type: self.memory_operation_type,
+            memory_kind: self.memory_kind,
+            correlation_id: self.correlation_id,
+            address: self.address,
+            bytes: self.bytes,
+            timestamp: self.timestamp,
+            pc: self.pc,
+            process_id: self.process_id,
+            device_id: self.device_id,
+            context_id: self.context_id,
+            stream_id: self.stream_id,
+            name: self.name.map(|s| s.into_owned(interner)),
+            is_async: self.is_async,
+            memory_pool_config: self.memory_pool_config,
+            source: self.source.map(|s| s.into_owned(interner)),
+        }
+    }
+}
+
+/// A memory pool creation, destruction or trimming.
+///
+/// Produced for [`ActivityKind::MemoryPool`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemoryPool {
+    /// The memory pool operation requested by the user.
+    pub memory_pool_operation_type: ActivityMemoryPoolOperationType,
+    /// The type of the memory pool.
+    pub memory_pool_type: ActivityMemoryPoolType,
+    /// The correlation ID of the memory pool operation.
+    pub correlation_id: u32,
+    /// The ID of the process to which this record belongs to.
+    pub process_id: u32,
+    /// The ID of the device where the memory pool is created.
+    pub device_id: u32,
+    /// The minimum bytes to keep of the memory pool.
+    pub min_bytes_to_keep: u64,
+    /// The virtual address of the allocation.
+    pub address: u64,
+    /// The size of the memory pool operation, in bytes.
+    pub size: u64,
+    /// The release threshold of the memory pool.
+    pub release_threshold: u64,
+    /// The start timestamp for the memory operation, in ns.
+    pub timestamp: u64,
+    /// The utilized size of the memory pool, in bytes.
+    pub utilized_size: Option<u64>,
+    /// Whether the memory pool is a managed memory pool.
+    pub is_managed_pool: Option<bool>,
+}
+
+impl ActivityMemoryPool {
+    fn from_raw(raw: &CUpti_ActivityMemoryPool3, revision: u8) -> Self {
+        Self {
+            memory_pool_operation_type: raw.memoryPoolOperationType.into(),
+            memory_pool_type: raw.memoryPoolType.into(),
+            correlation_id: raw.correlationId,
+            process_id: raw.processId,
+            device_id: raw.deviceId,
+            min_bytes_to_keep: raw.minBytesToKeep as u64,
+            address: raw.address,
+            size: raw.size,
+            release_threshold: raw.releaseThreshold,
+            timestamp: raw.timestamp,
+            utilized_size: (revision >= 2).then_some(raw.utilizedSize),
+            is_managed_pool: (revision >= 3).then_some(raw.isManagedPool != 0),
+        }
+    }
+}
+
+/// The execution of a CUDA graph.
+///
+/// Produced for [`ActivityKind::GraphTrace`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityGraphTrace {
+    /// The correlation ID of the graph launch.
+    pub correlation_id: u32,
+    /// The start timestamp for the graph execution, in ns.
+    pub start: u64,
+    /// The end timestamp for the graph execution, in ns.
+    pub end: u64,
+    /// The ID of the device where the graph execution starts.
+    pub device_id: u32,
+    /// The unique ID of the graph that is launched.
+    pub graph_id: u32,
+    /// The ID of the context where the graph is launched.
+    pub context_id: u32,
+    /// The ID of the stream where the graph is launched.
+    pub stream_id: u32,
+    /// The ID of the device where the graph execution ends.
+    pub end_device_id: Option<u32>,
+    /// The ID of the context where the graph execution ends.
+    pub end_context_id: Option<u32>,
+}
+
+impl ActivityGraphTrace {
+    fn from_raw(raw: &CUpti_ActivityGraphTrace2, revision: u8) -> Self {
+        Self {
+            correlation_id: raw.correlationId,
+            start: raw.start,
+            end: raw.end,
+            device_id: raw.deviceId,
+            graph_id: raw.graphId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            end_device_id: (revision >= 2).then_some(raw.endDeviceId),
+            end_context_id: (revision >= 2).then_some(raw.endContextId),
+        }
+    }
+}
+
+/// The execution of a device-launched CUDA graph.
+///
+/// Produced for [`ActivityKind::DeviceGraphTrace`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityDeviceGraphTrace {
+    /// The ID of the device where the graph execution occurs.
+    pub device_id: u32,
+    /// The start timestamp for the graph execution, in ns.
+    pub start: u64,
+    /// The end timestamp for the graph execution, in ns.
+    pub end: u64,
+    /// The unique ID of the graph that is launched.
+    pub graph_id: u32,
+    /// The unique ID of the graph that launched this graph.
+    pub launcher_graph_id: u32,
+    /// The type of launch.
+    pub device_launch_mode: DeviceGraphLaunchMode,
+    /// The ID of the context where the graph is launched.
+    pub context_id: u32,
+    /// The ID of the stream where the graph is launched.
+    pub stream_id: u64,
+}
+
+impl ActivityDeviceGraphTrace {
+    fn from_raw(raw: &CUpti_ActivityDeviceGraphTrace) -> Self {
+        Self {
+            device_id: raw.deviceId,
+            start: raw.start,
+            end: raw.end,
+            graph_id: raw.graphId,
+            launcher_graph_id: raw.launcherGraphId,
+            device_launch_mode: raw.deviceLaunchMode.into(),
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+        }
+    }
+}
+
+/// A JIT compilation or compute cache operation.
+///
+/// Produced for [`ActivityKind::Jit`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityJit<'a> {
+    /// The JIT entry type.
+    pub jit_entry_type: ActivityJitEntryType,
+    /// The JIT operation type.
+    pub jit_operation_type: ActivityJitOperationType,
+    /// The device ID.
+    pub device_id: u32,
+    /// The start timestamp for the JIT operation, in ns.
+    pub start: u64,
+    /// The end timestamp for the JIT operation, in ns.
+    pub end: u64,
+    /// The correlation ID of the JIT operation.
+    pub correlation_id: u32,
+    /// The correlation ID to correlate JIT compilation, load and store
+    /// operations.
+    pub jit_operation_correlation_id: u64,
+    /// The size of compute cache, in bytes.
+    pub cache_size: u64,
+    /// The path where the fat binary is cached.
+    pub cache_path: Option<RecordStr<'a>>,
+    /// The ID of the process where the JIT operation is executing.
+    pub process_id: Option<u32>,
+    /// The ID of the thread where the JIT operation is executing.
+    pub thread_id: Option<u32>,
+}
+
+impl<'a> ActivityJit<'a> {
+    unsafe fn from_raw(raw: &CUpti_ActivityJit2, revision: u8) -> Self {
+        Self {
+            jit_entry_type: raw.jitEntryType.into(),
+            jit_operation_type: raw.jitOperationType.into(),
+            device_id: raw.deviceId,
+            start: raw.start,
+            end: raw.end,
+            correlation_id: raw.correlationId,
+            jit_operation_correlation_id: raw.jitOperationCorrelationId,
+            cache_size: raw.cacheSize,
+            cache_path: unsafe { cstr(raw.cachePath) },
+            process_id: (revision >= 2).then_some(raw.processId),
+            thread_id: (revision >= 2).then_some(raw.threadId),
+        }
+    }
+
+    /// Convert this record into one that does not borrow from the activity
+    /// buffer.
+    pub fn into_owned(self, interner: &mut Interner) -> ActivityJit<'static> {
+        ActivityJit {
+            jit_entry_type: self.jit_entry_type,
+            jit_operation_type: self.jit_operation_type,
+            device_id: self.device_id,
+            start: self.start,
+            end: self.end,
+            correlation_id: self.correlation_id,
+            jit_operation_correlation_id: self.jit_operation_correlation_id,
+            cache_size: self.cache_size,
+            cache_path: self.cache_path.map(|s| s.into_owned(interner)),
+            process_id: self.process_id,
+            thread_id: self.thread_id,
+        }
+    }
+}
+
+/// A batch of memory decompression operations.
+///
+/// Produced for [`ActivityKind::MemDecompress`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityMemDecompress {
+    /// The ID of the device where the decompression is occurring.
+    pub device_id: u32,
+    /// The ID of the context where the decompression is occurring.
+    pub context_id: u32,
+    /// The ID of the stream where the decompression is occurring.
+    pub stream_id: u32,
+    /// The ID of the HW channel on which the decompression is occurring.
+    pub channel_id: u32,
+    /// The type of the channel.
+    pub channel_type: ChannelType,
+    /// The correlation ID of the decompression.
+    pub correlation_id: u32,
+    /// The number of decompression operations in the batch.
+    pub number_of_operations: u32,
+    /// The total number of compressed bytes.
+    pub source_bytes: u64,
+    /// The start timestamp for the decompression, in ns.
+    pub start: u64,
+    /// The end timestamp for the decompression, in ns.
+    pub end: u64,
+}
+
+impl ActivityMemDecompress {
+    fn from_raw(raw: &CUpti_ActivityMemDecompress) -> Self {
+        Self {
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            stream_id: raw.streamId,
+            channel_id: raw.channelID,
+            channel_type: raw.channelType.into(),
+            correlation_id: raw.correlationId,
+            number_of_operations: raw.numberOfOperations,
+            source_bytes: raw.sourceBytes,
+            start: raw.start,
+            end: raw.end,
+        }
+    }
+}
+
+/// A confidential compute key rotation event.
+///
+/// Produced for [`ActivityKind::ConfidentialComputeRotation`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActivityConfidentialComputeRotation {
+    /// The type of the rotation event.
+    pub event_type: ConfidentialComputeRotation,
+    /// The ID of the device.
+    pub device_id: u32,
+    /// The ID of the context.
+    pub context_id: u32,
+    /// The ID of the channel.
+    pub channel_id: u32,
+    /// The type of the channel.
+    pub channel_type: ChannelType,
+    /// The timestamp of the event, in ns.
+    pub timestamp: u64,
+}
+
+impl ActivityConfidentialComputeRotation {
+    fn from_raw(raw: &CUpti_ActivityConfidentialComputeRotation) -> Self {
+        Self {
+            event_type: raw.eventType.into(),
+            device_id: raw.deviceId,
+            context_id: raw.contextId,
+            channel_id: raw.channelId,
+            channel_type: raw.channelType.into(),
+            timestamp: raw.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::mem::size_of;
+
+    use super::*;
+
+    /// Every kind that has a decoded record type.
+    const KINDS: &[ActivityKind] = &[
+        ActivityKind::Memcpy,
+        ActivityKind::Memset,
+        ActivityKind::Kernel,
+        ActivityKind::Driver,
+        ActivityKind::Runtime,
+        ActivityKind::Device,
+        ActivityKind::Context,
+        ActivityKind::ConcurrentKernel,
+        ActivityKind::Name,
+        ActivityKind::Marker,
+        ActivityKind::MarkerData,
+        ActivityKind::Overhead,
+        ActivityKind::CdpKernel,
+        ActivityKind::Preemption,
+        ActivityKind::Environment,
+        ActivityKind::Memcpy2,
+        ActivityKind::UnifiedMemoryCounter,
+        ActivityKind::Function,
+        ActivityKind::Module,
+        ActivityKind::DeviceAttribute,
+        ActivityKind::OpenaccData,
+        ActivityKind::OpenaccLaunch,
+        ActivityKind::OpenaccOther,
+        ActivityKind::CudaEvent,
+        ActivityKind::Stream,
+        ActivityKind::Synchronization,
+        ActivityKind::ExternalCorrelation,
+        ActivityKind::NvLink,
+        ActivityKind::Memory,
+        ActivityKind::Pcie,
+        ActivityKind::Openmp,
+        ActivityKind::InternalLaunchApi,
+        ActivityKind::Memory2,
+        ActivityKind::MemoryPool,
+        ActivityKind::GraphTrace,
+        ActivityKind::Jit,
+        ActivityKind::DeviceGraphTrace,
+        ActivityKind::MemDecompress,
+        ActivityKind::ConfidentialComputeRotation,
+    ];
+
+    fn bytes_of<T: Copy>(raw: &T) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(raw as *const T as *const u8, size_of::<T>()) }
+    }
+
+    /// A zeroed record of `kind` with room to spare for any revision.
+    fn zeroed_record(kind: ActivityKind) -> Vec<u8> {
+        let mut bytes = vec![0; 4096];
+        bytes[..4].copy_from_slice(&u32::from(kind).to_ne_bytes());
+        bytes
+    }
+
+    #[test]
+    fn decodes_every_kind() {
+        for layout in [ActivityLayout::latest(), ActivityLayout::for_version(2)] {
+            for &kind in KINDS {
+                let bytes = zeroed_record(kind);
+                let record = unsafe { ActivityRecord::from_bytes(&bytes, &layout) }
+                    .unwrap_or_else(|e| panic!("failed to decode {kind:?}: {e:?}"));
+
+                assert_eq!(record.kind(), kind);
+
+                // The first revision of the unified memory counter record is not
+                // decoded.
+                let expect_unsupported = kind == ActivityKind::UnifiedMemoryCounter
+                    && layout.revision(Family::UnifiedMemoryCounter).revision == 1;
+                assert_eq!(
+                    matches!(record, ActivityRecord::Unsupported(_)),
+                    expect_unsupported,
+                    "{kind:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_records_are_rejected() {
+        for layout in [ActivityLayout::latest(), ActivityLayout::for_version(2)] {
+            for &kind in KINDS {
+                let size = layout.record_size(kind).unwrap();
+                let bytes = zeroed_record(kind);
+                let result = unsafe { ActivityRecord::from_bytes(&bytes[..size - 1], &layout) };
+
+                if kind == ActivityKind::UnifiedMemoryCounter
+                    && layout.revision(Family::UnifiedMemoryCounter).revision == 1
+                {
+                    continue;
+                }
+
+                assert!(
+                    matches!(result, Err(Error::ParameterSizeNotSufficient)),
+                    "{kind:?} decoded from {} bytes",
+                    size - 1
+                );
+            }
+        }
+
+        let result = unsafe { ActivityRecord::from_bytes(&[1, 0], &ActivityLayout::latest()) };
+        assert!(matches!(result, Err(Error::ParameterSizeNotSufficient)));
+    }
+
+    #[test]
+    fn unknown_kinds_are_unsupported() {
+        for kind in [ActivityKind::Event, ActivityKind::from(0x7fff_0000)] {
+            let bytes = zeroed_record(kind);
+            let record = unsafe { ActivityRecord::from_bytes(&bytes, &ActivityLayout::latest()) };
+
+            match record {
+                Ok(ActivityRecord::Unsupported(decoded)) => assert_eq!(decoded, kind),
+                other => panic!("unexpected result {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_kernel_fields() {
+        let name = CString::new("_Z6vecAddPKfS0_Pfi").unwrap();
+
+        let mut raw: CUpti_ActivityKernel10 = unsafe { std::mem::zeroed() };
+        raw.kind = ActivityKind::ConcurrentKernel.into();
+        raw.registersPerThread = 32;
+        raw.start = 1_000;
+        raw.end = 3_500;
+        raw.deviceId = 1;
+        raw.contextId = 2;
+        raw.streamId = 7;
+        raw.gridX = 128;
+        raw.gridY = 2;
+        raw.gridZ = 1;
+        raw.blockX = 256;
+        raw.blockY = 1;
+        raw.blockZ = 1;
+        raw.correlationId = 42;
+        raw.gridId = 9;
+        raw.name = name.as_ptr();
+
+        let layout = ActivityLayout::for_version(CUPTI_API_VERSION);
+        let bytes = bytes_of(&raw);
+        let record = unsafe { ActivityRecord::from_bytes(bytes, &layout) }.unwrap();
+
+        let ActivityRecord::ConcurrentKernel(kernel) = record else {
+            panic!("unexpected record {record:?}");
+        };
+        assert_eq!(kernel.registers_per_thread, 32);
+        assert_eq!((kernel.start, kernel.end), (1_000, 3_500));
+        assert_eq!(
+            (kernel.device_id, kernel.context_id, kernel.stream_id),
+            (1, 2, 7)
+        );
+        assert_eq!((kernel.grid_x, kernel.grid_y, kernel.grid_z), (128, 2, 1));
+        assert_eq!(
+            (kernel.block_x, kernel.block_y, kernel.block_z),
+            (256, 1, 1)
+        );
+        assert_eq!(kernel.correlation_id, 42);
+        assert_eq!(kernel.grid_id, Some(9));
+        assert_eq!(kernel.name.unwrap().as_c_str(), name.as_c_str());
+    }
+
+    #[test]
+    fn decodes_first_kernel_revision() {
+        let mut raw: CUpti_ActivityKernel = unsafe { std::mem::zeroed() };
+        raw.kind = ActivityKind::Kernel.into();
+        raw.cacheConfigRequested = 1;
+        raw.cacheConfigExecuted = 2;
+        raw.start = 10;
+        raw.end = 20;
+        raw.gridX = 4;
+        raw.correlationId = 5;
+
+        let layout = ActivityLayout::for_version(1);
+        let record = unsafe { ActivityRecord::from_bytes(bytes_of(&raw), &layout) }.unwrap();
+
+        let ActivityRecord::Kernel(kernel) = record else {
+            panic!("unexpected record {record:?}");
+        };
+        assert_eq!(kernel.cache_config_requested, 1);
+        assert_eq!(kernel.cache_config_executed, 2);
+        assert_eq!((kernel.start, kernel.end), (10, 20));
+        assert_eq!(kernel.grid_x, 4);
+        assert_eq!(kernel.correlation_id, 5);
+        assert_eq!(kernel.completed, None);
+        assert_eq!(kernel.grid_id, None);
+        assert!(kernel.name.is_none());
+    }
+
+    #[test]
+    fn decodes_api_fields() {
+        let mut raw: CUpti_ActivityAPI = unsafe { std::mem::zeroed() };
+        raw.kind = ActivityKind::Runtime.into();
+        raw.cbid = 211;
+        raw.start = 100;
+        raw.end = 150;
+        raw.processId = 1234;
+        raw.threadId = 5678;
+        raw.correlationId = 42;
+        raw.returnValue = 3;
+
+        let record =
+            unsafe { ActivityRecord::from_bytes(bytes_of(&raw), &ActivityLayout::latest()) }
+                .unwrap();
+
+        let ActivityRecord::Runtime(api) = record else {
+            panic!("unexpected record {record:?}");
+        };
+        assert_eq!(api.cbid, 211);
+        assert_eq!((api.start, api.end), (100, 150));
+        assert_eq!((api.process_id, api.thread_id), (1234, 5678));
+        assert_eq!(api.correlation_id, 42);
+        assert_eq!(api.return_value, 3);
+    }
+
+    #[test]
+    fn decodes_memcpy_fields() {
+        let mut raw: CUpti_ActivityMemcpy6 = unsafe { std::mem::zeroed() };
+        raw.kind = ActivityKind::Memcpy.into();
+        raw.copyKind = ActivityMemcpyKind::Htod.0 as u8;
+        raw.srcKind = ActivityMemoryKind::Pageable.0 as u8;
+        raw.dstKind = ActivityMemoryKind::Device.0 as u8;
+        raw.bytes = 1 << 20;
+        raw.start = 5;
+        raw.end = 9;
+        raw.streamId = 3;
+        raw.correlationId = 11;
+
+        let layout = ActivityLayout::for_version(CUPTI_API_VERSION);
+        let record = unsafe { ActivityRecord::from_bytes(bytes_of(&raw), &layout) }.unwrap();
+
+        let ActivityRecord::Memcpy(memcpy) = record else {
+            panic!("unexpected record {record:?}");
+        };
+        assert_eq!(memcpy.copy_kind, ActivityMemcpyKind::Htod);
+        assert_eq!(memcpy.src_kind, ActivityMemoryKind::Pageable);
+        assert_eq!(memcpy.dst_kind, ActivityMemoryKind::Device);
+        assert_eq!(memcpy.bytes, 1 << 20);
+        assert_eq!((memcpy.start, memcpy.end), (5, 9));
+        assert_eq!(memcpy.stream_id, 3);
+        assert_eq!(memcpy.correlation_id, 11);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let name = CString::new(&b"kernel_\xff"[..]).unwrap();
+
+        let mut raw: CUpti_ActivityKernel10 = unsafe { std::mem::zeroed() };
+        raw.kind = ActivityKind::ConcurrentKernel.into();
+        raw.start = 1_000;
+        raw.end = 3_500;
+        raw.gridX = 128;
+        raw.correlationId = 42;
+        raw.name = name.as_ptr();
+
+        let layout = ActivityLayout::for_version(CUPTI_API_VERSION);
+        let record = unsafe { ActivityRecord::from_bytes(bytes_of(&raw), &layout) }
+            .unwrap()
+            .into_owned(&mut Interner::new());
+
+        let check = |decoded: ActivityRecord<'static>| {
+            let ActivityRecord::ConcurrentKernel(kernel) = decoded else {
+                panic!("unexpected record {decoded:?}");
+            };
+            assert_eq!((kernel.start, kernel.end), (1_000, 3_500));
+            assert_eq!(kernel.grid_x, 128);
+            assert_eq!(kernel.correlation_id, 42);
+            assert_eq!(kernel.name.unwrap().as_c_str(), name.as_c_str());
+        };
+
+        let json = serde_json::to_string(&record).unwrap();
+        check(serde_json::from_str(&json).unwrap());
+
+        let bytes = postcard::to_stdvec(&record).unwrap();
+        check(postcard::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn record_size_tracks_buffer() {
+        let layout = ActivityLayout::for_version(1);
+        let size = size_of::<CUpti_ActivityKernel10>();
+        let layout = layout.with_record_size(ActivityKind::Kernel, size);
+
+        assert_eq!(layout.record_size(ActivityKind::Kernel), Some(size));
+        assert_eq!(layout.revision(Family::Kernel).revision, 10);
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cupti_sys::*;
+
+use super::get_timestamp;
+use crate::*;
+
+type ClockFn = Box<dyn Fn() -> u64 + Send + Sync>;
+
+static CLOCK: AtomicPtr<ClockFn> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Register a clock that CUPTI uses for the timestamps in activity records.
+///
+/// This wraps `cuptiActivityRegisterTimestampCallback`. By default CUPTI uses
+/// its own clock (see [`get_timestamp`]). Registering a clock makes the
+/// timestamps of activity records share a domain with other host-side
+/// timestamps taken from the same clock. CUPTI converts GPU timestamps into
+/// this domain by interpolation.
+///
+/// `clock` can be a plain `fn() -> u64` or a closure, and should return
+/// nanoseconds. It is called from many threads, including from within CUDA
+/// API calls, so it should be cheap. Panicking within `clock` will abort the
+/// process. [`realtime_timestamp`] can be used to report timestamps from the
+/// system clock, and [`monotonic_raw_timestamp`] from `CLOCK_MONOTONIC_RAW`
+/// on Linux.
+///
+/// The clock should be registered before any activity kinds are enabled.
+/// Records created before the clock is changed may still report timestamps
+/// using the previous clock. Since CUPTI may still be calling a previous clock
+/// when it is replaced, registering a new clock leaks the previous one. For the
+/// same reason the new clock is also leaked if registration fails.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+pub fn register_timestamp_callback<F>(clock: F) -> Result<()>
+where
+    F: Fn() -> u64 + Send + Sync + 'static,
+{
+    let clock: *mut ClockFn = Box::into_raw(Box::new(Box::new(clock)));
+    let previous = CLOCK.swap(clock, Ordering::AcqRel);
+
+    let code = unsafe { cuptiActivityRegisterTimestampCallback(Some(timestamp_callback)) };
+    if code != CUPTI_SUCCESS {
+        // If an earlier registration succeeded then CUPTI may already have
+        // called the new clock through `timestamp_callback`, so it is leaked
+        // rather than freed.
+        let _ = CLOCK.compare_exchange(clock, previous, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    Error::result(code)
+}
+
+unsafe extern "C" fn timestamp_callback() -> u64 {
+    let clock = CLOCK.load(Ordering::Acquire);
+
+    match unsafe { clock.as_ref() } {
+        Some(clock) => clock(),
+        None => 0,
+    }
+}
+
+/// The current system time as nanoseconds since the Unix epoch.
+///
+/// This reads the same clock as [`SystemTime::now`] (`CLOCK_REALTIME` on
+/// Linux) and is suitable for passing to [`register_timestamp_callback`].
+pub fn realtime_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// The current value of `CLOCK_MONOTONIC_RAW` in nanoseconds.
+///
+/// Unlike [`realtime_timestamp`], this clock never jumps and is not slewed by
+/// NTP, which makes it a good match for CUPTI's own clock when correlating
+/// with other tools that use it, such as `perf`. It is suitable for passing to
+/// [`register_timestamp_callback`].
+#[cfg(target_os = "linux")]
+pub fn monotonic_raw_timestamp() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // CLOCK_MONOTONIC_RAW is always available on Linux and `ts` is valid, so
+    // this cannot fail.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC_RAW, &mut ts) };
+
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// A mapping from activity record timestamps to [`SystemTime`].
+///
+/// The mapping is established by sampling the timestamp clock alongside the
+/// system clock and measuring the offset between the two. The clocks may
+/// drift apart over time, so long-running processes should periodically
+/// create a new `ClockDomain`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ClockDomain {
+    /// The system time, in nanoseconds since the Unix epoch, minus the
+    /// timestamp at the same instant.
+    offset: i128,
+}
+
+impl ClockDomain {
+    /// The number of samples taken when calibrating a clock.
+    const SAMPLES: usize = 16;
+
+    /// Measure the offset between [`get_timestamp`] and the system clock.
+    ///
+    /// This assumes that no clock has been registered with
+    /// [`register_timestamp_callback`]. Use [`calibrate_with`] to calibrate a
+    /// registered clock instead.
+    ///
+    /// [`calibrate_with`]: Self::calibrate_with
+    pub fn calibrate() -> Self {
+        Self::calibrate_with(get_timestamp)
+    }
+
+    /// Measure the offset between `clock` and the system clock.
+    ///
+    /// `clock` should be the same clock that was passed to
+    /// [`register_timestamp_callback`].
+    pub fn calibrate_with<F: Fn() -> u64>(clock: F) -> Self {
+        // Take several samples and use the one where the system clock reads
+        // were closest together, since it has the smallest error.
+        let (_, offset) = (0..Self::SAMPLES)
+            .map(|_| {
+                let before = system_nanos(SystemTime::now());
+                let timestamp = clock();
+                let after = system_nanos(SystemTime::now());
+
+                let window = after - before;
+                let offset = before + window / 2 - i128::from(timestamp);
+                (window, offset)
+            })
+            .min_by_key(|&(window, _)| window)
+            .expect("at least one sample is taken");
+
+        Self { offset }
+    }
+
+    /// Create a clock domain from a known offset.
+    ///
+    /// `offset` is the system time, in nanoseconds since the Unix epoch, minus
+    /// the timestamp taken at the same instant.
+    pub fn from_offset(offset: i128) -> Self {
+        Self { offset }
+    }
+
+    /// The system time, in nanoseconds since the Unix epoch, minus the
+    /// timestamp taken at the same instant.
+    pub fn offset(&self) -> i128 {
+        self.offset
+    }
+
+    /// Convert an activity record timestamp to a [`SystemTime`].
+    pub fn to_system_time(&self, timestamp: u64) -> SystemTime {
+        let nanos = i128::from(timestamp) + self.offset;
+
+        if nanos >= 0 {
+            UNIX_EPOCH + duration_from_nanos(nanos.unsigned_abs())
+        } else {
+            UNIX_EPOCH - duration_from_nanos(nanos.unsigned_abs())
+        }
+    }
+
+    /// Convert a [`SystemTime`] to an activity record timestamp.
+    ///
+    /// Times that fall outside the range of the timestamp clock are clamped
+    /// to it.
+    pub fn to_timestamp(&self, time: SystemTime) -> u64 {
+        let nanos = system_nanos(time) - self.offset;
+
+        nanos.clamp(0, u64::MAX.into()) as u64
+    }
+}
+
+fn system_nanos(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+fn duration_from_nanos(nanos: u128) -> Duration {
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_with_offset() {
+        let clock = ClockDomain::from_offset(1_700_000_000_000_000_000);
+        assert_eq!(clock.offset(), 1_700_000_000_000_000_000);
+
+        let time = clock.to_system_time(1_500);
+        assert_eq!(time, UNIX_EPOCH + Duration::new(1_700_000_000, 1_500));
+        assert_eq!(clock.to_timestamp(time), 1_500);
+
+        for timestamp in [0, 1, 999_999_999, 1_000_000_000, u64::MAX / 2] {
+            assert_eq!(
+                clock.to_timestamp(clock.to_system_time(timestamp)),
+                timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn converts_with_negative_offset() {
+        let clock = ClockDomain::from_offset(-5_000_000_000);
+
+        assert_eq!(
+            clock.to_system_time(7_000_000_000),
+            UNIX_EPOCH + Duration::from_secs(2)
+        );
+        assert_eq!(
+            clock.to_system_time(1_000_000_000),
+            UNIX_EPOCH - Duration::from_secs(4)
+        );
+        assert_eq!(
+            clock.to_timestamp(UNIX_EPOCH - Duration::from_secs(4)),
+            1_000_000_000
+        );
+    }
+
+    #[test]
+    fn to_timestamp_clamps_to_clock_range() {
+        let clock = ClockDomain::from_offset(1_000_000_000);
+
+        // Before the clock's zero.
+        assert_eq!(clock.to_timestamp(UNIX_EPOCH), 0);
+        assert_eq!(clock.to_timestamp(UNIX_EPOCH - Duration::from_secs(60)), 0);
+
+        let clock = ClockDomain::from_offset(i128::from(u64::MAX) * -2);
+        assert_eq!(clock.to_timestamp(UNIX_EPOCH), u64::MAX);
+    }
+
+    #[test]
+    fn calibrates_against_clock() {
+        const OFFSET: i128 = 1_234_567_890_123;
+
+        let clock = || (system_nanos(SystemTime::now()) - OFFSET) as u64;
+        let calibrated = ClockDomain::calibrate_with(clock);
+
+        // The error is bounded by the time between the clock reads.
+        let error = (calibrated.offset() - OFFSET).abs();
+        assert!(error < 10_000_000, "error of {error} ns");
+    }
+}
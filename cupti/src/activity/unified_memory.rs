@@ -0,0 +1,316 @@
+use c_enum::CEnum;
+use cupti_sys::*;
+
+use super::{ActivityUnifiedMemoryCounterKind, ActivityUnifiedMemoryCounterScope};
+use crate::*;
+
+/// The configuration of a single unified memory counter.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct UnifiedMemoryCounterConfig {
+    /// The scope of the counter (deprecated in CUDA 7.0).
+    pub scope: ActivityUnifiedMemoryCounterScope,
+    /// The kind of the counter.
+    pub kind: ActivityUnifiedMemoryCounterKind,
+    /// The ID of the target device.
+    ///
+    /// This is only relevant for
+    /// [`ActivityUnifiedMemoryCounterScope::ProcessSingleDevice`] (deprecated
+    /// in CUDA 7.0).
+    pub device_id: u32,
+    /// Whether the counter should be enabled or disabled.
+    pub enable: bool,
+}
+
+impl UnifiedMemoryCounterConfig {
+    /// A configuration that enables `kind` for all devices in the process.
+    pub const fn new(kind: ActivityUnifiedMemoryCounterKind) -> Self {
+        Self {
+            scope: ActivityUnifiedMemoryCounterScope::ProcessAllDevices,
+            kind,
+            device_id: 0,
+            enable: true,
+        }
+    }
+
+    /// Check that this configuration is valid.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidParameter`] if the scope or kind is unknown
+    pub fn validate(&self) -> Result<()> {
+        let scope = matches!(
+            self.scope,
+            ActivityUnifiedMemoryCounterScope::ProcessSingleDevice
+                | ActivityUnifiedMemoryCounterScope::ProcessAllDevices
+        );
+        let kind = !matches!(self.kind, ActivityUnifiedMemoryCounterKind::Unknown)
+            && self.kind.variant_label().is_some();
+
+        if scope && kind {
+            Ok(())
+        } else {
+            Err(Error::InvalidParameter)
+        }
+    }
+
+    fn to_raw(self) -> CUpti_ActivityUnifiedMemoryCounterConfig {
+        CUpti_ActivityUnifiedMemoryCounterConfig {
+            scope: self.scope.into(),
+            kind: self.kind.into(),
+            deviceId: self.device_id,
+            enable: self.enable.into(),
+        }
+    }
+
+    /// Whether `other` configures the same counter as `self`.
+    fn same_counter(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.scope == other.scope
+            && (self.scope != ActivityUnifiedMemoryCounterScope::ProcessSingleDevice
+                || self.device_id == other.device_id)
+    }
+}
+
+/// Configure unified memory counters.
+///
+/// This wraps `cuptiActivityConfigureUnifiedMemoryCounter`. The counters should
+/// be configured after the CUDA driver has been initialized and before
+/// [`ActivityKind::UnifiedMemoryCounter`] is enabled.
+///
+/// The counters are configured all at once, so if any of them is not supported
+/// then none of them are configured. See [`UnifiedMemoryCounters`] for a way to
+/// find out which counters are supported.
+///
+/// # Errors
+///
+/// - [`Error::NotInitialized`]
+/// - [`Error::InvalidParameter`] if any of the configurations is not valid
+/// - [`Error::UmProfilingNotSupported`] if the platform does not support
+///   unified memory counters
+/// - [`Error::UmProfilingNotSupportedOnDevice`] if the device does not support
+///   unified memory counters
+/// - [`Error::UmProfilingNotSupportedOnNonP2PDevices`] if there are multiple
+///   GPUs without P2P support between them
+///
+/// [`ActivityKind::UnifiedMemoryCounter`]: super::ActivityKind::UnifiedMemoryCounter
+pub fn configure_unified_memory_counters(configs: &[UnifiedMemoryCounterConfig]) -> Result<()> {
+    let mut raw: Vec<_> = configs.iter().map(|config| config.to_raw()).collect();
+    let count = raw.len().try_into().map_err(|_| Error::InvalidParameter)?;
+
+    Error::result(unsafe { cuptiActivityConfigureUnifiedMemoryCounter(raw.as_mut_ptr(), count) })
+}
+
+/// A builder for a set of unified memory counter configurations.
+///
+/// Unlike [`configure_unified_memory_counters`], [`configure`] validates the
+/// configurations first and then determines which of them are supported on
+/// the current system, configuring only those.
+///
+/// [`configure`]: Self::configure
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UnifiedMemoryCounters {
+    configs: Vec<UnifiedMemoryCounterConfig>,
+}
+
+impl UnifiedMemoryCounters {
+    /// Create an empty set of counter configurations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a counter configuration.
+    pub fn config(mut self, config: UnifiedMemoryCounterConfig) -> Self {
+        self.configs.push(config);
+        self
+    }
+
+    /// Enable a counter for all devices in the process.
+    pub fn enable(self, kind: ActivityUnifiedMemoryCounterKind) -> Self {
+        self.config(UnifiedMemoryCounterConfig::new(kind))
+    }
+
+    /// Enable a counter for a single device (deprecated in CUDA 7.0).
+    pub fn enable_for_device(self, kind: ActivityUnifiedMemoryCounterKind, device_id: u32) -> Self {
+        self.config(UnifiedMemoryCounterConfig {
+            scope: ActivityUnifiedMemoryCounterScope::ProcessSingleDevice,
+            device_id,
+            ..UnifiedMemoryCounterConfig::new(kind)
+        })
+    }
+
+    /// Disable a counter for all devices in the process.
+    pub fn disable(self, kind: ActivityUnifiedMemoryCounterKind) -> Self {
+        self.config(UnifiedMemoryCounterConfig {
+            enable: false,
+            ..UnifiedMemoryCounterConfig::new(kind)
+        })
+    }
+
+    /// The counter configurations that have been added.
+    pub fn configs(&self) -> &[UnifiedMemoryCounterConfig] {
+        &self.configs
+    }
+
+    /// Check that every configuration is valid and that no counter is
+    /// configured more than once.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidParameter`] if a configuration is invalid or a counter
+    ///   is configured more than once
+    pub fn validate(&self) -> Result<()> {
+        for (index, config) in self.configs.iter().enumerate() {
+            config.validate()?;
+
+            if self.configs[..index]
+                .iter()
+                .any(|other| other.same_counter(config))
+            {
+                return Err(Error::InvalidParameter);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Configure the counters.
+    ///
+    /// If CUPTI rejects the full set of counters as unsupported, each counter
+    /// is tried on its own to determine which ones are supported, and then the
+    /// supported counters are configured together. The status of each counter
+    /// is returned in the same order that they were added.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidParameter`] if [`validate`](Self::validate) fails
+    /// - [`Error::NotInitialized`]
+    /// - Any other error that is not specific to one of the counters
+    pub fn configure(&self) -> Result<Vec<(UnifiedMemoryCounterConfig, Result<()>)>> {
+        self.validate()?;
+
+        match configure_unified_memory_counters(&self.configs) {
+            Ok(()) => {
+                return Ok(self
+                    .configs
+                    .iter()
+                    .map(|&config| (config, Ok(())))
+                    .collect());
+            }
+            Err(e) if is_unsupported(e) => (),
+            Err(e) => return Err(e),
+        }
+
+        let mut status = Vec::with_capacity(self.configs.len());
+        for &config in &self.configs {
+            match configure_unified_memory_counters(&[config]) {
+                Err(e) if !is_unsupported(e) => return Err(e),
+                result => status.push((config, result)),
+            }
+        }
+
+        let supported: Vec<_> = status
+            .iter()
+            .filter(|(_, result)| result.is_ok())
+            .map(|&(config, _)| config)
+            .collect();
+
+        if !supported.is_empty() {
+            configure_unified_memory_counters(&supported)?;
+        }
+
+        Ok(status)
+    }
+}
+
+fn is_unsupported(error: Error) -> bool {
+    matches!(
+        error,
+        Error::NotSupported
+            | Error::UmProfilingNotSupported
+            | Error::UmProfilingNotSupportedOnDevice
+            | Error::UmProfilingNotSupportedOnNonP2PDevices
+            | Error::UmProfilingNotSupportedWithMps
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTOD: ActivityUnifiedMemoryCounterKind =
+        ActivityUnifiedMemoryCounterKind::BytesTransferHtoD;
+    const DTOH: ActivityUnifiedMemoryCounterKind =
+        ActivityUnifiedMemoryCounterKind::BytesTransferDtoH;
+
+    #[test]
+    fn unknown_kinds_are_rejected() {
+        let unknown = UnifiedMemoryCounterConfig::new(ActivityUnifiedMemoryCounterKind::Unknown);
+        assert!(matches!(unknown.validate(), Err(Error::InvalidParameter)));
+
+        let unlabelled = UnifiedMemoryCounterConfig::new(0x7fff_fff0.into());
+        assert!(matches!(
+            unlabelled.validate(),
+            Err(Error::InvalidParameter)
+        ));
+
+        let counters = UnifiedMemoryCounters::new()
+            .enable(HTOD)
+            .enable(ActivityUnifiedMemoryCounterKind::Unknown);
+        assert!(matches!(counters.validate(), Err(Error::InvalidParameter)));
+    }
+
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        let config = UnifiedMemoryCounterConfig {
+            scope: ActivityUnifiedMemoryCounterScope::Unknown,
+            ..UnifiedMemoryCounterConfig::new(HTOD)
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidParameter)));
+    }
+
+    #[test]
+    fn all_devices_duplicates_ignore_device_id() {
+        let counters =
+            UnifiedMemoryCounters::new()
+                .enable(HTOD)
+                .config(UnifiedMemoryCounterConfig {
+                    device_id: 1,
+                    ..UnifiedMemoryCounterConfig::new(HTOD)
+                });
+        assert!(matches!(counters.validate(), Err(Error::InvalidParameter)));
+
+        let counters = UnifiedMemoryCounters::new().enable(HTOD).disable(HTOD);
+        assert!(matches!(counters.validate(), Err(Error::InvalidParameter)));
+    }
+
+    #[test]
+    fn single_device_duplicates_compare_device_id() {
+        let counters = UnifiedMemoryCounters::new()
+            .enable_for_device(HTOD, 0)
+            .enable_for_device(HTOD, 1);
+        assert!(counters.validate().is_ok());
+
+        let counters = UnifiedMemoryCounters::new()
+            .enable_for_device(HTOD, 0)
+            .enable_for_device(HTOD, 0);
+        assert!(matches!(counters.validate(), Err(Error::InvalidParameter)));
+    }
+
+    #[test]
+    fn different_kinds_and_scopes_are_not_duplicates() {
+        let counters = UnifiedMemoryCounters::new()
+            .enable(HTOD)
+            .enable(DTOH)
+            .enable_for_device(HTOD, 0);
+        assert!(counters.validate().is_ok());
+        assert_eq!(counters.configs().len(), 3);
+    }
+
+    #[test]
+    fn unsupported_errors_are_classified() {
+        assert!(is_unsupported(Error::NotSupported));
+        assert!(is_unsupported(Error::UmProfilingNotSupportedOnDevice));
+        assert!(!is_unsupported(Error::InvalidParameter));
+        assert!(!is_unsupported(Error::NotInitialized));
+    }
+}
@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::activity::{ActivityApi, ActivityKind, ActivityRecord, Interner, RecordStr};
+
+/// A driver or runtime API call that launched GPU work.
+#[derive(Clone, Debug)]
+pub struct ApiCall {
+    /// Either [`ActivityKind::Driver`] or [`ActivityKind::Runtime`].
+    pub kind: ActivityKind,
+    /// The API record.
+    pub record: ActivityApi,
+}
+
+/// A unit of work executed on the GPU.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GpuActivity {
+    /// The kind of record this activity was built from.
+    pub kind: ActivityKind,
+    /// The start timestamp of the work, in ns.
+    pub start: u64,
+    /// The end timestamp of the work, in ns.
+    ///
+    /// For [`ActivityKind::Memory2`] records this is the same as `start`.
+    pub end: u64,
+    /// The timestamp when a kernel was queued, in ns, if latency timestamps
+    /// are enabled.
+    pub queued: Option<u64>,
+    /// The timestamp when a kernel was submitted, in ns, if latency
+    /// timestamps are enabled.
+    pub submitted: Option<u64>,
+    /// The ID of the device the work ran on.
+    pub device_id: u32,
+    /// The ID of the context the work ran in.
+    pub context_id: u32,
+    /// The ID of the stream the work ran on.
+    pub stream_id: u32,
+    /// The correlation ID of the API call that launched the work.
+    pub correlation_id: u32,
+    /// The correlation ID of the runtime API call that launched a memory
+    /// copy, if it differs from `correlation_id`.
+    pub runtime_correlation_id: Option<u32>,
+    /// The name of the kernel or memory allocation, if any.
+    pub name: Option<RecordStr<'static>>,
+    /// The number of bytes copied, set or allocated, if applicable.
+    pub bytes: Option<u64>,
+}
+
+impl GpuActivity {
+    /// Build a GPU activity from a record, interning its name.
+    ///
+    /// Returns `None` if the record does not describe GPU work with a
+    /// correlation ID. The supported kinds are
+    /// [`Kernel`](ActivityKind::Kernel),
+    /// [`ConcurrentKernel`](ActivityKind::ConcurrentKernel),
+    /// [`Memcpy`](ActivityKind::Memcpy), [`Memset`](ActivityKind::Memset) and
+    /// [`Memory2`](ActivityKind::Memory2).
+    pub fn from_record(record: &ActivityRecord<'_>, interner: &mut Interner) -> Option<Self> {
+        let activity = match record {
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => Self {
+                kind: record.kind(),
+                start: r.start,
+                end: r.end,
+                queued: r.queued,
+                submitted: r.submitted,
+                device_id: r.device_id,
+                context_id: r.context_id,
+                stream_id: r.stream_id,
+                correlation_id: r.correlation_id,
+                runtime_correlation_id: None,
+                name: r.name.clone().map(|s| s.into_owned(interner)),
+                bytes: None,
+            },
+            ActivityRecord::Memcpy(r) => Self {
+                kind: record.kind(),
+                start: r.start,
+                end: r.end,
+                queued: None,
+                submitted: None,
+                device_id: r.device_id,
+                context_id: r.context_id,
+                stream_id: r.stream_id,
+                correlation_id: r.correlation_id,
+                runtime_correlation_id: (r.runtime_correlation_id != 0
+                    && r.runtime_correlation_id != r.correlation_id)
+                    .then_some(r.runtime_correlation_id),
+                name: None,
+                bytes: Some(r.bytes),
+            },
+            ActivityRecord::Memset(r) => Self {
+                kind: record.kind(),
+                start: r.start,
+                end: r.end,
+                queued: None,
+                submitted: None,
+                device_id: r.device_id,
+                context_id: r.context_id,
+                stream_id: r.stream_id,
+                correlation_id: r.correlation_id,
+                runtime_correlation_id: None,
+                name: None,
+                bytes: Some(r.bytes),
+            },
+            ActivityRecord::Memory2(r) => Self {
+                kind: record.kind(),
+                start: r.timestamp,
+                end: r.timestamp,
+                queued: None,
+                submitted: None,
+                device_id: r.device_id,
+                context_id: r.context_id,
+                stream_id: r.stream_id,
+                correlation_id: r.correlation_id,
+                runtime_correlation_id: None,
+                name: r.name.clone().map(|s| s.into_owned(interner)),
+                bytes: Some(r.bytes),
+            },
+            _ => return None,
+        };
+
+        Some(activity)
+    }
+
+    /// The time the work spent executing on the GPU, in ns.
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// An API call joined with the GPU work it launched.
+///
+/// Either half may be missing if the corresponding record was never seen
+/// before the launch was evicted from the [`Correlator`].
+#[derive(Clone, Debug)]
+pub struct Launch {
+    /// The correlation ID shared by the API call and the GPU work.
+    pub correlation_id: u32,
+    /// The API call, if its record was seen.
+    ///
+    /// If both a runtime and a driver record were seen, this is the runtime
+    /// call made by the application and the driver call it made is kept in
+    /// [`driver`](Self::driver).
+    pub api: Option<ApiCall>,
+    /// The driver API call made by the runtime call in `api`, if both were
+    /// seen.
+    pub driver: Option<ActivityApi>,
+    /// The GPU work launched by the API call, ordered by start time.
+    pub gpu: Vec<GpuActivity>,
+}
+
+impl Launch {
+    /// The timestamp at which the host entered the API call, in ns.
+    pub fn host_enter(&self) -> Option<u64> {
+        self.api.as_ref().map(|api| api.record.start)
+    }
+
+    /// The timestamp at which the host exited the API call, in ns.
+    pub fn host_exit(&self) -> Option<u64> {
+        self.api.as_ref().map(|api| api.record.end)
+    }
+
+    /// The earliest start and latest end of the GPU work, in ns.
+    pub fn gpu_span(&self) -> Option<(u64, u64)> {
+        let start = self.gpu.iter().map(|a| a.start).min()?;
+        let end = self.gpu.iter().map(|a| a.end).max()?;
+
+        Some((start, end))
+    }
+
+    /// The time between the host entering the API call and the GPU work
+    /// starting, in ns.
+    ///
+    /// This is `None` unless both the API call and the GPU work were seen.
+    pub fn queue_delay(&self) -> Option<u64> {
+        let (start, _) = self.gpu_span()?;
+
+        Some(start.saturating_sub(self.host_enter()?))
+    }
+
+    /// The earliest timestamp associated with this launch, in ns.
+    pub fn start(&self) -> u64 {
+        let gpu = self.gpu_span().map(|(start, _)| start);
+
+        match (self.host_enter(), gpu) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b).unwrap_or(0),
+        }
+    }
+
+    fn add_api(&mut self, api: ApiCall) {
+        match (&mut self.api, api.kind) {
+            (Some(existing), ActivityKind::Driver) if existing.kind == ActivityKind::Runtime => {
+                self.driver = Some(api.record);
+            }
+            (existing, ActivityKind::Runtime) => {
+                if let Some(previous) = existing.replace(api)
+                    && previous.kind == ActivityKind::Driver
+                {
+                    self.driver = Some(previous.record);
+                }
+            }
+            (existing, _) => *existing = Some(api),
+        }
+    }
+
+    fn merge(&mut self, other: Launch) {
+        if let Some(driver) = other.driver {
+            self.add_api(ApiCall {
+                kind: ActivityKind::Driver,
+                record: driver,
+            });
+        }
+        if let Some(api) = other.api {
+            self.add_api(api);
+        }
+        for gpu in other.gpu {
+            self.add_gpu(gpu);
+        }
+    }
+
+    fn add_gpu(&mut self, gpu: GpuActivity) {
+        let index = self.gpu.partition_point(|a| a.start <= gpu.start);
+        self.gpu.insert(index, gpu);
+    }
+
+    fn last_seen(&self) -> u64 {
+        let gpu = self.gpu_span().map(|(_, end)| end).unwrap_or(0);
+
+        self.host_exit().unwrap_or(0).max(gpu)
+    }
+}
+
+/// Joins API records with the GPU work they launched by correlation ID.
+///
+/// Records can be pushed in any order. CUPTI delivers API records and GPU
+/// records in different buffers, so either may arrive first. A launch is held
+/// until no record for it has been seen for `window` of record time, at which
+/// point it is returned by [`drain_ready`]. Launches that are still pending
+/// can be retrieved with [`finish`].
+///
+/// A memory copy made through the runtime API may carry a separate runtime
+/// correlation ID, in which case the runtime API record with that ID is joined
+/// to the launch of the copy.
+///
+/// [`drain_ready`]: Self::drain_ready
+/// [`finish`]: Self::finish
+#[derive(Debug)]
+pub struct Correlator {
+    window: u64,
+    latest: u64,
+    pending: HashMap<u32, Launch>,
+    /// Runtime correlation IDs of memory copies, mapped to the correlation ID
+    /// of their launch.
+    runtime: HashMap<u32, u32>,
+    interner: Interner,
+}
+
+impl Correlator {
+    /// Create a correlator that evicts launches after `window`.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: window.as_nanos().try_into().unwrap_or(u64::MAX),
+            latest: 0,
+            pending: HashMap::new(),
+            runtime: HashMap::new(),
+            interner: Interner::new(),
+        }
+    }
+
+    /// Add a record to the correlator.
+    ///
+    /// Returns `false` if the record is not one that the correlator joins, in
+    /// which case it is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        let (correlation_id, api, gpu) = match record {
+            ActivityRecord::Driver(r) | ActivityRecord::Runtime(r) => {
+                let api = ApiCall {
+                    kind: record.kind(),
+                    record: r.clone(),
+                };
+
+                (r.correlation_id, Some(api), None)
+            }
+            _ => match GpuActivity::from_record(record, &mut self.interner) {
+                Some(gpu) => (gpu.correlation_id, None, Some(gpu)),
+                None => return false,
+            },
+        };
+
+        // Correlation ID 0 is used for records that have no associated API.
+        if correlation_id == 0 {
+            return false;
+        }
+
+        // A runtime API record for a memory copy that has already been seen.
+        let correlation_id = match &api {
+            Some(api) if api.kind == ActivityKind::Runtime => self
+                .runtime
+                .get(&correlation_id)
+                .copied()
+                .unwrap_or(correlation_id),
+            _ => correlation_id,
+        };
+
+        // A memory copy whose runtime API record may have already been seen.
+        let runtime = gpu
+            .as_ref()
+            .and_then(|gpu| gpu.runtime_correlation_id)
+            .and_then(|id| {
+                self.runtime.insert(id, correlation_id);
+                self.pending.remove(&id)
+            });
+
+        let launch = self
+            .pending
+            .entry(correlation_id)
+            .or_insert_with(|| Launch {
+                correlation_id,
+                api: None,
+                driver: None,
+                gpu: Vec::new(),
+            });
+
+        if let Some(runtime) = runtime {
+            launch.merge(runtime);
+        }
+        if let Some(api) = api {
+            launch.add_api(api);
+        }
+        if let Some(gpu) = gpu {
+            launch.add_gpu(gpu);
+        }
+
+        self.latest = self.latest.max(launch.last_seen());
+        true
+    }
+
+    /// Add all of the records in `records` to the correlator.
+    pub fn extend<'a, I>(&mut self, records: I)
+    where
+        I: IntoIterator<Item = &'a ActivityRecord<'a>>,
+    {
+        for record in records {
+            self.push(record);
+        }
+    }
+
+    /// Remove and return the launches that have not been updated within the
+    /// window, ordered by their start time.
+    pub fn drain_ready(&mut self) -> Vec<Launch> {
+        let cutoff = self.cutoff();
+        let ready: Vec<u32> = self
+            .pending
+            .iter()
+            .filter(|(_, launch)| launch.last_seen() < cutoff)
+            .map(|(&id, _)| id)
+            .collect();
+
+        let mut launches: Vec<_> = ready
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .collect();
+        launches.sort_by_key(|launch| (launch.start(), launch.correlation_id));
+
+        if !launches.is_empty() {
+            let pending = &self.pending;
+            self.runtime.retain(|_, id| pending.contains_key(id));
+
+            // Names are only deduplicated between launches pushed since the
+            // last drain, which keeps the interner from growing without bound.
+            // Evicted launches keep their copies of the names.
+            self.interner.clear();
+        }
+
+        launches
+    }
+
+    /// Remove and return all pending launches, ordered by their start time.
+    pub fn finish(&mut self) -> Vec<Launch> {
+        let mut launches: Vec<_> = self.pending.drain().map(|(_, launch)| launch).collect();
+        launches.sort_by_key(|launch| (launch.start(), launch.correlation_id));
+        self.runtime.clear();
+        self.interner.clear();
+        launches
+    }
+
+    /// The latest record timestamp seen so far, in ns.
+    pub(crate) fn latest(&self) -> u64 {
+        self.latest
+    }
+
+    /// The record time before which launches are evicted, in ns.
+    pub(crate) fn cutoff(&self) -> u64 {
+        self.latest.saturating_sub(self.window)
+    }
+
+    /// Whether a launch with `correlation_id` is pending, either as its
+    /// correlation ID or as the runtime correlation ID of a memory copy.
+    pub(crate) fn is_pending(&self, correlation_id: u32) -> bool {
+        self.pending.contains_key(&correlation_id) || self.runtime.contains_key(&correlation_id)
+    }
+
+    /// The number of launches that are still pending.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether there are no pending launches.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use cupti_sys::CUpti_ActivityMemcpy6;
+
+    use super::*;
+    use crate::testing::{self, api, kernel, memcpy};
+
+    const WINDOW: Duration = Duration::from_micros(1);
+
+    #[test]
+    fn joins_records_in_any_order() {
+        let mut correlator = Correlator::new(WINDOW);
+
+        assert!(correlator.push(&kernel(1, 200, 300, "k")));
+        assert!(correlator.push(&api(ActivityKind::Runtime, 1, 100, 150)));
+        assert!(correlator.push(&api(ActivityKind::Runtime, 2, 160, 170)));
+        assert!(correlator.push(&kernel(2, 400, 500, "k")));
+        assert!(!correlator.push(&api(ActivityKind::Runtime, 0, 100, 150)));
+        assert_eq!(correlator.len(), 2);
+
+        let launches = correlator.finish();
+        assert_eq!(launches.len(), 2);
+        assert_eq!(launches[0].correlation_id, 1);
+        assert_eq!(launches[0].queue_delay(), Some(100));
+        assert_eq!(launches[0].gpu_span(), Some((200, 300)));
+        assert_eq!(launches[1].correlation_id, 2);
+        assert_eq!(launches[1].gpu[0].name.as_deref(), Some(c"k"));
+    }
+
+    #[test]
+    fn drains_after_window() {
+        let mut correlator = Correlator::new(WINDOW);
+
+        correlator.push(&api(ActivityKind::Runtime, 1, 100, 150));
+        correlator.push(&kernel(1, 200, 300, "k"));
+        assert!(correlator.drain_ready().is_empty());
+
+        correlator.push(&kernel(2, 2_000, 2_100, "k"));
+        let ready = correlator.drain_ready();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].correlation_id, 1);
+        assert_eq!(correlator.len(), 1);
+    }
+
+    #[test]
+    fn interner_is_cleared_when_idle() {
+        // Names borrowed from a buffer are interned by the correlator.
+        let borrowed = |correlation_id, start, name: &'static CStr| {
+            let mut record = kernel(correlation_id, start, start + 100, "");
+            if let ActivityRecord::ConcurrentKernel(kernel) = &mut record {
+                kernel.name = Some(RecordStr::Borrowed(name));
+            }
+            record
+        };
+
+        let mut correlator = Correlator::new(WINDOW);
+
+        correlator.push(&borrowed(1, 200, c"a"));
+        correlator.push(&borrowed(2, 300, c"a"));
+        assert_eq!(correlator.interner.len(), 1);
+        assert!(correlator.drain_ready().is_empty());
+
+        correlator.push(&borrowed(3, 5_000, c"b"));
+        assert_eq!(correlator.interner.len(), 2);
+        assert_eq!(correlator.drain_ready().len(), 2);
+        assert!(correlator.interner.is_empty());
+
+        correlator.push(&borrowed(4, 5_100, c"b"));
+        assert_eq!(correlator.interner.len(), 1);
+        correlator.finish();
+        assert!(correlator.interner.is_empty());
+    }
+
+    #[test]
+    fn keeps_driver_and_runtime_calls() {
+        for driver_first in [false, true] {
+            let mut correlator = Correlator::new(WINDOW);
+
+            let runtime = api(ActivityKind::Runtime, 1, 100, 200);
+            let driver = api(ActivityKind::Driver, 1, 110, 190);
+            if driver_first {
+                correlator.push(&driver);
+                correlator.push(&runtime);
+            } else {
+                correlator.push(&runtime);
+                correlator.push(&driver);
+            }
+
+            let launch = correlator.finish().remove(0);
+            let api = launch.api.unwrap();
+            assert_eq!(api.kind, ActivityKind::Runtime);
+            assert_eq!((api.record.start, api.record.end), (100, 200));
+            let driver = launch.driver.unwrap();
+            assert_eq!((driver.start, driver.end), (110, 190));
+        }
+    }
+
+    #[test]
+    fn driver_only_launch() {
+        let mut correlator = Correlator::new(WINDOW);
+        correlator.push(&api(ActivityKind::Driver, 1, 110, 190));
+
+        let launch = correlator.finish().remove(0);
+        assert_eq!(launch.api.unwrap().kind, ActivityKind::Driver);
+        assert!(launch.driver.is_none());
+    }
+
+    #[test]
+    fn joins_memcpy_by_runtime_correlation_id() {
+        let copy = |runtime_correlation_id| {
+            let mut raw: CUpti_ActivityMemcpy6 = testing::raw(ActivityKind::Memcpy);
+            raw.correlationId = 10;
+            raw.runtimeCorrelationId = runtime_correlation_id;
+            raw.start = 300;
+            raw.end = 400;
+            raw.bytes = 64;
+            testing::record(&raw)
+        };
+
+        for runtime_first in [false, true] {
+            let mut correlator = Correlator::new(WINDOW);
+
+            let runtime = api(ActivityKind::Runtime, 9, 100, 200);
+            let driver = api(ActivityKind::Driver, 10, 110, 190);
+            if runtime_first {
+                correlator.push(&runtime);
+                correlator.push(&driver);
+                correlator.push(&copy(9));
+            } else {
+                correlator.push(&copy(9));
+                correlator.push(&driver);
+                correlator.push(&runtime);
+            }
+
+            let launches = correlator.finish();
+            assert_eq!(launches.len(), 1, "{launches:?}");
+            let launch = &launches[0];
+            assert_eq!(launch.correlation_id, 10);
+            assert_eq!(launch.api.as_ref().unwrap().kind, ActivityKind::Runtime);
+            assert_eq!(launch.driver.as_ref().unwrap().correlation_id, 10);
+            assert_eq!(launch.gpu[0].runtime_correlation_id, Some(9));
+        }
+
+        let mut correlator = Correlator::new(WINDOW);
+        correlator.push(&memcpy(10, 300, 400, 64));
+        assert_eq!(correlator.finish()[0].gpu[0].runtime_correlation_id, None);
+    }
+}
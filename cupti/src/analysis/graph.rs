@@ -0,0 +1,672 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Write as _};
+
+use super::QuantileSketch;
+use crate::activity::{ActivityRecord, DeviceGraphLaunchMode};
+use crate::callbacks::{CallbackIdResource, GraphData, ResourceData};
+use crate::*;
+
+/// A node in a CUDA graph tracked by a [`GraphTracker`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct GraphNodeInfo {
+    /// The unique ID of the node.
+    pub id: u64,
+    /// The type of the node.
+    pub node_type: GraphNodeType,
+}
+
+/// The DAG of a CUDA graph tracked by a [`GraphTracker`].
+#[derive(Clone, Debug, Default)]
+pub struct GraphDag {
+    /// The unique ID of the graph.
+    pub id: u32,
+    /// The graph this graph was cloned from, if it is a clone.
+    pub cloned_from: Option<u32>,
+    /// The nodes of the graph, by ID.
+    pub nodes: BTreeMap<u64, GraphNodeInfo>,
+    /// The dependencies between nodes, as `(node, dependent)` pairs.
+    pub edges: BTreeSet<(u64, u64)>,
+}
+
+/// A single execution of a CUDA graph.
+#[derive(Copy, Clone, Debug)]
+pub struct GraphLaunch {
+    /// The unique ID of the graph that was launched.
+    ///
+    /// This is the ID of the graph that the executable graph was instantiated
+    /// from, not the ID of the executable graph itself.
+    pub graph_id: u32,
+    /// The graph that `graph_id` was ultimately cloned from.
+    pub original_graph_id: u32,
+    /// The correlation ID of the launch API call.
+    ///
+    /// This is `None` for device-launched graphs.
+    pub correlation_id: Option<u32>,
+    /// For device-launched graphs, the graph that launched this one.
+    pub launcher_graph_id: Option<u32>,
+    /// For device-launched graphs, how the graph was launched.
+    pub device_launch_mode: Option<DeviceGraphLaunchMode>,
+    /// The ID of the device the graph ran on.
+    pub device_id: u32,
+    /// The ID of the stream the graph ran on.
+    pub stream_id: u64,
+    /// The start timestamp of the graph execution, in ns.
+    pub start: u64,
+    /// The end timestamp of the graph execution, in ns.
+    pub end: u64,
+}
+
+impl GraphLaunch {
+    /// The duration of the graph execution, in ns.
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// The execution times of a single graph node.
+#[derive(Clone, Debug)]
+pub struct GraphNodeTiming {
+    /// The ID of the node in the graph that it was originally created in.
+    pub node_id: u64,
+    /// The ID of the graph that the node was originally created in, if known.
+    pub graph_id: Option<u32>,
+    /// The name of the kernel executed by the node, if it is a kernel node.
+    pub name: Option<String>,
+    /// The distribution of execution times, in ns.
+    pub duration: QuantileSketch,
+}
+
+/// The IDs reported by a graph resource callback.
+#[derive(Copy, Clone, Debug)]
+struct GraphEvent {
+    graph: Option<u32>,
+    original_graph: Option<u32>,
+    node: Option<u64>,
+    original_node: Option<u64>,
+    node_type: GraphNodeType,
+    dependency: Option<u64>,
+    exec: Option<u32>,
+}
+
+/// Tracks the structure of CUDA graphs from resource callbacks and attributes
+/// graph activity records to them.
+///
+/// Pass every resource callback to [`handle`](Self::handle) and every
+/// activity record to [`push`](Self::push). Since the tracker is updated from
+/// within callbacks, it will usually need to be wrapped in a mutex.
+///
+/// When a graph is cloned, or instantiated into an executable graph, CUDA
+/// creates new nodes with new IDs. The tracker remembers where each node and
+/// graph came from, so that timings for work in an instantiated graph are
+/// attributed to the nodes of the graph that it was instantiated from.
+#[derive(Debug, Default)]
+pub struct GraphTracker {
+    graphs: HashMap<u32, GraphDag>,
+    /// The node each cloned node was cloned from.
+    node_origins: HashMap<u64, u64>,
+    /// The graph each node was created in.
+    node_graphs: HashMap<u64, u32>,
+    /// The nodes cloned while instantiating each executable graph.
+    exec_nodes: HashMap<u32, Vec<u64>>,
+    launches: Vec<GraphLaunch>,
+    timings: HashMap<u64, GraphNodeTiming>,
+}
+
+impl GraphTracker {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the tracker from a resource callback.
+    ///
+    /// Callbacks that are not related to graphs are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotInitialized`] if the ID of a graph or node could not be
+    ///   retrieved
+    pub fn handle(&mut self, cbid: CallbackIdResource, data: &ResourceData<'_>) -> Result<()> {
+        match data.graph_data(cbid) {
+            Some(graph) => self.handle_graph(cbid, &graph),
+            None => Ok(()),
+        }
+    }
+
+    /// Update the tracker from the graph data of a resource callback.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotInitialized`] if the ID of a graph or node could not be
+    ///   retrieved
+    pub fn handle_graph(&mut self, cbid: CallbackIdResource, data: &GraphData<'_>) -> Result<()> {
+        use CallbackIdResource as Id;
+
+        // The other handles are only set for some callbacks.
+        let mut event = GraphEvent {
+            graph: data.graph().map(|g| g.id()).transpose()?,
+            original_graph: None,
+            node: data.node().map(|n| n.id()).transpose()?,
+            original_node: None,
+            node_type: data.node_type(),
+            dependency: None,
+            exec: None,
+        };
+        match cbid {
+            Id::GraphCloned => {
+                event.original_graph = data.original_graph().map(|g| g.id()).transpose()?;
+            }
+            Id::GraphNodeCloned => {
+                event.original_node = data.original_node().map(|n| n.id()).transpose()?;
+                event.exec = data.graph_exec().map(|e| e.id()).transpose()?;
+            }
+            Id::GraphNodeDependencyCreated | Id::GraphNodeDependencyDestroyStarting => {
+                event.dependency = data.dependency().map(|n| n.id()).transpose()?;
+            }
+            Id::GraphExecDestroyStarting => {
+                event.exec = data.graph_exec().map(|e| e.id()).transpose()?;
+            }
+            _ => (),
+        }
+
+        self.update(cbid, &event);
+        Ok(())
+    }
+
+    fn update(&mut self, cbid: CallbackIdResource, event: &GraphEvent) {
+        let GraphEvent { graph, node, .. } = *event;
+
+        match cbid {
+            CallbackIdResource::GraphCreated => {
+                if let Some(id) = graph {
+                    self.graph_mut(id);
+                }
+            }
+            CallbackIdResource::GraphCloned => {
+                if let Some(id) = graph {
+                    self.graph_mut(id).cloned_from = event.original_graph;
+                }
+            }
+            CallbackIdResource::GraphDestroyStarting => {
+                if let Some(id) = graph
+                    && let Some(dag) = self.graphs.remove(&id)
+                {
+                    for &node in dag.nodes.keys() {
+                        self.remove_node(node);
+                    }
+                }
+            }
+            CallbackIdResource::GraphNodeCreated => {
+                if let (Some(graph), Some(node)) = (graph, node) {
+                    self.add_node(graph, node, event.node_type);
+                }
+            }
+            CallbackIdResource::GraphNodeCloned => {
+                if let Some(node) = node {
+                    if let Some(original) = event.original_node {
+                        self.node_origins.insert(node, original);
+                    }
+
+                    // Nodes cloned while instantiating an executable graph do
+                    // not belong to a graph of their own, and are forgotten
+                    // when the executable graph is destroyed.
+                    match event.exec {
+                        Some(exec) => self.exec_nodes.entry(exec).or_default().push(node),
+                        None => {
+                            if let Some(graph) = graph {
+                                self.add_node(graph, node, event.node_type);
+                            }
+                        }
+                    }
+                }
+            }
+            CallbackIdResource::GraphNodeDestroyStarting => {
+                if let (Some(graph), Some(node)) = (graph, node)
+                    && let Some(dag) = self.graphs.get_mut(&graph)
+                {
+                    dag.nodes.remove(&node);
+                    dag.edges.retain(|&(from, to)| from != node && to != node);
+                }
+                if let Some(node) = node {
+                    self.remove_node(node);
+                }
+            }
+            CallbackIdResource::GraphNodeDependencyCreated
+            | CallbackIdResource::GraphNodeDependencyDestroyStarting => {
+                if let (Some(graph), Some(node), Some(dependent)) = (graph, node, event.dependency)
+                {
+                    let edges = &mut self.graph_mut(graph).edges;
+
+                    if cbid == CallbackIdResource::GraphNodeDependencyCreated {
+                        edges.insert((node, dependent));
+                    } else {
+                        edges.remove(&(node, dependent));
+                    }
+                }
+            }
+            CallbackIdResource::GraphExecDestroyStarting => {
+                if let Some(exec) = event.exec {
+                    for node in self.exec_nodes.remove(&exec).unwrap_or_default() {
+                        self.remove_node(node);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn graph_mut(&mut self, id: u32) -> &mut GraphDag {
+        self.graphs.entry(id).or_insert_with(|| GraphDag {
+            id,
+            ..Default::default()
+        })
+    }
+
+    fn add_node(&mut self, graph: u32, node: u64, node_type: GraphNodeType) {
+        self.node_graphs.insert(node, graph);
+        self.graph_mut(graph).nodes.insert(
+            node,
+            GraphNodeInfo {
+                id: node,
+                node_type,
+            },
+        );
+    }
+
+    /// Forget where a destroyed node came from.
+    ///
+    /// Timings already recorded for the node are kept.
+    fn remove_node(&mut self, node: u64) {
+        self.node_graphs.remove(&node);
+        self.node_origins.remove(&node);
+    }
+
+    /// Add an activity record to the tracker.
+    ///
+    /// [`GraphTrace`](ActivityRecord::GraphTrace) and
+    /// [`DeviceGraphTrace`](ActivityRecord::DeviceGraphTrace) records are
+    /// recorded as launches. Kernel, memcpy and memset records for work that
+    /// was executed as part of a graph are recorded as node timings. Returns
+    /// `false` if the record is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        let (node, start, end, name) = match record {
+            ActivityRecord::GraphTrace(r) => {
+                self.launches.push(GraphLaunch {
+                    graph_id: r.graph_id,
+                    original_graph_id: self.original_graph(r.graph_id),
+                    correlation_id: Some(r.correlation_id),
+                    launcher_graph_id: None,
+                    device_launch_mode: None,
+                    device_id: r.device_id,
+                    stream_id: r.stream_id.into(),
+                    start: r.start,
+                    end: r.end,
+                });
+                return true;
+            }
+            ActivityRecord::DeviceGraphTrace(r) => {
+                self.launches.push(GraphLaunch {
+                    graph_id: r.graph_id,
+                    original_graph_id: self.original_graph(r.graph_id),
+                    correlation_id: None,
+                    launcher_graph_id: Some(r.launcher_graph_id),
+                    device_launch_mode: Some(r.device_launch_mode),
+                    device_id: r.device_id,
+                    stream_id: r.stream_id,
+                    start: r.start,
+                    end: r.end,
+                });
+                return true;
+            }
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => (
+                r.graph_node_id,
+                r.start,
+                r.end,
+                r.name.as_ref().map(|name| name.to_string_lossy()),
+            ),
+            ActivityRecord::Memcpy(r) => (r.graph_node_id, r.start, r.end, None),
+            ActivityRecord::Memcpy2(r) => (r.graph_node_id, r.start, r.end, None),
+            ActivityRecord::Memset(r) => (r.graph_node_id, r.start, r.end, None),
+            _ => return false,
+        };
+
+        // Work that was not launched as part of a graph has a node ID of 0.
+        let Some(node) = node.filter(|&node| node != 0) else {
+            return false;
+        };
+
+        let node = self.original_node(node);
+        let graph_id = self.node_graphs.get(&node).copied();
+        let timing = self.timings.entry(node).or_insert_with(|| GraphNodeTiming {
+            node_id: node,
+            graph_id,
+            name: None,
+            duration: QuantileSketch::new(),
+        });
+
+        if timing.name.is_none() {
+            timing.name = name.map(|name| name.into_owned());
+        }
+        timing.graph_id = timing.graph_id.or(graph_id);
+        timing.duration.insert(end.saturating_sub(start));
+
+        true
+    }
+
+    /// Follow clones of `graph` back to the graph that it was originally
+    /// created as.
+    ///
+    /// `graph` is the ID of a graph, not of an executable graph.
+    pub fn original_graph(&self, mut graph: u32) -> u32 {
+        // Guard against cycles in case IDs are reused.
+        for _ in 0..self.graphs.len() {
+            match self.graphs.get(&graph).and_then(|dag| dag.cloned_from) {
+                Some(source) => graph = source,
+                None => break,
+            }
+        }
+
+        graph
+    }
+
+    /// Follow clones of `node` back to the node that it was originally
+    /// created as.
+    pub fn original_node(&self, mut node: u64) -> u64 {
+        for _ in 0..=self.node_origins.len() {
+            match self.node_origins.get(&node) {
+                Some(&source) => node = source,
+                None => break,
+            }
+        }
+
+        node
+    }
+
+    /// The DAG of a graph, if it has been created and not yet destroyed.
+    pub fn graph(&self, id: u32) -> Option<&GraphDag> {
+        self.graphs.get(&id)
+    }
+
+    /// All graphs that have been created and not yet destroyed, in no
+    /// particular order.
+    pub fn graphs(&self) -> impl Iterator<Item = &GraphDag> {
+        self.graphs.values()
+    }
+
+    /// The graph launches recorded so far, in the order they were pushed.
+    pub fn launches(&self) -> &[GraphLaunch] {
+        &self.launches
+    }
+
+    /// Remove and return the graph launches recorded so far.
+    pub fn take_launches(&mut self) -> Vec<GraphLaunch> {
+        std::mem::take(&mut self.launches)
+    }
+
+    /// The timings of every node of `graph` that has executed, ordered by
+    /// node ID.
+    pub fn node_timings(&self, graph: u32) -> Vec<&GraphNodeTiming> {
+        let mut timings: Vec<_> = self
+            .timings
+            .values()
+            .filter(|timing| timing.graph_id == Some(graph))
+            .collect();
+        timings.sort_by_key(|timing| timing.node_id);
+        timings
+    }
+
+    /// The timing of a single node.
+    ///
+    /// `node` may be the ID of a clone of the node.
+    pub fn node_timing(&self, node: u64) -> Option<&GraphNodeTiming> {
+        self.timings.get(&self.original_node(node))
+    }
+
+    /// Write the DAG of `graph` in Graphviz DOT format.
+    ///
+    /// Each node is labeled with its ID and type and, if it has executed, its
+    /// kernel name and mean execution time. Returns `Ok(false)` if the graph
+    /// is not known.
+    pub fn write_dot<W: fmt::Write>(
+        &self,
+        graph: u32,
+        w: &mut W,
+    ) -> std::result::Result<bool, fmt::Error> {
+        let Some(dag) = self.graphs.get(&graph) else {
+            return Ok(false);
+        };
+
+        writeln!(w, "digraph \"graph {}\" {{", dag.id)?;
+        writeln!(w, "  node [shape=box];")?;
+
+        for node in dag.nodes.values() {
+            let mut label = format!("{}\\n{:?}", node.id, node.node_type);
+            if let Some(timing) = self.node_timing(node.id) {
+                if let Some(name) = &timing.name {
+                    write!(label, "\\n{}", escape(name))?;
+                }
+                if let Some(mean) = timing.duration.mean() {
+                    write!(label, "\\n{} x {:.0} ns", timing.duration.count(), mean)?;
+                }
+            }
+
+            writeln!(w, "  n{} [label=\"{}\"];", node.id, label)?;
+        }
+
+        for (from, to) in &dag.edges {
+            writeln!(w, "  n{from} -> n{to};")?;
+        }
+
+        writeln!(w, "}}")?;
+        Ok(true)
+    }
+
+    /// The DAG of `graph` in Graphviz DOT format.
+    ///
+    /// See [`write_dot`](Self::write_dot).
+    pub fn to_dot(&self, graph: u32) -> Option<String> {
+        let mut dot = String::new();
+
+        match self.write_dot(graph, &mut dot) {
+            Ok(true) => Some(dot),
+            _ => None,
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use cupti_sys::CUpti_ActivityGraphTrace2;
+
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::testing::{self, kernel};
+
+    fn event(graph: u32, node: Option<u64>) -> GraphEvent {
+        GraphEvent {
+            graph: Some(graph),
+            original_graph: None,
+            node,
+            original_node: None,
+            node_type: GraphNodeType::Kernel,
+            dependency: None,
+            exec: None,
+        }
+    }
+
+    fn add_node(tracker: &mut GraphTracker, graph: u32, node: u64) {
+        tracker.update(
+            CallbackIdResource::GraphNodeCreated,
+            &event(graph, Some(node)),
+        );
+    }
+
+    fn add_edge(tracker: &mut GraphTracker, graph: u32, node: u64, dependent: u64) {
+        let event = GraphEvent {
+            dependency: Some(dependent),
+            ..event(graph, Some(node))
+        };
+        tracker.update(CallbackIdResource::GraphNodeDependencyCreated, &event);
+    }
+
+    fn graph_kernel(node: u64, start: u64, end: u64, name: &str) -> ActivityRecord<'static> {
+        let mut record = kernel(1, start, end, name);
+        if let ActivityRecord::ConcurrentKernel(kernel) = &mut record {
+            kernel.graph_node_id = Some(node);
+        }
+        record
+    }
+
+    fn graph_trace(graph_id: u32) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityGraphTrace2 = testing::raw(ActivityKind::GraphTrace);
+        raw.graphId = graph_id;
+        raw.correlationId = 9;
+        raw.start = 100;
+        raw.end = 400;
+        testing::record(&raw)
+    }
+
+    /// Graph 1 with nodes 10 -> 11 -> 12.
+    fn chain() -> GraphTracker {
+        let mut tracker = GraphTracker::new();
+        tracker.update(CallbackIdResource::GraphCreated, &event(1, None));
+        for node in 10..=12 {
+            add_node(&mut tracker, 1, node);
+        }
+        add_edge(&mut tracker, 1, 10, 11);
+        add_edge(&mut tracker, 1, 11, 12);
+        tracker
+    }
+
+    #[test]
+    fn tracks_nodes_and_edges() {
+        let mut tracker = chain();
+
+        let dag = tracker.graph(1).unwrap();
+        assert_eq!(dag.nodes.keys().copied().collect::<Vec<_>>(), [10, 11, 12]);
+        assert_eq!(
+            dag.edges.iter().copied().collect::<Vec<_>>(),
+            [(10, 11), (11, 12)]
+        );
+
+        let removed = GraphEvent {
+            dependency: Some(12),
+            ..event(1, Some(11))
+        };
+        tracker.update(
+            CallbackIdResource::GraphNodeDependencyDestroyStarting,
+            &removed,
+        );
+        assert_eq!(
+            tracker.graph(1).unwrap().edges.iter().collect::<Vec<_>>(),
+            [&(10, 11)]
+        );
+    }
+
+    #[test]
+    fn node_destruction_removes_its_edges() {
+        let mut tracker = chain();
+
+        tracker.update(
+            CallbackIdResource::GraphNodeDestroyStarting,
+            &event(1, Some(11)),
+        );
+
+        let dag = tracker.graph(1).unwrap();
+        assert_eq!(dag.nodes.keys().copied().collect::<Vec<_>>(), [10, 12]);
+        assert!(dag.edges.is_empty());
+        assert!(!tracker.node_graphs.contains_key(&11));
+
+        tracker.update(CallbackIdResource::GraphDestroyStarting, &event(1, None));
+        assert!(tracker.graph(1).is_none());
+        assert!(tracker.node_graphs.is_empty());
+    }
+
+    #[test]
+    fn clones_resolve_to_their_original() {
+        let mut tracker = chain();
+
+        // Graph 2 is a clone of graph 1, and graph 3 a clone of graph 2.
+        for (clone, original) in [(2, 1), (3, 2)] {
+            let cloned = GraphEvent {
+                original_graph: Some(original),
+                ..event(clone, None)
+            };
+            tracker.update(CallbackIdResource::GraphCloned, &cloned);
+        }
+        assert_eq!(tracker.original_graph(3), 1);
+        assert_eq!(tracker.original_graph(1), 1);
+        assert_eq!(tracker.original_graph(42), 42);
+
+        assert!(tracker.push(&graph_trace(3)));
+        let launch = tracker.launches()[0];
+        assert_eq!((launch.graph_id, launch.original_graph_id), (3, 1));
+        assert_eq!(launch.correlation_id, Some(9));
+        assert_eq!(launch.duration(), 300);
+    }
+
+    #[test]
+    fn exec_timings_are_attributed_to_the_original_node() {
+        let mut tracker = chain();
+
+        // Instantiating graph 1 as executable graph 7 clones its nodes.
+        for (clone, original) in [(20, 10), (21, 11)] {
+            let cloned = GraphEvent {
+                original_node: Some(original),
+                exec: Some(7),
+                ..event(1, Some(clone))
+            };
+            tracker.update(CallbackIdResource::GraphNodeCloned, &cloned);
+        }
+        // Instantiated nodes do not belong to the graph.
+        assert_eq!(tracker.graph(1).unwrap().nodes.len(), 3);
+
+        assert!(tracker.push(&graph_kernel(20, 100, 200, "a")));
+        assert!(tracker.push(&graph_kernel(20, 300, 500, "a")));
+        assert!(tracker.push(&graph_kernel(21, 200, 250, "b")));
+        assert!(!tracker.push(&graph_kernel(0, 0, 10, "c")));
+
+        let timings: Vec<_> = tracker
+            .node_timings(1)
+            .iter()
+            .map(|t| (t.node_id, t.name.as_deref(), t.duration.count()))
+            .collect();
+        assert_eq!(timings, [(10, Some("a"), 2), (11, Some("b"), 1)]);
+        assert_eq!(tracker.node_timing(20).unwrap().node_id, 10);
+
+        let destroyed = GraphEvent {
+            exec: Some(7),
+            ..event(1, None)
+        };
+        tracker.update(CallbackIdResource::GraphExecDestroyStarting, &destroyed);
+        assert!(tracker.node_origins.is_empty());
+        assert!(tracker.exec_nodes.is_empty());
+        assert_eq!(tracker.node_timing(10).unwrap().duration.count(), 2);
+    }
+
+    #[test]
+    fn writes_dot() {
+        let mut tracker = chain();
+        tracker.push(&graph_kernel(10, 100, 200, r#"add<"x">"#));
+
+        let dot = tracker.to_dot(1).unwrap();
+        assert_eq!(
+            dot,
+            concat!(
+                "digraph \"graph 1\" {\n",
+                "  node [shape=box];\n",
+                "  n10 [label=\"10\\nGraphNodeType::Kernel\\nadd<\\\"x\\\">\\n1 x 100 ns\"];\n",
+                "  n11 [label=\"11\\nGraphNodeType::Kernel\"];\n",
+                "  n12 [label=\"12\\nGraphNodeType::Kernel\"];\n",
+                "  n10 -> n11;\n",
+                "  n11 -> n12;\n",
+                "}\n",
+            )
+        );
+        assert!(tracker.to_dot(2).is_none());
+    }
+}
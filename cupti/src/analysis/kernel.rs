@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::QuantileSketch;
+use crate::activity::{ActivityKernel, ActivityRecord};
+
+/// The launch configuration that kernel statistics are grouped by.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KernelKey {
+    /// The kernel name, or an empty string if the record had no name.
+    ///
+    /// This is the name returned by the demangler of the [`KernelStats`]. See
+    /// [`KernelStats::demangle_with`].
+    pub name: String,
+    /// The grid size, in blocks.
+    pub grid: [i32; 3],
+    /// The block size, in threads.
+    pub block: [i32; 3],
+    /// The dynamic shared memory reserved for the kernel, in bytes.
+    pub dynamic_shared_memory: i32,
+}
+
+/// Statistics for all launches of a kernel with a single [`KernelKey`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KernelSummary {
+    /// The launch configuration.
+    pub key: KernelKey,
+    /// The distribution of kernel durations, in ns.
+    pub duration: QuantileSketch,
+    /// The number of registers required for each thread.
+    ///
+    /// This is the maximum seen across all launches.
+    pub registers_per_thread: u16,
+    /// The static shared memory allocated for the kernel, in bytes.
+    ///
+    /// This is the maximum seen across all launches.
+    pub static_shared_memory: i32,
+    /// The local memory reserved for each thread, in bytes.
+    ///
+    /// This is the maximum seen across all launches.
+    pub local_memory_per_thread: u32,
+    /// The total local memory reserved for the kernel, in bytes.
+    ///
+    /// This is the maximum seen across all launches.
+    pub local_memory_total: u64,
+}
+
+impl KernelSummary {
+    fn new(key: KernelKey) -> Self {
+        Self {
+            key,
+            duration: QuantileSketch::new(),
+            registers_per_thread: 0,
+            static_shared_memory: 0,
+            local_memory_per_thread: 0,
+            local_memory_total: 0,
+        }
+    }
+
+    fn insert(&mut self, kernel: &ActivityKernel<'_>) {
+        self.duration
+            .insert(kernel.end.saturating_sub(kernel.start));
+        self.registers_per_thread = self.registers_per_thread.max(kernel.registers_per_thread);
+        self.static_shared_memory = self.static_shared_memory.max(kernel.static_shared_memory);
+        self.local_memory_per_thread = self
+            .local_memory_per_thread
+            .max(kernel.local_memory_per_thread);
+        self.local_memory_total = self.local_memory_total.max(kernel.local_memory_total);
+    }
+
+    /// Merge the statistics in `other` into this summary.
+    ///
+    /// The keys of the two summaries are not checked.
+    pub fn merge(&mut self, other: &Self) {
+        self.duration.merge(&other.duration);
+        self.registers_per_thread = self.registers_per_thread.max(other.registers_per_thread);
+        self.static_shared_memory = self.static_shared_memory.max(other.static_shared_memory);
+        self.local_memory_per_thread = self
+            .local_memory_per_thread
+            .max(other.local_memory_per_thread);
+        self.local_memory_total = self.local_memory_total.max(other.local_memory_total);
+    }
+
+    /// The number of launches.
+    pub fn count(&self) -> u64 {
+        self.duration.count()
+    }
+
+    /// The total time spent executing the kernel, in ns.
+    pub fn total(&self) -> u64 {
+        self.duration.sum()
+    }
+
+    /// The shortest duration, in ns.
+    pub fn min(&self) -> u64 {
+        self.duration.min().unwrap_or(0)
+    }
+
+    /// The longest duration, in ns.
+    pub fn max(&self) -> u64 {
+        self.duration.max().unwrap_or(0)
+    }
+
+    /// The mean duration, in ns.
+    pub fn mean(&self) -> f64 {
+        self.duration.mean().unwrap_or(0.0)
+    }
+
+    /// The estimated median duration, in ns.
+    pub fn p50(&self) -> u64 {
+        self.duration.quantile(0.5).unwrap_or(0)
+    }
+
+    /// The estimated 99th percentile duration, in ns.
+    pub fn p99(&self) -> u64 {
+        self.duration.quantile(0.99).unwrap_or(0)
+    }
+}
+
+/// Aggregates kernel records into per-launch-configuration statistics.
+///
+/// Durations are tracked with a [`QuantileSketch`], so memory use depends only
+/// on the number of distinct [`KernelKey`]s and not on the number of launches.
+///
+/// To combine statistics from several processes, serialize each process's
+/// [`summaries`](Self::summaries) and [`extend`](Extend::extend) a single
+/// `KernelStats` with them.
+#[derive(Clone)]
+pub struct KernelStats {
+    kernels: HashMap<KernelKey, KernelSummary>,
+    demangle: Option<DemangleFn>,
+}
+
+/// A function that converts kernel names before they are grouped by.
+type DemangleFn = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+impl KernelStats {
+    /// Create an empty set of statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `demangle` to convert kernel names before grouping by them.
+    ///
+    /// With the `demangle` feature the default is `demangle::demangle`, so
+    /// kernels are grouped by their demangled names. Otherwise kernels are
+    /// grouped by the name that CUPTI reports, which is usually a mangled
+    /// C++ name. Pass `str::to_owned` to group by the reported names even
+    /// when the feature is enabled.
+    ///
+    /// `demangle` is called for every kernel record, so it should cache its
+    /// results, as `demangle::demangle` and `demangle::Simplify::demangle` do.
+    pub fn demangle_with<F>(mut self, demangle: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.demangle = Some(Arc::new(demangle));
+        self
+    }
+
+    /// Add a record to the statistics.
+    ///
+    /// Returns `false` if the record is not a
+    /// [`Kernel`](ActivityRecord::Kernel) or
+    /// [`ConcurrentKernel`](ActivityRecord::ConcurrentKernel) record, in which
+    /// case it is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        match record {
+            ActivityRecord::Kernel(kernel) | ActivityRecord::ConcurrentKernel(kernel) => {
+                self.push_kernel(kernel);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Add a kernel record to the statistics.
+    pub fn push_kernel(&mut self, kernel: &ActivityKernel<'_>) {
+        let name = match &kernel.name {
+            Some(name) => {
+                let name = name.to_string_lossy();
+                match &self.demangle {
+                    Some(demangle) => demangle(&name),
+                    None => name.into_owned(),
+                }
+            }
+            None => String::new(),
+        };
+
+        let key = KernelKey {
+            name,
+            grid: [kernel.grid_x, kernel.grid_y, kernel.grid_z],
+            block: [kernel.block_x, kernel.block_y, kernel.block_z],
+            dynamic_shared_memory: kernel.dynamic_shared_memory,
+        };
+
+        self.kernels
+            .entry(key)
+            .or_insert_with_key(|key| KernelSummary::new(key.clone()))
+            .insert(kernel);
+    }
+
+    /// Merge the statistics in `other` into these statistics.
+    pub fn merge(&mut self, other: &Self) {
+        self.extend(other.kernels.values().cloned());
+    }
+
+    /// The statistics for a single launch configuration.
+    pub fn get(&self, key: &KernelKey) -> Option<&KernelSummary> {
+        self.kernels.get(key)
+    }
+
+    /// The statistics for every launch configuration, ordered by total time
+    /// spent executing, longest first.
+    pub fn summaries(&self) -> Vec<&KernelSummary> {
+        let mut summaries: Vec<_> = self.kernels.values().collect();
+        summaries.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.key.cmp(&b.key)));
+        summaries
+    }
+
+    /// The number of distinct launch configurations.
+    pub fn len(&self) -> usize {
+        self.kernels.len()
+    }
+
+    /// Whether no kernels have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.kernels.is_empty()
+    }
+}
+
+impl Default for KernelStats {
+    fn default() -> Self {
+        Self {
+            kernels: HashMap::new(),
+            demangle: default_demangle(),
+        }
+    }
+}
+
+impl fmt::Debug for KernelStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KernelStats")
+            .field("kernels", &self.kernels)
+            .field("demangle", &self.demangle.is_some())
+            .finish()
+    }
+}
+
+#[cfg(feature = "demangle")]
+fn default_demangle() -> Option<DemangleFn> {
+    Some(Arc::new(|name| crate::demangle::demangle(name).to_string()))
+}
+
+#[cfg(not(feature = "demangle"))]
+fn default_demangle() -> Option<DemangleFn> {
+    None
+}
+
+impl Extend<KernelSummary> for KernelStats {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = KernelSummary>,
+    {
+        for summary in iter {
+            match self.kernels.get_mut(&summary.key) {
+                Some(existing) => existing.merge(&summary),
+                None => {
+                    self.kernels.insert(summary.key.clone(), summary);
+                }
+            }
+        }
+    }
+}
+
+impl FromIterator<KernelSummary> for KernelStats {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = KernelSummary>,
+    {
+        let mut stats = Self::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::testing::{api, kernel};
+
+    #[test]
+    fn groups_by_launch_configuration() {
+        let mut stats = KernelStats::new().demangle_with(str::to_owned);
+
+        assert!(stats.push(&kernel(1, 0, 100, "_Z1av")));
+        assert!(stats.push(&kernel(2, 0, 300, "_Z1av")));
+        assert!(stats.push(&kernel(3, 0, 50, "_Z1bv")));
+        assert!(!stats.push(&api(ActivityKind::Runtime, 4, 0, 10)));
+
+        let summaries = stats.summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].key.name, "_Z1av");
+        assert_eq!(summaries[0].count(), 2);
+        assert_eq!(summaries[0].total(), 400);
+        assert_eq!((summaries[0].min(), summaries[0].max()), (100, 300));
+        assert_eq!(summaries[1].key.name, "_Z1bv");
+
+        let merged: KernelStats = summaries.into_iter().cloned().collect();
+        let mut twice = merged.clone();
+        twice.merge(&merged);
+        assert_eq!(twice.len(), 2);
+        assert_eq!(twice.summaries()[0].count(), 4);
+    }
+
+    #[cfg(feature = "demangle")]
+    #[test]
+    fn demangles_by_default() {
+        let mut stats = KernelStats::new();
+        stats.push(&kernel(1, 0, 100, "_Z6vecAddPKfS0_Pfi"));
+
+        assert_eq!(
+            stats.summaries()[0].key.name,
+            "vecAdd(float const*, float const*, float*, int)"
+        );
+    }
+
+    #[cfg(feature = "demangle")]
+    #[test]
+    fn demangles_with_simplified_names() {
+        use crate::demangle::Simplify;
+
+        const SIMPLIFY: Simplify = Simplify::new()
+            .strip_template_args(true)
+            .collapse_namespaces(true);
+
+        let mut stats =
+            KernelStats::new().demangle_with(|name| SIMPLIFY.demangle(name).to_string());
+        stats.push(&kernel(1, 0, 100, "_ZN3cub6detail6reduceIfLi256EEEvPT_"));
+
+        assert_eq!(stats.summaries()[0].key.name, "void reduce(float*)");
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+
+use super::{ApiCall, Launch, QuantileSketch};
+use crate::activity::{ActivityFlag, ActivityMemcpyKind, ActivityMemoryKind, ActivityRecord};
+
+/// The maximum number of pageable copies kept by [`MemcpyStats`] by default.
+const DEFAULT_MAX_FINDINGS: usize = 1024;
+
+/// The properties of a memory copy that bandwidth statistics are grouped by.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemcpyKey {
+    /// The direction of the copy.
+    pub copy_kind: ActivityMemcpyKind,
+    /// The kind of the source memory.
+    pub src_kind: ActivityMemoryKind,
+    /// The kind of the destination memory.
+    pub dst_kind: ActivityMemoryKind,
+    /// Whether the copy was asynchronous.
+    pub is_async: bool,
+}
+
+/// Statistics for all memory copies with a single [`MemcpyKey`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemcpySummary {
+    /// The properties of the copies.
+    pub key: MemcpyKey,
+    /// The distribution of achieved bandwidth, in bytes per second.
+    ///
+    /// Copies with no bytes or with a zero duration are not included.
+    pub bandwidth: QuantileSketch,
+    /// The number of copies.
+    pub count: u64,
+    /// The total number of bytes copied.
+    pub bytes: u64,
+    /// The total time spent copying, in ns.
+    pub duration: u64,
+}
+
+impl MemcpySummary {
+    fn new(key: MemcpyKey) -> Self {
+        Self {
+            key,
+            bandwidth: QuantileSketch::new(),
+            count: 0,
+            bytes: 0,
+            duration: 0,
+        }
+    }
+
+    fn insert(&mut self, bytes: u64, duration: u64) {
+        self.count += 1;
+        self.bytes = self.bytes.saturating_add(bytes);
+        self.duration = self.duration.saturating_add(duration);
+
+        if bytes != 0 && duration != 0 {
+            let bandwidth = u128::from(bytes) * 1_000_000_000 / u128::from(duration);
+            self.bandwidth
+                .insert(bandwidth.try_into().unwrap_or(u64::MAX));
+        }
+    }
+
+    /// Merge the statistics in `other` into this summary.
+    ///
+    /// The keys of the two summaries are not checked.
+    pub fn merge(&mut self, other: &Self) {
+        self.bandwidth.merge(&other.bandwidth);
+        self.count += other.count;
+        self.bytes = self.bytes.saturating_add(other.bytes);
+        self.duration = self.duration.saturating_add(other.duration);
+    }
+
+    /// The total bytes copied divided by the total time spent copying, in
+    /// bytes per second.
+    pub fn mean_bandwidth(&self) -> f64 {
+        if self.duration == 0 {
+            return 0.0;
+        }
+
+        self.bytes as f64 * 1e9 / self.duration as f64
+    }
+}
+
+/// A memory copy to or from pageable host memory.
+///
+/// Copies involving pageable memory are staged through a pinned buffer by the
+/// driver, which limits their bandwidth and prevents them from overlapping
+/// with other work even when they are issued asynchronously.
+#[derive(Clone, Debug)]
+pub struct PageableCopy {
+    /// The properties of the copy.
+    pub key: MemcpyKey,
+    /// The number of bytes copied.
+    pub bytes: u64,
+    /// The start timestamp of the copy, in ns.
+    pub start: u64,
+    /// The end timestamp of the copy, in ns.
+    pub end: u64,
+    /// The ID of the device the copy ran on.
+    pub device_id: u32,
+    /// The ID of the context the copy ran in.
+    pub context_id: u32,
+    /// The ID of the stream the copy ran on.
+    pub stream_id: u32,
+    /// The correlation ID of the API call that issued the copy.
+    pub correlation_id: u32,
+    /// The API call that issued the copy.
+    ///
+    /// This is `None` until [`MemcpyStats::attach_call_sites`] is called with
+    /// the launch for this copy.
+    pub api: Option<ApiCall>,
+}
+
+/// Aggregates memory copy records into bandwidth statistics and collects
+/// copies that involve pageable memory.
+///
+/// Both [`Memcpy`](ActivityRecord::Memcpy) and peer-to-peer
+/// [`Memcpy2`](ActivityRecord::Memcpy2) records are supported. Statistics can
+/// be combined across processes in the same way as
+/// [`KernelStats`](super::KernelStats).
+#[derive(Clone, Debug)]
+pub struct MemcpyStats {
+    copies: HashMap<MemcpyKey, MemcpySummary>,
+    pageable: Vec<PageableCopy>,
+    pageable_count: u64,
+    max_findings: usize,
+}
+
+impl MemcpyStats {
+    /// Create an empty set of statistics.
+    pub fn new() -> Self {
+        Self {
+            copies: HashMap::new(),
+            pageable: Vec::new(),
+            pageable_count: 0,
+            max_findings: DEFAULT_MAX_FINDINGS,
+        }
+    }
+
+    /// Keep at most `max` pageable copies.
+    ///
+    /// Once the limit is reached further pageable copies are still counted,
+    /// but not kept. The default limit is 1024.
+    pub fn max_findings(mut self, max: usize) -> Self {
+        self.max_findings = max;
+        self
+    }
+
+    /// Add a record to the statistics.
+    ///
+    /// Returns `false` if the record is not a
+    /// [`Memcpy`](ActivityRecord::Memcpy)
+    /// or [`Memcpy2`](ActivityRecord::Memcpy2) record, in which case it is
+    /// ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        let (key, copy) = match record {
+            ActivityRecord::Memcpy(r) => (
+                MemcpyKey {
+                    copy_kind: r.copy_kind,
+                    src_kind: r.src_kind,
+                    dst_kind: r.dst_kind,
+                    is_async: r.flags.contains(ActivityFlag::MEMCPY_ASYNC),
+                },
+                (
+                    r.bytes,
+                    r.start,
+                    r.end,
+                    r.device_id,
+                    r.context_id,
+                    r.stream_id,
+                    r.correlation_id,
+                ),
+            ),
+            ActivityRecord::Memcpy2(r) => (
+                MemcpyKey {
+                    copy_kind: r.copy_kind,
+                    src_kind: r.src_kind,
+                    dst_kind: r.dst_kind,
+                    is_async: r.flags.contains(ActivityFlag::MEMCPY_ASYNC),
+                },
+                (
+                    r.bytes,
+                    r.start,
+                    r.end,
+                    r.device_id,
+                    r.context_id,
+                    r.stream_id,
+                    r.correlation_id,
+                ),
+            ),
+            _ => return false,
+        };
+        let (bytes, start, end, device_id, context_id, stream_id, correlation_id) = copy;
+
+        self.copies
+            .entry(key)
+            .or_insert_with(|| MemcpySummary::new(key))
+            .insert(bytes, end.saturating_sub(start));
+
+        if key.src_kind == ActivityMemoryKind::Pageable
+            || key.dst_kind == ActivityMemoryKind::Pageable
+        {
+            self.pageable_count += 1;
+
+            if self.pageable.len() < self.max_findings {
+                self.pageable.push(PageableCopy {
+                    key,
+                    bytes,
+                    start,
+                    end,
+                    device_id,
+                    context_id,
+                    stream_id,
+                    correlation_id,
+                    api: None,
+                });
+            }
+        }
+
+        true
+    }
+
+    /// Fill in the API call for each pageable copy from the matching launch in
+    /// `launches`.
+    pub fn attach_call_sites<'a, I>(&mut self, launches: I)
+    where
+        I: IntoIterator<Item = &'a Launch>,
+    {
+        let mut missing: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (index, copy) in self.pageable.iter().enumerate() {
+            if copy.api.is_none() {
+                missing.entry(copy.correlation_id).or_default().push(index);
+            }
+        }
+
+        for launch in launches {
+            let Some(api) = &launch.api else { continue };
+            let Some(indices) = missing.remove(&launch.correlation_id) else {
+                continue;
+            };
+
+            for index in indices {
+                self.pageable[index].api = Some(api.clone());
+            }
+        }
+    }
+
+    /// Merge the statistics in `other` into these statistics.
+    ///
+    /// Pageable copies from `other` are kept up to the limit set by
+    /// [`max_findings`](Self::max_findings).
+    pub fn merge(&mut self, other: &Self) {
+        self.extend(other.copies.values().cloned());
+
+        let room = self.max_findings.saturating_sub(self.pageable.len());
+        self.pageable
+            .extend(other.pageable.iter().take(room).cloned());
+        self.pageable_count += other.pageable_count;
+    }
+
+    /// The statistics for a single kind of copy.
+    pub fn get(&self, key: &MemcpyKey) -> Option<&MemcpySummary> {
+        self.copies.get(key)
+    }
+
+    /// The statistics for every kind of copy, ordered by total bytes copied,
+    /// largest first.
+    pub fn summaries(&self) -> Vec<&MemcpySummary> {
+        let mut summaries: Vec<_> = self.copies.values().collect();
+        summaries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| b.count.cmp(&a.count)));
+        summaries
+    }
+
+    /// The copies that involved pageable host memory, in the order they were
+    /// added.
+    pub fn pageable_copies(&self) -> &[PageableCopy] {
+        &self.pageable
+    }
+
+    /// The total number of copies that involved pageable host memory,
+    /// including those that were not kept.
+    pub fn pageable_count(&self) -> u64 {
+        self.pageable_count
+    }
+}
+
+impl Default for MemcpyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Extend<MemcpySummary> for MemcpyStats {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = MemcpySummary>,
+    {
+        for summary in iter {
+            match self.copies.get_mut(&summary.key) {
+                Some(existing) => existing.merge(&summary),
+                None => {
+                    self.copies.insert(summary.key, summary);
+                }
+            }
+        }
+    }
+}
+
+impl FromIterator<MemcpySummary> for MemcpyStats {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = MemcpySummary>,
+    {
+        let mut stats = Self::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cupti_sys::*;
+
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::analysis::Correlator;
+    use crate::testing::{self, api, kernel, memcpy};
+
+    const HTOD_PAGEABLE: MemcpyKey = MemcpyKey {
+        copy_kind: ActivityMemcpyKind::Htod,
+        src_kind: ActivityMemoryKind::Pageable,
+        dst_kind: ActivityMemoryKind::Device,
+        is_async: false,
+    };
+
+    /// A copy with the given memory kinds and flags.
+    fn copy(
+        correlation_id: u32,
+        start: u64,
+        end: u64,
+        bytes: u64,
+        kinds: (ActivityMemcpyKind, ActivityMemoryKind, ActivityMemoryKind),
+        flags: ActivityFlag,
+    ) -> ActivityRecord<'static> {
+        let mut record = memcpy(correlation_id, start, end, bytes);
+        if let ActivityRecord::Memcpy(r) = &mut record {
+            (r.copy_kind, r.src_kind, r.dst_kind) = kinds;
+            r.flags = flags;
+        }
+        record
+    }
+
+    /// A peer-to-peer copy from device 0 to device 1.
+    fn peer(correlation_id: u32, start: u64, end: u64, bytes: u64) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityMemcpyPtoP4 = testing::raw(ActivityKind::Memcpy2);
+        raw.copyKind = CUPTI_ACTIVITY_MEMCPY_KIND_PTOP as u8;
+        raw.srcKind = CUPTI_ACTIVITY_MEMORY_KIND_DEVICE as u8;
+        raw.dstKind = CUPTI_ACTIVITY_MEMORY_KIND_DEVICE as u8;
+        raw.flags = ActivityFlag::MEMCPY_ASYNC.bits() as u8;
+        raw.correlationId = correlation_id;
+        raw.start = start;
+        raw.end = end;
+        raw.bytes = bytes;
+        raw.srcDeviceId = 0;
+        raw.dstDeviceId = 1;
+        testing::record(&raw)
+    }
+
+    #[test]
+    fn groups_copies_by_key() {
+        let device = (
+            ActivityMemcpyKind::Htod,
+            ActivityMemoryKind::Pinned,
+            ActivityMemoryKind::Device,
+        );
+
+        let mut stats = MemcpyStats::new();
+        assert!(stats.push(&memcpy(1, 0, 100, 1000)));
+        assert!(stats.push(&memcpy(2, 100, 300, 3000)));
+        assert!(stats.push(&copy(3, 0, 100, 500, device, ActivityFlag::empty())));
+        assert!(stats.push(&copy(4, 0, 100, 700, device, ActivityFlag::MEMCPY_ASYNC)));
+        assert!(stats.push(&peer(5, 0, 1000, 8000)));
+        assert!(!stats.push(&kernel(6, 0, 100, "k")));
+
+        let pageable = stats.get(&HTOD_PAGEABLE).unwrap();
+        assert_eq!(
+            (pageable.count, pageable.bytes, pageable.duration),
+            (2, 4000, 300)
+        );
+
+        let sync = MemcpyKey {
+            src_kind: ActivityMemoryKind::Pinned,
+            ..HTOD_PAGEABLE
+        };
+        let is_async = MemcpyKey {
+            is_async: true,
+            ..sync
+        };
+        assert_eq!(stats.get(&sync).unwrap().bytes, 500);
+        assert_eq!(stats.get(&is_async).unwrap().bytes, 700);
+
+        let p2p = MemcpyKey {
+            copy_kind: ActivityMemcpyKind::Ptop,
+            src_kind: ActivityMemoryKind::Device,
+            dst_kind: ActivityMemoryKind::Device,
+            is_async: true,
+        };
+        assert_eq!(stats.get(&p2p).unwrap().bytes, 8000);
+
+        let order: Vec<_> = stats.summaries().iter().map(|s| s.bytes).collect();
+        assert_eq!(order, [8000, 4000, 700, 500]);
+    }
+
+    #[test]
+    fn bandwidth_skips_empty_copies() {
+        let mut stats = MemcpyStats::new();
+        stats.push(&memcpy(1, 0, 1000, 2000));
+        stats.push(&memcpy(2, 0, 1000, 0));
+        stats.push(&memcpy(3, 500, 500, 4000));
+
+        let summary = stats.get(&HTOD_PAGEABLE).unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.bandwidth.count(), 1);
+        assert_eq!(summary.bandwidth.min(), Some(2_000_000_000));
+        assert_eq!(summary.mean_bandwidth(), 3e9);
+    }
+
+    #[test]
+    fn collects_pageable_copies() {
+        let to_host = (
+            ActivityMemcpyKind::Dtoh,
+            ActivityMemoryKind::Device,
+            ActivityMemoryKind::Pageable,
+        );
+        let pinned = (
+            ActivityMemcpyKind::Dtoh,
+            ActivityMemoryKind::Device,
+            ActivityMemoryKind::Pinned,
+        );
+
+        let mut stats = MemcpyStats::new().max_findings(2);
+        stats.push(&memcpy(1, 0, 100, 10));
+        stats.push(&copy(2, 0, 100, 20, pinned, ActivityFlag::empty()));
+        stats.push(&copy(3, 0, 100, 30, to_host, ActivityFlag::empty()));
+        stats.push(&memcpy(4, 0, 100, 40));
+
+        let kept: Vec<_> = stats
+            .pageable_copies()
+            .iter()
+            .map(|copy| (copy.correlation_id, copy.key.copy_kind))
+            .collect();
+        assert_eq!(
+            kept,
+            [(1, ActivityMemcpyKind::Htod), (3, ActivityMemcpyKind::Dtoh)]
+        );
+        assert_eq!(stats.pageable_count(), 3);
+    }
+
+    #[test]
+    fn attaches_call_sites_by_correlation_id() {
+        let mut correlator = Correlator::new(Duration::from_micros(1));
+        correlator.push(&api(ActivityKind::Runtime, 1, 0, 50));
+        correlator.push(&memcpy(1, 60, 100, 10));
+        correlator.push(&api(ActivityKind::Runtime, 3, 200, 250));
+        correlator.push(&kernel(3, 260, 300, "k"));
+        let launches = correlator.finish();
+
+        let mut stats = MemcpyStats::new();
+        stats.push(&memcpy(1, 60, 100, 10));
+        stats.push(&memcpy(2, 160, 200, 10));
+        stats.attach_call_sites(&launches);
+
+        let copies = stats.pageable_copies();
+        let api = copies[0].api.as_ref().unwrap();
+        assert_eq!(api.kind, ActivityKind::Runtime);
+        assert_eq!(api.record.correlation_id, 1);
+        assert!(copies[1].api.is_none());
+    }
+
+    #[test]
+    fn merge_respects_max_findings() {
+        let mut a = MemcpyStats::new().max_findings(3);
+        a.push(&memcpy(1, 0, 100, 10));
+        a.push(&memcpy(2, 0, 100, 10));
+
+        let mut b = MemcpyStats::new();
+        b.push(&memcpy(3, 0, 100, 10));
+        b.push(&memcpy(4, 0, 100, 10));
+
+        a.merge(&b);
+        let kept: Vec<_> = a
+            .pageable_copies()
+            .iter()
+            .map(|copy| copy.correlation_id)
+            .collect();
+        assert_eq!(kept, [1, 2, 3]);
+        assert_eq!(a.pageable_count(), 4);
+
+        let summary = a.get(&HTOD_PAGEABLE).unwrap();
+        assert_eq!((summary.count, summary.bytes), (4, 40));
+        assert_eq!(summary.bandwidth.count(), 4);
+    }
+}
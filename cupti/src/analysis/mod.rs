@@ -0,0 +1,29 @@
+//! Analysis of decoded activity records.
+//!
+//! The types in this module operate on [`ActivityRecord`]s that have already
+//! been decoded from activity buffers, and do not call into CUPTI themselves.
+//!
+//! [`ActivityRecord`]: crate::activity::ActivityRecord
+
+mod correlate;
+mod graph;
+mod kernel;
+mod memcpy;
+mod nvtx;
+mod sketch;
+mod timeline;
+mod unified_memory;
+
+pub use self::correlate::{ApiCall, Correlator, GpuActivity, Launch};
+pub use self::graph::{GraphDag, GraphLaunch, GraphNodeInfo, GraphNodeTiming, GraphTracker};
+pub use self::kernel::{KernelKey, KernelStats, KernelSummary};
+pub use self::memcpy::{MemcpyKey, MemcpyStats, MemcpySummary, PageableCopy};
+pub use self::nvtx::{NvtxAggregator, NvtxRange};
+pub use self::sketch::QuantileSketch;
+pub use self::timeline::{
+    DeviceStats, Gap, Interval, IntervalKind, OccupancySample, StreamKey, StreamStats,
+    TimelineAnalyzer, TimelineReport,
+};
+pub use self::unified_memory::{
+    AddressRangeStats, MigrationSample, MigrationStats, UnifiedMemoryAnalyzer, UnifiedMemoryTotals,
+};
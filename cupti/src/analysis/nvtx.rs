@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+
+use super::{GpuActivity, Launch};
+use crate::activity::{
+    ActivityFlag, ActivityKind, ActivityMarker, ActivityMarkerData, ActivityObjectId,
+    ActivityRecord, Interner, RecordStr,
+};
+
+/// An NVTX range reconstructed from its start and end markers.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NvtxRange {
+    /// The marker ID shared by the start and end markers.
+    pub id: u32,
+    /// The name of the range.
+    pub name: Option<RecordStr<'static>>,
+    /// The name of the domain of the range, or `None` for the default domain.
+    pub domain: Option<RecordStr<'static>>,
+    /// The ARGB color of the range, if one was set.
+    pub color: Option<u32>,
+    /// The category of the range, if its marker data was seen.
+    pub category: Option<u32>,
+    /// The thread that started the range.
+    pub thread: ActivityObjectId,
+    /// The thread that ended the range.
+    ///
+    /// This is only different from [`thread`](Self::thread) for start/end
+    /// ranges that were ended on a different thread.
+    pub end_thread: ActivityObjectId,
+    /// The timestamp of the start marker, in ns.
+    pub start: u64,
+    /// The timestamp of the end marker, in ns.
+    pub end: u64,
+    /// The number of push/pop ranges that were open on the same thread when
+    /// this range started.
+    ///
+    /// For push/pop ranges this is the nesting depth, with 0 for the
+    /// outermost range. It is always 0 for ranges that were identified as
+    /// start/end ranges.
+    pub depth: u32,
+    /// The GPU kernels launched from within this range.
+    ///
+    /// This is empty until [`attach_kernels`](Self::attach_kernels) is called.
+    pub kernels: Vec<GpuActivity>,
+}
+
+impl NvtxRange {
+    /// The duration of the range, in ns.
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Whether the range was started and ended on different threads.
+    pub fn crosses_threads(&self) -> bool {
+        self.thread != self.end_thread
+    }
+
+    /// Attach the kernels from `launches` whose API call was made within this
+    /// range.
+    ///
+    /// For ranges that started and ended on the same thread only launches
+    /// from that thread are considered. For ranges that cross threads,
+    /// launches from any thread in the same process are considered.
+    pub fn attach_kernels<'a, I>(&mut self, launches: I)
+    where
+        I: IntoIterator<Item = &'a Launch>,
+    {
+        let (process_id, thread_id) = match self.thread {
+            ActivityObjectId::Process {
+                process_id,
+                thread_id,
+            } => (process_id, thread_id),
+            _ => return,
+        };
+        let any_thread = self.crosses_threads();
+
+        for launch in launches {
+            let Some(api) = &launch.api else { continue };
+            let api = &api.record;
+
+            if api.process_id != process_id || (!any_thread && api.thread_id != thread_id) {
+                continue;
+            }
+            if api.start < self.start || api.start > self.end {
+                continue;
+            }
+
+            let kernels = launch.gpu.iter().filter(|activity| {
+                matches!(
+                    activity.kind,
+                    ActivityKind::Kernel | ActivityKind::ConcurrentKernel
+                )
+            });
+            self.kernels.extend(kernels.cloned());
+        }
+
+        self.kernels.sort_by_key(|kernel| kernel.start);
+    }
+}
+
+#[derive(Debug)]
+struct OpenRange {
+    name: Option<RecordStr<'static>>,
+    domain: Option<RecordStr<'static>>,
+    data: Option<ActivityMarkerData>,
+    thread: ActivityObjectId,
+    start: u64,
+    depth: u32,
+}
+
+/// Pairs NVTX start and end [`ActivityMarker`] records into [`NvtxRange`]s.
+///
+/// Markers for push/pop ranges and for start/end ranges are both supported.
+/// The color and category of each range are taken from the matching
+/// [`ActivityMarkerData`] record, which must be pushed after the start marker
+/// but may be pushed before or after the end marker. Marker data for any other
+/// marker, including instantaneous markers, is dropped. Instantaneous markers
+/// are ignored.
+///
+/// CUPTI does not record whether a range is a push/pop range, so ranges that
+/// end on a different thread or out of nesting order are treated as start/end
+/// ranges. These are removed from the nesting depth of the ranges still open
+/// on their thread when they end. A start/end range that ends in nesting order
+/// on the thread that started it is treated as a push/pop range.
+///
+/// Markers from a single thread must be pushed in the order that CUPTI
+/// delivered them, but markers from different threads may be interleaved
+/// arbitrarily.
+#[derive(Debug, Default)]
+pub struct NvtxAggregator {
+    open: HashMap<u32, OpenRange>,
+    /// The IDs of the ranges that are open on each thread, innermost last.
+    stacks: HashMap<ActivityObjectId, Vec<u32>>,
+    completed: Vec<NvtxRange>,
+    interner: Interner,
+}
+
+impl NvtxAggregator {
+    /// Create an empty aggregator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record to the aggregator.
+    ///
+    /// Returns `false` if the record is not a
+    /// [`Marker`](ActivityRecord::Marker) or
+    /// [`MarkerData`](ActivityRecord::MarkerData) record, in which case it is
+    /// ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        match record {
+            ActivityRecord::Marker(marker) => self.push_marker(marker),
+            ActivityRecord::MarkerData(data) => self.push_data(data),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn push_marker(&mut self, marker: &ActivityMarker<'_>) {
+        if marker.flags.contains(ActivityFlag::MARKER_START) {
+            let stack = self.stacks.entry(marker.object_id).or_default();
+            let depth = stack.len() as u32;
+            stack.push(marker.id);
+
+            self.open.insert(
+                marker.id,
+                OpenRange {
+                    name: marker
+                        .name
+                        .clone()
+                        .map(|s| s.into_owned(&mut self.interner)),
+                    domain: marker
+                        .domain
+                        .clone()
+                        .map(|s| s.into_owned(&mut self.interner)),
+                    data: None,
+                    thread: marker.object_id,
+                    start: marker.timestamp,
+                    depth,
+                },
+            );
+        } else if marker.flags.contains(ActivityFlag::MARKER_END) {
+            let Some(open) = self.open.remove(&marker.id) else {
+                return;
+            };
+
+            let mut depth = open.depth;
+            if let Some(stack) = self.stacks.get_mut(&open.thread)
+                && let Some(index) = stack.iter().rposition(|&id| id == marker.id)
+            {
+                stack.remove(index);
+
+                // Push/pop ranges always end innermost first on the thread
+                // that started them. Anything else is a start/end range, which
+                // the ranges above it should not have been nested in.
+                if index != stack.len() || marker.object_id != open.thread {
+                    depth = 0;
+                    for id in &stack[index..] {
+                        if let Some(above) = self.open.get_mut(id) {
+                            above.depth = above.depth.saturating_sub(1);
+                        }
+                    }
+                }
+                if stack.is_empty() {
+                    self.stacks.remove(&open.thread);
+                }
+            }
+
+            let mut range = NvtxRange {
+                id: marker.id,
+                name: open.name,
+                domain: open.domain,
+                color: None,
+                category: None,
+                thread: open.thread,
+                end_thread: marker.object_id,
+                start: open.start,
+                end: marker.timestamp,
+                depth,
+                kernels: Vec::new(),
+            };
+            if let Some(data) = &open.data {
+                apply_data(&mut range, data);
+            }
+            self.completed.push(range);
+        }
+    }
+
+    fn push_data(&mut self, data: &ActivityMarkerData) {
+        if let Some(open) = self.open.get_mut(&data.id) {
+            open.data = Some(data.clone());
+        } else if let Some(range) = self.completed.iter_mut().rfind(|range| range.id == data.id) {
+            apply_data(range, data);
+        }
+    }
+
+    /// Remove and return the ranges that have been completed so far, ordered
+    /// by their start time.
+    ///
+    /// Marker data that is pushed after its range has been taken is dropped.
+    /// If no ranges are open, the interned range and domain names are
+    /// released as well.
+    pub fn take_completed(&mut self) -> Vec<NvtxRange> {
+        let mut completed = std::mem::take(&mut self.completed);
+        completed.sort_by_key(|range| (range.start, range.depth));
+
+        if self.open.is_empty() {
+            // Names are only deduplicated until no ranges are open, which
+            // keeps the interner from growing without bound. Taken ranges keep
+            // their copies of the names.
+            self.interner.clear();
+        }
+        completed
+    }
+
+    /// The number of ranges that have been started but not yet ended.
+    pub fn open_ranges(&self) -> usize {
+        self.open.len()
+    }
+}
+
+fn apply_data(range: &mut NvtxRange, data: &ActivityMarkerData) {
+    range.category = Some(data.category);
+    if !data.flags.contains(ActivityFlag::MARKER_COLOR_NONE) {
+        range.color = Some(data.color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::testing::{marker, marker_data};
+
+    const START: ActivityFlag = ActivityFlag::MARKER_START;
+    const END: ActivityFlag = ActivityFlag::MARKER_END;
+
+    fn thread(thread_id: u32) -> ActivityObjectId {
+        ActivityObjectId::Process {
+            process_id: 100,
+            thread_id,
+        }
+    }
+
+    #[test]
+    fn nested_push_pop_ranges() {
+        let mut nvtx = NvtxAggregator::new();
+
+        nvtx.push(&marker(START, 1, 1, 100, Some("outer")));
+        nvtx.push(&marker(START, 2, 1, 200, Some("inner")));
+        nvtx.push(&marker(END, 2, 1, 300, None));
+        nvtx.push(&marker(START, 3, 1, 400, Some("inner2")));
+        nvtx.push(&marker(END, 3, 1, 500, None));
+        assert_eq!(nvtx.open_ranges(), 1);
+        nvtx.push(&marker(END, 1, 1, 600, None));
+        assert_eq!(nvtx.open_ranges(), 0);
+
+        let ranges = nvtx.take_completed();
+        let summary: Vec<_> = ranges
+            .iter()
+            .map(|r| (r.id, r.depth, r.start, r.end))
+            .collect();
+        assert_eq!(
+            summary,
+            [(1, 0, 100, 600), (2, 1, 200, 300), (3, 1, 400, 500)]
+        );
+        assert_eq!(ranges[0].name.as_deref(), Some(c"outer"));
+        assert!(!ranges[0].crosses_threads());
+        assert!(nvtx.stacks.is_empty());
+    }
+
+    #[test]
+    fn applies_marker_data() {
+        let mut nvtx = NvtxAggregator::new();
+
+        // Data pushed while the range is open.
+        nvtx.push(&marker(START, 1, 1, 100, Some("a")));
+        nvtx.push(&marker_data(1, 0xff00_00ff, 3));
+        nvtx.push(&marker(END, 1, 1, 200, None));
+
+        // Data pushed after the range has completed.
+        nvtx.push(&marker(START, 2, 1, 300, Some("b")));
+        nvtx.push(&marker(END, 2, 1, 400, None));
+        nvtx.push(&marker_data(2, 0xff00_ff00, 4));
+
+        let ranges = nvtx.take_completed();
+        assert_eq!(ranges[0].color, Some(0xff00_00ff));
+        assert_eq!(ranges[0].category, Some(3));
+        assert_eq!(ranges[1].color, Some(0xff00_ff00));
+        assert_eq!(ranges[1].category, Some(4));
+    }
+
+    #[test]
+    fn drops_unmatched_marker_data() {
+        let mut nvtx = NvtxAggregator::new();
+
+        nvtx.push(&marker(
+            ActivityFlag::MARKER_INSTANTANEOUS,
+            1,
+            1,
+            100,
+            Some("i"),
+        ));
+        nvtx.push(&marker_data(1, 0, 0));
+        nvtx.push(&marker_data(2, 0, 0));
+
+        nvtx.push(&marker(START, 3, 1, 200, Some("a")));
+        nvtx.push(&marker(END, 3, 1, 300, None));
+        let ranges = nvtx.take_completed();
+        assert_eq!(ranges.len(), 1);
+        nvtx.push(&marker_data(3, 0, 0));
+
+        assert!(nvtx.open.is_empty());
+        assert!(nvtx.completed.is_empty());
+    }
+
+    #[test]
+    fn start_end_ranges_are_not_nested() {
+        let mut nvtx = NvtxAggregator::new();
+
+        // A start/end range that is ended on another thread.
+        nvtx.push(&marker(START, 1, 1, 100, Some("async")));
+        nvtx.push(&marker(START, 2, 1, 200, Some("push")));
+        nvtx.push(&marker(END, 1, 2, 300, None));
+        nvtx.push(&marker(START, 3, 1, 400, Some("nested")));
+        nvtx.push(&marker(END, 3, 1, 500, None));
+        nvtx.push(&marker(END, 2, 1, 600, None));
+
+        let ranges = nvtx.take_completed();
+        let summary: Vec<_> = ranges.iter().map(|r| (r.id, r.depth)).collect();
+        assert_eq!(summary, [(1, 0), (2, 0), (3, 1)]);
+        assert!(ranges[0].crosses_threads());
+        assert_eq!(ranges[0].thread, thread(1));
+        assert_eq!(ranges[0].end_thread, thread(2));
+        assert!(nvtx.stacks.is_empty());
+    }
+
+    #[test]
+    fn start_end_ranges_ended_out_of_order() {
+        let mut nvtx = NvtxAggregator::new();
+
+        nvtx.push(&marker(START, 1, 1, 100, Some("a")));
+        nvtx.push(&marker(START, 2, 1, 200, Some("b")));
+        nvtx.push(&marker(END, 1, 1, 300, None));
+        nvtx.push(&marker(START, 3, 1, 400, Some("c")));
+        nvtx.push(&marker(END, 3, 1, 500, None));
+        nvtx.push(&marker(END, 2, 1, 600, None));
+
+        let ranges = nvtx.take_completed();
+        let summary: Vec<_> = ranges.iter().map(|r| (r.id, r.depth)).collect();
+        assert_eq!(summary, [(1, 0), (2, 0), (3, 1)]);
+    }
+
+    #[test]
+    fn interner_is_cleared_when_idle() {
+        // Names borrowed from a buffer are interned by the aggregator.
+        let borrowed = |flags, id, timestamp, name: Option<&'static CStr>| {
+            let mut record = marker(flags, id, 1, timestamp, None);
+            if let ActivityRecord::Marker(marker) = &mut record {
+                marker.name = name.map(RecordStr::Borrowed);
+            }
+            record
+        };
+
+        let mut nvtx = NvtxAggregator::new();
+
+        nvtx.push(&borrowed(START, 1, 100, Some(c"outer")));
+        nvtx.push(&borrowed(START, 2, 200, Some(c"inner")));
+        nvtx.push(&borrowed(END, 2, 300, None));
+        assert_eq!(nvtx.take_completed().len(), 1);
+        assert_eq!(nvtx.interner.len(), 2);
+
+        nvtx.push(&borrowed(END, 1, 400, None));
+        let ranges = nvtx.take_completed();
+        assert!(nvtx.interner.is_empty());
+        assert_eq!(ranges[0].name.as_deref(), Some(c"outer"));
+
+        nvtx.push(&borrowed(START, 3, 500, Some(c"outer")));
+        assert_eq!(nvtx.interner.len(), 1);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+
+/// The relative error of the quantiles reported by a [`QuantileSketch`].
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// A streaming sketch of a distribution of integer values.
+///
+/// Values are counted in logarithmically sized buckets, so quantiles are
+/// reported to within 1% of the true value while using a bounded amount of
+/// memory: a sketch never holds more than a few thousand buckets no matter how
+/// many values are inserted. Sketches can be [merged](Self::merge), including
+/// sketches that were built in different processes and serialized.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuantileSketch {
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+    zeros: u64,
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl QuantileSketch {
+    /// Create an empty sketch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn gamma() -> f64 {
+        (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+    }
+
+    fn bucket(value: u64) -> i32 {
+        ((value as f64).ln() / Self::gamma().ln()).ceil() as i32
+    }
+
+    fn bucket_value(bucket: i32) -> f64 {
+        let gamma = Self::gamma();
+
+        2.0 * gamma.powi(bucket) / (gamma + 1.0)
+    }
+
+    /// Add a value to the sketch.
+    pub fn insert(&mut self, value: u64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+
+        if value == 0 {
+            self.zeros += 1;
+        } else {
+            *self.buckets.entry(Self::bucket(value)).or_default() += 1;
+        }
+    }
+
+    /// Add all of the values in `other` to this sketch.
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+
+        if self.count == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.zeros += other.zeros;
+
+        for (&bucket, &count) in &other.buckets {
+            *self.buckets.entry(bucket).or_default() += count;
+        }
+    }
+
+    /// The number of values in the sketch.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The sum of all values in the sketch.
+    ///
+    /// This saturates at `u64::MAX`.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// The smallest value in the sketch.
+    pub fn min(&self) -> Option<u64> {
+        (self.count != 0).then_some(self.min)
+    }
+
+    /// The largest value in the sketch.
+    pub fn max(&self) -> Option<u64> {
+        (self.count != 0).then_some(self.max)
+    }
+
+    /// The mean of the values in the sketch.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count != 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// An estimate of the value at quantile `q`, which is clamped to the range
+    /// `0.0..=1.0`.
+    ///
+    /// The estimate is within 1% of the true value. Quantiles 0 and 1 are
+    /// exact.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let q = q.clamp(0.0, 1.0);
+        if q == 0.0 {
+            return Some(self.min);
+        }
+        if q == 1.0 {
+            return Some(self.max);
+        }
+
+        let rank = (q * (self.count - 1) as f64) as u64;
+        if rank < self.zeros {
+            return Some(0);
+        }
+
+        let mut seen = self.zeros;
+        for (&bucket, &count) in &self.buckets {
+            seen += count;
+
+            if seen > rank {
+                let value = Self::bucket_value(bucket).round() as u64;
+                return Some(value.clamp(self.min, self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_quantile(sorted: &[u64], q: f64) -> u64 {
+        sorted[(q * (sorted.len() - 1) as f64) as usize]
+    }
+
+    fn assert_close(estimate: u64, exact: u64) {
+        let error = (estimate as f64 - exact as f64).abs();
+        assert!(
+            error <= exact as f64 * RELATIVE_ACCURACY + 1.0,
+            "estimate {estimate} is not within 1% of {exact}"
+        );
+    }
+
+    #[test]
+    fn empty_sketch() {
+        let sketch = QuantileSketch::new();
+
+        assert!(sketch.is_empty());
+        assert_eq!(sketch.min(), None);
+        assert_eq!(sketch.max(), None);
+        assert_eq!(sketch.mean(), None);
+        assert_eq!(sketch.quantile(0.5), None);
+    }
+
+    #[test]
+    fn quantiles_are_within_accuracy() {
+        // A skewed distribution spanning several orders of magnitude.
+        let mut values: Vec<u64> = (0..10_000u64).map(|i| i * i % 1_000_003 + i).collect();
+        values.extend([0, 0, 0]);
+
+        let mut sketch = QuantileSketch::new();
+        for &value in &values {
+            sketch.insert(value);
+        }
+        values.sort_unstable();
+
+        assert_eq!(sketch.count(), values.len() as u64);
+        assert_eq!(sketch.sum(), values.iter().sum::<u64>());
+        assert_eq!(sketch.min(), Some(0));
+        assert_eq!(sketch.max(), values.last().copied());
+        assert_eq!(sketch.quantile(0.0), Some(0));
+        assert_eq!(sketch.quantile(1.0), values.last().copied());
+        assert_eq!(sketch.quantile(-1.0), Some(0));
+
+        for q in [0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999] {
+            assert_close(sketch.quantile(q).unwrap(), exact_quantile(&values, q));
+        }
+    }
+
+    #[test]
+    fn quantiles_are_clamped_to_range() {
+        let mut sketch = QuantileSketch::new();
+        for _ in 0..10 {
+            sketch.insert(1_000);
+        }
+
+        assert_eq!(sketch.quantile(0.5), Some(1_000));
+        assert_eq!(sketch.mean(), Some(1_000.0));
+    }
+
+    #[test]
+    fn merge_matches_single_sketch() {
+        let mut all = QuantileSketch::new();
+        let mut a = QuantileSketch::new();
+        let mut b = QuantileSketch::new();
+
+        for value in 1..=1_000u64 {
+            all.insert(value * 7);
+            if value % 3 == 0 {
+                a.insert(value * 7);
+            } else {
+                b.insert(value * 7);
+            }
+        }
+
+        let mut merged = QuantileSketch::new();
+        merged.merge(&QuantileSketch::new());
+        merged.merge(&a);
+        merged.merge(&b);
+        assert_eq!(merged, all);
+
+        a.merge(&b);
+        assert_eq!(a, all);
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::activity::{ActivityRecord, Interner, RecordStr};
+
+/// The kind of work performed during an [`Interval`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum IntervalKind {
+    /// A kernel execution.
+    Compute,
+    /// A memory copy, including peer-to-peer copies.
+    Copy,
+    /// A memory set.
+    Memset,
+}
+
+/// A span of time during which a stream was executing work.
+#[derive(Clone, Debug)]
+pub struct Interval {
+    /// The kind of work.
+    pub kind: IntervalKind,
+    /// The start timestamp, in ns.
+    pub start: u64,
+    /// The end timestamp, in ns.
+    pub end: u64,
+    /// The correlation ID of the API call that launched the work.
+    pub correlation_id: u32,
+    /// The kernel name, for [`IntervalKind::Compute`] intervals.
+    pub name: Option<RecordStr<'static>>,
+}
+
+impl Interval {
+    /// The duration of the interval, in ns.
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Identifies a stream on a device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct StreamKey {
+    /// The device ID.
+    pub device_id: u32,
+    /// The stream ID.
+    pub stream_id: u32,
+}
+
+/// A span of time during which a stream or device was not executing any work.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Gap {
+    /// The end of the preceding work, in ns.
+    pub start: u64,
+    /// The start of the following work, in ns.
+    pub end: u64,
+}
+
+impl Gap {
+    /// The duration of the gap, in ns.
+    pub fn duration(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// Utilization statistics for a single stream.
+#[derive(Clone, Debug)]
+pub struct StreamStats {
+    /// The stream these statistics are for.
+    pub key: StreamKey,
+    /// The start of the first interval and the end of the last interval, in
+    /// ns.
+    pub span: (u64, u64),
+    /// The total time that the stream was executing work, in ns.
+    pub busy: u64,
+    /// The total time within `span` that the stream was idle, in ns.
+    pub idle: u64,
+    /// The time spent executing kernels, in ns.
+    pub compute: u64,
+    /// The time spent executing memory copies, in ns.
+    pub copy: u64,
+    /// The time spent executing memory sets, in ns.
+    pub memset: u64,
+    /// The idle gaps between work that are at least as long as the configured
+    /// minimum, in time order.
+    pub gaps: Vec<Gap>,
+    /// The number of intervals executed on the stream.
+    pub intervals: usize,
+}
+
+/// A span of time with a constant number of concurrently executing kernels.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OccupancySample {
+    /// The start of the span, in ns.
+    pub start: u64,
+    /// The end of the span, in ns.
+    pub end: u64,
+    /// The number of kernels executing during the span.
+    pub kernels: u32,
+}
+
+/// Utilization statistics for a device, combining all of its streams.
+#[derive(Clone, Debug)]
+pub struct DeviceStats {
+    /// The device ID.
+    pub device_id: u32,
+    /// The start of the first interval and the end of the last interval on
+    /// any stream, in ns.
+    pub span: (u64, u64),
+    /// The total time that any stream was executing work, in ns.
+    pub busy: u64,
+    /// The total time within `span` that no stream was executing work, in ns.
+    pub idle: u64,
+    /// The total time that at least one kernel was executing, in ns.
+    pub compute_busy: u64,
+    /// The total time that at least one memory copy was executing, in ns.
+    pub copy_busy: u64,
+    /// The total time that a memory copy and a kernel were executing at the
+    /// same time, in ns.
+    pub copy_compute_overlap: u64,
+    /// The idle gaps across all streams that are at least as long as the
+    /// configured minimum, in time order.
+    pub gaps: Vec<Gap>,
+    /// The number of concurrently executing kernels over time.
+    ///
+    /// Spans where no kernel is executing are omitted.
+    pub occupancy: Vec<OccupancySample>,
+    /// The maximum number of kernels executing at the same time.
+    pub max_concurrent_kernels: u32,
+    /// The average number of kernels executing at the same time, weighted by
+    /// time, over the time that at least one kernel was executing.
+    pub mean_concurrent_kernels: f64,
+}
+
+/// The results of a [`TimelineAnalyzer`].
+#[derive(Clone, Debug, Default)]
+pub struct TimelineReport {
+    /// Statistics for each stream, ordered by device and stream ID.
+    pub streams: Vec<StreamStats>,
+    /// Statistics for each device, ordered by device ID.
+    pub devices: Vec<DeviceStats>,
+}
+
+/// Computes per-stream and per-device GPU utilization from kernel, memory copy
+/// and memory set records.
+///
+/// Records can be pushed in any order. The analyzer only looks at the records
+/// themselves, so it can be used on records that were recorded earlier and
+/// read back from storage.
+#[derive(Debug, Default)]
+pub struct TimelineAnalyzer {
+    streams: BTreeMap<StreamKey, Vec<Interval>>,
+    min_gap: u64,
+    interner: Interner,
+}
+
+impl TimelineAnalyzer {
+    /// Create an empty analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only report idle gaps that are at least `min_gap` long.
+    ///
+    /// Shorter gaps still count towards idle time.
+    pub fn min_gap(mut self, min_gap: Duration) -> Self {
+        self.min_gap = min_gap.as_nanos().try_into().unwrap_or(u64::MAX);
+        self
+    }
+
+    /// Add a record to the analyzer.
+    ///
+    /// Returns `false` if the record is not a kernel, memory copy or memory
+    /// set record, in which case it is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        let (key, interval) = match record {
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => (
+                (r.device_id, r.stream_id),
+                Interval {
+                    kind: IntervalKind::Compute,
+                    start: r.start,
+                    end: r.end,
+                    correlation_id: r.correlation_id,
+                    name: r.name.clone().map(|s| s.into_owned(&mut self.interner)),
+                },
+            ),
+            ActivityRecord::Memcpy(r) => (
+                (r.device_id, r.stream_id),
+                Interval {
+                    kind: IntervalKind::Copy,
+                    start: r.start,
+                    end: r.end,
+                    correlation_id: r.correlation_id,
+                    name: None,
+                },
+            ),
+            ActivityRecord::Memcpy2(r) => (
+                (r.device_id, r.stream_id),
+                Interval {
+                    kind: IntervalKind::Copy,
+                    start: r.start,
+                    end: r.end,
+                    correlation_id: r.correlation_id,
+                    name: None,
+                },
+            ),
+            ActivityRecord::Memset(r) => (
+                (r.device_id, r.stream_id),
+                Interval {
+                    kind: IntervalKind::Memset,
+                    start: r.start,
+                    end: r.end,
+                    correlation_id: r.correlation_id,
+                    name: None,
+                },
+            ),
+            _ => return false,
+        };
+
+        // Records for work that was dropped or never completed have no
+        // timestamps.
+        if interval.start == 0 || interval.end < interval.start {
+            return false;
+        }
+
+        let (device_id, stream_id) = key;
+        self.streams
+            .entry(StreamKey {
+                device_id,
+                stream_id,
+            })
+            .or_default()
+            .push(interval);
+
+        true
+    }
+
+    /// The intervals recorded for each stream, ordered by start time.
+    pub fn timeline(&mut self) -> &BTreeMap<StreamKey, Vec<Interval>> {
+        for intervals in self.streams.values_mut() {
+            intervals.sort_by_key(|i| (i.start, i.end));
+        }
+
+        &self.streams
+    }
+
+    /// Compute statistics for every stream and device.
+    pub fn analyze(&mut self) -> TimelineReport {
+        let min_gap = self.min_gap;
+        let mut report = TimelineReport::default();
+        let mut devices: BTreeMap<u32, Vec<&Interval>> = BTreeMap::new();
+
+        for (&key, intervals) in self.timeline() {
+            if intervals.is_empty() {
+                continue;
+            }
+
+            let busy = union(intervals.iter());
+            let kind_time = |kind| total(&union(intervals.iter().filter(|i| i.kind == kind)));
+
+            let span = (busy[0].0, busy[busy.len() - 1].1);
+            let busy_time = total(&busy);
+
+            report.streams.push(StreamStats {
+                key,
+                span,
+                busy: busy_time,
+                idle: (span.1 - span.0) - busy_time,
+                compute: kind_time(IntervalKind::Compute),
+                copy: kind_time(IntervalKind::Copy),
+                memset: kind_time(IntervalKind::Memset),
+                gaps: gaps(&busy, min_gap),
+                intervals: intervals.len(),
+            });
+
+            devices.entry(key.device_id).or_default().extend(intervals);
+        }
+
+        for (device_id, intervals) in devices {
+            let busy = union(intervals.iter().copied());
+            let compute = union(
+                intervals
+                    .iter()
+                    .copied()
+                    .filter(|i| i.kind == IntervalKind::Compute),
+            );
+            let copy = union(
+                intervals
+                    .iter()
+                    .copied()
+                    .filter(|i| i.kind == IntervalKind::Copy),
+            );
+
+            let span = (busy[0].0, busy[busy.len() - 1].1);
+            let busy_time = total(&busy);
+            let occupancy = occupancy(intervals.iter().filter(|i| i.kind == IntervalKind::Compute));
+
+            let compute_busy = total(&compute);
+            let weighted: u128 = occupancy
+                .iter()
+                .map(|s| u128::from(s.end - s.start) * u128::from(s.kernels))
+                .sum();
+
+            report.devices.push(DeviceStats {
+                device_id,
+                span,
+                busy: busy_time,
+                idle: (span.1 - span.0) - busy_time,
+                compute_busy,
+                copy_busy: total(&copy),
+                copy_compute_overlap: overlap(&compute, &copy),
+                gaps: gaps(&busy, min_gap),
+                max_concurrent_kernels: occupancy.iter().map(|s| s.kernels).max().unwrap_or(0),
+                mean_concurrent_kernels: if compute_busy == 0 {
+                    0.0
+                } else {
+                    weighted as f64 / compute_busy as f64
+                },
+                occupancy,
+            });
+        }
+
+        report
+    }
+}
+
+/// Merge intervals into a sorted list of disjoint `(start, end)` spans.
+fn union<'a, I>(intervals: I) -> Vec<(u64, u64)>
+where
+    I: Iterator<Item = &'a Interval>,
+{
+    let mut spans: Vec<_> = intervals.map(|i| (i.start, i.end)).collect();
+    spans.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+fn total(spans: &[(u64, u64)]) -> u64 {
+    spans.iter().map(|(start, end)| end - start).sum()
+}
+
+/// The total length of the intersection of two sets of disjoint sorted spans.
+fn overlap(a: &[(u64, u64)], b: &[(u64, u64)]) -> u64 {
+    let (mut i, mut j) = (0, 0);
+    let mut overlap = 0;
+
+    while i < a.len() && j < b.len() {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        overlap += end.saturating_sub(start);
+
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    overlap
+}
+
+fn gaps(busy: &[(u64, u64)], min_gap: u64) -> Vec<Gap> {
+    busy.windows(2)
+        .map(|w| Gap {
+            start: w[0].1,
+            end: w[1].0,
+        })
+        .filter(|gap| gap.duration() > 0 && gap.duration() >= min_gap)
+        .collect()
+}
+
+fn occupancy<'a, I>(kernels: I) -> Vec<OccupancySample>
+where
+    I: Iterator<Item = &'a &'a Interval>,
+{
+    let mut events: Vec<(u64, i32)> = kernels.flat_map(|i| [(i.start, 1), (i.end, -1)]).collect();
+    // Process ends before starts at the same timestamp so back-to-back
+    // kernels are not counted as concurrent.
+    events.sort_unstable();
+
+    let mut samples: Vec<OccupancySample> = Vec::new();
+    let mut active = 0i32;
+    let mut last = 0;
+
+    for (time, delta) in events {
+        if active > 0 && time > last {
+            match samples.last_mut() {
+                Some(s) if s.end == last && s.kernels == active as u32 => s.end = time,
+                _ => samples.push(OccupancySample {
+                    start: last,
+                    end: time,
+                    kernels: active as u32,
+                }),
+            }
+        }
+
+        active += delta;
+        last = time;
+    }
+
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{kernel, memcpy};
+
+    fn interval(kind: IntervalKind, start: u64, end: u64) -> Interval {
+        Interval {
+            kind,
+            start,
+            end,
+            correlation_id: 0,
+            name: None,
+        }
+    }
+
+    fn spans(intervals: &[(u64, u64)]) -> Vec<(u64, u64)> {
+        let intervals: Vec<_> = intervals
+            .iter()
+            .map(|&(start, end)| interval(IntervalKind::Compute, start, end))
+            .collect();
+        union(intervals.iter())
+    }
+
+    #[test]
+    fn union_merges_overlapping_and_touching_spans() {
+        assert_eq!(spans(&[]), []);
+        assert_eq!(
+            spans(&[(50, 60), (0, 10), (5, 20), (20, 30), (40, 45), (41, 44)]),
+            [(0, 30), (40, 45), (50, 60)]
+        );
+        assert_eq!(total(&spans(&[(0, 10), (5, 20), (30, 35)])), 25);
+    }
+
+    #[test]
+    fn overlap_of_disjoint_spans() {
+        let a = [(0, 10), (20, 30), (40, 50)];
+        let b = [(5, 25), (28, 45), (60, 70)];
+
+        assert_eq!(overlap(&a, &b), 5 + 5 + 2 + 5);
+        assert_eq!(overlap(&b, &a), 17);
+        assert_eq!(overlap(&a, &[]), 0);
+        assert_eq!(overlap(&[(0, 10)], &[(10, 20)]), 0);
+        assert_eq!(overlap(&[(0, 100)], &[(10, 20), (30, 40)]), 20);
+    }
+
+    #[test]
+    fn gaps_respect_minimum() {
+        let busy = [(0, 10), (15, 20), (120, 130)];
+
+        assert_eq!(
+            gaps(&busy, 0),
+            [
+                Gap { start: 10, end: 15 },
+                Gap {
+                    start: 20,
+                    end: 120
+                }
+            ]
+        );
+        assert_eq!(
+            gaps(&busy, 50),
+            [Gap {
+                start: 20,
+                end: 120
+            }]
+        );
+    }
+
+    #[test]
+    fn occupancy_counts_concurrent_kernels() {
+        let intervals = [
+            interval(IntervalKind::Compute, 0, 10),
+            interval(IntervalKind::Compute, 5, 15),
+            interval(IntervalKind::Compute, 15, 20),
+            interval(IntervalKind::Compute, 30, 40),
+        ];
+        let refs: Vec<_> = intervals.iter().collect();
+
+        let samples = occupancy(refs.iter());
+        let summary: Vec<_> = samples
+            .iter()
+            .map(|s| (s.start, s.end, s.kernels))
+            .collect();
+        assert_eq!(summary, [(0, 5, 1), (5, 10, 2), (10, 20, 1), (30, 40, 1)]);
+    }
+
+    #[test]
+    fn analyze_stream_and_device() {
+        let mut timeline = TimelineAnalyzer::new().min_gap(Duration::from_nanos(50));
+
+        assert!(timeline.push(&kernel(1, 100, 200, "a")));
+        assert!(timeline.push(&kernel(2, 150, 300, "b")));
+        assert!(timeline.push(&memcpy(3, 250, 400, 64)));
+        assert!(timeline.push(&kernel(4, 500, 600, "c")));
+        assert!(!timeline.push(&kernel(5, 0, 0, "dropped")));
+
+        let report = timeline.analyze();
+        assert_eq!(report.streams.len(), 1);
+        let stream = &report.streams[0];
+        assert_eq!(stream.span, (100, 600));
+        assert_eq!(stream.busy, 400);
+        assert_eq!(stream.idle, 100);
+        assert_eq!(stream.compute, 300);
+        assert_eq!(stream.copy, 150);
+        assert_eq!(
+            stream.gaps,
+            [Gap {
+                start: 400,
+                end: 500
+            }]
+        );
+        assert_eq!(stream.intervals, 4);
+
+        let device = &report.devices[0];
+        assert_eq!(device.busy, 400);
+        assert_eq!(device.compute_busy, 300);
+        assert_eq!(device.copy_busy, 150);
+        assert_eq!(device.copy_compute_overlap, 50);
+        assert_eq!(device.max_concurrent_kernels, 2);
+        assert!((device.mean_concurrent_kernels - 350.0 / 300.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::activity::{
+    ActivityFlag, ActivityRecord, ActivityUnifiedMemoryCounter, ActivityUnifiedMemoryCounterKind,
+    ActivityUnifiedMemoryMigrationCause,
+};
+
+/// The default size of the address ranges used by [`UnifiedMemoryAnalyzer`].
+///
+/// This matches the 2 MiB blocks that the driver migrates unified memory in.
+const DEFAULT_RANGE_SIZE: u64 = 2 << 20;
+
+/// The maximum number of address ranges that a single transfer is split
+/// between.
+const MAX_TRANSFER_RANGES: u64 = 1 << 16;
+
+/// The default interval of the migrated bytes time series.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Totals of the unified memory counters for an address range or device.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct UnifiedMemoryTotals {
+    /// The number of bytes migrated from host to device.
+    pub bytes_htod: u64,
+    /// The number of bytes migrated from device to host.
+    pub bytes_dtoh: u64,
+    /// The number of bytes migrated between devices.
+    pub bytes_dtod: u64,
+    /// The number of CPU page faults.
+    pub cpu_page_faults: u64,
+    /// The number of GPU page faults.
+    pub gpu_page_faults: u64,
+    /// The number of thrashing events.
+    pub thrashing: u64,
+    /// The number of thrashing events in which the CPU was involved.
+    pub thrashing_in_cpu: u64,
+    /// The number of throttling events.
+    pub throttling: u64,
+    /// The number of throttling events in which the CPU was throttled.
+    pub throttling_in_cpu: u64,
+    /// The number of remote maps.
+    pub remote_maps: u64,
+}
+
+impl UnifiedMemoryTotals {
+    /// Add `record` to the totals, counting `value` in place of its value.
+    fn insert(&mut self, record: &ActivityUnifiedMemoryCounter, value: u64) {
+        let flags = ActivityFlag::from_bits_retain(record.flags);
+
+        match record.counter_kind {
+            ActivityUnifiedMemoryCounterKind::BytesTransferHtoD => self.bytes_htod += value,
+            ActivityUnifiedMemoryCounterKind::BytesTransferDtoH => self.bytes_dtoh += value,
+            ActivityUnifiedMemoryCounterKind::BytesTransferDtoD => self.bytes_dtod += value,
+            ActivityUnifiedMemoryCounterKind::CpuPageFaultCount => self.cpu_page_faults += value,
+            ActivityUnifiedMemoryCounterKind::GpuPageFault => self.gpu_page_faults += value,
+            ActivityUnifiedMemoryCounterKind::Thrashing => {
+                self.thrashing += 1;
+                if flags.contains(ActivityFlag::THRASHING_IN_CPU) {
+                    self.thrashing_in_cpu += 1;
+                }
+            }
+            ActivityUnifiedMemoryCounterKind::Throttling => {
+                self.throttling += 1;
+                if flags.contains(ActivityFlag::THROTTLING_IN_CPU) {
+                    self.throttling_in_cpu += 1;
+                }
+            }
+            ActivityUnifiedMemoryCounterKind::RemoteMap => self.remote_maps += 1,
+            _ => (),
+        }
+    }
+
+    /// The total number of bytes migrated in any direction.
+    pub fn bytes_migrated(&self) -> u64 {
+        self.bytes_htod + self.bytes_dtoh + self.bytes_dtod
+    }
+
+    /// The total number of CPU and GPU page faults.
+    pub fn page_faults(&self) -> u64 {
+        self.cpu_page_faults + self.gpu_page_faults
+    }
+
+    /// The total number of thrashing, throttling and remote map events.
+    pub fn contention(&self) -> u64 {
+        self.thrashing + self.throttling + self.remote_maps
+    }
+
+    fn severity(&self) -> (u64, u64, u64) {
+        (self.contention(), self.page_faults(), self.bytes_migrated())
+    }
+}
+
+/// Unified memory counters for a range of virtual addresses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AddressRangeStats {
+    /// The first address in the range.
+    pub start: u64,
+    /// The address one past the end of the range.
+    pub end: u64,
+    /// The counter totals for the range.
+    pub totals: UnifiedMemoryTotals,
+    /// The time of the first event in the range, in ns.
+    pub first_seen: u64,
+    /// The time of the last event in the range, in ns.
+    pub last_seen: u64,
+}
+
+/// The number of migrations and bytes migrated for a single cause.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MigrationStats {
+    /// The number of migrations.
+    pub count: u64,
+    /// The number of bytes migrated.
+    pub bytes: u64,
+}
+
+/// The number of bytes migrated during one interval of the time series.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MigrationSample {
+    /// The start of the interval, in ns.
+    pub start: u64,
+    /// The number of bytes migrated from host to device.
+    pub htod: u64,
+    /// The number of bytes migrated from device to host.
+    pub dtoh: u64,
+    /// The number of bytes migrated between devices.
+    pub dtod: u64,
+}
+
+/// Summarizes unified memory counter records per address range, per device
+/// and per migration cause.
+///
+/// Transfers are split between the ranges covered by the bytes they transfer,
+/// and other counters are attributed to the range containing their base
+/// address. Ranges are aligned to a fixed size, which defaults to 2 MiB. A
+/// transfer is split between at most 65536 ranges, and any bytes beyond the
+/// last of them are attributed to it. Counters are attributed to the devices
+/// involved as follows:
+///
+/// - host-to-device transfers and remote maps to the destination,
+/// - device-to-host transfers, GPU page faults, thrashing and throttling to the
+///   source,
+/// - device-to-device transfers to both the source and the destination.
+///
+/// CPU page faults are not attributed to any device.
+#[derive(Clone, Debug)]
+pub struct UnifiedMemoryAnalyzer {
+    range_size: u64,
+    interval: u64,
+    ranges: HashMap<u64, AddressRangeStats>,
+    devices: BTreeMap<u32, UnifiedMemoryTotals>,
+    causes: HashMap<ActivityUnifiedMemoryMigrationCause, MigrationStats>,
+    series: BTreeMap<u64, MigrationSample>,
+}
+
+impl UnifiedMemoryAnalyzer {
+    /// Create an empty analyzer.
+    pub fn new() -> Self {
+        Self {
+            range_size: DEFAULT_RANGE_SIZE,
+            interval: DEFAULT_INTERVAL.as_nanos() as u64,
+            ranges: HashMap::new(),
+            devices: BTreeMap::new(),
+            causes: HashMap::new(),
+            series: BTreeMap::new(),
+        }
+    }
+
+    /// Group addresses into ranges of `size` bytes.
+    ///
+    /// This should be set before any records are added. A size of 0 is
+    /// treated as 1.
+    pub fn range_size(mut self, size: u64) -> Self {
+        self.range_size = size.max(1);
+        self
+    }
+
+    /// Use `interval` as the width of each sample in the migrated bytes time
+    /// series.
+    ///
+    /// This should be set before any records are added. The default is 10ms.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.as_nanos().clamp(1, u64::MAX as u128) as u64;
+        self
+    }
+
+    /// Add a record to the analyzer.
+    ///
+    /// Returns `false` if the record is not a
+    /// [`UnifiedMemoryCounter`](ActivityRecord::UnifiedMemoryCounter) record,
+    /// in which case it is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        match record {
+            ActivityRecord::UnifiedMemoryCounter(counter) => {
+                self.push_counter(counter);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Add a unified memory counter record to the analyzer.
+    pub fn push_counter(&mut self, record: &ActivityUnifiedMemoryCounter) {
+        use ActivityUnifiedMemoryCounterKind as Kind;
+
+        let transfer = matches!(
+            record.counter_kind,
+            Kind::BytesTransferHtoD | Kind::BytesTransferDtoH | Kind::BytesTransferDtoD
+        );
+        // The address one past the last byte covered by the counter.
+        let end = if transfer {
+            record.address.saturating_add(record.value)
+        } else {
+            record.address
+        };
+
+        // Transfers cover every range from the one containing their first
+        // byte to the one containing their last byte.
+        let first = record.address / self.range_size;
+        let last = if end > record.address {
+            (end - 1) / self.range_size
+        } else {
+            first
+        };
+        let last = last.min(first.saturating_add(MAX_TRANSFER_RANGES - 1));
+
+        for block in first..=last {
+            let start = block * self.range_size;
+            let range_end = start.saturating_add(self.range_size);
+            let value = if transfer {
+                // The last range also takes any bytes beyond the cap.
+                let block_end = if block == last { end } else { range_end };
+                block_end - record.address.max(start)
+            } else {
+                record.value
+            };
+
+            let range = self
+                .ranges
+                .entry(start)
+                .or_insert_with(|| AddressRangeStats {
+                    start,
+                    end: range_end,
+                    totals: UnifiedMemoryTotals::default(),
+                    first_seen: record.start,
+                    last_seen: record.end,
+                });
+            range.totals.insert(record, value);
+            range.first_seen = range.first_seen.min(record.start);
+            range.last_seen = range.last_seen.max(record.end.max(record.start));
+        }
+
+        let devices: &[u32] = match record.counter_kind {
+            Kind::BytesTransferHtoD | Kind::RemoteMap => &[record.dst_id],
+            Kind::BytesTransferDtoH | Kind::GpuPageFault | Kind::Thrashing | Kind::Throttling => {
+                &[record.src_id]
+            }
+            Kind::BytesTransferDtoD if record.src_id != record.dst_id => {
+                &[record.src_id, record.dst_id]
+            }
+            Kind::BytesTransferDtoD => &[record.src_id],
+            _ => &[],
+        };
+        for &device in devices {
+            self.devices
+                .entry(device)
+                .or_default()
+                .insert(record, record.value);
+        }
+
+        if let Some(cause) = record.migration_cause {
+            let stats = self.causes.entry(cause).or_default();
+            stats.count += 1;
+            stats.bytes += record.value;
+        }
+
+        if transfer {
+            let bucket = record.start - record.start % self.interval;
+            let sample = self
+                .series
+                .entry(bucket)
+                .or_insert_with(|| MigrationSample {
+                    start: bucket,
+                    ..Default::default()
+                });
+            match record.counter_kind {
+                Kind::BytesTransferHtoD => sample.htod += record.value,
+                Kind::BytesTransferDtoH => sample.dtoh += record.value,
+                _ => sample.dtod += record.value,
+            }
+        }
+    }
+
+    /// The counter totals for every device, ordered by device ID.
+    pub fn devices(&self) -> &BTreeMap<u32, UnifiedMemoryTotals> {
+        &self.devices
+    }
+
+    /// The number of migrations and bytes migrated for each cause.
+    pub fn causes(&self) -> &HashMap<ActivityUnifiedMemoryMigrationCause, MigrationStats> {
+        &self.causes
+    }
+
+    /// The address ranges that had any counter events, in no particular
+    /// order.
+    pub fn ranges(&self) -> impl Iterator<Item = &AddressRangeStats> {
+        self.ranges.values()
+    }
+
+    /// The `n` worst address ranges, worst first.
+    ///
+    /// Ranges are ordered by the number of thrashing, throttling and remote
+    /// map events, then by the number of page faults, then by the number of
+    /// bytes migrated.
+    pub fn worst_ranges(&self, n: usize) -> Vec<&AddressRangeStats> {
+        let mut ranges: Vec<_> = self.ranges.values().collect();
+        ranges.sort_by(|a, b| {
+            b.totals
+                .severity()
+                .cmp(&a.totals.severity())
+                .then_with(|| a.start.cmp(&b.start))
+        });
+        ranges.truncate(n);
+        ranges
+    }
+
+    /// The address ranges that had thrashing or throttling events, ordered by
+    /// the number of such events, most first.
+    pub fn thrashing_hot_spots(&self) -> Vec<&AddressRangeStats> {
+        let mut ranges: Vec<_> = self
+            .ranges
+            .values()
+            .filter(|range| range.totals.thrashing + range.totals.throttling != 0)
+            .collect();
+        ranges.sort_by(|a, b| {
+            let a_events = a.totals.thrashing + a.totals.throttling;
+            let b_events = b.totals.thrashing + b.totals.throttling;
+
+            b_events.cmp(&a_events).then_with(|| a.start.cmp(&b.start))
+        });
+        ranges
+    }
+
+    /// The number of bytes migrated in each interval, in time order.
+    ///
+    /// Each transfer is counted in the interval containing its start time.
+    /// Intervals with no transfers are omitted.
+    pub fn migrated_bytes(&self) -> Vec<MigrationSample> {
+        self.series.values().copied().collect()
+    }
+}
+
+impl Default for UnifiedMemoryAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cupti_sys::*;
+
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::testing;
+
+    const MIB: u64 = 1 << 20;
+
+    fn counter(
+        kind: CUpti_ActivityUnifiedMemoryCounterKind,
+        address: u64,
+        value: u64,
+        start: u64,
+    ) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityUnifiedMemoryCounter3 =
+            testing::raw(ActivityKind::UnifiedMemoryCounter);
+        raw.counterKind = kind;
+        raw.address = address;
+        raw.value = value;
+        raw.start = start;
+        raw.end = start + 10;
+        raw.srcId = 0;
+        raw.dstId = 1;
+        testing::record(&raw)
+    }
+
+    #[test]
+    fn transfers_are_split_across_ranges() {
+        let mut um = UnifiedMemoryAnalyzer::new();
+
+        // 5 MiB starting 1 MiB into the first range covers three ranges.
+        assert!(um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_BYTES_TRANSFER_HTOD,
+            MIB,
+            5 * MIB,
+            100,
+        )));
+
+        let mut ranges: Vec<_> = um
+            .ranges()
+            .map(|r| (r.start, r.end, r.totals.bytes_htod))
+            .collect();
+        ranges.sort_unstable();
+        assert_eq!(
+            ranges,
+            [
+                (0, 2 * MIB, MIB),
+                (2 * MIB, 4 * MIB, 2 * MIB),
+                (4 * MIB, 6 * MIB, 2 * MIB)
+            ]
+        );
+        assert_eq!(um.devices()[&1].bytes_htod, 5 * MIB);
+    }
+
+    #[test]
+    fn transfers_spanning_many_ranges() {
+        let mut um = UnifiedMemoryAnalyzer::new().range_size(4096);
+
+        // From the middle of range 1 to the middle of range 1000.
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_BYTES_TRANSFER_DTOH,
+            6144,
+            999 * 4096,
+            100,
+        ));
+
+        let mut ranges: Vec<_> = um
+            .ranges()
+            .map(|r| (r.start, r.totals.bytes_dtoh))
+            .collect();
+        ranges.sort_unstable();
+        assert_eq!(ranges.len(), 1000);
+        assert_eq!(ranges[0], (4096, 2048));
+        assert!(ranges[1..999].iter().all(|&(_, bytes)| bytes == 4096));
+        assert_eq!(ranges[999], (1000 * 4096, 2048));
+    }
+
+    #[test]
+    fn huge_transfers_are_capped() {
+        let mut um = UnifiedMemoryAnalyzer::new().range_size(1);
+
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_BYTES_TRANSFER_HTOD,
+            0,
+            1 << 40,
+            100,
+        ));
+
+        let ranges: Vec<_> = um.ranges().collect();
+        assert_eq!(ranges.len() as u64, MAX_TRANSFER_RANGES);
+        let total: u64 = ranges.iter().map(|r| r.totals.bytes_htod).sum();
+        assert_eq!(total, 1 << 40);
+
+        let last = ranges.iter().max_by_key(|r| r.start).unwrap();
+        assert_eq!(last.start, MAX_TRANSFER_RANGES - 1);
+        assert_eq!(
+            last.totals.bytes_htod,
+            (1 << 40) - (MAX_TRANSFER_RANGES - 1)
+        );
+    }
+
+    #[test]
+    fn other_counters_use_base_address() {
+        let mut um = UnifiedMemoryAnalyzer::new();
+
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_GPU_PAGE_FAULT,
+            3 * MIB,
+            40,
+            100,
+        ));
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_THRASHING,
+            3 * MIB,
+            0,
+            200,
+        ));
+
+        let ranges: Vec<_> = um.ranges().collect();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].start, 2 * MIB);
+        assert_eq!(ranges[0].totals.gpu_page_faults, 40);
+        assert_eq!(ranges[0].totals.thrashing, 1);
+        assert_eq!((ranges[0].first_seen, ranges[0].last_seen), (100, 210));
+        assert_eq!(um.thrashing_hot_spots().len(), 1);
+
+        // Only transfers appear in the time series.
+        assert!(um.migrated_bytes().is_empty());
+    }
+
+    #[test]
+    fn migrated_bytes_series() {
+        let mut um = UnifiedMemoryAnalyzer::new().interval(Duration::from_nanos(100));
+
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_BYTES_TRANSFER_HTOD,
+            0,
+            64,
+            110,
+        ));
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_BYTES_TRANSFER_DTOH,
+            0,
+            32,
+            150,
+        ));
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_BYTES_TRANSFER_HTOD,
+            0,
+            16,
+            420,
+        ));
+        um.push(&counter(
+            CUPTI_ACTIVITY_UNIFIED_MEMORY_COUNTER_KIND_CPU_PAGE_FAULT_COUNT,
+            0,
+            1,
+            250,
+        ));
+
+        let series = um.migrated_bytes();
+        assert_eq!(
+            series,
+            [
+                MigrationSample {
+                    start: 100,
+                    htod: 64,
+                    dtoh: 32,
+                    dtod: 0,
+                },
+                MigrationSample {
+                    start: 400,
+                    htod: 16,
+                    dtoh: 0,
+                    dtod: 0,
+                },
+            ]
+        );
+    }
+}
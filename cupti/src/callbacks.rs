@@ -4,16 +4,16 @@ use std::ffi::{CStr, CString, c_char, c_void};
 use std::fmt;
 use std::marker::PhantomData;
 
-use c_enum::c_enum;
 use cupti_sys::*;
 
 use crate::util::NonPoisonMutex;
 use crate::*;
 
-c_enum! {
+serde_c_enum! {
     /// Specifies the point in an API call that a callback is issued.
     ///
     /// This value is communicated to the callback function via [`CUpti_CallbackData::callbackSite`].
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum ApiCallbackSite : CUpti_ApiCallbackSite {
         /// The callback is at the entry of the API call.
         Enter = CUPTI_API_ENTER,
@@ -23,7 +23,7 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Callback domains.
     ///
     /// Each domain represents callback points for a group of related API functions or CUDA driver activity.
@@ -52,11 +52,12 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Callback IDs for resource domain.
     ///
     /// Callback IDs for resource domain, [`CallbackDomain::Resource`]. This value is communicated
     /// to the callback function via the `cbid` parameter.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum CallbackIdResource : CUpti_CallbackIdResource {
         /// Invalid resource callback ID.
         Invalid = CUPTI_CBID_RESOURCE_INVALID,
@@ -132,11 +133,12 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Callback IDs for synchronization domain.
     ///
     /// Callback IDs for synchronization domain, [`CallbackDomain::Synchronize`]. This value is
     /// communicated to the callback function via the `cbid` parameter.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum CallbackIdSync : CUpti_CallbackIdSync {
         /// Invalid synchronize callback ID.
         Invalid = CUPTI_CBID_SYNCHRONIZE_INVALID,
@@ -149,11 +151,12 @@ c_enum! {
     }
 }
 
-c_enum! {
+serde_c_enum! {
     /// Callback IDs for state domain.
     ///
     /// Callback IDs for state domain, [`CallbackDomain::State`]. This value is communicated
     /// to the callback function via the `cbid` parameter.
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum CallbackIdState : CUpti_CallbackIdState {
         /// Invalid state callback ID.
         Invalid = CUPTI_CBID_STATE_INVALID,
@@ -223,6 +226,16 @@ impl<'a> CallbackData<'a> {
         }
     }
 
+    /// The demangled [`symbol_name`](Self::symbol_name), using a cache local
+    /// to the calling thread.
+    ///
+    /// See [`demangle`](crate::demangle::demangle).
+    #[cfg(feature = "demangle")]
+    pub fn demangled_symbol_name(&self) -> Option<std::sync::Arc<str>> {
+        let name = self.symbol_name()?;
+        Some(crate::demangle::demangle(&name.to_string_lossy()))
+    }
+
     /// The driver context current to the thread, or null if no context is
     /// current. This value can change from the entry to exit callback of a
     /// runtime API function if the runtime initializes a context.
@@ -306,6 +319,42 @@ impl<'a> ResourceData<'a> {
     pub fn stream(&self) -> Option<&'a Stream> {
         unsafe { Stream::from_ptr(self.raw.resourceHandle.stream) }
     }
+
+    /// The graph data associated with this event.
+    ///
+    /// This is only present for the graph callbacks, from
+    /// [`CallbackIdResource::GraphCreated`] through
+    /// [`CallbackIdResource::GraphNodeCloned`], and for
+    /// [`CallbackIdResource::GraphNodeUpdated`] and
+    /// [`CallbackIdResource::GraphNodeSetParams`]. `cbid` must be the ID of
+    /// the callback that this data was passed to.
+    pub fn graph_data(&self, cbid: CallbackIdResource) -> Option<GraphData<'a>> {
+        use CallbackIdResource as Id;
+
+        let is_graph = matches!(
+            cbid,
+            Id::GraphCreated
+                | Id::GraphDestroyStarting
+                | Id::GraphCloned
+                | Id::GraphNodeCreateStarting
+                | Id::GraphNodeCreated
+                | Id::GraphNodeDestroyStarting
+                | Id::GraphNodeDependencyCreated
+                | Id::GraphNodeDependencyDestroyStarting
+                | Id::GraphExecCreateStarting
+                | Id::GraphExecCreated
+                | Id::GraphExecDestroyStarting
+                | Id::GraphNodeCloned
+                | Id::GraphNodeUpdated
+                | Id::GraphNodeSetParams
+        );
+        if !is_graph || self.raw.resourceDescriptor.is_null() {
+            return None;
+        }
+
+        let raw = unsafe { *(self.raw.resourceDescriptor as *const CUpti_GraphData) };
+        Some(unsafe { GraphData::from_raw(raw) })
+    }
 }
 
 /// Module data passed into a resource callback function.
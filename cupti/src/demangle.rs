@@ -0,0 +1,410 @@
+//! Demangling of C++ symbol names.
+//!
+//! CUPTI reports kernel and function names as mangled Itanium C++ names, such
+//! as `_Z6vecAddPKfS0_Pfi`. The functions here turn them back into readable
+//! names like `vecAdd(float const*, float const*, float*, int)`, and can
+//! optionally [`Simplify`] them further for use in reports and traces.
+//!
+//! Demangling is cached, since a trace typically contains millions of
+//! launches of a few hundred distinct kernels. [`demangle`] and
+//! [`Simplify::demangle`] use caches local to the calling thread, so they can
+//! be called from activity and callback handlers without taking a lock, while
+//! a [`Demangler`] owns its own cache. Cached names are returned as `Arc<str>`,
+//! so looking up a name that is already cached does not allocate.
+//!
+//! [`KernelStats`] groups kernels by the names returned by [`demangle`] by
+//! default. To group them by simplified names instead, pass a closure that
+//! calls [`Simplify::demangle`] on a `const` [`Simplify`] to
+//! [`KernelStats::demangle_with`].
+//!
+//! This module requires the `demangle` feature.
+//!
+//! [`KernelStats`]: crate::analysis::KernelStats
+//! [`KernelStats::demangle_with`]: crate::analysis::KernelStats::demangle_with
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cpp_demangle::{DemangleOptions, Symbol};
+
+/// The default number of names held by a [`Demangler`] cache.
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    /// The caches used by [`Simplify::demangle`], one for each set of options.
+    static CACHES: RefCell<HashMap<Simplify, Demangler>> = RefCell::new(HashMap::new());
+}
+
+/// Demangle `name`, using a cache local to the calling thread.
+///
+/// Names that are not mangled C++ names, or that cannot be demangled, are
+/// returned unchanged.
+pub fn demangle(name: &str) -> Arc<str> {
+    Simplify::new().demangle(name)
+}
+
+fn demangle_uncached(name: &str) -> Cow<'_, str> {
+    if !name.starts_with("_Z") {
+        return Cow::Borrowed(name);
+    }
+
+    Symbol::new(name)
+        .ok()
+        .and_then(|symbol| symbol.demangle(&DemangleOptions::new()).ok())
+        .map_or(Cow::Borrowed(name), Cow::Owned)
+}
+
+/// Options for making demangled names shorter.
+///
+/// The default leaves names unchanged. The steps are applied in the order
+/// that the methods are listed here.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Simplify {
+    strip_template_args: bool,
+    collapse_namespaces: bool,
+    max_len: Option<usize>,
+}
+
+impl Simplify {
+    /// Create options that leave names unchanged.
+    pub const fn new() -> Self {
+        Self {
+            strip_template_args: false,
+            collapse_namespaces: false,
+            max_len: None,
+        }
+    }
+
+    /// Remove template arguments, so that `reduce<float, 256>(float*)`
+    /// becomes `reduce(float*)`.
+    pub const fn strip_template_args(mut self, strip: bool) -> Self {
+        self.strip_template_args = strip;
+        self
+    }
+
+    /// Remove namespace and class qualifiers, so that
+    /// `cub::detail::reduce(thrust::complex*)` becomes `reduce(complex*)`.
+    pub const fn collapse_namespaces(mut self, collapse: bool) -> Self {
+        self.collapse_namespaces = collapse;
+        self
+    }
+
+    /// Truncate names longer than `max_len` characters, replacing the end
+    /// with `…`.
+    ///
+    /// A `max_len` of 0 truncates every name to an empty string.
+    pub const fn truncate(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Simplify a name that has already been demangled.
+    pub fn apply(&self, name: &str) -> String {
+        let mut name = Cow::Borrowed(name);
+
+        if self.strip_template_args {
+            name = Cow::Owned(strip_template_args(&name));
+        }
+        if self.collapse_namespaces {
+            name = Cow::Owned(collapse_namespaces(&name));
+        }
+        if let Some(max_len) = self.max_len
+            && name.chars().nth(max_len).is_some()
+        {
+            let truncated = match max_len.checked_sub(1) {
+                Some(keep) => name.chars().take(keep).chain(['…']).collect(),
+                None => String::new(),
+            };
+            name = Cow::Owned(truncated);
+        }
+
+        name.into_owned()
+    }
+
+    /// Demangle `name` and then simplify it, using a cache local to the
+    /// calling thread for each set of options.
+    pub fn demangle(&self, name: &str) -> Arc<str> {
+        CACHES
+            .try_with(|caches| {
+                caches
+                    .borrow_mut()
+                    .entry(*self)
+                    .or_insert_with(|| Demangler::new().simplify(*self))
+                    .demangle(name)
+            })
+            // The cache has already been destroyed if this thread is exiting.
+            .unwrap_or_else(|_| self.apply(&demangle_uncached(name)).into())
+    }
+}
+
+/// Demangles names, caching the results.
+///
+/// Each name is demangled and simplified once, after which the cached result
+/// is returned. The cache holds a bounded number of names and is cleared when
+/// it fills up.
+#[derive(Clone, Debug)]
+pub struct Demangler {
+    simplify: Simplify,
+    capacity: usize,
+    cache: HashMap<Box<str>, Arc<str>>,
+}
+
+impl Demangler {
+    /// Create a demangler that does not simplify names.
+    pub fn new() -> Self {
+        Self {
+            simplify: Simplify::new(),
+            capacity: DEFAULT_CAPACITY,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Simplify names after demangling them.
+    pub fn simplify(mut self, simplify: Simplify) -> Self {
+        self.simplify = simplify;
+        self.cache.clear();
+        self
+    }
+
+    /// Hold at most `capacity` names in the cache. The default is 65536.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Demangle and simplify `name`.
+    ///
+    /// Names that are not mangled C++ names, or that cannot be demangled, are
+    /// only simplified.
+    pub fn demangle(&mut self, name: &str) -> Arc<str> {
+        if let Some(demangled) = self.cache.get(name) {
+            return demangled.clone();
+        }
+
+        if self.cache.len() >= self.capacity {
+            self.cache.clear();
+        }
+
+        let demangled: Arc<str> = self.simplify.apply(&demangle_uncached(name)).into();
+        self.cache.insert(name.into(), demangled.clone());
+        demangled
+    }
+
+    /// Remove all names from the cache.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+}
+
+impl Default for Demangler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The operator symbols containing characters that are also used for template
+/// arguments, longest first so that `operator>>=<char>` is read as `>>=`
+/// followed by template arguments.
+const OPERATORS: [&str; 18] = [
+    "<=>", "<<=", ">>=", "->*", "<<", ">>", "<=", ">=", "->", "--", "-=", "*=", "==", "<", ">",
+    "-", "*", "=",
+];
+
+/// If `rest` starts with an operator name such as `operator<<`, return it so
+/// that its angle brackets are not mistaken for template arguments.
+fn operator_name<'a>(out: &str, rest: &'a str) -> Option<&'a str> {
+    let symbol = rest.strip_prefix("operator")?;
+    if out
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+
+    let len = OPERATORS
+        .iter()
+        .find(|operator| symbol.starts_with(*operator))
+        .map_or(0, |operator| operator.len());
+    Some(&rest[.."operator".len() + len])
+}
+
+fn strip_template_args(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    let mut rest = name;
+
+    while let Some(c) = rest.chars().next() {
+        if depth == 0
+            && let Some(operator) = operator_name(&out, rest)
+        {
+            out.push_str(operator);
+            rest = &rest[operator.len()..];
+            continue;
+        }
+
+        match c {
+            '<' => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    out
+}
+
+fn collapse_namespaces(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    // The start of the name currently being written, which is removed if it
+    // turns out to be a qualifier.
+    let mut start = 0;
+    let mut outer = Vec::new();
+    let mut rest = name;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(tail) = rest.strip_prefix("::") {
+            out.truncate(start);
+            rest = tail;
+            continue;
+        }
+        if let Some(operator) = operator_name(&out, rest) {
+            out.push_str(operator);
+            rest = &rest[operator.len()..];
+            continue;
+        }
+
+        out.push(c);
+        match c {
+            '<' | '(' | '[' | '{' => {
+                outer.push(start);
+                start = out.len();
+            }
+            '>' | ')' | ']' | '}' => start = outer.pop().unwrap_or(0),
+            ' ' | ',' | '*' | '&' => start = out.len(),
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangles_names() {
+        assert_eq!(
+            &*demangle("_Z6vecAddPKfS0_Pfi"),
+            "vecAdd(float const*, float const*, float*, int)"
+        );
+        assert_eq!(&*demangle("vecAdd"), "vecAdd");
+        assert_eq!(&*demangle("_Znot_mangled"), "_Znot_mangled");
+    }
+
+    #[test]
+    fn cache_hits_share_names() {
+        let first = demangle("_Z6vecAddPfi");
+        assert!(Arc::ptr_eq(&first, &demangle("_Z6vecAddPfi")));
+
+        const SIMPLIFY: Simplify = Simplify::new().collapse_namespaces(true);
+        let simplified = SIMPLIFY.demangle("_ZN3cub6detail6reduceEPf");
+        assert_eq!(&*simplified, "reduce(float*)");
+        assert!(Arc::ptr_eq(
+            &simplified,
+            &SIMPLIFY.demangle("_ZN3cub6detail6reduceEPf")
+        ));
+    }
+
+    #[test]
+    fn demangler_clears_full_cache() {
+        let mut demangler = Demangler::new().capacity(2);
+        let first = demangler.demangle("_Z1av");
+        assert!(Arc::ptr_eq(&first, &demangler.demangle("_Z1av")));
+
+        demangler.demangle("_Z1bv");
+        demangler.demangle("_Z1cv");
+        assert_eq!(demangler.cache.len(), 1);
+        assert!(!Arc::ptr_eq(&first, &demangler.demangle("_Z1av")));
+    }
+
+    #[test]
+    fn strips_template_args() {
+        for (name, stripped) in [
+            ("reduce<float, 256>(float*)", "reduce(float*)"),
+            (
+                "void cub::Kernel<cub::Policy<int>, 4>(int*)",
+                "void cub::Kernel(int*)",
+            ),
+            (
+                "operator<<(std::ostream&, int)",
+                "operator<<(std::ostream&, int)",
+            ),
+            ("foo::operator< <int>(int)", "foo::operator< (int)"),
+            ("Ptr<int>::operator->()", "Ptr::operator->()"),
+            ("operator>>=<char>(char)", "operator>>=(char)"),
+            ("void apply<main::{lambda(int)#1}>(int)", "void apply(int)"),
+            ("coperator<int>()", "coperator()"),
+        ] {
+            assert_eq!(strip_template_args(name), stripped, "{name}");
+        }
+    }
+
+    #[test]
+    fn collapses_namespaces() {
+        for (name, collapsed) in [
+            ("cub::detail::reduce(thrust::complex*)", "reduce(complex*)"),
+            (
+                "void ns::kernel<ns::Op, 4>(ns::Op const&)",
+                "void kernel<Op, 4>(Op const&)",
+            ),
+            (
+                "std::ostream::operator<<(std::ostream&, int)",
+                "operator<<(ostream&, int)",
+            ),
+            ("Ptr<ns::T>::operator->()", "operator->()"),
+            (
+                "main::{lambda(int)#1}::operator()(int) const",
+                "operator()(int) const",
+            ),
+            (
+                "void apply<main::{lambda(int)#1}>(int)",
+                "void apply<{lambda(int)#1}>(int)",
+            ),
+        ] {
+            assert_eq!(collapse_namespaces(name), collapsed, "{name}");
+        }
+    }
+
+    #[test]
+    fn truncates_names() {
+        assert_eq!(Simplify::new().truncate(8).apply("vecAdd(int)"), "vecAdd(…");
+        assert_eq!(
+            Simplify::new().truncate(11).apply("vecAdd(int)"),
+            "vecAdd(int)"
+        );
+        assert_eq!(Simplify::new().truncate(3).apply("αβγδ"), "αβ…");
+        assert_eq!(Simplify::new().truncate(1).apply("vecAdd"), "…");
+        assert_eq!(Simplify::new().truncate(0).apply("vecAdd"), "");
+        assert_eq!(Simplify::new().truncate(0).apply(""), "");
+    }
+
+    #[test]
+    fn applies_steps_in_order() {
+        let simplify = Simplify::new()
+            .strip_template_args(true)
+            .collapse_namespaces(true)
+            .truncate(16);
+
+        assert_eq!(
+            simplify.apply("void cub::detail::reduce<cub::Sum, 256>(float const*, float*)"),
+            "void reduce(flo…"
+        );
+        assert_eq!(Simplify::new().apply("a::b<c>()"), "a::b<c>()");
+    }
+}
@@ -1,7 +1,6 @@
-use c_enum::c_enum;
 use cupti_sys::*;
 
-c_enum! {
+serde_c_enum! {
     #[derive(Copy, Clone, PartialEq, Eq, Hash)]
     pub enum DriverApiTraceCbid : CUpti_driver_api_trace_cbid {
         INVALID = CUPTI_DRIVER_TRACE_CBID_INVALID,
@@ -0,0 +1,627 @@
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    Int32Builder, Int64Builder, StringDictionaryBuilder, UInt16Builder, UInt32Builder,
+    UInt64Builder,
+};
+use arrow_array::types::Int32Type;
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use super::{ApiNames, label};
+use crate::activity::{ActivityApi, ActivityRecord};
+
+/// A kind of activity record with its own Arrow schema.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ActivityTable {
+    /// Kernels, from both [`ActivityRecord::Kernel`] and
+    /// [`ActivityRecord::ConcurrentKernel`].
+    Kernel,
+    /// Memory copies, from both [`ActivityRecord::Memcpy`] and
+    /// [`ActivityRecord::Memcpy2`].
+    Memcpy,
+    /// Memory sets.
+    Memset,
+    /// Driver API calls.
+    Driver,
+    /// Runtime API calls.
+    Runtime,
+}
+
+impl ActivityTable {
+    /// All of the tables.
+    pub const ALL: [Self; 5] = [
+        Self::Kernel,
+        Self::Memcpy,
+        Self::Memset,
+        Self::Driver,
+        Self::Runtime,
+    ];
+
+    /// A short name for the table, suitable for use as a file or table name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Kernel => "kernel",
+            Self::Memcpy => "memcpy",
+            Self::Memset => "memset",
+            Self::Driver => "driver",
+            Self::Runtime => "runtime",
+        }
+    }
+
+    /// The schema of the batches built for this table.
+    pub fn schema(self) -> SchemaRef {
+        let fields = match self {
+            Self::Kernel => KernelColumns::fields(),
+            Self::Memcpy => MemcpyColumns::fields(),
+            Self::Memset => MemsetColumns::fields(),
+            Self::Driver | Self::Runtime => ApiColumns::fields(),
+        };
+
+        Arc::new(Schema::new(fields))
+    }
+}
+
+/// Builds Arrow record batches from activity records.
+///
+/// Records are appended column by column to a separate set of columns for each
+/// [`ActivityTable`], and turned into a [`RecordBatch`] by
+/// [`finish`](Self::finish) or [`finish_all`](Self::finish_all). The batches
+/// can be written to Parquet files, or handed directly to a query engine such
+/// as DataFusion or Polars.
+///
+/// Columns are named after the fields of the records they come from. Kernel
+/// and API names and the labels of enums such as the memory copy kind are
+/// dictionary-encoded, so repeated names are stored once per batch. Timestamps
+/// are stored as `UInt64` ns rather than as an Arrow timestamp type, since
+/// CUPTI timestamps are not necessarily relative to the Unix epoch.
+#[derive(Default)]
+pub struct ActivityBatchBuilder {
+    kernels: KernelColumns,
+    memcpys: MemcpyColumns,
+    memsets: MemsetColumns,
+    driver: ApiColumns,
+    runtime: ApiColumns,
+    api_names: ApiNames,
+}
+
+impl ActivityBatchBuilder {
+    /// Create a builder with no rows.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a record to the columns of its table.
+    ///
+    /// Returns `false` if the kind of record is not supported, in which case it
+    /// is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        match record {
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => {
+                let c = &mut self.kernels;
+                c.start.append_value(r.start);
+                c.end.append_value(r.end);
+                c.device_id.append_value(r.device_id);
+                c.context_id.append_value(r.context_id);
+                c.stream_id.append_value(r.stream_id);
+                c.correlation_id.append_value(r.correlation_id);
+                c.name
+                    .append_option(r.name.as_ref().map(|name| name.to_string_lossy()));
+                c.grid_x.append_value(r.grid_x);
+                c.grid_y.append_value(r.grid_y);
+                c.grid_z.append_value(r.grid_z);
+                c.block_x.append_value(r.block_x);
+                c.block_y.append_value(r.block_y);
+                c.block_z.append_value(r.block_z);
+                c.registers_per_thread.append_value(r.registers_per_thread);
+                c.static_shared_memory.append_value(r.static_shared_memory);
+                c.dynamic_shared_memory
+                    .append_value(r.dynamic_shared_memory);
+                c.local_memory_per_thread
+                    .append_value(r.local_memory_per_thread);
+                c.local_memory_total.append_value(r.local_memory_total);
+                c.grid_id.append_option(r.grid_id);
+                c.queued.append_option(r.queued);
+                c.submitted.append_option(r.submitted);
+                c.graph_id.append_option(r.graph_id);
+                c.graph_node_id.append_option(r.graph_node_id);
+                c.rows += 1;
+            }
+            ActivityRecord::Memcpy(r) => {
+                let c = &mut self.memcpys;
+                c.start.append_value(r.start);
+                c.end.append_value(r.end);
+                c.device_id.append_value(r.device_id);
+                c.context_id.append_value(r.context_id);
+                c.stream_id.append_value(r.stream_id);
+                c.correlation_id.append_value(r.correlation_id);
+                c.bytes.append_value(r.bytes);
+                c.copy_kind.append_value(label(r.copy_kind));
+                c.src_kind.append_value(label(r.src_kind));
+                c.dst_kind.append_value(label(r.dst_kind));
+                c.src_device_id.append_null();
+                c.src_context_id.append_null();
+                c.dst_device_id.append_null();
+                c.dst_context_id.append_null();
+                c.graph_id.append_option(r.graph_id);
+                c.graph_node_id.append_option(r.graph_node_id);
+                c.rows += 1;
+            }
+            ActivityRecord::Memcpy2(r) => {
+                let c = &mut self.memcpys;
+                c.start.append_value(r.start);
+                c.end.append_value(r.end);
+                c.device_id.append_value(r.device_id);
+                c.context_id.append_value(r.context_id);
+                c.stream_id.append_value(r.stream_id);
+                c.correlation_id.append_value(r.correlation_id);
+                c.bytes.append_value(r.bytes);
+                c.copy_kind.append_value(label(r.copy_kind));
+                c.src_kind.append_value(label(r.src_kind));
+                c.dst_kind.append_value(label(r.dst_kind));
+                c.src_device_id.append_value(r.src_device_id);
+                c.src_context_id.append_value(r.src_context_id);
+                c.dst_device_id.append_value(r.dst_device_id);
+                c.dst_context_id.append_value(r.dst_context_id);
+                c.graph_id.append_option(r.graph_id);
+                c.graph_node_id.append_option(r.graph_node_id);
+                c.rows += 1;
+            }
+            ActivityRecord::Memset(r) => {
+                let c = &mut self.memsets;
+                c.start.append_value(r.start);
+                c.end.append_value(r.end);
+                c.device_id.append_value(r.device_id);
+                c.context_id.append_value(r.context_id);
+                c.stream_id.append_value(r.stream_id);
+                c.correlation_id.append_value(r.correlation_id);
+                c.value.append_value(r.value);
+                c.bytes.append_value(r.bytes);
+                c.memory_kind.append_value(label(r.memory_kind));
+                c.graph_id.append_option(r.graph_id);
+                c.graph_node_id.append_option(r.graph_node_id);
+                c.rows += 1;
+            }
+            ActivityRecord::Driver(api) => {
+                let name = self.api_names.get(record.kind(), api.cbid);
+                self.driver.push(api, &name);
+            }
+            ActivityRecord::Runtime(api) => {
+                let name = self.api_names.get(record.kind(), api.cbid);
+                self.runtime.push(api, &name);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// The number of rows appended to `table` since it was last finished.
+    pub fn rows(&self, table: ActivityTable) -> usize {
+        match table {
+            ActivityTable::Kernel => self.kernels.rows,
+            ActivityTable::Memcpy => self.memcpys.rows,
+            ActivityTable::Memset => self.memsets.rows,
+            ActivityTable::Driver => self.driver.rows,
+            ActivityTable::Runtime => self.runtime.rows,
+        }
+    }
+
+    /// Whether no rows have been appended to any table since they were last
+    /// finished.
+    pub fn is_empty(&self) -> bool {
+        ActivityTable::ALL
+            .into_iter()
+            .all(|table| self.rows(table) == 0)
+    }
+
+    /// Build a batch from the rows appended to `table`, and reset its columns.
+    ///
+    /// Returns `Ok(None)` if no rows have been appended.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from Arrow while building the batch.
+    pub fn finish(&mut self, table: ActivityTable) -> Result<Option<RecordBatch>, ArrowError> {
+        if self.rows(table) == 0 {
+            return Ok(None);
+        }
+
+        let columns = match table {
+            ActivityTable::Kernel => self.kernels.finish(),
+            ActivityTable::Memcpy => self.memcpys.finish(),
+            ActivityTable::Memset => self.memsets.finish(),
+            ActivityTable::Driver => self.driver.finish(),
+            ActivityTable::Runtime => self.runtime.finish(),
+        };
+
+        RecordBatch::try_new(table.schema(), columns).map(Some)
+    }
+
+    /// Build a batch for every table that has rows, and reset all columns.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from Arrow while building the batches.
+    pub fn finish_all(&mut self) -> Result<Vec<(ActivityTable, RecordBatch)>, ArrowError> {
+        let mut batches = Vec::new();
+        for table in ActivityTable::ALL {
+            if let Some(batch) = self.finish(table)? {
+                batches.push((table, batch));
+            }
+        }
+
+        Ok(batches)
+    }
+}
+
+type DictionaryBuilder = StringDictionaryBuilder<Int32Type>;
+
+fn dictionary(name: &str, nullable: bool) -> Field {
+    Field::new_dictionary(name, DataType::Int32, DataType::Utf8, nullable)
+}
+
+#[derive(Default)]
+struct KernelColumns {
+    rows: usize,
+    start: UInt64Builder,
+    end: UInt64Builder,
+    device_id: UInt32Builder,
+    context_id: UInt32Builder,
+    stream_id: UInt32Builder,
+    correlation_id: UInt32Builder,
+    name: DictionaryBuilder,
+    grid_x: Int32Builder,
+    grid_y: Int32Builder,
+    grid_z: Int32Builder,
+    block_x: Int32Builder,
+    block_y: Int32Builder,
+    block_z: Int32Builder,
+    registers_per_thread: UInt16Builder,
+    static_shared_memory: Int32Builder,
+    dynamic_shared_memory: Int32Builder,
+    local_memory_per_thread: UInt32Builder,
+    local_memory_total: UInt64Builder,
+    grid_id: Int64Builder,
+    queued: UInt64Builder,
+    submitted: UInt64Builder,
+    graph_id: UInt32Builder,
+    graph_node_id: UInt64Builder,
+}
+
+impl KernelColumns {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("start", DataType::UInt64, false),
+            Field::new("end", DataType::UInt64, false),
+            Field::new("device_id", DataType::UInt32, false),
+            Field::new("context_id", DataType::UInt32, false),
+            Field::new("stream_id", DataType::UInt32, false),
+            Field::new("correlation_id", DataType::UInt32, false),
+            dictionary("name", true),
+            Field::new("grid_x", DataType::Int32, false),
+            Field::new("grid_y", DataType::Int32, false),
+            Field::new("grid_z", DataType::Int32, false),
+            Field::new("block_x", DataType::Int32, false),
+            Field::new("block_y", DataType::Int32, false),
+            Field::new("block_z", DataType::Int32, false),
+            Field::new("registers_per_thread", DataType::UInt16, false),
+            Field::new("static_shared_memory", DataType::Int32, false),
+            Field::new("dynamic_shared_memory", DataType::Int32, false),
+            Field::new("local_memory_per_thread", DataType::UInt32, false),
+            Field::new("local_memory_total", DataType::UInt64, false),
+            Field::new("grid_id", DataType::Int64, true),
+            Field::new("queued", DataType::UInt64, true),
+            Field::new("submitted", DataType::UInt64, true),
+            Field::new("graph_id", DataType::UInt32, true),
+            Field::new("graph_node_id", DataType::UInt64, true),
+        ]
+    }
+
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        self.rows = 0;
+
+        vec![
+            Arc::new(self.start.finish()),
+            Arc::new(self.end.finish()),
+            Arc::new(self.device_id.finish()),
+            Arc::new(self.context_id.finish()),
+            Arc::new(self.stream_id.finish()),
+            Arc::new(self.correlation_id.finish()),
+            Arc::new(self.name.finish()),
+            Arc::new(self.grid_x.finish()),
+            Arc::new(self.grid_y.finish()),
+            Arc::new(self.grid_z.finish()),
+            Arc::new(self.block_x.finish()),
+            Arc::new(self.block_y.finish()),
+            Arc::new(self.block_z.finish()),
+            Arc::new(self.registers_per_thread.finish()),
+            Arc::new(self.static_shared_memory.finish()),
+            Arc::new(self.dynamic_shared_memory.finish()),
+            Arc::new(self.local_memory_per_thread.finish()),
+            Arc::new(self.local_memory_total.finish()),
+            Arc::new(self.grid_id.finish()),
+            Arc::new(self.queued.finish()),
+            Arc::new(self.submitted.finish()),
+            Arc::new(self.graph_id.finish()),
+            Arc::new(self.graph_node_id.finish()),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct MemcpyColumns {
+    rows: usize,
+    start: UInt64Builder,
+    end: UInt64Builder,
+    device_id: UInt32Builder,
+    context_id: UInt32Builder,
+    stream_id: UInt32Builder,
+    correlation_id: UInt32Builder,
+    bytes: UInt64Builder,
+    copy_kind: DictionaryBuilder,
+    src_kind: DictionaryBuilder,
+    dst_kind: DictionaryBuilder,
+    src_device_id: UInt32Builder,
+    src_context_id: UInt32Builder,
+    dst_device_id: UInt32Builder,
+    dst_context_id: UInt32Builder,
+    graph_id: UInt32Builder,
+    graph_node_id: UInt64Builder,
+}
+
+impl MemcpyColumns {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("start", DataType::UInt64, false),
+            Field::new("end", DataType::UInt64, false),
+            Field::new("device_id", DataType::UInt32, false),
+            Field::new("context_id", DataType::UInt32, false),
+            Field::new("stream_id", DataType::UInt32, false),
+            Field::new("correlation_id", DataType::UInt32, false),
+            Field::new("bytes", DataType::UInt64, false),
+            dictionary("copy_kind", false),
+            dictionary("src_kind", false),
+            dictionary("dst_kind", false),
+            Field::new("src_device_id", DataType::UInt32, true),
+            Field::new("src_context_id", DataType::UInt32, true),
+            Field::new("dst_device_id", DataType::UInt32, true),
+            Field::new("dst_context_id", DataType::UInt32, true),
+            Field::new("graph_id", DataType::UInt32, true),
+            Field::new("graph_node_id", DataType::UInt64, true),
+        ]
+    }
+
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        self.rows = 0;
+
+        vec![
+            Arc::new(self.start.finish()),
+            Arc::new(self.end.finish()),
+            Arc::new(self.device_id.finish()),
+            Arc::new(self.context_id.finish()),
+            Arc::new(self.stream_id.finish()),
+            Arc::new(self.correlation_id.finish()),
+            Arc::new(self.bytes.finish()),
+            Arc::new(self.copy_kind.finish()),
+            Arc::new(self.src_kind.finish()),
+            Arc::new(self.dst_kind.finish()),
+            Arc::new(self.src_device_id.finish()),
+            Arc::new(self.src_context_id.finish()),
+            Arc::new(self.dst_device_id.finish()),
+            Arc::new(self.dst_context_id.finish()),
+            Arc::new(self.graph_id.finish()),
+            Arc::new(self.graph_node_id.finish()),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct MemsetColumns {
+    rows: usize,
+    start: UInt64Builder,
+    end: UInt64Builder,
+    device_id: UInt32Builder,
+    context_id: UInt32Builder,
+    stream_id: UInt32Builder,
+    correlation_id: UInt32Builder,
+    value: UInt32Builder,
+    bytes: UInt64Builder,
+    memory_kind: DictionaryBuilder,
+    graph_id: UInt32Builder,
+    graph_node_id: UInt64Builder,
+}
+
+impl MemsetColumns {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("start", DataType::UInt64, false),
+            Field::new("end", DataType::UInt64, false),
+            Field::new("device_id", DataType::UInt32, false),
+            Field::new("context_id", DataType::UInt32, false),
+            Field::new("stream_id", DataType::UInt32, false),
+            Field::new("correlation_id", DataType::UInt32, false),
+            Field::new("value", DataType::UInt32, false),
+            Field::new("bytes", DataType::UInt64, false),
+            dictionary("memory_kind", false),
+            Field::new("graph_id", DataType::UInt32, true),
+            Field::new("graph_node_id", DataType::UInt64, true),
+        ]
+    }
+
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        self.rows = 0;
+
+        vec![
+            Arc::new(self.start.finish()),
+            Arc::new(self.end.finish()),
+            Arc::new(self.device_id.finish()),
+            Arc::new(self.context_id.finish()),
+            Arc::new(self.stream_id.finish()),
+            Arc::new(self.correlation_id.finish()),
+            Arc::new(self.value.finish()),
+            Arc::new(self.bytes.finish()),
+            Arc::new(self.memory_kind.finish()),
+            Arc::new(self.graph_id.finish()),
+            Arc::new(self.graph_node_id.finish()),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct ApiColumns {
+    rows: usize,
+    start: UInt64Builder,
+    end: UInt64Builder,
+    process_id: UInt32Builder,
+    thread_id: UInt32Builder,
+    correlation_id: UInt32Builder,
+    cbid: UInt32Builder,
+    name: DictionaryBuilder,
+    return_value: UInt32Builder,
+}
+
+impl ApiColumns {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("start", DataType::UInt64, false),
+            Field::new("end", DataType::UInt64, false),
+            Field::new("process_id", DataType::UInt32, false),
+            Field::new("thread_id", DataType::UInt32, false),
+            Field::new("correlation_id", DataType::UInt32, false),
+            Field::new("cbid", DataType::UInt32, false),
+            dictionary("name", false),
+            Field::new("return_value", DataType::UInt32, false),
+        ]
+    }
+
+    fn push(&mut self, api: &ActivityApi, name: &str) {
+        self.start.append_value(api.start);
+        self.end.append_value(api.end);
+        self.process_id.append_value(api.process_id);
+        self.thread_id.append_value(api.thread_id);
+        self.correlation_id.append_value(api.correlation_id);
+        self.cbid.append_value(api.cbid);
+        self.name.append_value(name);
+        self.return_value.append_value(api.return_value);
+        self.rows += 1;
+    }
+
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        self.rows = 0;
+
+        vec![
+            Arc::new(self.start.finish()),
+            Arc::new(self.end.finish()),
+            Arc::new(self.process_id.finish()),
+            Arc::new(self.thread_id.finish()),
+            Arc::new(self.correlation_id.finish()),
+            Arc::new(self.cbid.finish()),
+            Arc::new(self.name.finish()),
+            Arc::new(self.return_value.finish()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::StringArray;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt32Type;
+    use cupti_sys::*;
+
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::testing::{self, api, kernel, memcpy};
+
+    fn peer(correlation_id: u32) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityMemcpyPtoP4 = testing::raw(ActivityKind::Memcpy2);
+        raw.copyKind = CUPTI_ACTIVITY_MEMCPY_KIND_PTOP as u8;
+        raw.correlationId = correlation_id;
+        raw.bytes = 256;
+        raw.srcDeviceId = 1;
+        raw.dstDeviceId = 2;
+        testing::record(&raw)
+    }
+
+    fn memset(correlation_id: u32) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityMemset4 = testing::raw(ActivityKind::Memset);
+        raw.correlationId = correlation_id;
+        raw.bytes = 128;
+        raw.memoryKind = CUPTI_ACTIVITY_MEMORY_KIND_DEVICE as u16;
+        testing::record(&raw)
+    }
+
+    fn builder() -> ActivityBatchBuilder {
+        let mut builder = ActivityBatchBuilder::new();
+        for record in [
+            kernel(1, 100, 200, "vecAdd"),
+            kernel(2, 300, 400, "vecAdd"),
+            memcpy(3, 500, 600, 64),
+            peer(4),
+            memset(5),
+            api(ActivityKind::Driver, 6, 0, 10),
+            api(ActivityKind::Runtime, 7, 0, 10),
+        ] {
+            assert!(builder.push(&record));
+        }
+        builder
+    }
+
+    #[test]
+    fn batches_match_table_schemas() {
+        let mut builder = builder();
+
+        let rows: Vec<_> = ActivityTable::ALL
+            .into_iter()
+            .map(|table| builder.rows(table))
+            .collect();
+        assert_eq!(rows, [2, 2, 1, 1, 1]);
+
+        for table in ActivityTable::ALL {
+            let batch = builder.finish(table).unwrap().unwrap();
+            assert_eq!(batch.schema(), table.schema(), "{}", table.name());
+            assert_eq!(builder.rows(table), 0);
+            assert!(builder.finish(table).unwrap().is_none());
+        }
+        assert!(builder.is_empty());
+    }
+
+    #[test]
+    fn peer_copies_fill_device_columns() {
+        let mut builder = builder();
+        let batch = builder.finish(ActivityTable::Memcpy).unwrap().unwrap();
+
+        let src = batch["src_device_id"].as_primitive::<UInt32Type>();
+        let dst = batch["dst_device_id"].as_primitive::<UInt32Type>();
+        assert_eq!(src.iter().collect::<Vec<_>>(), [None, Some(1)]);
+        assert_eq!(dst.iter().collect::<Vec<_>>(), [None, Some(2)]);
+
+        let kinds = batch["copy_kind"].as_dictionary::<Int32Type>();
+        let kinds = kinds.downcast_dict::<StringArray>().unwrap();
+        assert_eq!(
+            kinds.into_iter().collect::<Vec<_>>(),
+            [Some("Htod"), Some("Ptop")]
+        );
+    }
+
+    #[test]
+    fn kernel_names_are_dictionary_encoded() {
+        let mut builder = builder();
+        let batches = builder.finish_all().unwrap();
+        let (_, batch) = batches
+            .iter()
+            .find(|(table, _)| *table == ActivityTable::Kernel)
+            .unwrap();
+
+        assert_eq!(
+            batch.schema().field_with_name("name").unwrap().data_type(),
+            &DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+        );
+        let names = batch["name"].as_dictionary::<Int32Type>();
+        assert_eq!(names.values().len(), 1);
+        assert_eq!(names.keys().values().to_vec(), [0, 0]);
+        assert_eq!(batches.len(), ActivityTable::ALL.len());
+        assert!(builder.is_empty());
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+
+use super::{ApiNames, DEVICE_PID_BASE, label};
+use crate::activity::{
+    ActivityApi, ActivityMemoryKind, ActivityMemoryOperationType, ActivityObjectId, ActivityRecord,
+};
+use crate::analysis::NvtxRange;
+
+/// Writes activity records as a Chrome Trace Event Format JSON document.
+///
+/// The output can be opened in `ui.perfetto.dev` or `chrome://tracing`. Each
+/// device is shown as a process with one thread per stream, and API calls and
+/// NVTX ranges are shown on the host thread that made them. API calls are
+/// linked to the GPU work they launched with flow events, and device memory
+/// usage is shown as a counter for each device.
+///
+/// Events are written as they are added, so the trace does not need to fit in
+/// memory. [`finish`](Self::finish) must be called to complete the document.
+pub struct ChromeTraceWriter<W: Write> {
+    out: W,
+    first: bool,
+    processes: HashSet<u64>,
+    threads: HashSet<(u64, u64)>,
+    allocated: HashMap<u32, u64>,
+    api_names: ApiNames,
+}
+
+impl<W: Write> ChromeTraceWriter<W> {
+    /// Create a writer and write the start of the document to `out`.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(b"{\"displayTimeUnit\":\"ns\",\"traceEvents\":[\n")?;
+
+        Ok(Self {
+            out,
+            first: true,
+            processes: HashSet::new(),
+            threads: HashSet::new(),
+            allocated: HashMap::new(),
+            api_names: ApiNames::default(),
+        })
+    }
+
+    /// Write a record to the trace.
+    ///
+    /// Returns `Ok(false)` if nothing was written for the record. The supported
+    /// kinds are driver and runtime API calls, kernels, memory copies, memory
+    /// sets, and memory allocations and memory pool operations. Of the memory
+    /// records, only allocations and releases of device memory are written.
+    pub fn write_record(&mut self, record: &ActivityRecord<'_>) -> io::Result<bool> {
+        match record {
+            ActivityRecord::Driver(api) | ActivityRecord::Runtime(api) => {
+                let name = self.api_names.get(record.kind(), api.cbid);
+                self.write_api(&name, api)?;
+            }
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => {
+                let name = match &r.name {
+                    Some(name) => name.to_string_lossy(),
+                    None => "kernel".into(),
+                };
+                let args = format!(
+                    "\"correlation_id\":{},\"grid\":[{},{},{}],\"block\":[{},{},{}],\
+                     \"registers_per_thread\":{},\"static_shared_memory\":{},\
+                     \"dynamic_shared_memory\":{}",
+                    r.correlation_id,
+                    r.grid_x,
+                    r.grid_y,
+                    r.grid_z,
+                    r.block_x,
+                    r.block_y,
+                    r.block_z,
+                    r.registers_per_thread,
+                    r.static_shared_memory,
+                    r.dynamic_shared_memory,
+                );
+                let track = self.device_track(r.device_id, r.stream_id)?;
+                self.write_gpu(
+                    track,
+                    GpuSlice {
+                        name: &name,
+                        category: "kernel",
+                        start: r.start,
+                        end: r.end,
+                        correlation_id: r.correlation_id,
+                        args: &args,
+                    },
+                )?;
+            }
+            ActivityRecord::Memcpy(r) => {
+                let name = format!("Memcpy {}", label(r.copy_kind));
+                let args = format!(
+                    "\"correlation_id\":{},\"bytes\":{},\"src_kind\":\"{}\",\"dst_kind\":\"{}\"",
+                    r.correlation_id,
+                    r.bytes,
+                    label(r.src_kind),
+                    label(r.dst_kind),
+                );
+                let track = self.device_track(r.device_id, r.stream_id)?;
+                self.write_gpu(
+                    track,
+                    GpuSlice {
+                        name: &name,
+                        category: "memcpy",
+                        start: r.start,
+                        end: r.end,
+                        correlation_id: r.correlation_id,
+                        args: &args,
+                    },
+                )?;
+            }
+            ActivityRecord::Memcpy2(r) => {
+                let name = format!("Memcpy {}", label(r.copy_kind));
+                let args = format!(
+                    "\"correlation_id\":{},\"bytes\":{},\"src_device\":{},\"dst_device\":{}",
+                    r.correlation_id, r.bytes, r.src_device_id, r.dst_device_id,
+                );
+                let track = self.device_track(r.device_id, r.stream_id)?;
+                self.write_gpu(
+                    track,
+                    GpuSlice {
+                        name: &name,
+                        category: "memcpy",
+                        start: r.start,
+                        end: r.end,
+                        correlation_id: r.correlation_id,
+                        args: &args,
+                    },
+                )?;
+            }
+            ActivityRecord::Memset(r) => {
+                let args = format!(
+                    "\"correlation_id\":{},\"bytes\":{},\"value\":{},\"memory_kind\":\"{}\"",
+                    r.correlation_id,
+                    r.bytes,
+                    r.value,
+                    label(r.memory_kind),
+                );
+                let track = self.device_track(r.device_id, r.stream_id)?;
+                self.write_gpu(
+                    track,
+                    GpuSlice {
+                        name: "Memset",
+                        category: "memset",
+                        start: r.start,
+                        end: r.end,
+                        correlation_id: r.correlation_id,
+                        args: &args,
+                    },
+                )?;
+            }
+            ActivityRecord::Memory2(r) => {
+                if !matches!(
+                    r.memory_kind,
+                    ActivityMemoryKind::Device | ActivityMemoryKind::DeviceStatic
+                ) {
+                    return Ok(false);
+                }
+
+                let allocated = self.allocated.entry(r.device_id).or_default();
+                match r.memory_operation_type {
+                    ActivityMemoryOperationType::Allocation => *allocated += r.bytes,
+                    ActivityMemoryOperationType::Release => {
+                        *allocated = allocated.saturating_sub(r.bytes)
+                    }
+                    _ => return Ok(false),
+                }
+                let args = format!("\"allocated\":{allocated}");
+
+                let pid = self.device_pid(r.device_id)?;
+                self.write_counter(pid, "Device memory", r.timestamp, &args)?;
+            }
+            ActivityRecord::MemoryPool(r) => {
+                let args = match r.utilized_size {
+                    Some(utilized) => format!("\"size\":{},\"utilized\":{}", r.size, utilized),
+                    None => format!("\"size\":{}", r.size),
+                };
+
+                let pid = self.device_pid(r.device_id)?;
+                self.write_counter(pid, "Memory pool", r.timestamp, &args)?;
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Write an NVTX range to the trace, on the thread that started it.
+    pub fn write_nvtx_range(&mut self, range: &NvtxRange) -> io::Result<()> {
+        let ActivityObjectId::Process {
+            process_id,
+            thread_id,
+        } = range.thread
+        else {
+            return Ok(());
+        };
+
+        let name = match &range.name {
+            Some(name) => name.to_string_lossy(),
+            None => "range".into(),
+        };
+        let domain = match &range.domain {
+            Some(domain) => domain.to_string_lossy(),
+            None => "nvtx".into(),
+        };
+
+        self.event(format_args!(
+            "{{\"ph\":\"X\",\"cat\":\"{}\",\"name\":\"{}\",\"pid\":{},\"tid\":{},\"ts\":{},\"dur\":{}}}",
+            Escape(&domain),
+            Escape(&name),
+            process_id,
+            thread_id,
+            Micros(range.start),
+            Micros(range.duration()),
+        ))
+    }
+
+    /// Write the end of the document and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(b"\n]}\n")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn event(&mut self, event: fmt::Arguments<'_>) -> io::Result<()> {
+        if !self.first {
+            self.out.write_all(b",\n")?;
+        }
+        self.first = false;
+
+        self.out.write_fmt(event)
+    }
+
+    fn device_pid(&mut self, device_id: u32) -> io::Result<u64> {
+        let pid = DEVICE_PID_BASE + u64::from(device_id);
+
+        if self.processes.insert(pid) {
+            self.event(format_args!(
+                "{{\"ph\":\"M\",\"name\":\"process_name\",\"pid\":{pid},\"args\":{{\"name\":\"GPU {device_id}\"}}}}"
+            ))?;
+            self.event(format_args!(
+                "{{\"ph\":\"M\",\"name\":\"process_sort_index\",\"pid\":{pid},\"args\":{{\"sort_index\":{pid}}}}}"
+            ))?;
+        }
+
+        Ok(pid)
+    }
+
+    fn device_track(&mut self, device_id: u32, stream_id: u32) -> io::Result<(u64, u64)> {
+        let pid = self.device_pid(device_id)?;
+        let tid = u64::from(stream_id);
+
+        if self.threads.insert((pid, tid)) {
+            self.event(format_args!(
+                "{{\"ph\":\"M\",\"name\":\"thread_name\",\"pid\":{pid},\"tid\":{tid},\"args\":{{\"name\":\"Stream {stream_id}\"}}}}"
+            ))?;
+        }
+
+        Ok((pid, tid))
+    }
+
+    fn write_api(&mut self, name: &str, api: &ActivityApi) -> io::Result<()> {
+        let flow = if api.correlation_id != 0 {
+            format!(",\"bind_id\":{},\"flow_out\":true", api.correlation_id)
+        } else {
+            String::new()
+        };
+
+        self.event(format_args!(
+            "{{\"ph\":\"X\",\"cat\":\"api\",\"name\":\"{}\",\"pid\":{},\"tid\":{},\"ts\":{},\"dur\":{}{},\
+             \"args\":{{\"correlation_id\":{},\"cbid\":{},\"return_value\":{}}}}}",
+            Escape(name),
+            api.process_id,
+            api.thread_id,
+            Micros(api.start),
+            Micros(api.end.saturating_sub(api.start)),
+            flow,
+            api.correlation_id,
+            api.cbid,
+            api.return_value,
+        ))
+    }
+
+    fn write_gpu(&mut self, (pid, tid): (u64, u64), slice: GpuSlice<'_>) -> io::Result<()> {
+        let GpuSlice {
+            name,
+            category,
+            start,
+            end,
+            correlation_id,
+            args,
+        } = slice;
+        let flow = if correlation_id != 0 {
+            format!(",\"bind_id\":{correlation_id},\"flow_in\":true")
+        } else {
+            String::new()
+        };
+
+        self.event(format_args!(
+            "{{\"ph\":\"X\",\"cat\":\"{}\",\"name\":\"{}\",\"pid\":{},\"tid\":{},\"ts\":{},\"dur\":{}{},\"args\":{{{}}}}}",
+            category,
+            Escape(name),
+            pid,
+            tid,
+            Micros(start),
+            Micros(end.saturating_sub(start)),
+            flow,
+            args,
+        ))
+    }
+
+    fn write_counter(&mut self, pid: u64, name: &str, ts: u64, args: &str) -> io::Result<()> {
+        self.event(format_args!(
+            "{{\"ph\":\"C\",\"name\":\"{}\",\"pid\":{},\"ts\":{},\"args\":{{{}}}}}",
+            Escape(name),
+            pid,
+            Micros(ts),
+            args,
+        ))
+    }
+}
+
+/// A span of GPU work to be written by [`ChromeTraceWriter::write_gpu`].
+struct GpuSlice<'a> {
+    name: &'a str,
+    category: &'a str,
+    start: u64,
+    end: u64,
+    correlation_id: u32,
+    args: &'a str,
+}
+
+/// Formats a timestamp in ns as a decimal number of µs.
+struct Micros(u64);
+
+impl fmt::Display for Micros {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:03}", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+/// Escapes a string for inclusion in a JSON string literal.
+struct Escape<'a>(&'a str);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if u32::from(c) < 0x20 => write!(f, "\\u{:04x}", u32::from(c))?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cupti_sys::*;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::testing::{self, api, kernel, memcpy};
+
+    fn memory(
+        operation: CUpti_ActivityMemoryOperationType,
+        memory_kind: CUpti_ActivityMemoryKind,
+        bytes: u64,
+        timestamp: u64,
+    ) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityMemory4 = testing::raw(ActivityKind::Memory2);
+        raw.memoryOperationType = operation;
+        raw.memoryKind = memory_kind;
+        raw.bytes = bytes;
+        raw.timestamp = timestamp;
+        testing::record(&raw)
+    }
+
+    fn trace(records: &[ActivityRecord<'_>]) -> Vec<Value> {
+        let mut writer = ChromeTraceWriter::new(Vec::new()).unwrap();
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        let json = writer.finish().unwrap();
+
+        let mut document: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(document["displayTimeUnit"], "ns");
+        match document["traceEvents"].take() {
+            Value::Array(events) => events,
+            events => panic!("unexpected traceEvents: {events}"),
+        }
+    }
+
+    #[test]
+    fn writes_metadata_once_per_track() {
+        let mut other_stream = kernel(3, 300, 400, "k");
+        if let ActivityRecord::ConcurrentKernel(kernel) = &mut other_stream {
+            kernel.stream_id = 8;
+        }
+        let events = trace(&[
+            kernel(1, 100, 200, "k"),
+            memcpy(2, 200, 300, 64),
+            other_stream,
+        ]);
+
+        let metadata: Vec<_> = events
+            .iter()
+            .filter(|event| event["ph"] == "M")
+            .map(|event| {
+                (
+                    event["name"].as_str().unwrap(),
+                    event["tid"].as_u64(),
+                    event["args"]["name"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            metadata,
+            [
+                ("process_name", None, Some("GPU 0")),
+                ("process_sort_index", None, None),
+                ("thread_name", Some(7), Some("Stream 7")),
+                ("thread_name", Some(8), Some("Stream 8")),
+            ]
+        );
+        assert!(
+            events
+                .iter()
+                .all(|event| event["pid"].as_u64() == Some(DEVICE_PID_BASE))
+        );
+    }
+
+    #[test]
+    fn links_api_calls_to_gpu_work() {
+        let events = trace(&[
+            api(ActivityKind::Driver, 5, 1000, 1234),
+            kernel(5, 2000, 3500, "vecAdd"),
+        ]);
+
+        let api = &events[0];
+        assert_eq!(api["cat"], "api");
+        assert_eq!(
+            (api["pid"].as_u64(), api["tid"].as_u64()),
+            (Some(100), Some(200))
+        );
+        assert_eq!(api["ts"].as_f64(), Some(1.0));
+        assert_eq!(api["dur"].as_f64(), Some(0.234));
+        assert_eq!(api["bind_id"], 5);
+        assert_eq!(api["flow_out"], true);
+
+        let kernel = events
+            .iter()
+            .find(|event| event["cat"] == "kernel")
+            .unwrap();
+        assert_eq!(kernel["name"], "vecAdd");
+        assert_eq!(kernel["dur"].as_f64(), Some(1.5));
+        assert_eq!(kernel["bind_id"], 5);
+        assert_eq!(kernel["flow_in"], true);
+        assert_eq!(kernel["args"]["grid"], serde_json::json!([1, 1, 1]));
+    }
+
+    #[test]
+    fn tracks_device_memory() {
+        let mut writer = ChromeTraceWriter::new(Vec::new()).unwrap();
+        let records = [
+            (
+                CUPTI_ACTIVITY_MEMORY_OPERATION_TYPE_ALLOCATION,
+                CUPTI_ACTIVITY_MEMORY_KIND_DEVICE,
+                1000,
+            ),
+            (
+                CUPTI_ACTIVITY_MEMORY_OPERATION_TYPE_ALLOCATION,
+                CUPTI_ACTIVITY_MEMORY_KIND_PINNED,
+                4000,
+            ),
+            (
+                CUPTI_ACTIVITY_MEMORY_OPERATION_TYPE_ALLOCATION,
+                CUPTI_ACTIVITY_MEMORY_KIND_DEVICE,
+                500,
+            ),
+            (
+                CUPTI_ACTIVITY_MEMORY_OPERATION_TYPE_RELEASE,
+                CUPTI_ACTIVITY_MEMORY_KIND_DEVICE,
+                300,
+            ),
+        ];
+
+        let written: Vec<_> = records
+            .iter()
+            .enumerate()
+            .map(|(i, &(operation, kind, bytes))| {
+                let record = memory(operation, kind, bytes, i as u64 * 1000);
+                writer.write_record(&record).unwrap()
+            })
+            .collect();
+        assert_eq!(written, [true, false, true, true]);
+
+        let json = writer.finish().unwrap();
+        let document: Value = serde_json::from_slice(&json).unwrap();
+        let counters: Vec<_> = document["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|event| event["ph"] == "C")
+            .map(|event| {
+                assert_eq!(event["name"], "Device memory");
+                (
+                    event["ts"].as_f64().unwrap(),
+                    event["args"]["allocated"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(counters, [(0.0, 1000), (2.0, 1500), (3.0, 1200)]);
+    }
+
+    #[test]
+    fn escapes_names() {
+        let name = "k<\"a\\b\">\n\t\u{1}é";
+        let events = trace(&[kernel(1, 0, 10, name)]);
+
+        let kernel = events
+            .iter()
+            .find(|event| event["cat"] == "kernel")
+            .unwrap();
+        assert_eq!(kernel["name"], name);
+        assert_eq!(Escape("\"\\\r\u{1f}x").to_string(), "\\\"\\\\\\r\\u001fx");
+    }
+
+    #[test]
+    fn formats_micros() {
+        assert_eq!(Micros(1234).to_string(), "1.234");
+        assert_eq!(Micros(5).to_string(), "0.005");
+        assert_eq!(Micros(2_000_000).to_string(), "2000.000");
+    }
+}
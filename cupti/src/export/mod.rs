@@ -0,0 +1,98 @@
+//! Exporters that write decoded activity records to other trace formats.
+//!
+//! Like [`analysis`](crate::analysis), the exporters operate on
+//! [`ActivityRecord`]s that have already been decoded from activity buffers.
+//!
+//! [`ActivityRecord`]: crate::activity::ActivityRecord
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+use cupti_sys::*;
+
+use crate::activity::ActivityKind;
+use crate::callbacks::CallbackDomain;
+use crate::*;
+
+#[cfg(feature = "arrow")]
+mod arrow;
+mod chrome;
+mod otel;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod perfetto;
+mod prometheus;
+mod protobuf;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "arrow")]
+pub use self::arrow::{ActivityBatchBuilder, ActivityTable};
+pub use self::chrome::ChromeTraceWriter;
+pub use self::otel::{
+    AttributeValue, InMemorySpanExporter, OtelTracer, OtlpHttpExporter, Span, SpanExporter,
+    TraceContext,
+};
+#[cfg(feature = "parquet")]
+pub use self::parquet::ParquetWriter;
+pub use self::perfetto::PerfettoTraceWriter;
+pub use self::prometheus::{MetricsServer, PrometheusMetrics};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteWriter;
+
+/// The process ID used for the first device.
+///
+/// Devices are given process IDs well above the range used for host processes
+/// so that the two never collide.
+const DEVICE_PID_BASE: u64 = 0x4000_0000;
+
+/// Resolves the names of driver and runtime API calls from their callback IDs.
+///
+/// Driver API names are known statically. Runtime API names are looked up
+/// with `cuptiGetCallbackName` and cached, falling back to a name containing
+/// the callback ID if CUPTI does not know it.
+#[derive(Debug, Default)]
+pub(crate) struct ApiNames {
+    runtime: HashMap<u32, Cow<'static, str>>,
+}
+
+impl ApiNames {
+    pub(crate) fn get(&mut self, kind: ActivityKind, cbid: u32) -> Cow<'static, str> {
+        use c_enum::CEnum;
+
+        match kind {
+            ActivityKind::Driver => match DriverApiTraceCbid::from(cbid).variant_label() {
+                Some(name) => Cow::Borrowed(name),
+                None => Cow::Owned(format!("driver cbid {cbid}")),
+            },
+            _ => self
+                .runtime
+                .entry(cbid)
+                .or_insert_with(|| match runtime_api_name(cbid) {
+                    Some(name) => Cow::Owned(name.to_string_lossy().into_owned()),
+                    None => Cow::Owned(format!("runtime cbid {cbid}")),
+                })
+                .clone(),
+        }
+    }
+}
+
+fn runtime_api_name(cbid: u32) -> Option<&'static CStr> {
+    let mut name = std::ptr::null();
+    let code = unsafe { cuptiGetCallbackName(CallbackDomain::RuntimeApi.into(), cbid, &mut name) };
+
+    if Error::result(code).is_err() || name.is_null() {
+        return None;
+    }
+
+    Some(unsafe { CStr::from_ptr(name) })
+}
+
+fn label<T>(value: T) -> &'static str
+where
+    T: c_enum::CEnum,
+    T::Inner: PartialEq,
+{
+    value.variant_label().unwrap_or("Unknown")
+}
@@ -0,0 +1,869 @@
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::protobuf::Message;
+use super::{ApiNames, label};
+use crate::activity::{ActivityKind, ActivityRecord, ClockDomain, ExternalCorrelationId};
+use crate::analysis::{Correlator, GpuActivity, Launch};
+
+/// The default time a launch is held for records that arrive late.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(1);
+
+/// The default port for OTLP/HTTP.
+const DEFAULT_OTLP_PORT: u16 = 4318;
+
+/// The default path for trace exports over OTLP/HTTP.
+const DEFAULT_OTLP_PATH: &str = "/v1/traces";
+
+/// The W3C trace context of a span that GPU work should be attached to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TraceContext {
+    /// The ID of the trace.
+    pub trace_id: u128,
+    /// The ID of the parent span within the trace.
+    pub span_id: u64,
+}
+
+impl TraceContext {
+    /// Create a new trace context.
+    pub const fn new(trace_id: u128, span_id: u64) -> Self {
+        Self { trace_id, span_id }
+    }
+
+    /// Parse a W3C `traceparent` header value.
+    ///
+    /// Returns `None` if `value` is not a valid version 00 `traceparent`, or
+    /// if either ID is zero.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+
+        if version != "00" || parts.next().is_some() {
+            return None;
+        }
+        if trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let span_id = u64::from_str_radix(span_id, 16).ok()?;
+        u8::from_str_radix(flags, 16).ok()?;
+
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some(Self { trace_id, span_id })
+    }
+
+    /// Format this context as a W3C `traceparent` header value, with the
+    /// sampled flag set.
+    pub fn to_traceparent(&self) -> String {
+        format!("00-{:032x}-{:016x}-01", self.trace_id, self.span_id)
+    }
+}
+
+/// The value of a span attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeValue {
+    /// A string value.
+    String(String),
+    /// A signed integer value.
+    Int(i64),
+    /// A boolean value.
+    Bool(bool),
+    /// A floating point value.
+    Double(f64),
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(value) => f.write_str(value),
+            Self::Int(value) => value.fmt(f),
+            Self::Bool(value) => value.fmt(f),
+            Self::Double(value) => value.fmt(f),
+        }
+    }
+}
+
+/// A completed span, ready to be exported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    /// The ID of the trace the span belongs to.
+    pub trace_id: u128,
+    /// The ID of the span.
+    pub span_id: u64,
+    /// The ID of the parent span, or `None` for a root span.
+    pub parent_span_id: Option<u64>,
+    /// The name of the span.
+    ///
+    /// This is the name of the API function for API spans, and the kernel
+    /// name or kind of work for GPU spans.
+    pub name: String,
+    /// The time the span started.
+    pub start: SystemTime,
+    /// The time the span ended.
+    pub end: SystemTime,
+    /// The attributes of the span.
+    pub attributes: Vec<(&'static str, AttributeValue)>,
+}
+
+impl Span {
+    /// The value of the attribute named `key`, if it is set.
+    pub fn attribute(&self, key: &str) -> Option<&AttributeValue> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+}
+
+/// A destination for spans produced by an [`OtelTracer`].
+pub trait SpanExporter {
+    /// Export a batch of spans.
+    ///
+    /// Spans are exported in batches as launches become ready. Child spans are
+    /// always exported in the same batch as their parent API span.
+    fn export(&mut self, spans: Vec<Span>) -> io::Result<()>;
+}
+
+impl<E: SpanExporter + ?Sized> SpanExporter for &mut E {
+    fn export(&mut self, spans: Vec<Span>) -> io::Result<()> {
+        (**self).export(spans)
+    }
+}
+
+impl<E: SpanExporter + ?Sized> SpanExporter for Box<E> {
+    fn export(&mut self, spans: Vec<Span>) -> io::Result<()> {
+        (**self).export(spans)
+    }
+}
+
+/// A [`SpanExporter`] that keeps spans in memory.
+///
+/// Clones of an exporter share the same spans, so a clone can be kept to
+/// inspect the spans after the original has been given to an [`OtelTracer`].
+#[derive(Clone, Debug, Default)]
+pub struct InMemorySpanExporter {
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+impl InMemorySpanExporter {
+    /// Create an empty exporter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A copy of all the spans that have been exported.
+    pub fn spans(&self) -> Vec<Span> {
+        self.lock().clone()
+    }
+
+    /// Remove and return all the spans that have been exported.
+    pub fn take(&self) -> Vec<Span> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Span>> {
+        // The lock is never held across a panic point, but recover anyway.
+        self.spans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SpanExporter for InMemorySpanExporter {
+    fn export(&mut self, spans: Vec<Span>) -> io::Result<()> {
+        self.lock().extend(spans);
+        Ok(())
+    }
+}
+
+/// A [`SpanExporter`] that sends spans to an OTLP/HTTP collector.
+///
+/// Spans are encoded as a binary protobuf `ExportTraceServiceRequest` and
+/// posted to the collector, with one request per batch. Only plain `http://`
+/// endpoints are supported, so a collector or agent running alongside the
+/// process should be used to forward spans elsewhere.
+#[derive(Clone, Debug)]
+pub struct OtlpHttpExporter {
+    host: String,
+    port: u16,
+    path: String,
+    timeout: Duration,
+    service_name: String,
+}
+
+impl OtlpHttpExporter {
+    /// Create an exporter that posts spans to `endpoint`.
+    ///
+    /// `endpoint` is an `http://host[:port][/path]` URL. The port defaults to
+    /// 4318 and the path to `/v1/traces`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::InvalidInput`] error if `endpoint` is not
+    /// a valid `http://` URL.
+    pub fn new(endpoint: &str) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_owned());
+
+        let rest = endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| invalid("OTLP endpoint must be an http:// URL"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                let port = port
+                    .parse()
+                    .map_err(|_| invalid("OTLP endpoint has an invalid port"))?;
+                (host, port)
+            }
+            _ => (authority, DEFAULT_OTLP_PORT),
+        };
+
+        if host.is_empty() {
+            return Err(invalid("OTLP endpoint has no host"));
+        }
+
+        let path = match path {
+            "" | "/" => DEFAULT_OTLP_PATH,
+            path => path,
+        };
+
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+            timeout: Duration::from_secs(10),
+            service_name: "cupti".to_owned(),
+        })
+    }
+
+    /// Set the `service.name` resource attribute. The default is `cupti`.
+    pub fn service_name(mut self, name: impl Into<String>) -> Self {
+        self.service_name = name.into();
+        self
+    }
+
+    /// Set the timeout for connecting to the collector and for each read and
+    /// write. The default is 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn encode(&self, spans: &[Span]) -> Message {
+        let mut scope = Message::new();
+        scope
+            .string(field::SCOPE_NAME, env!("CARGO_PKG_NAME"))
+            .string(field::SCOPE_VERSION, env!("CARGO_PKG_VERSION"));
+
+        let mut scope_spans = Message::new();
+        scope_spans.message(field::SCOPE_SPANS_SCOPE, &scope);
+        for span in spans {
+            scope_spans.message(field::SCOPE_SPANS_SPANS, &encode_span(span));
+        }
+
+        let mut resource = Message::new();
+        resource.message(
+            field::RESOURCE_ATTRIBUTES,
+            &encode_attribute(
+                "service.name",
+                &AttributeValue::String(self.service_name.clone()),
+            ),
+        );
+
+        let mut resource_spans = Message::new();
+        resource_spans
+            .message(field::RESOURCE_SPANS_RESOURCE, &resource)
+            .message(field::RESOURCE_SPANS_SCOPE_SPANS, &scope_spans);
+
+        let mut request = Message::new();
+        request.message(field::REQUEST_RESOURCE_SPANS, &resource_spans);
+        request
+    }
+
+    fn post(&self, body: &[u8]) -> io::Result<()> {
+        use std::net::ToSocketAddrs;
+
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        let mut last_error = None;
+        let mut stream = None;
+        for addr in (host, self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let stream = match (stream, last_error) {
+            (Some(stream), _) => stream,
+            (None, Some(e)) => return Err(e),
+            (None, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "OTLP endpoint host did not resolve to any address",
+                ));
+            }
+        };
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut writer = io::BufWriter::new(&stream);
+        write!(
+            writer,
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/x-protobuf\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            self.port,
+            body.len(),
+        )?;
+        writer.write_all(body)?;
+        writer.flush()?;
+        drop(writer);
+
+        let mut status = String::new();
+        BufReader::new(&stream).read_line(&mut status)?;
+
+        let code = status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok());
+        match code {
+            Some(200..=299) => Ok(()),
+            Some(_) => Err(io::Error::other(format!(
+                "OTLP endpoint returned `{}`",
+                status.trim_end()
+            ))),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "OTLP endpoint returned an invalid HTTP response",
+            )),
+        }
+    }
+}
+
+impl SpanExporter for OtlpHttpExporter {
+    fn export(&mut self, spans: Vec<Span>) -> io::Result<()> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        self.post(self.encode(&spans).as_bytes())
+    }
+}
+
+/// Builds OpenTelemetry spans from activity records.
+///
+/// Each driver or runtime API call becomes a span, with a child span for each
+/// kernel, memory copy, memory set and allocation that it launched. API calls
+/// and GPU work are joined by correlation ID using a [`Correlator`].
+///
+/// If an [`ActivityExternalCorrelation`] record was seen for an API call, the
+/// API span is attached to the trace context registered for that external ID
+/// with [`set_context`](Self::set_context). This allows GPU time to show up
+/// inside the span of the request that caused it: push the request's ID with
+/// [`external_correlation`] while handling it, and register the request's
+/// trace context under the same ID. If no context was registered for an
+/// external ID, all of its spans are placed in a single trace of their own.
+///
+/// Activity records are timestamped with CUPTI's own clock by default, which
+/// has to be mapped to the system clock for the spans to line up with those
+/// from other sources. Either set a [`ClockDomain`] with
+/// [`clock_domain`](Self::clock_domain) or register
+/// [`realtime_timestamp`] as the timestamp clock.
+///
+/// [`ActivityExternalCorrelation`]: crate::activity::ActivityExternalCorrelation
+/// [`external_correlation`]: crate::activity::external_correlation
+/// [`realtime_timestamp`]: crate::activity::realtime_timestamp
+pub struct OtelTracer<E: SpanExporter> {
+    exporter: E,
+    correlator: Correlator,
+    clock: ClockDomain,
+    /// The external ID of each correlation ID, and the latest record time
+    /// when it was pushed.
+    external: HashMap<u32, (ExternalCorrelationId, u64)>,
+    contexts: HashMap<ExternalCorrelationId, TraceContext>,
+    ids: IdGenerator,
+    api_names: ApiNames,
+}
+
+impl<E: SpanExporter> OtelTracer<E> {
+    /// Create a tracer that exports spans to `exporter`.
+    pub fn new(exporter: E) -> Self {
+        Self {
+            exporter,
+            correlator: Correlator::new(DEFAULT_WINDOW),
+            clock: ClockDomain::from_offset(0),
+            external: HashMap::new(),
+            contexts: HashMap::new(),
+            ids: IdGenerator::new(),
+            api_names: ApiNames::default(),
+        }
+    }
+
+    /// Hold each launch for `window` of record time after its last record
+    /// before exporting it. The default is 1 second.
+    ///
+    /// This must be called before any records are pushed.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.correlator = Correlator::new(window);
+        self
+    }
+
+    /// Use `clock` to convert record timestamps to span times.
+    ///
+    /// By default record timestamps are assumed to already be nanoseconds
+    /// since the Unix epoch.
+    pub fn clock_domain(mut self, clock: ClockDomain) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Attach API calls made under the external correlation ID `id` to the
+    /// span described by `context`.
+    pub fn set_context(&mut self, id: ExternalCorrelationId, context: TraceContext) {
+        self.contexts.insert(id, context);
+    }
+
+    /// Remove the trace context registered for `id`, returning it.
+    ///
+    /// Contexts are not removed automatically since an external ID may be
+    /// used for many API calls, so this should be called once records for all
+    /// of the API calls made under `id` have been pushed.
+    pub fn remove_context(&mut self, id: ExternalCorrelationId) -> Option<TraceContext> {
+        self.contexts.remove(&id)
+    }
+
+    /// Get a reference to the exporter.
+    pub fn exporter(&self) -> &E {
+        &self.exporter
+    }
+
+    /// Add a record to the tracer.
+    ///
+    /// Returns `false` if the record is not used to build spans, in which case
+    /// it is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        if let ActivityRecord::ExternalCorrelation(r) = record {
+            if r.correlation_id == 0 {
+                return false;
+            }
+
+            // Prefer an external ID with a registered context when several
+            // kinds are pushed at once.
+            let id = r.external();
+            match self.external.get(&r.correlation_id) {
+                Some((existing, _)) if self.contexts.contains_key(existing) => (),
+                _ => {
+                    self.external
+                        .insert(r.correlation_id, (id, self.correlator.latest()));
+                }
+            }
+            return true;
+        }
+
+        self.correlator.push(record)
+    }
+
+    /// Export spans for launches that are ready, returning the number of
+    /// spans that were exported.
+    ///
+    /// External correlation records that have not matched a launch within
+    /// the window are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the exporter. The spans are dropped in that
+    /// case.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        let launches = self.correlator.drain_ready();
+        let result = self.export(launches);
+
+        let cutoff = self.correlator.cutoff();
+        let correlator = &self.correlator;
+        self.external.retain(|&correlation_id, &mut (_, seen)| {
+            seen >= cutoff || correlator.is_pending(correlation_id)
+        });
+
+        result
+    }
+
+    /// Export spans for all pending launches and return the exporter.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the exporter.
+    pub fn finish(mut self) -> io::Result<E> {
+        let launches = self.correlator.finish();
+        self.export(launches)?;
+        Ok(self.exporter)
+    }
+
+    fn export(&mut self, launches: Vec<Launch>) -> io::Result<usize> {
+        let mut spans = Vec::new();
+        for launch in launches {
+            self.build_spans(launch, &mut spans);
+        }
+
+        let count = spans.len();
+        if count > 0 {
+            self.exporter.export(spans)?;
+        }
+
+        Ok(count)
+    }
+
+    fn build_spans(&mut self, launch: Launch, spans: &mut Vec<Span>) {
+        let external = self
+            .external
+            .remove(&launch.correlation_id)
+            .map(|(id, _)| id);
+        let context = external.and_then(|id| self.contexts.get(&id).copied());
+
+        let trace_id = match (context, external) {
+            (Some(context), _) => context.trace_id,
+            (None, Some(id)) => self.ids.trace_id_for(id),
+            (None, None) => self.ids.trace_id(),
+        };
+        let mut parent = context.map(|context| context.span_id);
+
+        if let Some(api) = &launch.api {
+            let record = &api.record;
+            let span_id = self.ids.span_id();
+
+            let mut attributes = vec![
+                (
+                    "cuda.api",
+                    AttributeValue::String(match api.kind {
+                        ActivityKind::Driver => "driver".into(),
+                        _ => "runtime".into(),
+                    }),
+                ),
+                ("cuda.cbid", AttributeValue::Int(record.cbid.into())),
+                (
+                    "cuda.correlation_id",
+                    AttributeValue::Int(launch.correlation_id.into()),
+                ),
+                (
+                    "cuda.return_value",
+                    AttributeValue::Int(record.return_value.into()),
+                ),
+                ("process.pid", AttributeValue::Int(record.process_id.into())),
+                ("thread.id", AttributeValue::Int(record.thread_id.into())),
+            ];
+            if let Some(id) = external {
+                attributes.push((
+                    "cuda.external_kind",
+                    AttributeValue::String(label(id.kind).into()),
+                ));
+                attributes.push(("cuda.external_id", AttributeValue::Int(id.id as i64)));
+            }
+            if let Some(delay) = launch.queue_delay() {
+                attributes.push(("cuda.queue_delay_ns", AttributeValue::Int(delay as i64)));
+            }
+
+            spans.push(Span {
+                trace_id,
+                span_id,
+                parent_span_id: parent,
+                name: self.api_names.get(api.kind, record.cbid).into_owned(),
+                start: self.clock.to_system_time(record.start),
+                end: self.clock.to_system_time(record.end.max(record.start)),
+                attributes,
+            });
+
+            parent = Some(span_id);
+        }
+
+        for gpu in &launch.gpu {
+            spans.push(Span {
+                trace_id,
+                span_id: self.ids.span_id(),
+                parent_span_id: parent,
+                name: gpu_span_name(gpu),
+                start: self.clock.to_system_time(gpu.start),
+                end: self.clock.to_system_time(gpu.end.max(gpu.start)),
+                attributes: gpu_attributes(gpu, launch.correlation_id),
+            });
+        }
+    }
+}
+
+fn gpu_span_name(gpu: &GpuActivity) -> String {
+    match (&gpu.name, gpu.kind) {
+        (Some(name), ActivityKind::Kernel | ActivityKind::ConcurrentKernel) => {
+            name.to_string_lossy().into_owned()
+        }
+        (_, ActivityKind::Kernel | ActivityKind::ConcurrentKernel) => "kernel".to_owned(),
+        (_, ActivityKind::Memcpy) => "Memcpy".to_owned(),
+        (_, ActivityKind::Memset) => "Memset".to_owned(),
+        (_, ActivityKind::Memory2) => "Memory allocation".to_owned(),
+        (_, kind) => label(kind).to_owned(),
+    }
+}
+
+fn gpu_attributes(gpu: &GpuActivity, correlation_id: u32) -> Vec<(&'static str, AttributeValue)> {
+    let mut attributes = vec![
+        (
+            "cuda.activity_kind",
+            AttributeValue::String(label(gpu.kind).into()),
+        ),
+        (
+            "cuda.correlation_id",
+            AttributeValue::Int(correlation_id.into()),
+        ),
+        ("cuda.device_id", AttributeValue::Int(gpu.device_id.into())),
+        (
+            "cuda.context_id",
+            AttributeValue::Int(gpu.context_id.into()),
+        ),
+        ("cuda.stream_id", AttributeValue::Int(gpu.stream_id.into())),
+    ];
+    if let Some(bytes) = gpu.bytes {
+        attributes.push(("cuda.bytes", AttributeValue::Int(bytes as i64)));
+    }
+    if gpu.kind == ActivityKind::Memory2
+        && let Some(name) = &gpu.name
+    {
+        attributes.push((
+            "cuda.name",
+            AttributeValue::String(name.to_string_lossy().into_owned()),
+        ));
+    }
+
+    attributes
+}
+
+/// Generates trace and span IDs.
+///
+/// IDs are generated with splitmix64 from a per-process random seed, which is
+/// enough to make collisions with other processes unlikely.
+struct IdGenerator {
+    seed: u64,
+    state: u64,
+}
+
+impl IdGenerator {
+    fn new() -> Self {
+        let seed = RandomState::new().hash_one(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+
+        Self { seed, state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    fn span_id(&mut self) -> u64 {
+        loop {
+            let id = self.next();
+            if id != 0 {
+                return id;
+            }
+        }
+    }
+
+    fn trace_id(&mut self) -> u128 {
+        (u128::from(self.span_id()) << 64) | u128::from(self.next())
+    }
+
+    /// A trace ID that is the same for every launch made under `id`.
+    fn trace_id_for(&self, id: ExternalCorrelationId) -> u128 {
+        let hi = mix(self.seed ^ u64::from(u32::from(id.kind)));
+        let lo = mix(hi ^ id.id);
+
+        (u128::from(hi | 1) << 64) | u128::from(lo)
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn encode_span(span: &Span) -> Message {
+    let mut message = Message::new();
+    message
+        .bytes(field::SPAN_TRACE_ID, &span.trace_id.to_be_bytes())
+        .bytes(field::SPAN_SPAN_ID, &span.span_id.to_be_bytes());
+    if let Some(parent) = span.parent_span_id {
+        message.bytes(field::SPAN_PARENT_SPAN_ID, &parent.to_be_bytes());
+    }
+    message
+        .string(field::SPAN_NAME, &span.name)
+        .uint(field::SPAN_KIND, SPAN_KIND_INTERNAL)
+        .fixed64(field::SPAN_START_TIME, unix_nanos(span.start))
+        .fixed64(field::SPAN_END_TIME, unix_nanos(span.end));
+    for (key, value) in &span.attributes {
+        message.message(field::SPAN_ATTRIBUTES, &encode_attribute(key, value));
+    }
+
+    message
+}
+
+fn encode_attribute(key: &str, value: &AttributeValue) -> Message {
+    let mut any = Message::new();
+    match value {
+        AttributeValue::String(value) => any.string(field::ANY_STRING, value),
+        AttributeValue::Int(value) => any.int(field::ANY_INT, *value),
+        AttributeValue::Bool(value) => any.bool(field::ANY_BOOL, *value),
+        AttributeValue::Double(value) => any.double(field::ANY_DOUBLE, *value),
+    };
+
+    let mut message = Message::new();
+    message
+        .string(field::KEY_VALUE_KEY, key)
+        .message(field::KEY_VALUE_VALUE, &any);
+    message
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos().try_into().unwrap_or(u64::MAX))
+        .unwrap_or(0)
+}
+
+const SPAN_KIND_INTERNAL: u64 = 1;
+
+// Field numbers from opentelemetry/proto/collector/trace/v1/trace_service.proto
+// and the messages it references.
+mod field {
+    pub const REQUEST_RESOURCE_SPANS: u32 = 1;
+
+    pub const RESOURCE_SPANS_RESOURCE: u32 = 1;
+    pub const RESOURCE_SPANS_SCOPE_SPANS: u32 = 2;
+    pub const RESOURCE_ATTRIBUTES: u32 = 1;
+
+    pub const SCOPE_SPANS_SCOPE: u32 = 1;
+    pub const SCOPE_SPANS_SPANS: u32 = 2;
+    pub const SCOPE_NAME: u32 = 1;
+    pub const SCOPE_VERSION: u32 = 2;
+
+    pub const SPAN_TRACE_ID: u32 = 1;
+    pub const SPAN_SPAN_ID: u32 = 2;
+    pub const SPAN_PARENT_SPAN_ID: u32 = 4;
+    pub const SPAN_NAME: u32 = 5;
+    pub const SPAN_KIND: u32 = 6;
+    pub const SPAN_START_TIME: u32 = 7;
+    pub const SPAN_END_TIME: u32 = 8;
+    pub const SPAN_ATTRIBUTES: u32 = 9;
+
+    pub const KEY_VALUE_KEY: u32 = 1;
+    pub const KEY_VALUE_VALUE: u32 = 2;
+
+    pub const ANY_STRING: u32 = 1;
+    pub const ANY_BOOL: u32 = 2;
+    pub const ANY_INT: u32 = 3;
+    pub const ANY_DOUBLE: u32 = 4;
+}
+
+#[cfg(test)]
+mod tests {
+    use cupti_sys::*;
+
+    use super::*;
+    use crate::activity::ExternalCorrelationKind;
+    use crate::testing::{self, api, kernel};
+
+    const EXTERNAL: ExternalCorrelationId =
+        ExternalCorrelationId::new(ExternalCorrelationKind::Custom0, 42);
+
+    fn external(correlation_id: u32, id: ExternalCorrelationId) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityExternalCorrelation =
+            testing::raw(ActivityKind::ExternalCorrelation);
+        raw.externalKind = id.kind.into();
+        raw.externalId = id.id;
+        raw.correlationId = correlation_id;
+        testing::record(&raw)
+    }
+
+    fn trace(tracer: &mut OtelTracer<InMemorySpanExporter>, correlation_id: u32) {
+        assert!(tracer.push(&external(correlation_id, EXTERNAL)));
+        assert!(tracer.push(&api(ActivityKind::Runtime, correlation_id, 100, 200)));
+        assert!(tracer.push(&kernel(correlation_id, 300, 400, "vecAdd")));
+    }
+
+    #[test]
+    fn kernel_spans_are_children_of_api_spans() {
+        let mut tracer = OtelTracer::new(InMemorySpanExporter::new());
+        trace(&mut tracer, 1);
+
+        let spans = tracer.finish().unwrap().take();
+        assert_eq!(spans.len(), 2);
+        let (api, gpu) = (&spans[0], &spans[1]);
+
+        assert_eq!(api.parent_span_id, None);
+        assert_eq!(gpu.parent_span_id, Some(api.span_id));
+        assert_eq!(gpu.trace_id, api.trace_id);
+        assert_eq!(gpu.name, "vecAdd");
+        assert_eq!(
+            api.attribute("cuda.external_id"),
+            Some(&AttributeValue::Int(42))
+        );
+        assert_eq!(api.start, UNIX_EPOCH + Duration::from_nanos(100));
+        assert_eq!(gpu.end, UNIX_EPOCH + Duration::from_nanos(400));
+    }
+
+    #[test]
+    fn spans_use_registered_context() {
+        let context = TraceContext::new(0x0123_4567_89ab_cdef, 0xfeed);
+
+        let mut tracer = OtelTracer::new(InMemorySpanExporter::new());
+        tracer.set_context(EXTERNAL, context);
+        trace(&mut tracer, 1);
+
+        let spans = tracer.finish().unwrap().take();
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|span| span.trace_id == context.trace_id));
+        assert_eq!(spans[0].parent_span_id, Some(context.span_id));
+        assert_eq!(spans[1].parent_span_id, Some(spans[0].span_id));
+    }
+
+    #[test]
+    fn launches_with_the_same_external_id_share_a_trace() {
+        let mut tracer = OtelTracer::new(InMemorySpanExporter::new());
+        trace(&mut tracer, 1);
+        trace(&mut tracer, 2);
+
+        let spans = tracer.finish().unwrap().take();
+        assert_eq!(spans.len(), 4);
+        assert!(spans.iter().all(|span| span.trace_id == spans[0].trace_id));
+        assert_eq!(spans[2].parent_span_id, None);
+    }
+
+    #[test]
+    fn unmatched_external_correlations_expire() {
+        let mut tracer =
+            OtelTracer::new(InMemorySpanExporter::new()).window(Duration::from_nanos(1_000));
+        assert!(tracer.push(&external(1, EXTERNAL)));
+        assert!(tracer.push(&api(ActivityKind::Runtime, 2, 100, 200)));
+        tracer.flush().unwrap();
+        assert_eq!(tracer.external.len(), 1);
+
+        assert!(tracer.push(&api(ActivityKind::Runtime, 3, 5_000, 5_100)));
+        assert_eq!(tracer.flush().unwrap(), 1);
+        assert!(tracer.external.is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::Result;
+use parquet::file::properties::WriterProperties;
+
+use super::{ActivityBatchBuilder, ActivityTable};
+use crate::activity::ActivityRecord;
+
+/// The default number of rows in each batch written to a file.
+const DEFAULT_BATCH_SIZE: usize = 64 * 1024;
+
+/// Writes activity records to a directory of Parquet files.
+///
+/// Each [`ActivityTable`] is written to its own file in the directory, named
+/// after [`ActivityTable::name`] (`kernel.parquet`, `memcpy.parquet` and so on)
+/// and using the table's schema. Files are only created once a record for that
+/// table has been written.
+///
+/// Records are collected with an [`ActivityBatchBuilder`] and written out
+/// whenever a table reaches the batch size, so memory use stays bounded no
+/// matter how many records are written. [`finish`](Self::finish) must be
+/// called to write the remaining rows and the file footers, without which the
+/// files cannot be read.
+pub struct ParquetWriter {
+    dir: PathBuf,
+    batch_size: usize,
+    properties: WriterProperties,
+    batches: ActivityBatchBuilder,
+    writers: HashMap<ActivityTable, ArrowWriter<File>>,
+}
+
+impl ParquetWriter {
+    /// Create a writer for the directory `dir`, creating it if it does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory could not be created.
+    pub fn create<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            batch_size: DEFAULT_BATCH_SIZE,
+            properties: WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+            batches: ActivityBatchBuilder::new(),
+            writers: HashMap::new(),
+        })
+    }
+
+    /// Write a batch once a table has `size` rows. The default is 65536.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Set the properties used to write the files. The default uses Snappy
+    /// compression and otherwise the defaults of the `parquet` crate.
+    pub fn properties(mut self, properties: WriterProperties) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// Write a record to the file for its table.
+    ///
+    /// Returns `Ok(false)` if the kind of record is not supported, in which
+    /// case nothing is written.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from creating the file or writing a batch to it.
+    pub fn write_record(&mut self, record: &ActivityRecord<'_>) -> Result<bool> {
+        if !self.batches.push(record) {
+            return Ok(false);
+        }
+
+        for table in ActivityTable::ALL {
+            if self.batches.rows(table) >= self.batch_size {
+                self.write_batch(table)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Write the remaining rows, close all of the files, and return the paths
+    /// of the files that were written.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from writing the remaining rows or the file footers.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>> {
+        for table in ActivityTable::ALL {
+            self.write_batch(table)?;
+        }
+
+        let mut paths = Vec::new();
+        for table in ActivityTable::ALL {
+            if let Some(writer) = self.writers.remove(&table) {
+                writer.close()?;
+                paths.push(self.dir.join(file_name(table)));
+            }
+        }
+
+        Ok(paths)
+    }
+
+    fn write_batch(&mut self, table: ActivityTable) -> Result<()> {
+        let Some(batch) = self.batches.finish(table)? else {
+            return Ok(());
+        };
+
+        let writer = match self.writers.entry(table) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let file = File::create(self.dir.join(file_name(table)))?;
+                let writer =
+                    ArrowWriter::try_new(file, table.schema(), Some(self.properties.clone()))?;
+                entry.insert(writer)
+            }
+        };
+
+        writer.write(&batch)
+    }
+}
+
+fn file_name(table: ActivityTable) -> String {
+    format!("{}.parquet", table.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+    use crate::testing::{kernel, memcpy};
+
+    #[test]
+    fn round_trips_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ParquetWriter::create(dir.path().join("trace"))
+            .unwrap()
+            .batch_size(2);
+
+        for id in 0..5 {
+            let start = u64::from(id) * 100;
+            assert!(
+                writer
+                    .write_record(&kernel(id, start, start + 50, "k"))
+                    .unwrap()
+            );
+        }
+        assert!(writer.write_record(&memcpy(9, 0, 10, 64)).unwrap());
+        let paths = writer.finish().unwrap();
+        assert_eq!(
+            paths,
+            [
+                dir.path().join("trace/kernel.parquet"),
+                dir.path().join("trace/memcpy.parquet"),
+            ]
+        );
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&paths[0]).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+        assert_eq!(batches[0].schema(), ActivityTable::Kernel.schema());
+    }
+}
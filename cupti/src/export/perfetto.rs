@@ -0,0 +1,1063 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, Write};
+
+use super::protobuf::{Message, write_varint};
+use super::{ApiNames, DEVICE_PID_BASE, label};
+use crate::activity::{ActivityApi, ActivityObjectId, ActivityRecord};
+use crate::analysis::NvtxRange;
+use crate::pmsampling::{CounterDataImage, Sampler};
+use crate::*;
+
+/// The default number of events held back to put them in timestamp order.
+const DEFAULT_REORDER_CAPACITY: usize = 1 << 16;
+
+/// The ID of the only packet sequence in the trace.
+const SEQUENCE_ID: u64 = 1;
+
+/// The builtin `BOOTTIME` clock, which timestamps are assumed to be in.
+const CLOCK_BOOTTIME: u64 = 6;
+/// The sequence-scoped incremental clock used for event timestamps.
+const CLOCK_INCREMENTAL: u64 = 64;
+
+const SEQ_INCREMENTAL_STATE_CLEARED: u64 = 1;
+const SEQ_NEEDS_INCREMENTAL_STATE: u64 = 2;
+
+const TYPE_SLICE_BEGIN: u64 = 1;
+const TYPE_SLICE_END: u64 = 2;
+const TYPE_COUNTER: u64 = 4;
+
+const UNIT_SIZE_BYTES: u64 = 3;
+
+// Field numbers from perfetto/trace/trace_packet.proto and the messages it
+// references.
+mod field {
+    pub const TRACE_PACKET: u32 = 1;
+
+    pub const PACKET_CLOCK_SNAPSHOT: u32 = 6;
+    pub const PACKET_TIMESTAMP: u32 = 8;
+    pub const PACKET_SEQUENCE_ID: u32 = 10;
+    pub const PACKET_TRACK_EVENT: u32 = 11;
+    pub const PACKET_INTERNED_DATA: u32 = 12;
+    pub const PACKET_SEQUENCE_FLAGS: u32 = 13;
+    pub const PACKET_TIMESTAMP_CLOCK_ID: u32 = 58;
+    pub const PACKET_DEFAULTS: u32 = 59;
+    pub const PACKET_TRACK_DESCRIPTOR: u32 = 60;
+
+    pub const CLOCK_SNAPSHOT_CLOCKS: u32 = 1;
+    pub const CLOCK_ID: u32 = 1;
+    pub const CLOCK_TIMESTAMP: u32 = 2;
+    pub const CLOCK_IS_INCREMENTAL: u32 = 3;
+
+    pub const DEFAULTS_TIMESTAMP_CLOCK_ID: u32 = 58;
+
+    pub const TRACK_UUID: u32 = 1;
+    pub const TRACK_NAME: u32 = 2;
+    pub const TRACK_PROCESS: u32 = 3;
+    pub const TRACK_THREAD: u32 = 4;
+    pub const TRACK_PARENT_UUID: u32 = 5;
+    pub const TRACK_COUNTER: u32 = 8;
+    pub const PROCESS_PID: u32 = 1;
+    pub const PROCESS_NAME: u32 = 6;
+    pub const THREAD_PID: u32 = 1;
+    pub const THREAD_TID: u32 = 2;
+    pub const COUNTER_UNIT: u32 = 3;
+
+    pub const EVENT_CATEGORY_IIDS: u32 = 3;
+    pub const EVENT_DEBUG_ANNOTATIONS: u32 = 4;
+    pub const EVENT_TYPE: u32 = 9;
+    pub const EVENT_NAME_IID: u32 = 10;
+    pub const EVENT_TRACK_UUID: u32 = 11;
+    pub const EVENT_COUNTER_VALUE: u32 = 30;
+    pub const EVENT_DOUBLE_COUNTER_VALUE: u32 = 44;
+    pub const EVENT_FLOW_IDS: u32 = 47;
+    pub const EVENT_TERMINATING_FLOW_IDS: u32 = 48;
+
+    pub const ANNOTATION_NAME_IID: u32 = 1;
+    pub const ANNOTATION_UINT_VALUE: u32 = 3;
+    pub const ANNOTATION_STRING_VALUE: u32 = 6;
+
+    pub const INTERNED_EVENT_CATEGORIES: u32 = 1;
+    pub const INTERNED_EVENT_NAMES: u32 = 2;
+    pub const INTERNED_ANNOTATION_NAMES: u32 = 3;
+    pub const INTERNED_IID: u32 = 1;
+    pub const INTERNED_NAME: u32 = 2;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+enum TrackKey {
+    Device(u32),
+    Stream(u32, u32),
+    Thread(u32, u32),
+    Nvtx(u32, u32),
+    Counter(u32, String),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Interned {
+    Category,
+    Name,
+    Annotation,
+}
+
+impl Interned {
+    fn field(self) -> u32 {
+        match self {
+            Self::Category => field::INTERNED_EVENT_CATEGORIES,
+            Self::Name => field::INTERNED_EVENT_NAMES,
+            Self::Annotation => field::INTERNED_ANNOTATION_NAMES,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Arg {
+    Uint(u64),
+    Str(&'static str),
+}
+
+#[derive(Debug)]
+enum EventKind {
+    Begin {
+        category: &'static str,
+        name: String,
+        flow: Option<u64>,
+        terminating_flow: Option<u64>,
+        args: Vec<(&'static str, Arg)>,
+    },
+    End,
+    Counter(f64),
+}
+
+#[derive(Debug)]
+struct Event {
+    track: u64,
+    kind: EventKind,
+}
+
+/// Writes activity records as a Perfetto protobuf trace.
+///
+/// The output is a `perfetto.protos.Trace` message made up of `TracePacket`s,
+/// which can be opened in `ui.perfetto.dev` or loaded with `trace_processor`.
+/// It uses the same track layout as
+/// [`ChromeTraceWriter`](super::ChromeTraceWriter), but is much more compact:
+/// kernel names, API names and other strings are interned, and timestamps are
+/// written as deltas from the previous event.
+///
+/// Delta timestamps require events to be written in timestamp order, but
+/// activity records are not delivered in order. The writer holds back a
+/// bounded number of events, set with
+/// [`reorder_capacity`](Self::reorder_capacity), and writes them in timestamp
+/// order. Events that still arrive too late are written with an absolute
+/// timestamp instead. Strings are interned as events are written, so every
+/// interned string is defined before the first packet that uses it.
+///
+/// [`finish`](Self::finish) must be called to write the held back events.
+pub struct PerfettoTraceWriter<W: Write> {
+    out: W,
+    tracks: HashMap<TrackKey, u64>,
+    /// The strings that have been written as interned data, for each
+    /// [`Interned`] kind.
+    interned: [HashMap<String, u64>; 3],
+    next_iid: u64,
+    pending: BinaryHeap<Reverse<(u64, u8, u64)>>,
+    events: HashMap<u64, Event>,
+    next_seq: u64,
+    capacity: usize,
+    last_timestamp: Option<u64>,
+    api_names: ApiNames,
+}
+
+impl<W: Write> PerfettoTraceWriter<W> {
+    /// Create a writer that writes to `out`.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            tracks: HashMap::new(),
+            interned: Default::default(),
+            next_iid: 1,
+            pending: BinaryHeap::new(),
+            events: HashMap::new(),
+            next_seq: 0,
+            capacity: DEFAULT_REORDER_CAPACITY,
+            last_timestamp: None,
+            api_names: ApiNames::default(),
+        }
+    }
+
+    /// Hold back up to `capacity` events to put them in timestamp order.
+    ///
+    /// Larger values use more memory but allow records to arrive further out
+    /// of order while still being written with a delta timestamp. The default
+    /// is 65536.
+    pub fn reorder_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Write a record to the trace.
+    ///
+    /// Returns `Ok(false)` if the kind of record is not supported, in which
+    /// case nothing is written. The supported kinds are driver and runtime API
+    /// calls, kernels, memory copies and memory sets.
+    pub fn write_record(&mut self, record: &ActivityRecord<'_>) -> io::Result<bool> {
+        match record {
+            ActivityRecord::Driver(api) | ActivityRecord::Runtime(api) => {
+                let name = self.api_names.get(record.kind(), api.cbid);
+                self.write_api(&name, api)?;
+            }
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => {
+                let name = match &r.name {
+                    Some(name) => name.to_string_lossy(),
+                    None => "kernel".into(),
+                };
+                let args = vec![
+                    ("correlation_id", Arg::Uint(r.correlation_id.into())),
+                    (
+                        "registers_per_thread",
+                        Arg::Uint(r.registers_per_thread.into()),
+                    ),
+                    (
+                        "grid_size",
+                        Arg::Uint(grid_size(r.grid_x, r.grid_y, r.grid_z)),
+                    ),
+                    (
+                        "block_size",
+                        Arg::Uint(grid_size(r.block_x, r.block_y, r.block_z)),
+                    ),
+                ];
+                let track = self.track(TrackKey::Stream(r.device_id, r.stream_id))?;
+                self.slice(
+                    track,
+                    "kernel",
+                    &name,
+                    (r.start, r.end),
+                    r.correlation_id,
+                    args,
+                );
+            }
+            ActivityRecord::Memcpy(r) => {
+                let name = format!("Memcpy {}", label(r.copy_kind));
+                let args = vec![
+                    ("correlation_id", Arg::Uint(r.correlation_id.into())),
+                    ("bytes", Arg::Uint(r.bytes)),
+                    ("src_kind", Arg::Str(label(r.src_kind))),
+                    ("dst_kind", Arg::Str(label(r.dst_kind))),
+                ];
+                let track = self.track(TrackKey::Stream(r.device_id, r.stream_id))?;
+                self.slice(
+                    track,
+                    "memcpy",
+                    &name,
+                    (r.start, r.end),
+                    r.correlation_id,
+                    args,
+                );
+            }
+            ActivityRecord::Memcpy2(r) => {
+                let name = format!("Memcpy {}", label(r.copy_kind));
+                let args = vec![
+                    ("correlation_id", Arg::Uint(r.correlation_id.into())),
+                    ("bytes", Arg::Uint(r.bytes)),
+                    ("src_device", Arg::Uint(r.src_device_id.into())),
+                    ("dst_device", Arg::Uint(r.dst_device_id.into())),
+                ];
+                let track = self.track(TrackKey::Stream(r.device_id, r.stream_id))?;
+                self.slice(
+                    track,
+                    "memcpy",
+                    &name,
+                    (r.start, r.end),
+                    r.correlation_id,
+                    args,
+                );
+            }
+            ActivityRecord::Memset(r) => {
+                let args = vec![
+                    ("correlation_id", Arg::Uint(r.correlation_id.into())),
+                    ("bytes", Arg::Uint(r.bytes)),
+                    ("value", Arg::Uint(r.value.into())),
+                ];
+                let track = self.track(TrackKey::Stream(r.device_id, r.stream_id))?;
+                self.slice(
+                    track,
+                    "memset",
+                    "Memset",
+                    (r.start, r.end),
+                    r.correlation_id,
+                    args,
+                );
+            }
+            _ => return Ok(false),
+        }
+
+        self.flush_pending(self.capacity)?;
+        Ok(true)
+    }
+
+    /// Write an NVTX range to the trace.
+    ///
+    /// Ranges are written to an NVTX track below the thread that started them.
+    pub fn write_nvtx_range(&mut self, range: &NvtxRange) -> io::Result<()> {
+        let ActivityObjectId::Process {
+            process_id,
+            thread_id,
+        } = range.thread
+        else {
+            return Ok(());
+        };
+
+        let name = match &range.name {
+            Some(name) => name.to_string_lossy(),
+            None => "range".into(),
+        };
+
+        let track = self.track(TrackKey::Nvtx(process_id, thread_id))?;
+        self.slice(
+            track,
+            "nvtx",
+            &name,
+            (range.start, range.end),
+            0,
+            Vec::new(),
+        );
+        self.flush_pending(self.capacity)
+    }
+
+    /// Write a counter track for each metric in `metric_names`, with one value
+    /// for each completed sample in `image`.
+    ///
+    /// `metric_names` must be the metrics that `image` was created with.
+    /// Values are placed at the start of each sample.
+    ///
+    /// # Errors
+    ///
+    /// Errors from CUPTI while reading samples are returned as
+    /// [`io::ErrorKind::Other`] errors wrapping an [`Error`].
+    pub fn write_pm_samples(
+        &mut self,
+        device_id: u32,
+        sampler: &Sampler,
+        image: &CounterDataImage,
+        metric_names: &CStringSlice,
+    ) -> io::Result<()> {
+        let info = image.get_data_info().map_err(io::Error::other)?;
+
+        let mut tracks = Vec::with_capacity(metric_names.len());
+        for name in metric_names {
+            let name = name.to_string_lossy().into_owned();
+            tracks.push(self.track(TrackKey::Counter(device_id, name))?);
+        }
+
+        for index in 0..info.num_completed_samples {
+            let sample = image
+                .get_sample_info(sampler, index)
+                .map_err(io::Error::other)?;
+            let values = image
+                .evaluate(sampler, index, metric_names)
+                .map_err(io::Error::other)?;
+
+            for (&track, value) in tracks.iter().zip(values) {
+                self.push(sample.start_timestamp, track, EventKind::Counter(value));
+            }
+            self.flush_pending(self.capacity)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write any held back events and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_pending(0)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_api(&mut self, name: &str, api: &ActivityApi) -> io::Result<()> {
+        let track = self.track(TrackKey::Thread(api.process_id, api.thread_id))?;
+        let args = vec![
+            ("correlation_id", Arg::Uint(api.correlation_id.into())),
+            ("cbid", Arg::Uint(api.cbid.into())),
+            ("return_value", Arg::Uint(api.return_value.into())),
+        ];
+
+        let flow = (api.correlation_id != 0).then_some(u64::from(api.correlation_id));
+        let begin = EventKind::Begin {
+            category: "api",
+            name: name.to_owned(),
+            flow,
+            terminating_flow: None,
+            args,
+        };
+        self.push(api.start, track, begin);
+        self.push(api.end.max(api.start), track, EventKind::End);
+
+        Ok(())
+    }
+
+    fn slice(
+        &mut self,
+        track: u64,
+        category: &'static str,
+        name: &str,
+        (start, end): (u64, u64),
+        correlation_id: u32,
+        args: Vec<(&'static str, Arg)>,
+    ) {
+        let begin = EventKind::Begin {
+            category,
+            name: name.to_owned(),
+            flow: None,
+            terminating_flow: (correlation_id != 0).then_some(u64::from(correlation_id)),
+            args,
+        };
+        self.push(start, track, begin);
+        self.push(end.max(start), track, EventKind::End);
+    }
+
+    /// Get the interned ID of `value`, adding an entry for it to `interned` if
+    /// it has not been written yet.
+    fn intern(&mut self, kind: Interned, value: &str, interned: &mut Message) -> u64 {
+        let strings = &mut self.interned[kind as usize];
+        if let Some(&iid) = strings.get(value) {
+            return iid;
+        }
+
+        let iid = self.next_iid;
+        self.next_iid += 1;
+        strings.insert(value.to_owned(), iid);
+
+        let mut entry = Message::new();
+        entry
+            .uint(field::INTERNED_IID, iid)
+            .string(field::INTERNED_NAME, value);
+        interned.message(kind.field(), &entry);
+
+        iid
+    }
+
+    fn push(&mut self, timestamp: u64, track: u64, kind: EventKind) {
+        // Slice ends are written before anything else at the same timestamp,
+        // so that back-to-back slices on a track do not appear nested.
+        let order = match kind {
+            EventKind::End => 0,
+            EventKind::Counter(_) => 1,
+            EventKind::Begin { .. } => 2,
+        };
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.pending.push(Reverse((timestamp, order, seq)));
+        self.events.insert(seq, Event { track, kind });
+    }
+
+    fn flush_pending(&mut self, keep: usize) -> io::Result<()> {
+        while self.pending.len() > keep {
+            let Some(Reverse((timestamp, _, seq))) = self.pending.pop() else {
+                break;
+            };
+            let event = self
+                .events
+                .remove(&seq)
+                .expect("pending event should exist");
+
+            self.write_event(timestamp, event)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_event(&mut self, timestamp: u64, event: Event) -> io::Result<()> {
+        let mut interned = Message::new();
+        let mut track_event = Message::new();
+        track_event.uint(field::EVENT_TRACK_UUID, event.track);
+
+        match event.kind {
+            EventKind::Begin {
+                category,
+                name,
+                flow,
+                terminating_flow,
+                args,
+            } => {
+                let category = self.intern(Interned::Category, category, &mut interned);
+                let name = self.intern(Interned::Name, &name, &mut interned);
+                track_event
+                    .uint(field::EVENT_TYPE, TYPE_SLICE_BEGIN)
+                    .uint(field::EVENT_CATEGORY_IIDS, category)
+                    .uint(field::EVENT_NAME_IID, name);
+
+                if let Some(flow) = flow {
+                    track_event.fixed64(field::EVENT_FLOW_IDS, flow);
+                }
+                if let Some(flow) = terminating_flow {
+                    track_event.fixed64(field::EVENT_TERMINATING_FLOW_IDS, flow);
+                }
+
+                for (name, value) in args {
+                    let name = self.intern(Interned::Annotation, name, &mut interned);
+                    let mut annotation = Message::new();
+                    annotation.uint(field::ANNOTATION_NAME_IID, name);
+                    match value {
+                        Arg::Uint(value) => annotation.uint(field::ANNOTATION_UINT_VALUE, value),
+                        Arg::Str(value) => annotation.string(field::ANNOTATION_STRING_VALUE, value),
+                    };
+                    track_event.message(field::EVENT_DEBUG_ANNOTATIONS, &annotation);
+                }
+            }
+            EventKind::End => {
+                track_event.uint(field::EVENT_TYPE, TYPE_SLICE_END);
+            }
+            EventKind::Counter(value) => {
+                track_event.uint(field::EVENT_TYPE, TYPE_COUNTER);
+                if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+                    track_event.int(field::EVENT_COUNTER_VALUE, value as i64);
+                } else {
+                    track_event.double(field::EVENT_DOUBLE_COUNTER_VALUE, value);
+                }
+            }
+        }
+
+        let mut packet = Message::new();
+        match self.last_timestamp {
+            None => {
+                self.write_clock_snapshot(timestamp)?;
+                packet.uint(field::PACKET_TIMESTAMP, 0);
+                self.last_timestamp = Some(timestamp);
+            }
+            Some(last) if timestamp >= last => {
+                packet.uint(field::PACKET_TIMESTAMP, timestamp - last);
+                self.last_timestamp = Some(timestamp);
+            }
+            Some(_) => {
+                packet
+                    .uint(field::PACKET_TIMESTAMP, timestamp)
+                    .uint(field::PACKET_TIMESTAMP_CLOCK_ID, CLOCK_BOOTTIME);
+            }
+        }
+
+        packet
+            .uint(field::PACKET_SEQUENCE_ID, SEQUENCE_ID)
+            .uint(field::PACKET_SEQUENCE_FLAGS, SEQ_NEEDS_INCREMENTAL_STATE)
+            .message(field::PACKET_TRACK_EVENT, &track_event);
+        if !interned.is_empty() {
+            packet.message(field::PACKET_INTERNED_DATA, &interned);
+        }
+
+        self.write_packet(&packet)
+    }
+
+    /// Start the incremental clock at `timestamp`.
+    ///
+    /// This also clears the incremental state, so it must be written before
+    /// any packet that uses interned data.
+    fn write_clock_snapshot(&mut self, timestamp: u64) -> io::Result<()> {
+        let mut incremental = Message::new();
+        incremental
+            .uint(field::CLOCK_ID, CLOCK_INCREMENTAL)
+            .uint(field::CLOCK_TIMESTAMP, 0)
+            .bool(field::CLOCK_IS_INCREMENTAL, true);
+
+        let mut boottime = Message::new();
+        boottime
+            .uint(field::CLOCK_ID, CLOCK_BOOTTIME)
+            .uint(field::CLOCK_TIMESTAMP, timestamp);
+
+        let mut snapshot = Message::new();
+        snapshot
+            .message(field::CLOCK_SNAPSHOT_CLOCKS, &incremental)
+            .message(field::CLOCK_SNAPSHOT_CLOCKS, &boottime);
+
+        let mut defaults = Message::new();
+        defaults.uint(field::DEFAULTS_TIMESTAMP_CLOCK_ID, CLOCK_INCREMENTAL);
+
+        let mut packet = Message::new();
+        packet
+            .uint(field::PACKET_TIMESTAMP, timestamp)
+            .uint(field::PACKET_TIMESTAMP_CLOCK_ID, CLOCK_BOOTTIME)
+            .uint(field::PACKET_SEQUENCE_ID, SEQUENCE_ID)
+            .uint(field::PACKET_SEQUENCE_FLAGS, SEQ_INCREMENTAL_STATE_CLEARED)
+            .message(field::PACKET_CLOCK_SNAPSHOT, &snapshot)
+            .message(field::PACKET_DEFAULTS, &defaults);
+
+        self.write_packet(&packet)
+    }
+
+    fn track(&mut self, key: TrackKey) -> io::Result<u64> {
+        if let Some(&uuid) = self.tracks.get(&key) {
+            return Ok(uuid);
+        }
+
+        let parent = match &key {
+            TrackKey::Stream(device, _) | TrackKey::Counter(device, _) => {
+                Some(self.track(TrackKey::Device(*device))?)
+            }
+            TrackKey::Nvtx(pid, tid) => Some(self.track(TrackKey::Thread(*pid, *tid))?),
+            TrackKey::Device(_) | TrackKey::Thread(..) => None,
+        };
+
+        // Track UUIDs only need to be unique within the trace.
+        let uuid = self.tracks.len() as u64 + 1;
+
+        let mut track = Message::new();
+        track.uint(field::TRACK_UUID, uuid);
+        if let Some(parent) = parent {
+            track.uint(field::TRACK_PARENT_UUID, parent);
+        }
+
+        match &key {
+            TrackKey::Device(device) => {
+                let mut process = Message::new();
+                process
+                    .int(
+                        field::PROCESS_PID,
+                        (DEVICE_PID_BASE + u64::from(*device)) as i64,
+                    )
+                    .string(field::PROCESS_NAME, &format!("GPU {device}"));
+                track.message(field::TRACK_PROCESS, &process);
+            }
+            TrackKey::Stream(_, stream) => {
+                track.string(field::TRACK_NAME, &format!("Stream {stream}"));
+            }
+            TrackKey::Thread(pid, tid) => {
+                let mut thread = Message::new();
+                thread
+                    .int(field::THREAD_PID, (*pid).into())
+                    .int(field::THREAD_TID, (*tid).into());
+                track.message(field::TRACK_THREAD, &thread);
+            }
+            TrackKey::Nvtx(..) => {
+                track.string(field::TRACK_NAME, "NVTX");
+            }
+            TrackKey::Counter(_, name) => {
+                let mut counter = Message::new();
+                if name.contains("bytes") {
+                    counter.uint(field::COUNTER_UNIT, UNIT_SIZE_BYTES);
+                }
+                track
+                    .string(field::TRACK_NAME, name)
+                    .message(field::TRACK_COUNTER, &counter);
+            }
+        }
+
+        let mut packet = Message::new();
+        packet
+            .uint(field::PACKET_SEQUENCE_ID, SEQUENCE_ID)
+            .message(field::PACKET_TRACK_DESCRIPTOR, &track);
+        self.write_packet(&packet)?;
+
+        self.tracks.insert(key, uuid);
+        Ok(uuid)
+    }
+
+    fn write_packet(&mut self, packet: &Message) -> io::Result<()> {
+        let bytes = packet.as_bytes();
+
+        let mut header = Vec::with_capacity(6);
+        write_varint(&mut header, u64::from(field::TRACE_PACKET << 3 | 2));
+        write_varint(&mut header, bytes.len() as u64);
+
+        self.out.write_all(&header)?;
+        self.out.write_all(bytes)
+    }
+}
+
+fn grid_size(x: i32, y: i32, z: i32) -> u64 {
+    [x, y, z]
+        .into_iter()
+        .map(|d| u64::try_from(d).unwrap_or(0))
+        .product()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::activity::{ActivityFlag, ActivityKind};
+    use crate::analysis::NvtxAggregator;
+    use crate::testing::{api, kernel, marker};
+
+    #[derive(Debug)]
+    enum Value<'a> {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(&'a [u8]),
+    }
+
+    fn read_varint(buf: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = buf.split_first().expect("truncated varint");
+            *buf = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    /// Decode the fields of a protobuf message.
+    fn decode(mut buf: &[u8]) -> Vec<(u32, Value<'_>)> {
+        let mut fields = Vec::new();
+        while !buf.is_empty() {
+            let tag = read_varint(&mut buf);
+            let value = match tag & 7 {
+                0 => Value::Varint(read_varint(&mut buf)),
+                1 => {
+                    let (bytes, rest) = buf.split_first_chunk().expect("truncated fixed64");
+                    buf = rest;
+                    Value::Fixed64(u64::from_le_bytes(*bytes))
+                }
+                2 => {
+                    let len = read_varint(&mut buf) as usize;
+                    let (bytes, rest) = buf.split_at(len);
+                    buf = rest;
+                    Value::Bytes(bytes)
+                }
+                wire_type => panic!("unexpected wire type {wire_type}"),
+            };
+            fields.push(((tag >> 3) as u32, value));
+        }
+        fields
+    }
+
+    fn uint(fields: &[(u32, Value<'_>)], field: u32) -> Option<u64> {
+        fields.iter().find_map(|(f, v)| match v {
+            Value::Varint(v) if *f == field => Some(*v),
+            _ => None,
+        })
+    }
+
+    fn fixed64s(fields: &[(u32, Value<'_>)], field: u32) -> Vec<u64> {
+        fields
+            .iter()
+            .filter_map(|(f, v)| match v {
+                Value::Fixed64(v) if *f == field => Some(*v),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn messages<'a>(fields: &[(u32, Value<'a>)], field: u32) -> Vec<&'a [u8]> {
+        fields
+            .iter()
+            .filter_map(|(f, v)| match v {
+                Value::Bytes(b) if *f == field => Some(*b),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A slice begin event decoded from a trace.
+    #[derive(Debug, PartialEq)]
+    struct Slice {
+        timestamp: u64,
+        /// Whether the event was written with an absolute timestamp.
+        absolute: bool,
+        track: u64,
+        category: String,
+        name: String,
+        args: Vec<String>,
+        flows: Vec<u64>,
+        terminating_flows: Vec<u64>,
+    }
+
+    /// A counter value decoded from a trace.
+    #[derive(Debug, PartialEq)]
+    enum Counter {
+        Int(i64),
+        Double(f64),
+    }
+
+    /// The events and track descriptors of a trace.
+    #[derive(Debug, Default)]
+    struct Decoded<'a> {
+        slices: Vec<Slice>,
+        counters: Vec<(u64, Counter)>,
+        tracks: Vec<Vec<(u32, Value<'a>)>>,
+    }
+
+    /// Decode the slice begin and counter events and the track descriptors of
+    /// a trace, checking that every interned ID is defined before it is used.
+    fn decode_trace(trace: &[u8]) -> Decoded<'_> {
+        let mut strings: HashMap<(u32, u64), String> = HashMap::new();
+        let mut defined = HashSet::new();
+        let mut timestamp = 0;
+        let mut decoded = Decoded::default();
+
+        for (field, value) in decode(trace) {
+            assert_eq!(field, field::TRACE_PACKET);
+            let Value::Bytes(packet) = value else {
+                panic!("packet is not a message");
+            };
+            let packet = decode(packet);
+
+            // The incremental clock starts at the timestamp of the snapshot.
+            if !messages(&packet, field::PACKET_CLOCK_SNAPSHOT).is_empty() {
+                timestamp = uint(&packet, field::PACKET_TIMESTAMP).unwrap();
+                defined.clear();
+                continue;
+            }
+
+            for track in messages(&packet, field::PACKET_TRACK_DESCRIPTOR) {
+                decoded.tracks.push(decode(track));
+            }
+
+            for data in messages(&packet, field::PACKET_INTERNED_DATA) {
+                let data = decode(data);
+                for kind in [
+                    field::INTERNED_EVENT_CATEGORIES,
+                    field::INTERNED_EVENT_NAMES,
+                    field::INTERNED_ANNOTATION_NAMES,
+                ] {
+                    for entry in messages(&data, kind) {
+                        let entry = decode(entry);
+                        let iid = uint(&entry, field::INTERNED_IID).unwrap();
+                        let name = messages(&entry, field::INTERNED_NAME)[0];
+                        strings.insert((kind, iid), String::from_utf8(name.to_vec()).unwrap());
+                        assert!(defined.insert((kind, iid)), "iid {iid} defined twice");
+                    }
+                }
+            }
+
+            let Some(event) = messages(&packet, field::PACKET_TRACK_EVENT)
+                .first()
+                .copied()
+            else {
+                continue;
+            };
+            let event = decode(event);
+
+            // Late events are written with an absolute timestamp, which does
+            // not advance the incremental clock.
+            let value = uint(&packet, field::PACKET_TIMESTAMP).unwrap();
+            let absolute = uint(&packet, field::PACKET_TIMESTAMP_CLOCK_ID) == Some(CLOCK_BOOTTIME);
+            let event_timestamp = if absolute {
+                value
+            } else {
+                timestamp += value;
+                timestamp
+            };
+
+            match uint(&event, field::EVENT_TYPE) {
+                Some(TYPE_SLICE_BEGIN) => (),
+                Some(TYPE_COUNTER) => {
+                    let value = match uint(&event, field::EVENT_COUNTER_VALUE) {
+                        Some(value) => Counter::Int(value as i64),
+                        None => Counter::Double(f64::from_bits(
+                            fixed64s(&event, field::EVENT_DOUBLE_COUNTER_VALUE)[0],
+                        )),
+                    };
+                    decoded.counters.push((event_timestamp, value));
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let lookup = |kind, iid| {
+                assert!(
+                    defined.contains(&(kind, iid)),
+                    "iid {iid} used before it was defined"
+                );
+                strings[&(kind, iid)].clone()
+            };
+            let category = lookup(
+                field::INTERNED_EVENT_CATEGORIES,
+                uint(&event, field::EVENT_CATEGORY_IIDS).unwrap(),
+            );
+            let name = lookup(
+                field::INTERNED_EVENT_NAMES,
+                uint(&event, field::EVENT_NAME_IID).unwrap(),
+            );
+            let args = messages(&event, field::EVENT_DEBUG_ANNOTATIONS)
+                .into_iter()
+                .map(|annotation| {
+                    let annotation = decode(annotation);
+                    lookup(
+                        field::INTERNED_ANNOTATION_NAMES,
+                        uint(&annotation, field::ANNOTATION_NAME_IID).unwrap(),
+                    )
+                })
+                .collect();
+
+            decoded.slices.push(Slice {
+                timestamp: event_timestamp,
+                absolute,
+                track: uint(&event, field::EVENT_TRACK_UUID).unwrap(),
+                category,
+                name,
+                args,
+                flows: fixed64s(&event, field::EVENT_FLOW_IDS),
+                terminating_flows: fixed64s(&event, field::EVENT_TERMINATING_FLOW_IDS),
+            });
+        }
+
+        decoded
+    }
+
+    /// Decode the slice begin events of a trace.
+    fn slices(trace: &[u8]) -> Vec<Slice> {
+        decode_trace(trace).slices
+    }
+
+    #[test]
+    fn out_of_order_records_round_trip() {
+        let mut writer = PerfettoTraceWriter::new(Vec::new());
+
+        // The first record pushed is written last, so the strings it
+        // introduces must be interned with the earlier events instead.
+        writer.write_record(&kernel(2, 5_000, 6_000, "k")).unwrap();
+        writer.write_record(&kernel(1, 1_000, 2_000, "k")).unwrap();
+        writer
+            .write_record(&api(ActivityKind::Runtime, 1, 500, 900))
+            .unwrap();
+        writer
+            .write_record(&kernel(3, 3_000, 4_000, "other"))
+            .unwrap();
+
+        let trace = writer.finish().unwrap();
+        let slices = slices(&trace);
+        assert!(slices.iter().all(|s| !s.absolute));
+
+        let summary: Vec<_> = slices
+            .iter()
+            .map(|s| (s.timestamp, s.category.as_str(), s.name.as_str()))
+            .collect();
+        let api_name = ApiNames::default().get(ActivityKind::Runtime, 0);
+        assert_eq!(
+            summary,
+            [
+                (500, "api", api_name.as_ref()),
+                (1_000, "kernel", "k"),
+                (3_000, "kernel", "other"),
+                (5_000, "kernel", "k"),
+            ]
+        );
+        assert_eq!(slices[0].args, ["correlation_id", "cbid", "return_value"]);
+        assert_eq!(
+            slices[1].args,
+            [
+                "correlation_id",
+                "registers_per_thread",
+                "grid_size",
+                "block_size"
+            ]
+        );
+    }
+
+    #[test]
+    fn late_events_use_absolute_timestamps() {
+        let mut writer = PerfettoTraceWriter::new(Vec::new()).reorder_capacity(1);
+
+        writer
+            .write_record(&kernel(1, 5_000, 6_000, "first"))
+            .unwrap();
+        writer
+            .write_record(&kernel(2, 1_000, 2_000, "late"))
+            .unwrap();
+        writer
+            .write_record(&kernel(3, 7_000, 8_000, "next"))
+            .unwrap();
+
+        let trace = writer.finish().unwrap();
+        let summary: Vec<_> = slices(&trace)
+            .iter()
+            .map(|s| (s.timestamp, s.absolute, s.name.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (5_000, false, "first".to_owned()),
+                (1_000, true, "late".to_owned()),
+                (7_000, false, "next".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nvtx_tracks_are_below_their_thread() {
+        let mut nvtx = NvtxAggregator::new();
+        nvtx.push(&marker(ActivityFlag::MARKER_START, 1, 3, 100, Some("step")));
+        nvtx.push(&marker(ActivityFlag::MARKER_END, 1, 3, 400, None));
+
+        let mut writer = PerfettoTraceWriter::new(Vec::new());
+        for range in nvtx.take_completed() {
+            writer.write_nvtx_range(&range).unwrap();
+        }
+        let trace = writer.finish().unwrap();
+        let decoded = decode_trace(&trace);
+
+        let [thread, track] = &decoded.tracks[..] else {
+            panic!("unexpected tracks: {:?}", decoded.tracks);
+        };
+        let descriptor = decode(messages(thread, field::TRACK_THREAD)[0]);
+        assert_eq!(uint(&descriptor, field::THREAD_PID), Some(100));
+        assert_eq!(uint(&descriptor, field::THREAD_TID), Some(3));
+        assert_eq!(messages(track, field::TRACK_NAME), [b"NVTX"]);
+        assert_eq!(
+            uint(track, field::TRACK_PARENT_UUID),
+            uint(thread, field::TRACK_UUID)
+        );
+
+        let slice = &decoded.slices[0];
+        assert_eq!(
+            (slice.category.as_str(), slice.name.as_str()),
+            ("nvtx", "step")
+        );
+        assert_eq!(Some(slice.track), uint(track, field::TRACK_UUID));
+        assert!(slice.terminating_flows.is_empty());
+    }
+
+    #[test]
+    fn api_calls_flow_to_gpu_work() {
+        let mut writer = PerfettoTraceWriter::new(Vec::new());
+        writer
+            .write_record(&api(ActivityKind::Runtime, 7, 100, 200))
+            .unwrap();
+        writer.write_record(&kernel(7, 300, 400, "k")).unwrap();
+        writer.write_record(&kernel(0, 500, 600, "k")).unwrap();
+
+        let trace = writer.finish().unwrap();
+        let flows: Vec<_> = slices(&trace)
+            .into_iter()
+            .map(|s| (s.category, s.flows, s.terminating_flows))
+            .collect();
+        assert_eq!(
+            flows,
+            [
+                ("api".to_owned(), vec![7], vec![]),
+                ("kernel".to_owned(), vec![], vec![7]),
+                ("kernel".to_owned(), vec![], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn counters_use_int_values_when_possible() {
+        let mut writer = PerfettoTraceWriter::new(Vec::new());
+        let track = writer
+            .track(TrackKey::Counter(0, "dram__bytes_read.sum".to_owned()))
+            .unwrap();
+        for (timestamp, value) in [(100, 42.0), (200, 0.5), (300, -3.0), (400, 1e300)] {
+            writer.push(timestamp, track, EventKind::Counter(value));
+        }
+
+        let trace = writer.finish().unwrap();
+        let decoded = decode_trace(&trace);
+        assert_eq!(
+            decoded.counters,
+            [
+                (100, Counter::Int(42)),
+                (200, Counter::Double(0.5)),
+                (300, Counter::Int(-3)),
+                (400, Counter::Double(1e300)),
+            ]
+        );
+
+        let counter = decode(messages(&decoded.tracks[1], field::TRACK_COUNTER)[0]);
+        assert_eq!(uint(&counter, field::COUNTER_UNIT), Some(UNIT_SIZE_BYTES));
+    }
+}
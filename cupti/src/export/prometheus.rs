@@ -0,0 +1,683 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use cupti_sys::CUpti_ActivityMemcpyKind;
+
+use super::{ApiNames, label};
+use crate::activity::{
+    ActivityApi, ActivityKind, ActivityMemcpyKind, ActivityMemoryPoolOperationType, ActivityRecord,
+};
+use crate::*;
+
+/// The upper bounds of the API latency histogram buckets, in ns.
+const LATENCY_BUCKETS: [u64; 14] = [
+    1_000,
+    5_000,
+    10_000,
+    50_000,
+    100_000,
+    500_000,
+    1_000_000,
+    5_000_000,
+    10_000_000,
+    50_000_000,
+    100_000_000,
+    500_000_000,
+    1_000_000_000,
+    5_000_000_000,
+];
+
+/// How often the metrics server checks whether it has been shut down.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Default)]
+struct Totals {
+    count: u64,
+    sum: u64,
+}
+
+impl Totals {
+    fn add(&mut self, value: u64) {
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Histogram {
+    name: Cow<'static, str>,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    totals: Totals,
+}
+
+impl Histogram {
+    fn new(name: Cow<'static, str>) -> Self {
+        Self {
+            name,
+            buckets: [0; LATENCY_BUCKETS.len()],
+            totals: Totals::default(),
+        }
+    }
+
+    fn observe(&mut self, value: u64) {
+        let index = LATENCY_BUCKETS.partition_point(|&bound| bound < value);
+        if let Some(bucket) = self.buckets.get_mut(index) {
+            *bucket += 1;
+        }
+        self.totals.add(value);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct PoolUsage {
+    size: u64,
+    utilized: Option<u64>,
+}
+
+/// Aggregates activity records into Prometheus metrics.
+///
+/// The metrics are cheap to update, so records can be pushed continuously for
+/// the lifetime of a process. They can be rendered in the Prometheus text
+/// exposition format with [`render`](Self::render), or served over HTTP with a
+/// [`MetricsServer`].
+///
+/// The following metrics are collected:
+///
+/// | Metric | Type | Labels |
+/// |--------|------|--------|
+/// | `cupti_kernel_seconds_total` | counter | `device`, `stream` |
+/// | `cupti_kernel_launches_total` | counter | `device`, `stream` |
+/// | `cupti_memcpy_bytes_total` | counter | `kind` |
+/// | `cupti_memcpy_total` | counter | `kind` |
+/// | `cupti_api_duration_seconds` | histogram | `api`, `name`, `cbid` |
+/// | `cupti_memory_pool_size_bytes` | gauge | `device`, `address` |
+/// | `cupti_memory_pool_utilized_bytes` | gauge | `device`, `address` |
+/// | `cupti_dropped_records_total` | counter | |
+///
+/// Memory pools are removed from the gauges when they are destroyed.
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+    kernels: BTreeMap<(u32, u32), Totals>,
+    // Keyed by the raw value since the kind does not implement `Ord`.
+    memcpy: BTreeMap<CUpti_ActivityMemcpyKind, Totals>,
+    api: BTreeMap<(bool, u32), Histogram>,
+    pools: BTreeMap<(u32, u64), PoolUsage>,
+    dropped: u64,
+    api_names: ApiNames,
+}
+
+impl PrometheusMetrics {
+    /// Create an empty set of metrics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record to the metrics.
+    ///
+    /// Returns `false` if the record does not contribute to any metric, in
+    /// which case it is ignored.
+    pub fn push(&mut self, record: &ActivityRecord<'_>) -> bool {
+        match record {
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => {
+                self.kernels
+                    .entry((r.device_id, r.stream_id))
+                    .or_default()
+                    .add(r.end.saturating_sub(r.start));
+            }
+            ActivityRecord::Memcpy(r) => {
+                self.memcpy.entry(r.copy_kind.0).or_default().add(r.bytes);
+            }
+            ActivityRecord::Memcpy2(r) => {
+                self.memcpy.entry(r.copy_kind.0).or_default().add(r.bytes);
+            }
+            ActivityRecord::Driver(api) => self.push_api(ActivityKind::Driver, api),
+            ActivityRecord::Runtime(api) => self.push_api(ActivityKind::Runtime, api),
+            ActivityRecord::MemoryPool(r) => {
+                if r.memory_pool_operation_type == ActivityMemoryPoolOperationType::Destroyed {
+                    self.pools.remove(&(r.device_id, r.address));
+                    return true;
+                }
+
+                let usage = self.pools.entry((r.device_id, r.address)).or_default();
+                usage.size = r.size;
+                if let Some(utilized) = r.utilized_size {
+                    usage.utilized = Some(utilized);
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Add `count` records to the number of dropped records.
+    pub fn add_dropped_records(&mut self, count: u64) {
+        self.dropped += count;
+    }
+
+    /// Read the number of records dropped by CUPTI with
+    /// [`get_num_dropped_records`] and add it to the metrics, returning it.
+    ///
+    /// Since CUPTI resets its count each time it is read, this should be
+    /// called periodically, for example after each buffer is completed.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from [`get_num_dropped_records`].
+    ///
+    /// [`get_num_dropped_records`]: crate::activity::get_num_dropped_records
+    pub fn update_dropped_records(
+        &mut self,
+        context: Option<&Context>,
+        stream_id: u32,
+    ) -> Result<usize> {
+        let count = activity::get_num_dropped_records(context, stream_id)?;
+        self.dropped += count as u64;
+        Ok(count)
+    }
+
+    /// Clear all metrics.
+    pub fn reset(&mut self) {
+        self.kernels.clear();
+        self.memcpy.clear();
+        self.api.clear();
+        self.pools.clear();
+        self.dropped = 0;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.write_to(&mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Write the metrics in the Prometheus text exposition format to `out`.
+    pub fn write_to<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        header(
+            out,
+            "cupti_kernel_seconds_total",
+            "counter",
+            "Total time spent executing kernels.",
+        )?;
+        for ((device, stream), totals) in &self.kernels {
+            writeln!(
+                out,
+                "cupti_kernel_seconds_total{{device=\"{device}\",stream=\"{stream}\"}} {}",
+                Seconds(totals.sum)
+            )?;
+        }
+
+        header(
+            out,
+            "cupti_kernel_launches_total",
+            "counter",
+            "Total number of kernels executed.",
+        )?;
+        for ((device, stream), totals) in &self.kernels {
+            writeln!(
+                out,
+                "cupti_kernel_launches_total{{device=\"{device}\",stream=\"{stream}\"}} {}",
+                totals.count
+            )?;
+        }
+
+        header(
+            out,
+            "cupti_memcpy_bytes_total",
+            "counter",
+            "Total bytes copied by memory copies.",
+        )?;
+        for (&kind, totals) in &self.memcpy {
+            writeln!(
+                out,
+                "cupti_memcpy_bytes_total{{kind=\"{}\"}} {}",
+                label(ActivityMemcpyKind(kind)),
+                totals.sum
+            )?;
+        }
+
+        header(
+            out,
+            "cupti_memcpy_total",
+            "counter",
+            "Total number of memory copies.",
+        )?;
+        for (&kind, totals) in &self.memcpy {
+            writeln!(
+                out,
+                "cupti_memcpy_total{{kind=\"{}\"}} {}",
+                label(ActivityMemcpyKind(kind)),
+                totals.count
+            )?;
+        }
+
+        header(
+            out,
+            "cupti_api_duration_seconds",
+            "histogram",
+            "Time spent in driver and runtime API calls.",
+        )?;
+        for (&(driver, cbid), histogram) in &self.api {
+            let labels = format!(
+                "api=\"{}\",name=\"{}\",cbid=\"{cbid}\"",
+                if driver { "driver" } else { "runtime" },
+                Escape(&histogram.name),
+            );
+
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "cupti_api_duration_seconds_bucket{{{labels},le=\"{}\"}} {cumulative}",
+                    Seconds(*bound)
+                )?;
+            }
+            writeln!(
+                out,
+                "cupti_api_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                histogram.totals.count
+            )?;
+            writeln!(
+                out,
+                "cupti_api_duration_seconds_sum{{{labels}}} {}",
+                Seconds(histogram.totals.sum)
+            )?;
+            writeln!(
+                out,
+                "cupti_api_duration_seconds_count{{{labels}}} {}",
+                histogram.totals.count
+            )?;
+        }
+
+        header(
+            out,
+            "cupti_memory_pool_size_bytes",
+            "gauge",
+            "The size of each memory pool.",
+        )?;
+        for ((device, address), usage) in &self.pools {
+            writeln!(
+                out,
+                "cupti_memory_pool_size_bytes{{device=\"{device}\",address=\"{address:#x}\"}} {}",
+                usage.size
+            )?;
+        }
+
+        header(
+            out,
+            "cupti_memory_pool_utilized_bytes",
+            "gauge",
+            "The number of bytes in use in each memory pool.",
+        )?;
+        for ((device, address), usage) in &self.pools {
+            if let Some(utilized) = usage.utilized {
+                writeln!(
+                    out,
+                    "cupti_memory_pool_utilized_bytes{{device=\"{device}\",address=\"{address:#x}\"}} {utilized}",
+                )?;
+            }
+        }
+
+        header(
+            out,
+            "cupti_dropped_records_total",
+            "counter",
+            "Total number of activity records dropped by CUPTI.",
+        )?;
+        writeln!(out, "cupti_dropped_records_total {}", self.dropped)
+    }
+
+    fn push_api(&mut self, kind: ActivityKind, api: &ActivityApi) {
+        let driver = kind == ActivityKind::Driver;
+        let histogram = match self.api.get_mut(&(driver, api.cbid)) {
+            Some(histogram) => histogram,
+            None => {
+                let name = self.api_names.get(kind, api.cbid);
+                self.api
+                    .entry((driver, api.cbid))
+                    .or_insert_with(|| Histogram::new(name))
+            }
+        };
+
+        histogram.observe(api.end.saturating_sub(api.start));
+    }
+}
+
+fn header<W: fmt::Write>(out: &mut W, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} {kind}")
+}
+
+/// Serves [`PrometheusMetrics`] over HTTP on a background thread.
+///
+/// Every request is answered with the current metrics, regardless of its
+/// path, so the server can be scraped at `/metrics` as usual. The server is
+/// shut down when it is dropped.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Start serving `metrics` on `addr`.
+    ///
+    /// The metrics are locked while they are rendered for each request.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from binding to `addr`.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        metrics: Arc<Mutex<PrometheusMetrics>>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        let addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("cupti-metrics".into())
+            .spawn({
+                let shutdown = shutdown.clone();
+                move || serve(listener, metrics, shutdown)
+            })?;
+
+        Ok(Self {
+            addr,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop the server and wait for its thread to exit.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve(listener: TcpListener, metrics: Arc<Mutex<PrometheusMetrics>>, shutdown: Arc<AtomicBool>) {
+    while !shutdown.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // Errors only affect the one scrape, so they are ignored.
+                let _ = respond(stream, &metrics);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(_) => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+fn respond(stream: TcpStream, metrics: &Mutex<PrometheusMetrics>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    // Read the request line and headers. The request itself does not matter.
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let body = metrics.lock().unwrap_or_else(|e| e.into_inner()).render();
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+/// Formats a duration in ns as a decimal number of seconds.
+struct Seconds(u64);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frac = self.0 % 1_000_000_000;
+        if frac == 0 {
+            write!(f, "{}", self.0 / 1_000_000_000)
+        } else {
+            let frac = format!("{frac:09}");
+            write!(
+                f,
+                "{}.{}",
+                self.0 / 1_000_000_000,
+                frac.trim_end_matches('0')
+            )
+        }
+    }
+}
+
+/// Escapes a string for inclusion in a Prometheus label value.
+struct Escape<'a>(&'a str);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use cupti_sys::*;
+
+    use super::*;
+    use crate::testing::{self, api, kernel, memcpy};
+
+    fn pool(
+        operation: CUpti_ActivityMemoryPoolOperationType,
+        address: u64,
+        size: u64,
+        utilized: u64,
+    ) -> ActivityRecord<'static> {
+        let mut raw: CUpti_ActivityMemoryPool3 = testing::raw(ActivityKind::MemoryPool);
+        raw.memoryPoolOperationType = operation;
+        raw.deviceId = 1;
+        raw.address = address;
+        raw.size = size;
+        raw.utilizedSize = utilized;
+        testing::record(&raw)
+    }
+
+    fn driver(cbid: DriverApiTraceCbid, start: u64, end: u64) -> ActivityRecord<'static> {
+        let mut record = api(ActivityKind::Driver, 1, start, end);
+        if let ActivityRecord::Driver(api) = &mut record {
+            api.cbid = cbid.into();
+        }
+        record
+    }
+
+    #[test]
+    fn renders_metrics() {
+        let mut metrics = PrometheusMetrics::new();
+
+        assert!(metrics.push(&kernel(1, 100, 1_600, "k")));
+        assert!(metrics.push(&kernel(2, 2_000, 4_000, "k")));
+        assert!(metrics.push(&memcpy(3, 0, 100, 4096)));
+        assert!(metrics.push(&memcpy(4, 0, 100, 1024)));
+        for (start, end) in [(0, 1_000), (0, 1_500), (0, 7_000_000_000)] {
+            assert!(metrics.push(&driver(DriverApiTraceCbid::cuLaunchKernel, start, end)));
+        }
+        assert!(metrics.push(&pool(
+            CUPTI_ACTIVITY_MEMORY_POOL_OPERATION_TYPE_CREATED,
+            0x1000,
+            1 << 20,
+            4096,
+        )));
+        metrics.add_dropped_records(3);
+
+        let expected = r#"# HELP cupti_kernel_seconds_total Total time spent executing kernels.
+# TYPE cupti_kernel_seconds_total counter
+cupti_kernel_seconds_total{device="0",stream="7"} 0.0000035
+# HELP cupti_kernel_launches_total Total number of kernels executed.
+# TYPE cupti_kernel_launches_total counter
+cupti_kernel_launches_total{device="0",stream="7"} 2
+# HELP cupti_memcpy_bytes_total Total bytes copied by memory copies.
+# TYPE cupti_memcpy_bytes_total counter
+cupti_memcpy_bytes_total{kind="Htod"} 5120
+# HELP cupti_memcpy_total Total number of memory copies.
+# TYPE cupti_memcpy_total counter
+cupti_memcpy_total{kind="Htod"} 2
+# HELP cupti_api_duration_seconds Time spent in driver and runtime API calls.
+# TYPE cupti_api_duration_seconds histogram
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.000001"} 1
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.000005"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.00001"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.00005"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.0001"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.0005"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.001"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.005"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.01"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.05"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.1"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="0.5"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="1"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="5"} 2
+cupti_api_duration_seconds_bucket{api="driver",name="cuLaunchKernel",cbid="307",le="+Inf"} 3
+cupti_api_duration_seconds_sum{api="driver",name="cuLaunchKernel",cbid="307"} 7.0000025
+cupti_api_duration_seconds_count{api="driver",name="cuLaunchKernel",cbid="307"} 3
+# HELP cupti_memory_pool_size_bytes The size of each memory pool.
+# TYPE cupti_memory_pool_size_bytes gauge
+cupti_memory_pool_size_bytes{device="1",address="0x1000"} 1048576
+# HELP cupti_memory_pool_utilized_bytes The number of bytes in use in each memory pool.
+# TYPE cupti_memory_pool_utilized_bytes gauge
+cupti_memory_pool_utilized_bytes{device="1",address="0x1000"} 4096
+# HELP cupti_dropped_records_total Total number of activity records dropped by CUPTI.
+# TYPE cupti_dropped_records_total counter
+cupti_dropped_records_total 3
+"#;
+        assert_eq!(metrics.render(), expected);
+
+        metrics.reset();
+        assert!(!metrics.render().contains("device="));
+    }
+
+    #[test]
+    fn destroyed_pools_are_removed() {
+        let mut metrics = PrometheusMetrics::new();
+
+        metrics.push(&pool(
+            CUPTI_ACTIVITY_MEMORY_POOL_OPERATION_TYPE_CREATED,
+            0x1000,
+            1024,
+            0,
+        ));
+        metrics.push(&pool(
+            CUPTI_ACTIVITY_MEMORY_POOL_OPERATION_TYPE_CREATED,
+            0x2000,
+            2048,
+            0,
+        ));
+        metrics.push(&pool(
+            CUPTI_ACTIVITY_MEMORY_POOL_OPERATION_TYPE_TRIMMED,
+            0x2000,
+            512,
+            0,
+        ));
+        assert_eq!(metrics.pools[&(1, 0x2000)].size, 512);
+
+        assert!(metrics.push(&pool(
+            CUPTI_ACTIVITY_MEMORY_POOL_OPERATION_TYPE_DESTROYED,
+            0x2000,
+            0,
+            0,
+        )));
+        assert_eq!(metrics.pools.keys().collect::<Vec<_>>(), [&(1, 0x1000)]);
+        assert!(!metrics.render().contains("0x2000"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let mut metrics = PrometheusMetrics::new();
+        metrics
+            .api_names
+            .runtime
+            .insert(0, Cow::Borrowed("a\"b\\c\nd"));
+        metrics.push(&api(ActivityKind::Runtime, 1, 0, 10));
+
+        let expected =
+            r#"cupti_api_duration_seconds_count{api="runtime",name="a\"b\\c\nd",cbid="0"} 1"#;
+        assert!(metrics.render().lines().any(|line| line == expected));
+        assert_eq!(Escape("\"\\\n").to_string(), r#"\"\\\n"#);
+    }
+
+    #[test]
+    fn formats_seconds() {
+        assert_eq!(Seconds(1_500).to_string(), "0.0000015");
+        assert_eq!(Seconds(2_000_000_000).to_string(), "2");
+        assert_eq!(Seconds(1_250_000_000).to_string(), "1.25");
+        assert_eq!(Seconds(0).to_string(), "0");
+    }
+
+    #[test]
+    fn serves_metrics() {
+        let metrics = Arc::new(Mutex::new(PrometheusMetrics::new()));
+        metrics.lock().unwrap().add_dropped_records(5);
+
+        let server = MetricsServer::bind("127.0.0.1:0", metrics.clone()).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        server.shutdown();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert_eq!(body, metrics.lock().unwrap().render());
+        assert!(body.ends_with("cupti_dropped_records_total 5\n"));
+    }
+}
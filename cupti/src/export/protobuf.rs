@@ -0,0 +1,78 @@
+//! A minimal protobuf encoder for the exporters that write protobuf messages.
+
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LENGTH_DELIMITED: u32 = 2;
+
+/// An encoded protobuf message.
+///
+/// Fields are appended in the order the methods are called.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn tag(&mut self, field: u32, wire_type: u32) {
+        write_varint(&mut self.buf, u64::from((field << 3) | wire_type));
+    }
+
+    pub(crate) fn uint(&mut self, field: u32, value: u64) -> &mut Self {
+        self.tag(field, VARINT);
+        write_varint(&mut self.buf, value);
+        self
+    }
+
+    pub(crate) fn int(&mut self, field: u32, value: i64) -> &mut Self {
+        self.uint(field, value as u64)
+    }
+
+    pub(crate) fn bool(&mut self, field: u32, value: bool) -> &mut Self {
+        self.uint(field, value.into())
+    }
+
+    pub(crate) fn fixed64(&mut self, field: u32, value: u64) -> &mut Self {
+        self.tag(field, FIXED64);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn double(&mut self, field: u32, value: f64) -> &mut Self {
+        self.fixed64(field, value.to_bits())
+    }
+
+    pub(crate) fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.tag(field, LENGTH_DELIMITED);
+        write_varint(&mut self.buf, value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub(crate) fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    pub(crate) fn message(&mut self, field: u32, value: &Message) -> &mut Self {
+        self.bytes(field, &value.buf)
+    }
+}
+
+pub(crate) fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
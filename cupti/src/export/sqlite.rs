@@ -0,0 +1,919 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use rusqlite::{Connection, params};
+
+use super::{ApiNames, label};
+use crate::activity::{ActivityApi, ActivityMemcpyKind, ActivityMemoryKind, ActivityRecord};
+use crate::analysis::NvtxRange;
+
+/// The NVTX event type used by Nsight Systems for push/pop ranges.
+const NVTX_PUSH_POP_RANGE: u32 = 59;
+/// The NVTX event type used by Nsight Systems for start/end ranges.
+const NVTX_START_END_RANGE: u32 = 60;
+
+/// The multiplier used to pack a process ID into a global thread ID.
+const GLOBAL_PID_SHIFT: u64 = 0x100_0000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS StringIds (
+    id INTEGER NOT NULL PRIMARY KEY,
+    value TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS ENUM_CUDA_MEMCPY_OPER (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    label TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ENUM_CUDA_MEM_KIND (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    label TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS TARGET_INFO_GPU (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT,
+    uuid TEXT,
+    totalMemory INTEGER,
+    memoryBandwidth INTEGER,
+    l2CacheSize INTEGER,
+    clockRate INTEGER,
+    smCount INTEGER,
+    threadsPerWarp INTEGER,
+    maxBlocksPerSm INTEGER,
+    maxWarpsPerSm INTEGER,
+    maxThreadsPerBlock INTEGER,
+    maxRegistersPerBlock INTEGER,
+    maxShmemPerBlock INTEGER,
+    computeMajor INTEGER,
+    computeMinor INTEGER,
+    migEnabled INTEGER
+);
+CREATE TABLE IF NOT EXISTS TARGET_INFO_CUDA_STREAM (
+    streamId INTEGER NOT NULL,
+    contextId INTEGER NOT NULL,
+    deviceId INTEGER,
+    priority INTEGER,
+    flag INTEGER,
+    PRIMARY KEY (contextId, streamId)
+);
+CREATE TABLE IF NOT EXISTS CUPTI_ACTIVITY_KIND_KERNEL (
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    deviceId INTEGER NOT NULL,
+    contextId INTEGER NOT NULL,
+    streamId INTEGER NOT NULL,
+    correlationId INTEGER,
+    globalPid INTEGER,
+    demangledName INTEGER NOT NULL REFERENCES StringIds(id),
+    shortName INTEGER NOT NULL REFERENCES StringIds(id),
+    mangledName INTEGER REFERENCES StringIds(id),
+    launchType INTEGER,
+    cacheConfig INTEGER,
+    registersPerThread INTEGER NOT NULL,
+    gridX INTEGER NOT NULL,
+    gridY INTEGER NOT NULL,
+    gridZ INTEGER NOT NULL,
+    blockX INTEGER NOT NULL,
+    blockY INTEGER NOT NULL,
+    blockZ INTEGER NOT NULL,
+    staticSharedMemory INTEGER NOT NULL,
+    dynamicSharedMemory INTEGER NOT NULL,
+    localMemoryPerThread INTEGER NOT NULL,
+    localMemoryTotal INTEGER NOT NULL,
+    gridId INTEGER,
+    sharedMemoryExecuted INTEGER,
+    graphNodeId INTEGER,
+    graphId INTEGER,
+    queued INTEGER,
+    submitted INTEGER
+);
+CREATE TABLE IF NOT EXISTS CUPTI_ACTIVITY_KIND_MEMCPY (
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    deviceId INTEGER NOT NULL,
+    contextId INTEGER NOT NULL,
+    streamId INTEGER NOT NULL,
+    correlationId INTEGER,
+    globalPid INTEGER,
+    bytes INTEGER NOT NULL,
+    copyKind INTEGER NOT NULL REFERENCES ENUM_CUDA_MEMCPY_OPER(id),
+    srcKind INTEGER REFERENCES ENUM_CUDA_MEM_KIND(id),
+    dstKind INTEGER REFERENCES ENUM_CUDA_MEM_KIND(id),
+    srcDeviceId INTEGER,
+    srcContextId INTEGER,
+    dstDeviceId INTEGER,
+    dstContextId INTEGER,
+    graphNodeId INTEGER,
+    graphId INTEGER
+);
+CREATE TABLE IF NOT EXISTS CUPTI_ACTIVITY_KIND_MEMSET (
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    deviceId INTEGER NOT NULL,
+    contextId INTEGER NOT NULL,
+    streamId INTEGER NOT NULL,
+    correlationId INTEGER,
+    globalPid INTEGER,
+    value INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    graphNodeId INTEGER,
+    graphId INTEGER,
+    memKind INTEGER REFERENCES ENUM_CUDA_MEM_KIND(id)
+);
+CREATE TABLE IF NOT EXISTS CUPTI_ACTIVITY_KIND_RUNTIME (
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    globalTid INTEGER,
+    correlationId INTEGER,
+    nameId INTEGER NOT NULL REFERENCES StringIds(id),
+    returnValue INTEGER NOT NULL,
+    cbid INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS CUPTI_ACTIVITY_KIND_DRIVER (
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    globalTid INTEGER,
+    correlationId INTEGER,
+    nameId INTEGER NOT NULL REFERENCES StringIds(id),
+    returnValue INTEGER NOT NULL,
+    cbid INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS NVTX_EVENTS (
+    start INTEGER NOT NULL,
+    end INTEGER,
+    eventType INTEGER NOT NULL,
+    rangeId INTEGER,
+    category INTEGER,
+    color INTEGER,
+    text TEXT,
+    globalTid INTEGER,
+    endGlobalTid INTEGER,
+    textId INTEGER REFERENCES StringIds(id),
+    domainId INTEGER REFERENCES StringIds(id)
+);
+
+CREATE INDEX IF NOT EXISTS INDEX_KERNEL_CORRELATION ON CUPTI_ACTIVITY_KIND_KERNEL (correlationId);
+CREATE INDEX IF NOT EXISTS INDEX_KERNEL_START ON CUPTI_ACTIVITY_KIND_KERNEL (start);
+CREATE INDEX IF NOT EXISTS INDEX_MEMCPY_CORRELATION ON CUPTI_ACTIVITY_KIND_MEMCPY (correlationId);
+CREATE INDEX IF NOT EXISTS INDEX_MEMCPY_START ON CUPTI_ACTIVITY_KIND_MEMCPY (start);
+CREATE INDEX IF NOT EXISTS INDEX_MEMSET_CORRELATION ON CUPTI_ACTIVITY_KIND_MEMSET (correlationId);
+CREATE INDEX IF NOT EXISTS INDEX_MEMSET_START ON CUPTI_ACTIVITY_KIND_MEMSET (start);
+CREATE INDEX IF NOT EXISTS INDEX_RUNTIME_CORRELATION ON CUPTI_ACTIVITY_KIND_RUNTIME (correlationId);
+CREATE INDEX IF NOT EXISTS INDEX_RUNTIME_START ON CUPTI_ACTIVITY_KIND_RUNTIME (start);
+CREATE INDEX IF NOT EXISTS INDEX_DRIVER_CORRELATION ON CUPTI_ACTIVITY_KIND_DRIVER (correlationId);
+CREATE INDEX IF NOT EXISTS INDEX_DRIVER_START ON CUPTI_ACTIVITY_KIND_DRIVER (start);
+CREATE INDEX IF NOT EXISTS INDEX_NVTX_START ON NVTX_EVENTS (start);
+";
+
+/// Writes activity records into a SQLite database.
+///
+/// The schema follows the one used by `nsys export --type sqlite`, so that
+/// queries written against Nsight Systems exports also work on databases
+/// written by this type. The tables are:
+///
+/// | Table | Contents |
+/// |-------|----------|
+/// | `CUPTI_ACTIVITY_KIND_KERNEL` | Kernels |
+/// | `CUPTI_ACTIVITY_KIND_MEMCPY` | Memory copies, including peer-to-peer copies |
+/// | `CUPTI_ACTIVITY_KIND_MEMSET` | Memory sets |
+/// | `CUPTI_ACTIVITY_KIND_RUNTIME` | Runtime API calls |
+/// | `CUPTI_ACTIVITY_KIND_DRIVER` | Driver API calls |
+/// | `NVTX_EVENTS` | NVTX ranges |
+/// | `StringIds` | Kernel, API and NVTX domain names |
+/// | `TARGET_INFO_GPU` | Devices |
+/// | `TARGET_INFO_CUDA_STREAM` | Streams |
+/// | `ENUM_CUDA_MEMCPY_OPER` | Labels for memory copy kinds |
+/// | `ENUM_CUDA_MEM_KIND` | Labels for memory kinds |
+///
+/// Each table of GPU work and API calls is indexed on `correlationId` and
+/// `start`. Only the columns that can be filled in from CUPTI records are
+/// included, and thread IDs are packed into `globalTid` the same way as
+/// Nsight Systems does, so `globalTid / 0x1000000 % 0x1000000` is the process
+/// ID and `globalTid % 0x1000000` is the thread ID.
+///
+/// Kernel names are stored as given by CUPTI in `mangledName`. With the
+/// `demangle` feature, `demangledName` holds the demangled name and
+/// `shortName` the demangled name without its namespaces, template arguments
+/// and parameters, so `_Z6vecAddPfi` becomes `vecAdd(float*, int)` and
+/// `vecAdd`. Without the feature both hold the name as given by CUPTI.
+///
+/// GPU activity records do not include a process ID, so `globalPid` is filled
+/// in from the process ID set with [`process_id`](Self::process_id), or
+/// otherwise that of the first API call written. CUPTI only records activity
+/// in the process it is loaded in, so this is the same for every record of a
+/// session. Rows written before the process ID is known are filled in by the
+/// next [`flush`](Self::flush) or [`finish`](Self::finish) after it is.
+///
+/// NVTX ranges that started and ended on the same thread
+/// are given the push/pop `eventType` of 59, and others the start/end
+/// `eventType` of 60, since CUPTI does not distinguish the two.
+///
+/// Rows are written inside a transaction, which is committed by
+/// [`flush`](Self::flush) and [`finish`](Self::finish).
+pub struct SqliteWriter {
+    conn: Connection,
+    strings: HashMap<String, i64>,
+    kernel_names: HashMap<String, KernelNames>,
+    process_id: Option<u32>,
+    /// Whether rows may have been written without a `globalPid`.
+    missing_pid: bool,
+    streams: HashSet<(u32, u32)>,
+    memcpy_kinds: HashSet<ActivityMemcpyKind>,
+    memory_kinds: HashSet<ActivityMemoryKind>,
+    api_names: ApiNames,
+}
+
+impl SqliteWriter {
+    /// Open or create the database at `path` and create the tables.
+    ///
+    /// If the database already contains tables written by this type then new
+    /// rows are added to them.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from opening the database or creating the tables.
+    pub fn create<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Create the tables in an existing connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from creating the tables.
+    pub fn from_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+
+        let strings = conn
+            .prepare("SELECT id, value FROM StringIds")?
+            .query_map([], |row| Ok((row.get(1)?, row.get(0)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        conn.execute_batch("BEGIN")?;
+
+        Ok(Self {
+            conn,
+            strings,
+            kernel_names: HashMap::new(),
+            process_id: None,
+            missing_pid: false,
+            streams: HashSet::new(),
+            memcpy_kinds: HashSet::new(),
+            memory_kinds: HashSet::new(),
+            api_names: ApiNames::default(),
+        })
+    }
+
+    /// Fill in `globalPid` for GPU activity with `process_id`, rather than
+    /// the process ID of the first API call written.
+    pub fn process_id(mut self, process_id: u32) -> Self {
+        self.process_id = Some(process_id);
+        self
+    }
+
+    /// Write a record to the database.
+    ///
+    /// Returns `Ok(false)` if the kind of record is not supported, in which
+    /// case nothing is written. The supported kinds are driver and runtime API
+    /// calls, kernels, memory copies, memory sets, devices and streams.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from inserting the rows.
+    pub fn write_record(&mut self, record: &ActivityRecord<'_>) -> rusqlite::Result<bool> {
+        match record {
+            ActivityRecord::Driver(api) => {
+                let name = self.api_names.get(record.kind(), api.cbid);
+                self.write_api("CUPTI_ACTIVITY_KIND_DRIVER", &name, api)?;
+            }
+            ActivityRecord::Runtime(api) => {
+                let name = self.api_names.get(record.kind(), api.cbid);
+                self.write_api("CUPTI_ACTIVITY_KIND_RUNTIME", &name, api)?;
+            }
+            ActivityRecord::Kernel(r) | ActivityRecord::ConcurrentKernel(r) => {
+                let names = match &r.name {
+                    Some(name) => self.kernel_names(&name.to_string_lossy())?,
+                    None => {
+                        let name = self.string_id("kernel")?;
+                        KernelNames {
+                            demangled: name,
+                            short: name,
+                            mangled: None,
+                        }
+                    }
+                };
+                self.stream(r.device_id, r.context_id, r.stream_id)?;
+
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO CUPTI_ACTIVITY_KIND_KERNEL (
+                            start, end, deviceId, contextId, streamId, correlationId,
+                            globalPid, demangledName, shortName, mangledName, launchType,
+                            cacheConfig, registersPerThread, gridX, gridY, gridZ, blockX,
+                            blockY, blockZ, staticSharedMemory, dynamicSharedMemory,
+                            localMemoryPerThread, localMemoryTotal, gridId,
+                            sharedMemoryExecuted, graphNodeId, graphId, queued, submitted
+                        ) VALUES (
+                            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                            ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28,
+                            ?29
+                        )",
+                    )?
+                    .execute(params![
+                        r.start,
+                        r.end,
+                        r.device_id,
+                        r.context_id,
+                        r.stream_id,
+                        r.correlation_id,
+                        self.global_pid(),
+                        names.demangled,
+                        names.short,
+                        names.mangled,
+                        r.launch_type.map(u32::from),
+                        r.cache_config_executed,
+                        r.registers_per_thread,
+                        r.grid_x,
+                        r.grid_y,
+                        r.grid_z,
+                        r.block_x,
+                        r.block_y,
+                        r.block_z,
+                        r.static_shared_memory,
+                        r.dynamic_shared_memory,
+                        r.local_memory_per_thread,
+                        r.local_memory_total,
+                        r.grid_id,
+                        r.shared_memory_executed,
+                        r.graph_node_id,
+                        r.graph_id,
+                        r.queued,
+                        r.submitted,
+                    ])?;
+            }
+            ActivityRecord::Memcpy(r) => {
+                self.memcpy_kind(r.copy_kind)?;
+                self.memory_kind(r.src_kind)?;
+                self.memory_kind(r.dst_kind)?;
+                self.stream(r.device_id, r.context_id, r.stream_id)?;
+
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO CUPTI_ACTIVITY_KIND_MEMCPY (
+                            start, end, deviceId, contextId, streamId, correlationId,
+                            globalPid, bytes, copyKind, srcKind, dstKind, graphNodeId, graphId
+                        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    )?
+                    .execute(params![
+                        r.start,
+                        r.end,
+                        r.device_id,
+                        r.context_id,
+                        r.stream_id,
+                        r.correlation_id,
+                        self.global_pid(),
+                        r.bytes,
+                        u32::from(r.copy_kind),
+                        u32::from(r.src_kind),
+                        u32::from(r.dst_kind),
+                        r.graph_node_id,
+                        r.graph_id,
+                    ])?;
+            }
+            ActivityRecord::Memcpy2(r) => {
+                self.memcpy_kind(r.copy_kind)?;
+                self.memory_kind(r.src_kind)?;
+                self.memory_kind(r.dst_kind)?;
+                self.stream(r.device_id, r.context_id, r.stream_id)?;
+
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO CUPTI_ACTIVITY_KIND_MEMCPY (
+                            start, end, deviceId, contextId, streamId, correlationId,
+                            globalPid, bytes, copyKind, srcKind, dstKind, srcDeviceId,
+                            srcContextId, dstDeviceId, dstContextId, graphNodeId, graphId
+                        ) VALUES (
+                            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                            ?16, ?17
+                        )",
+                    )?
+                    .execute(params![
+                        r.start,
+                        r.end,
+                        r.device_id,
+                        r.context_id,
+                        r.stream_id,
+                        r.correlation_id,
+                        self.global_pid(),
+                        r.bytes,
+                        u32::from(r.copy_kind),
+                        u32::from(r.src_kind),
+                        u32::from(r.dst_kind),
+                        r.src_device_id,
+                        r.src_context_id,
+                        r.dst_device_id,
+                        r.dst_context_id,
+                        r.graph_node_id,
+                        r.graph_id,
+                    ])?;
+            }
+            ActivityRecord::Memset(r) => {
+                self.memory_kind(r.memory_kind)?;
+                self.stream(r.device_id, r.context_id, r.stream_id)?;
+
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO CUPTI_ACTIVITY_KIND_MEMSET (
+                            start, end, deviceId, contextId, streamId, correlationId,
+                            globalPid, value, bytes, graphNodeId, graphId, memKind
+                        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                    )?
+                    .execute(params![
+                        r.start,
+                        r.end,
+                        r.device_id,
+                        r.context_id,
+                        r.stream_id,
+                        r.correlation_id,
+                        self.global_pid(),
+                        r.value,
+                        r.bytes,
+                        r.graph_node_id,
+                        r.graph_id,
+                        u32::from(r.memory_kind),
+                    ])?;
+            }
+            ActivityRecord::Device(r) => {
+                let name = r.name.as_ref().map(|name| name.to_string_lossy());
+                let uuid = r.uuid.map(format_uuid);
+
+                self.conn
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO TARGET_INFO_GPU (
+                            id, name, uuid, totalMemory, memoryBandwidth, l2CacheSize,
+                            clockRate, smCount, threadsPerWarp, maxBlocksPerSm, maxWarpsPerSm,
+                            maxThreadsPerBlock, maxRegistersPerBlock, maxShmemPerBlock,
+                            computeMajor, computeMinor, migEnabled
+                        ) VALUES (
+                            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                            ?16, ?17
+                        )",
+                    )?
+                    .execute(params![
+                        r.id,
+                        name,
+                        uuid,
+                        r.global_memory_size,
+                        r.global_memory_bandwidth,
+                        r.l2_cache_size,
+                        r.core_clock_rate,
+                        r.num_multiprocessors,
+                        r.num_threads_per_warp,
+                        r.max_blocks_per_multiprocessor,
+                        r.max_warps_per_multiprocessor,
+                        r.max_threads_per_block,
+                        r.max_registers_per_block,
+                        r.max_shared_memory_per_block,
+                        r.compute_capability_major,
+                        r.compute_capability_minor,
+                        r.is_mig_enabled,
+                    ])?;
+            }
+            ActivityRecord::Stream(r) => {
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO TARGET_INFO_CUDA_STREAM (
+                            streamId, contextId, priority, flag
+                        ) VALUES (?1, ?2, ?3, ?4)
+                        ON CONFLICT (contextId, streamId) DO UPDATE
+                        SET priority = excluded.priority, flag = excluded.flag",
+                    )?
+                    .execute(params![
+                        r.stream_id,
+                        r.context_id,
+                        r.priority,
+                        u32::from(r.flag),
+                    ])?;
+            }
+            _ => return Ok(false),
+        }
+
+        self.missing_pid |= self.process_id.is_none();
+        Ok(true)
+    }
+
+    /// Write an NVTX range to the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from inserting the rows.
+    pub fn write_nvtx_range(&mut self, range: &NvtxRange) -> rusqlite::Result<()> {
+        let domain = match &range.domain {
+            Some(domain) => Some(self.string_id(&domain.to_string_lossy())?),
+            None => None,
+        };
+        let event_type = if range.crosses_threads() {
+            NVTX_START_END_RANGE
+        } else {
+            NVTX_PUSH_POP_RANGE
+        };
+
+        self.conn
+            .prepare_cached(
+                "INSERT INTO NVTX_EVENTS (
+                    start, end, eventType, rangeId, category, color, text, globalTid,
+                    endGlobalTid, domainId
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?
+            .execute(params![
+                range.start,
+                range.end,
+                event_type,
+                range.id,
+                range.category,
+                range.color,
+                range.name.as_ref().map(|name| name.to_string_lossy()),
+                global_tid(range.thread),
+                global_tid(range.end_thread),
+                domain,
+            ])?;
+
+        Ok(())
+    }
+
+    /// Commit the rows written so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from committing the transaction.
+    pub fn flush(&mut self) -> rusqlite::Result<()> {
+        self.fill_global_pid()?;
+        self.conn.execute_batch("COMMIT; BEGIN")
+    }
+
+    /// Commit the rows written so far and return the connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from committing the transaction.
+    pub fn finish(mut self) -> rusqlite::Result<Connection> {
+        self.fill_global_pid()?;
+        self.conn.execute_batch("COMMIT")?;
+        Ok(self.conn)
+    }
+
+    fn write_api(&mut self, table: &str, name: &str, api: &ActivityApi) -> rusqlite::Result<()> {
+        let name = self.string_id(name)?;
+        let global_tid = u64::from(api.process_id) * GLOBAL_PID_SHIFT
+            + u64::from(api.thread_id) % GLOBAL_PID_SHIFT;
+        self.process_id.get_or_insert(api.process_id);
+
+        self.conn
+            .prepare_cached(&format!(
+                "INSERT INTO {table} (
+                    start, end, globalTid, correlationId, nameId, returnValue, cbid
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+            ))?
+            .execute(params![
+                api.start,
+                api.end,
+                global_tid,
+                api.correlation_id,
+                name,
+                api.return_value,
+                api.cbid,
+            ])?;
+
+        Ok(())
+    }
+
+    fn global_pid(&self) -> Option<u64> {
+        self.process_id
+            .map(|process_id| u64::from(process_id) * GLOBAL_PID_SHIFT)
+    }
+
+    /// Fill in `globalPid` for rows written before the process ID was known.
+    fn fill_global_pid(&mut self) -> rusqlite::Result<()> {
+        let Some(global_pid) = self.global_pid().filter(|_| self.missing_pid) else {
+            return Ok(());
+        };
+
+        for table in [
+            "CUPTI_ACTIVITY_KIND_KERNEL",
+            "CUPTI_ACTIVITY_KIND_MEMCPY",
+            "CUPTI_ACTIVITY_KIND_MEMSET",
+        ] {
+            self.conn
+                .prepare_cached(&format!(
+                    "UPDATE {table} SET globalPid = ?1 WHERE globalPid IS NULL"
+                ))?
+                .execute([global_pid])?;
+        }
+
+        self.missing_pid = false;
+        Ok(())
+    }
+
+    fn kernel_names(&mut self, name: &str) -> rusqlite::Result<KernelNames> {
+        if let Some(&names) = self.kernel_names.get(name) {
+            return Ok(names);
+        }
+
+        let (demangled, short) = demangle(name);
+        let names = KernelNames {
+            demangled: self.string_id(&demangled)?,
+            short: self.string_id(&short)?,
+            mangled: Some(self.string_id(name)?),
+        };
+
+        self.kernel_names.insert(name.to_owned(), names);
+        Ok(names)
+    }
+
+    fn string_id(&mut self, value: &str) -> rusqlite::Result<i64> {
+        if let Some(&id) = self.strings.get(value) {
+            return Ok(id);
+        }
+
+        self.conn
+            .prepare_cached("INSERT INTO StringIds (value) VALUES (?1)")?
+            .execute([value])?;
+
+        let id = self.conn.last_insert_rowid();
+        self.strings.insert(value.to_owned(), id);
+        Ok(id)
+    }
+
+    fn stream(&mut self, device_id: u32, context_id: u32, stream_id: u32) -> rusqlite::Result<()> {
+        if !self.streams.insert((context_id, stream_id)) {
+            return Ok(());
+        }
+
+        self.conn
+            .prepare_cached(
+                "INSERT INTO TARGET_INFO_CUDA_STREAM (streamId, contextId, deviceId)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (contextId, streamId) DO UPDATE SET deviceId = excluded.deviceId",
+            )?
+            .execute(params![stream_id, context_id, device_id])?;
+
+        Ok(())
+    }
+
+    fn memcpy_kind(&mut self, kind: ActivityMemcpyKind) -> rusqlite::Result<()> {
+        if !self.memcpy_kinds.insert(kind) {
+            return Ok(());
+        }
+
+        self.conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO ENUM_CUDA_MEMCPY_OPER (id, name, label)
+                VALUES (?1, ?2, ?2)",
+            )?
+            .execute(params![u32::from(kind), label(kind)])?;
+
+        Ok(())
+    }
+
+    fn memory_kind(&mut self, kind: ActivityMemoryKind) -> rusqlite::Result<()> {
+        if !self.memory_kinds.insert(kind) {
+            return Ok(());
+        }
+
+        self.conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO ENUM_CUDA_MEM_KIND (id, name, label)
+                VALUES (?1, ?2, ?2)",
+            )?
+            .execute(params![u32::from(kind), label(kind)])?;
+
+        Ok(())
+    }
+}
+
+/// The string IDs of the names of a kernel.
+#[derive(Copy, Clone, Debug)]
+struct KernelNames {
+    demangled: i64,
+    short: i64,
+    mangled: Option<i64>,
+}
+
+/// The demangled and short names of the kernel named `name`.
+#[cfg(feature = "demangle")]
+fn demangle(name: &str) -> (String, String) {
+    const SIMPLIFY: crate::demangle::Simplify = crate::demangle::Simplify::new()
+        .strip_template_args(true)
+        .collapse_namespaces(true);
+
+    let demangled = crate::demangle::demangle(name);
+    let simplified = SIMPLIFY.apply(&demangled);
+
+    // The short name is the part before the parameters, without any return
+    // type in front of it.
+    let mut start = 0;
+    let mut end = simplified.len();
+    let mut depth = 0usize;
+    for (index, c) in simplified.char_indices() {
+        match c {
+            '(' if depth == 0 && index > start && !simplified[..index].ends_with("operator") => {
+                end = index;
+                break;
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ' ' if depth == 0 => start = index + 1,
+            _ => {}
+        }
+    }
+    let short = simplified[start..end].to_owned();
+
+    (demangled.to_string(), short)
+}
+
+#[cfg(not(feature = "demangle"))]
+fn demangle(name: &str) -> (String, String) {
+    (name.to_owned(), name.to_owned())
+}
+
+fn global_tid(thread: crate::activity::ActivityObjectId) -> Option<u64> {
+    match thread {
+        crate::activity::ActivityObjectId::Process {
+            process_id,
+            thread_id,
+        } => {
+            Some(u64::from(process_id) * GLOBAL_PID_SHIFT + u64::from(thread_id) % GLOBAL_PID_SHIFT)
+        }
+        _ => None,
+    }
+}
+
+fn format_uuid(uuid: [u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|b| format!("{b:02x}")).collect();
+
+    format!(
+        "GPU-{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::testing::{api, kernel, memcpy};
+
+    fn write(records: &[ActivityRecord<'_>]) -> Connection {
+        let mut writer =
+            SqliteWriter::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        for record in records {
+            assert!(writer.write_record(record).unwrap());
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn kernels_join_to_their_launches() {
+        let conn = write(&[
+            api(ActivityKind::Runtime, 1, 100, 200),
+            kernel(1, 300, 800, "_Z6vecAddPfi"),
+            api(ActivityKind::Runtime, 2, 900, 1_000),
+            memcpy(2, 1_100, 1_500, 4096),
+        ]);
+
+        // The kind of query used with Nsight Systems exports to find the API
+        // call that launched each kernel.
+        let rows: Vec<(String, String, String, i64, i64, i64, i64)> = conn
+            .prepare(
+                "SELECT demangled.value, short.value, mangled.value,
+                    kernel.end - kernel.start, kernel.globalPid / 0x1000000 % 0x1000000,
+                    runtime.globalTid % 0x1000000, kernel.start - runtime.end
+                FROM CUPTI_ACTIVITY_KIND_KERNEL AS kernel
+                JOIN CUPTI_ACTIVITY_KIND_RUNTIME AS runtime
+                    ON runtime.correlationId = kernel.correlationId
+                JOIN StringIds AS demangled ON demangled.id = kernel.demangledName
+                JOIN StringIds AS short ON short.id = kernel.shortName
+                JOIN StringIds AS mangled ON mangled.id = kernel.mangledName",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+
+        #[cfg(feature = "demangle")]
+        let (demangled, short) = ("vecAdd(float*, int)", "vecAdd");
+        #[cfg(not(feature = "demangle"))]
+        let (demangled, short) = ("_Z6vecAddPfi", "_Z6vecAddPfi");
+
+        assert_eq!(
+            rows,
+            [(
+                demangled.to_owned(),
+                short.to_owned(),
+                "_Z6vecAddPfi".to_owned(),
+                500,
+                100,
+                200,
+                100
+            )]
+        );
+
+        let memcpy: (i64, i64, String) = conn
+            .query_row(
+                "SELECT memcpy.bytes, memcpy.globalPid / 0x1000000, oper.label
+                FROM CUPTI_ACTIVITY_KIND_MEMCPY AS memcpy
+                JOIN ENUM_CUDA_MEMCPY_OPER AS oper ON oper.id = memcpy.copyKind",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(memcpy.0, 4096);
+        assert_eq!(memcpy.1, 100);
+    }
+
+    #[test]
+    fn global_pid_is_filled_in_for_earlier_rows() {
+        let mut writer =
+            SqliteWriter::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        writer.write_record(&kernel(1, 300, 800, "vecAdd")).unwrap();
+        writer.write_record(&memcpy(2, 900, 1_000, 64)).unwrap();
+        writer.flush().unwrap();
+        writer
+            .write_record(&api(ActivityKind::Runtime, 1, 100, 200))
+            .unwrap();
+        writer
+            .write_record(&kernel(3, 1_100, 1_200, "vecAdd"))
+            .unwrap();
+        let conn = writer.finish().unwrap();
+
+        let kernels: Vec<Option<i64>> = conn
+            .prepare("SELECT globalPid FROM CUPTI_ACTIVITY_KIND_KERNEL")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(kernels, [Some(100 << 24), Some(100 << 24)]);
+
+        let memcpy: Option<i64> = conn
+            .query_row(
+                "SELECT globalPid FROM CUPTI_ACTIVITY_KIND_MEMCPY",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(memcpy, Some(100 << 24));
+    }
+
+    #[test]
+    fn process_id_overrides_api_calls() {
+        let mut writer = SqliteWriter::from_connection(Connection::open_in_memory().unwrap())
+            .unwrap()
+            .process_id(7);
+        writer.write_record(&kernel(1, 300, 800, "vecAdd")).unwrap();
+        writer
+            .write_record(&api(ActivityKind::Runtime, 1, 100, 200))
+            .unwrap();
+        let conn = writer.finish().unwrap();
+
+        let global_pid: i64 = conn
+            .query_row(
+                "SELECT globalPid FROM CUPTI_ACTIVITY_KIND_KERNEL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(global_pid, 7 << 24);
+    }
+
+    #[cfg(feature = "demangle")]
+    #[test]
+    fn short_names_drop_qualifiers_and_parameters() {
+        for (name, short) in [
+            ("vecAdd", "vecAdd"),
+            ("_Z6vecAddPfi", "vecAdd"),
+            ("_ZN3cub6detail6reduceIfLi256EEEvPT_", "reduce"),
+            ("_ZN6thrust8cuda_cub4core13_kernel_agentEv", "_kernel_agent"),
+            ("_ZN7functorclEi", "operator()"),
+            ("_ZZ4mainENKUliE_clEi", "operator()"),
+        ] {
+            assert_eq!(demangle(name).1, short, "{name}");
+        }
+    }
+}
@@ -4,16 +4,26 @@
 mod macros;
 
 pub mod activity;
+pub mod analysis;
 pub mod callbacks;
 pub mod checkpoint;
+#[cfg(feature = "demangle")]
+pub mod demangle;
+pub mod export;
 pub mod pmsampling;
 pub mod profiler;
 pub mod rangeprofiling;
+#[cfg(feature = "tracefile")]
+pub mod tracefile;
 // pub mod pcsampling;
 mod cuda;
 mod driver_cbid;
 mod error;
 mod nvtx_cbid;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(test)]
+mod testing;
 mod util;
 
 pub use self::cuda::*;
@@ -103,3 +113,15 @@ pub fn get_device_chip_name(device_index: usize) -> Result<&'static str> {
     let chip_name = unsafe { CStr::from_ptr(params.pChipName) };
     Ok(chip_name.to_str().expect("chip name should be valid UTF-8"))
 }
+
+/// Get the CUPTI API version of the library loaded at run time.
+///
+/// This may differ from the `CUPTI_API_VERSION` that this crate was built
+/// against. Releases up to CUDA 12.x report a small sequential version number,
+/// while CUDA 13.0 and later report a value derived from the CUDA release
+/// (e.g. `130001`).
+pub fn get_version() -> Result<u32> {
+    let mut version = 0;
+    Error::result(unsafe { cupti_sys::cuptiGetVersion(&mut version) })?;
+    Ok(version)
+}
@@ -1 +1,52 @@
+/// Declare a C-like enum using [`c_enum!`](c_enum::c_enum) that also
+/// implements `Serialize` and `Deserialize` when the `serde` feature is
+/// enabled.
+///
+/// Human-readable formats represent known values by the name of their variant
+/// and unknown values by their raw integer value. Other formats always use the
+/// raw integer value.
+macro_rules! serde_c_enum {
+    {
+        $( #[$attr:meta] )*
+        $vis:vis enum $name:ident : $inner:ty {
+            $(
+                $( #[$field_attr:meta] )*
+                $field:ident $( = $value:expr )?
+            ),* $(,)?
+        }
+    } => {
+        c_enum::c_enum! {
+            $( #[$attr] )*
+            $vis enum $name : $inner {
+                $(
+                    $( #[$field_attr] )*
+                    $field $( = $value )?,
+                )*
+            }
+        }
 
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                $crate::serde_impl::serialize_c_enum(self, serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                const VARIANTS: &[(&str, $name)] = &[
+                    $( (::std::stringify!($field), $name::$field), )*
+                ];
+
+                $crate::serde_impl::deserialize_c_enum(deserializer, VARIANTS)
+            }
+        }
+    };
+}
@@ -1,7 +1,7 @@
-use c_enum::c_enum;
 use cupti_sys::*;
 
-c_enum! {
+serde_c_enum! {
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
     pub enum NvtxApiTraceCbid : CUpti_nvtx_api_trace_cbid {
         INVALID = CUPTI_CBID_NVTX_INVALID,
         nvtxMarkA = CUPTI_CBID_NVTX_nvtxMarkA,
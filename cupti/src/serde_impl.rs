@@ -0,0 +1,73 @@
+//! Helpers for the `serde` implementations generated by `serde_c_enum!`.
+
+use std::fmt;
+
+use c_enum::CEnum;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub(crate) fn serialize_c_enum<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: CEnum + Copy,
+    T::Inner: From<T> + PartialEq + Serialize,
+    S: Serializer,
+{
+    match value.variant_label() {
+        Some(label) if serializer.is_human_readable() => serializer.serialize_str(label),
+        _ => T::Inner::from(*value).serialize(serializer),
+    }
+}
+
+pub(crate) fn deserialize_c_enum<'de, T, D>(
+    deserializer: D,
+    variants: &'static [(&'static str, T)],
+) -> Result<T, D::Error>
+where
+    T: CEnum + Copy + From<T::Inner>,
+    T::Inner: TryFrom<u64> + TryFrom<i64> + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(CEnumVisitor { variants })
+    } else {
+        // Read back exactly what `serialize_c_enum` wrote, since binary
+        // formats may encode integers of different types differently.
+        T::Inner::deserialize(deserializer).map(T::from)
+    }
+}
+
+struct CEnumVisitor<T: 'static> {
+    variants: &'static [(&'static str, T)],
+}
+
+impl<'de, T> Visitor<'de> for CEnumVisitor<T>
+where
+    T: CEnum + Copy + From<T::Inner>,
+    T::Inner: TryFrom<u64> + TryFrom<i64>,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a variant name or integer value")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        self.variants
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, variant)| *variant)
+            .ok_or_else(|| E::unknown_variant(value, &[]))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+        T::Inner::try_from(value)
+            .map(T::from)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+        T::Inner::try_from(value)
+            .map(T::from)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+}
@@ -0,0 +1,122 @@
+//! Synthetic activity records for tests.
+
+use std::ffi::CString;
+use std::mem::size_of;
+
+use cupti_sys::*;
+
+use crate::activity::{ActivityFlag, ActivityKind, ActivityLayout, ActivityRecord, Interner};
+
+/// A zeroed raw record with its kind set.
+pub(crate) fn raw<T>(kind: ActivityKind) -> T {
+    let mut raw: T = unsafe { std::mem::zeroed() };
+    unsafe { (&mut raw as *mut T as *mut CUpti_ActivityKind).write(kind.into()) };
+    raw
+}
+
+/// Decode a raw record with the latest layout.
+///
+/// The record is padded with zeroes, so `T` may be an older revision than the
+/// latest one as long as it is a prefix of it.
+pub(crate) fn record<T>(raw: &T) -> ActivityRecord<'static> {
+    let mut bytes = vec![0u8; 4096];
+    let raw = unsafe { std::slice::from_raw_parts(raw as *const T as *const u8, size_of::<T>()) };
+    bytes[..raw.len()].copy_from_slice(raw);
+
+    unsafe { ActivityRecord::from_bytes(&bytes, &ActivityLayout::latest()) }
+        .unwrap()
+        .into_owned(&mut Interner::new())
+}
+
+/// A driver or runtime API record.
+pub(crate) fn api(
+    kind: ActivityKind,
+    correlation_id: u32,
+    start: u64,
+    end: u64,
+) -> ActivityRecord<'static> {
+    let mut raw: CUpti_ActivityAPI = raw(kind);
+    raw.correlationId = correlation_id;
+    raw.start = start;
+    raw.end = end;
+    raw.processId = 100;
+    raw.threadId = 200;
+    record(&raw)
+}
+
+/// A concurrent kernel record.
+pub(crate) fn kernel(
+    correlation_id: u32,
+    start: u64,
+    end: u64,
+    name: &str,
+) -> ActivityRecord<'static> {
+    let name = CString::new(name).unwrap();
+
+    let mut raw: CUpti_ActivityKernel10 = raw(ActivityKind::ConcurrentKernel);
+    raw.correlationId = correlation_id;
+    raw.start = start;
+    raw.end = end;
+    raw.deviceId = 0;
+    raw.contextId = 1;
+    raw.streamId = 7;
+    raw.gridX = 1;
+    raw.gridY = 1;
+    raw.gridZ = 1;
+    raw.blockX = 128;
+    raw.blockY = 1;
+    raw.blockZ = 1;
+    raw.name = name.as_ptr();
+    record(&raw)
+}
+
+/// A host to device memory copy record.
+pub(crate) fn memcpy(
+    correlation_id: u32,
+    start: u64,
+    end: u64,
+    bytes: u64,
+) -> ActivityRecord<'static> {
+    let mut raw: CUpti_ActivityMemcpy6 = raw(ActivityKind::Memcpy);
+    raw.copyKind = CUPTI_ACTIVITY_MEMCPY_KIND_HTOD as u8;
+    raw.srcKind = CUPTI_ACTIVITY_MEMORY_KIND_PAGEABLE as u8;
+    raw.dstKind = CUPTI_ACTIVITY_MEMORY_KIND_DEVICE as u8;
+    raw.correlationId = correlation_id;
+    raw.start = start;
+    raw.end = end;
+    raw.bytes = bytes;
+    raw.contextId = 1;
+    raw.streamId = 7;
+    record(&raw)
+}
+
+/// An NVTX marker on thread `thread_id` of process 100.
+pub(crate) fn marker(
+    flags: ActivityFlag,
+    id: u32,
+    thread_id: u32,
+    timestamp: u64,
+    name: Option<&str>,
+) -> ActivityRecord<'static> {
+    let name = name.map(|name| CString::new(name).unwrap());
+
+    let mut raw: CUpti_ActivityMarker2 = raw(ActivityKind::Marker);
+    raw.flags = flags.bits();
+    raw.id = id;
+    raw.timestamp = timestamp;
+    raw.objectKind = CUPTI_ACTIVITY_OBJECT_THREAD;
+    raw.objectId.pt.processId = 100;
+    raw.objectId.pt.threadId = thread_id;
+    raw.name = name.as_ref().map_or(std::ptr::null(), |name| name.as_ptr());
+    record(&raw)
+}
+
+/// The marker data for the marker `id`.
+pub(crate) fn marker_data(id: u32, color: u32, category: u32) -> ActivityRecord<'static> {
+    let mut raw: CUpti_ActivityMarkerData2 = raw(ActivityKind::MarkerData);
+    raw.flags = ActivityFlag::MARKER_COLOR_ARGB.bits();
+    raw.id = id;
+    raw.color = color;
+    raw.category = category;
+    record(&raw)
+}
@@ -0,0 +1,560 @@
+//! A compact binary file format for captured activity.
+//!
+//! Trace files let activity be captured on one machine and analyzed on
+//! another. A [`TraceWriter`] streams activity records, callback events, NVTX
+//! ranges, PM sampling values and metadata to a file as they are produced, and
+//! a [`TraceReader`] reads them back as the same types, so they can be fed into
+//! the [`analysis`](crate::analysis) and [`export`](crate::export) types just
+//! like records from a live session.
+//!
+//! Chunks are read without copying when they are not compressed, but the
+//! entries in them are decoded into owned values, so strings such as kernel
+//! names are copied out of the file.
+//!
+//! This module requires the `tracefile` feature.
+//!
+//! # Format
+//!
+//! All integers in headers are little-endian.
+//!
+//! A file starts with a 16 byte header:
+//!
+//! | Offset | Size | Field |
+//! |--------|------|-------|
+//! | 0 | 8 | The magic bytes `CUPTITRC` |
+//! | 8 | 2 | The format version, currently 1 |
+//! | 10 | 2 | Flags, currently 0 |
+//! | 12 | 4 | Reserved, currently 0 |
+//!
+//! The rest of the file is a sequence of chunks, each of which starts with a
+//! 20 byte header:
+//!
+//! | Offset | Size | Field |
+//! |--------|------|-------|
+//! | 0 | 1 | The compression used for the payload: 0 for none or 1 for LZ4 |
+//! | 1 | 3 | Reserved, currently 0 |
+//! | 4 | 4 | The number of entries in the chunk |
+//! | 8 | 4 | The length of the payload once decompressed |
+//! | 12 | 4 | The length of the payload as stored |
+//! | 16 | 4 | The CRC-32 of the first 16 bytes of the header followed by the payload as stored |
+//!
+//! The decompressed length of a payload is at most 1 GiB. Uncompressed
+//! payloads have the same length as stored, and LZ4 payloads are compressed as
+//! a single LZ4 block, which is at most 255 times smaller than the original.
+//! Once decompressed, a payload is a sequence of entries, each made up of a one
+//! byte tag, the length of the entry body as a LEB128 varint, and the body
+//! itself encoded with [postcard]. The tags are:
+//!
+//! | Tag | Body |
+//! |-----|------|
+//! | 1 | [`TraceMetadata`] |
+//! | 2 | [`ActivityRecord`] |
+//! | 3 | [`CallbackEvent`] |
+//! | 4 | [`NvtxRange`] |
+//! | 5 | [`PmSample`] |
+//!
+//! Readers skip entries with unknown tags, so new kinds of entries can be added
+//! without changing the format version.
+//!
+//! # Versioning
+//!
+//! Postcard is not self-describing: an entry body is only the values of its
+//! fields in declaration order, so it can only be decoded by the same
+//! definition of its type that encoded it. Any change to how one of the entry
+//! types above serializes, including to the activity record types they
+//! contain, must therefore increment the format version. This includes adding,
+//! removing, reordering or retyping fields, and adding enum variants anywhere
+//! but at the end. Readers only accept files with the format version of the
+//! crate.
+//!
+//! The tests check the writer's output against files checked in under
+//! `tests/fixtures`, so such a change fails them until the format version is
+//! incremented and the fixtures are regenerated by running the tests with
+//! `CUPTI_UPDATE_FIXTURES=1` set.
+//!
+//! [postcard]: https://docs.rs/postcard
+//! [`ActivityRecord`]: crate::activity::ActivityRecord
+//! [`NvtxRange`]: crate::analysis::NvtxRange
+
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::callbacks::{ApiCallbackSite, CallbackData, CallbackDomain};
+use crate::pmsampling::{CounterDataImage, Sampler};
+use crate::*;
+
+mod reader;
+mod writer;
+
+pub use self::reader::{Chunk, Entries, TraceEntry, TraceReader};
+pub use self::writer::TraceWriter;
+
+/// The magic bytes at the start of every trace file.
+const MAGIC: [u8; 8] = *b"CUPTITRC";
+
+/// The version of the format written by [`TraceWriter`].
+///
+/// This must be incremented whenever the serialized form of an entry changes.
+/// See the [module documentation](self#versioning).
+const FORMAT_VERSION: u16 = 1;
+
+const FILE_HEADER_LEN: usize = 16;
+const CHUNK_HEADER_LEN: usize = 20;
+
+/// The length of the part of a chunk header that is covered by its checksum.
+const CHUNK_CHECKED_LEN: usize = 16;
+
+/// The largest decompressed length of a chunk, which bounds the memory a
+/// reader allocates for a corrupt file.
+const MAX_CHUNK_LEN: usize = 1 << 30;
+
+/// The largest ratio between the decompressed and compressed lengths of an
+/// LZ4 block.
+const MAX_LZ4_RATIO: usize = 255;
+
+const TAG_METADATA: u8 = 1;
+const TAG_RECORD: u8 = 2;
+const TAG_CALLBACK: u8 = 3;
+const TAG_NVTX_RANGE: u8 = 4;
+const TAG_PM_SAMPLE: u8 = 5;
+
+/// The compression applied to each chunk of a trace file.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Compression {
+    /// Chunks are stored uncompressed.
+    None,
+    /// Chunks are compressed with LZ4.
+    ///
+    /// Chunks that do not get smaller when compressed are stored
+    /// uncompressed.
+    #[default]
+    Lz4,
+}
+
+impl Compression {
+    fn to_raw(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz4 => 1,
+        }
+    }
+
+    fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(Self::None),
+            1 => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+}
+
+/// Information about the session a trace was captured in.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TraceMetadata {
+    /// The CUPTI API version that was loaded at run time, as returned by
+    /// [`get_version`].
+    pub cupti_version: Option<u32>,
+    /// The devices that were present.
+    pub devices: Vec<DeviceMetadata>,
+    /// Any other information, such as the host name or command line.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl TraceMetadata {
+    /// Collect metadata for the current process.
+    ///
+    /// This records the CUPTI version and the chip name of each device that
+    /// CUPTI reports. Information that is not available is left empty.
+    pub fn current() -> Self {
+        let devices = (0..)
+            .map_while(|index| {
+                let chip_name = get_device_chip_name(index).ok()?;
+
+                Some(DeviceMetadata {
+                    index: index as u32,
+                    chip_name: chip_name.to_owned(),
+                })
+            })
+            .collect();
+
+        Self {
+            cupti_version: get_version().ok(),
+            devices,
+            properties: BTreeMap::new(),
+        }
+    }
+}
+
+/// Information about a device a trace was captured on.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DeviceMetadata {
+    /// The index of the device.
+    pub index: u32,
+    /// The chip name of the device, as returned by [`get_device_chip_name`].
+    pub chip_name: String,
+}
+
+/// A driver or runtime API callback, as captured by a [`Subscriber`].
+///
+/// [`Subscriber`]: crate::Subscriber
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CallbackEvent {
+    /// The callback domain, either [`CallbackDomain::DriverApi`] or
+    /// [`CallbackDomain::RuntimeApi`].
+    pub domain: CallbackDomain,
+    /// The callback ID within the domain.
+    pub cbid: u32,
+    /// Whether this is the entry or exit callback.
+    pub site: ApiCallbackSite,
+    /// The time the callback was issued, in ns.
+    pub timestamp: u64,
+    /// The correlation ID of the API call.
+    pub correlation_id: u32,
+    /// The unique ID of the context current to the thread.
+    pub context_uid: u32,
+    /// The name of the API function.
+    pub function_name: String,
+    /// The name of the kernel for launch callbacks.
+    pub symbol_name: Option<String>,
+}
+
+impl CallbackEvent {
+    /// Capture a callback event from the data passed to a subscriber
+    /// callback.
+    ///
+    /// `timestamp` is usually taken with [`get_timestamp`] inside the
+    /// callback.
+    ///
+    /// [`get_timestamp`]: crate::activity::get_timestamp
+    pub fn new(domain: CallbackDomain, cbid: u32, data: &CallbackData<'_>, timestamp: u64) -> Self {
+        Self {
+            domain,
+            cbid,
+            site: data.site(),
+            timestamp,
+            correlation_id: data.correlation_id(),
+            context_uid: data.context_uid(),
+            function_name: data.function_name().to_string_lossy().into_owned(),
+            symbol_name: data
+                .symbol_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// The metric values of a single PM sampling sample.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PmSample {
+    /// The device the sample was taken on.
+    pub device_id: u32,
+    /// The start timestamp of the sample, in ns.
+    pub start: u64,
+    /// The end timestamp of the sample, in ns.
+    pub end: u64,
+    /// The name and value of each metric.
+    pub values: Vec<(String, f64)>,
+}
+
+impl PmSample {
+    /// Read the completed samples in `image`.
+    ///
+    /// `metric_names` must be the metrics that `image` was created with.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from CUPTI while reading samples.
+    pub fn read_all(
+        device_id: u32,
+        sampler: &Sampler,
+        image: &CounterDataImage,
+        metric_names: &CStringSlice,
+    ) -> Result<Vec<Self>> {
+        let info = image.get_data_info()?;
+        let names: Vec<String> = metric_names
+            .into_iter()
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+
+        (0..info.num_completed_samples)
+            .map(|index| {
+                let sample = image.get_sample_info(sampler, index)?;
+                let values = image.evaluate(sampler, index, metric_names)?;
+
+                Ok(Self {
+                    device_id,
+                    start: sample.start_timestamp,
+                    end: sample.end_timestamp,
+                    values: names.iter().cloned().zip(values).collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// The value of the metric named `name`, if it was sampled.
+    pub fn value(&self, name: &str) -> Option<f64> {
+        self.values
+            .iter()
+            .find(|(metric, _)| metric == name)
+            .map(|&(_, value)| value)
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::activity::{ActivityKind, ActivityObjectId, RecordStr};
+    use crate::analysis::NvtxRange;
+    use crate::testing::{api, kernel, memcpy};
+
+    fn metadata() -> TraceMetadata {
+        TraceMetadata {
+            cupti_version: Some(26),
+            devices: vec![DeviceMetadata {
+                index: 0,
+                chip_name: "GH100".to_owned(),
+            }],
+            properties: BTreeMap::from([("host".to_owned(), "gpu-01".to_owned())]),
+        }
+    }
+
+    fn callback() -> CallbackEvent {
+        CallbackEvent {
+            domain: CallbackDomain::RuntimeApi,
+            cbid: 211,
+            site: ApiCallbackSite::Enter,
+            timestamp: 1_000,
+            correlation_id: 1,
+            context_uid: 1,
+            function_name: "cudaLaunchKernel".to_owned(),
+            symbol_name: Some("vecAdd".to_owned()),
+        }
+    }
+
+    fn nvtx_range() -> NvtxRange {
+        let thread = ActivityObjectId::Process {
+            process_id: 100,
+            thread_id: 200,
+        };
+
+        NvtxRange {
+            id: 1,
+            name: Some(RecordStr::Shared(Arc::from(c"step"))),
+            domain: None,
+            color: Some(0xff00ff00),
+            category: None,
+            thread,
+            end_thread: thread,
+            start: 500,
+            end: 5_000,
+            depth: 0,
+            kernels: Vec::new(),
+        }
+    }
+
+    fn pm_sample() -> PmSample {
+        PmSample {
+            device_id: 0,
+            start: 1_000,
+            end: 2_000,
+            values: vec![("sm__cycles_active.avg".to_owned(), 1234.5)],
+        }
+    }
+
+    /// Write a trace with one of each kind of entry, and enough kernels to
+    /// span several chunks.
+    fn write_trace(compression: Compression) -> Vec<u8> {
+        let mut writer = TraceWriter::new(Vec::new())
+            .unwrap()
+            .compression(compression)
+            .chunk_size(1024);
+
+        writer.write_metadata(&metadata()).unwrap();
+        writer.write_callback(&callback()).unwrap();
+        writer.write_nvtx_range(&nvtx_range()).unwrap();
+        writer.write_pm_sample(&pm_sample()).unwrap();
+        for id in 0..16 {
+            let start = u64::from(id) * 1_000;
+            writer
+                .write_record(&api(ActivityKind::Runtime, id, start, start + 100))
+                .unwrap();
+            writer
+                .write_record(&kernel(id, start + 200, start + 900, "vecAdd"))
+                .unwrap();
+        }
+        writer
+            .write_record(&memcpy(16, 20_000, 21_000, 4096))
+            .unwrap();
+
+        writer.finish().unwrap()
+    }
+
+    /// The entries in `data`, formatted with `Debug` since not every entry
+    /// type implements `PartialEq`.
+    fn read_entries(data: &[u8]) -> io::Result<Vec<String>> {
+        let mut entries = Vec::new();
+        TraceReader::new(data)?.replay(|entry| entries.push(format!("{entry:?}")))?;
+        Ok(entries)
+    }
+
+    fn count_chunks(data: &[u8]) -> usize {
+        let mut reader = TraceReader::new(data).unwrap();
+        let mut chunks = 0;
+        while reader.next_chunk().unwrap().is_some() {
+            chunks += 1;
+        }
+        chunks
+    }
+
+    fn fixture_path(compression: Compression) -> PathBuf {
+        let name = match compression {
+            Compression::None => "none",
+            Compression::Lz4 => "lz4",
+        };
+
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("trace-v{FORMAT_VERSION}-{name}.bin"))
+    }
+
+    #[test]
+    fn round_trip() {
+        let expected = read_entries(&write_trace(Compression::None)).unwrap();
+        assert_eq!(expected.len(), 37);
+        assert!(expected[0].starts_with("Metadata("));
+        assert!(expected[4].starts_with("Record(Runtime("));
+        assert!(expected[5].contains("\"vecAdd\""));
+
+        for compression in [Compression::None, Compression::Lz4] {
+            let data = write_trace(compression);
+            assert!(count_chunks(&data) > 1);
+            assert_eq!(read_entries(&data).unwrap(), expected, "{compression:?}");
+        }
+
+        assert!(write_trace(Compression::Lz4).len() < write_trace(Compression::None).len());
+    }
+
+    #[test]
+    fn replay_records_skips_other_entries() {
+        let data = write_trace(Compression::Lz4);
+
+        let mut kinds = Vec::new();
+        TraceReader::new(&data)
+            .unwrap()
+            .replay_records(|record| kinds.push(record.kind()))
+            .unwrap();
+
+        assert_eq!(kinds.len(), 33);
+        assert_eq!(kinds[1], ActivityKind::ConcurrentKernel);
+        assert_eq!(kinds[32], ActivityKind::Memcpy);
+    }
+
+    #[test]
+    fn fixtures_are_up_to_date() {
+        for compression in [Compression::None, Compression::Lz4] {
+            let path = fixture_path(compression);
+            let data = write_trace(compression);
+
+            if std::env::var_os("CUPTI_UPDATE_FIXTURES").is_some() {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, &data).unwrap();
+                continue;
+            }
+
+            let fixture = std::fs::read(&path)
+                .unwrap_or_else(|e| panic!("failed to read {}: {e}", path.display()));
+            assert!(
+                fixture == data,
+                "{} does not match the output of the writer. If the format changed, increment \
+                 FORMAT_VERSION and run the tests with CUPTI_UPDATE_FIXTURES=1 set.",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn fixtures_decode() {
+        let expected = read_entries(&write_trace(Compression::None)).unwrap();
+
+        for compression in [Compression::None, Compression::Lz4] {
+            let fixture = std::fs::read(fixture_path(compression)).unwrap();
+            assert_eq!(read_entries(&fixture).unwrap(), expected, "{compression:?}");
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut data = write_trace(Compression::Lz4);
+        data[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        let error = TraceReader::new(&data).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunk_headers_are_checksummed() {
+        let data = write_trace(Compression::Lz4);
+
+        // Offsets of the entry count and decompressed length of the first
+        // chunk.
+        for offset in [FILE_HEADER_LEN + 4, FILE_HEADER_LEN + 8] {
+            let mut corrupt = data.clone();
+            corrupt[offset] ^= 1;
+
+            let error = read_entries(&corrupt).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains("checksum"), "{error}");
+        }
+    }
+
+    #[test]
+    fn chunk_lengths_are_bounded() {
+        let payload = [0u8; 16];
+
+        for (compression, raw_len) in [
+            (Compression::None, 17),
+            (Compression::Lz4, 16 * MAX_LZ4_RATIO + 1),
+            (Compression::Lz4, u32::MAX as usize),
+        ] {
+            let mut data = write_trace(Compression::None)[..FILE_HEADER_LEN].to_vec();
+
+            let mut header = [0; CHUNK_HEADER_LEN];
+            header[0] = compression.to_raw();
+            header[4..8].copy_from_slice(&1u32.to_le_bytes());
+            header[8..12].copy_from_slice(&(raw_len as u32).to_le_bytes());
+            header[12..16].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(&header[..CHUNK_CHECKED_LEN]);
+            hasher.update(&payload);
+            header[16..20].copy_from_slice(&hasher.finalize().to_le_bytes());
+            data.extend_from_slice(&header);
+            data.extend_from_slice(&payload);
+
+            let error = TraceReader::new(&data).unwrap().next_chunk().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains("length"), "{error}");
+        }
+    }
+
+    #[test]
+    fn truncated_files_keep_complete_chunks() {
+        let data = write_trace(Compression::Lz4);
+        let chunks = count_chunks(&data);
+
+        let mut reader = TraceReader::new(&data[..data.len() - 1]).unwrap();
+        for _ in 1..chunks {
+            assert!(reader.next_chunk().unwrap().is_some());
+        }
+        let error = reader.next_chunk().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,292 @@
+use std::io;
+
+use serde::Deserialize;
+
+use super::*;
+use crate::activity::ActivityRecord;
+use crate::analysis::NvtxRange;
+
+/// An entry read from a trace file.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum TraceEntry<'a> {
+    /// Metadata about the session.
+    Metadata(TraceMetadata),
+    /// An activity record.
+    Record(ActivityRecord<'a>),
+    /// A callback event.
+    Callback(CallbackEvent),
+    /// An NVTX range.
+    NvtxRange(NvtxRange),
+    /// The metric values of a PM sampling sample.
+    PmSample(PmSample),
+    /// An entry with a tag that this version of the crate does not know
+    /// about.
+    Unknown {
+        /// The tag of the entry.
+        tag: u8,
+        /// The encoded body of the entry.
+        data: &'a [u8],
+    },
+}
+
+/// Reads a trace file written by a [`TraceWriter`].
+///
+/// The reader operates on the complete contents of a file, which will usually
+/// be read into memory or memory-mapped by the caller. Uncompressed chunks are
+/// decoded directly from the input, and compressed chunks are decompressed
+/// into a buffer that is reused for each chunk. Entries own their data, so
+/// strings in them are copied out of the chunk as it is decoded.
+///
+/// Every chunk is checked against its checksum before it is decoded.
+///
+/// [`TraceWriter`]: super::TraceWriter
+pub struct TraceReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    version: u16,
+    buffer: Vec<u8>,
+}
+
+impl<'a> TraceReader<'a> {
+    /// Create a reader for the trace file in `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::InvalidData`] error if `data` does not
+    /// start with a trace file header, or if the file was written with a
+    /// different version of the format.
+    pub fn new(data: &'a [u8]) -> io::Result<Self> {
+        let header = data
+            .get(..FILE_HEADER_LEN)
+            .ok_or_else(|| invalid_data("trace file is too short"))?;
+
+        if header[..8] != MAGIC {
+            return Err(invalid_data("not a trace file"));
+        }
+
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "trace file version {version} is not supported, expected version {FORMAT_VERSION}"
+            )));
+        }
+
+        Ok(Self {
+            data,
+            offset: FILE_HEADER_LEN,
+            version,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// The format version of the file.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Read the next chunk, or `None` at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::UnexpectedEof`] error if the file ends
+    /// partway through a chunk, as happens when a capture is interrupted, and
+    /// an [`io::ErrorKind::InvalidData`] error if the chunk is corrupt.
+    pub fn next_chunk(&mut self) -> io::Result<Option<Chunk<'_>>> {
+        let rest = &self.data[self.offset..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+
+        let header = rest.get(..CHUNK_HEADER_LEN).ok_or_else(truncated)?;
+        let field =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+        let compression = Compression::from_raw(header[0])
+            .ok_or_else(|| invalid_data(format!("unknown trace file compression {}", header[0])))?;
+        let entries = field(4);
+        let raw_len = field(8) as usize;
+        let stored_len = field(12) as usize;
+        let checksum = field(16);
+
+        let payload = rest
+            .get(CHUNK_HEADER_LEN..CHUNK_HEADER_LEN + stored_len)
+            .ok_or_else(truncated)?;
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[..CHUNK_CHECKED_LEN]);
+        hasher.update(payload);
+        if hasher.finalize() != checksum {
+            return Err(invalid_data("trace file chunk checksum mismatch"));
+        }
+
+        let max_len = match compression {
+            Compression::None => stored_len,
+            Compression::Lz4 => stored_len.saturating_mul(MAX_LZ4_RATIO),
+        };
+        if raw_len > max_len.min(MAX_CHUNK_LEN) {
+            return Err(invalid_data("trace file chunk has the wrong length"));
+        }
+
+        self.offset += CHUNK_HEADER_LEN + stored_len;
+
+        let data = match compression {
+            Compression::None => payload,
+            Compression::Lz4 => {
+                self.buffer.resize(raw_len, 0);
+                let len = lz4_flex::block::decompress_into(payload, &mut self.buffer)
+                    .map_err(invalid_data)?;
+                if len != raw_len {
+                    return Err(invalid_data("trace file chunk has the wrong length"));
+                }
+
+                &self.buffer[..]
+            }
+        };
+
+        Ok(Some(Chunk { data, entries }))
+    }
+
+    /// Call `f` with every entry in the file, in the order they were written.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered while reading the file. Entries
+    /// before the error will already have been passed to `f`.
+    pub fn replay<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(TraceEntry<'_>),
+    {
+        while let Some(chunk) = self.next_chunk()? {
+            for entry in chunk.entries() {
+                f(entry?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call `f` with every activity record in the file, skipping all other
+    /// entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered while reading the file.
+    pub fn replay_records<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&ActivityRecord<'_>),
+    {
+        self.replay(|entry| {
+            if let TraceEntry::Record(record) = entry {
+                f(&record);
+            }
+        })
+    }
+}
+
+/// A decoded chunk of a trace file.
+#[derive(Copy, Clone, Debug)]
+pub struct Chunk<'a> {
+    data: &'a [u8],
+    entries: u32,
+}
+
+impl<'a> Chunk<'a> {
+    /// The number of entries in the chunk.
+    pub fn len(&self) -> usize {
+        self.entries as usize
+    }
+
+    /// Whether the chunk contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Iterate over the entries in the chunk.
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            data: self.data,
+            remaining: self.entries,
+        }
+    }
+}
+
+/// An iterator over the entries in a [`Chunk`].
+///
+/// Once an error is returned the iterator is finished.
+#[derive(Clone, Debug)]
+pub struct Entries<'a> {
+    data: &'a [u8],
+    remaining: u32,
+}
+
+impl<'a> Entries<'a> {
+    fn read_entry(&mut self) -> io::Result<TraceEntry<'a>> {
+        let (&tag, rest) = self.data.split_first().ok_or_else(truncated_chunk)?;
+        let (len, rest) = read_varint(rest).ok_or_else(truncated_chunk)?;
+        let len = usize::try_from(len).map_err(|_| truncated_chunk())?;
+        if rest.len() < len {
+            return Err(truncated_chunk());
+        }
+
+        let (body, rest) = rest.split_at(len);
+        self.data = rest;
+
+        Ok(match tag {
+            TAG_METADATA => TraceEntry::Metadata(decode(body)?),
+            TAG_RECORD => TraceEntry::Record(decode(body)?),
+            TAG_CALLBACK => TraceEntry::Callback(decode(body)?),
+            TAG_NVTX_RANGE => TraceEntry::NvtxRange(decode(body)?),
+            TAG_PM_SAMPLE => TraceEntry::PmSample(decode(body)?),
+            tag => TraceEntry::Unknown { tag, data: body },
+        })
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = io::Result<TraceEntry<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match self.read_entry() {
+            Ok(entry) => {
+                self.remaining -= 1;
+                Some(Ok(entry))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining as usize))
+    }
+}
+
+fn decode<'a, T: Deserialize<'a>>(body: &'a [u8]) -> io::Result<T> {
+    postcard::from_bytes(body).map_err(invalid_data)
+}
+
+fn read_varint(data: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0u64;
+    for (index, &byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some((value, &data[index + 1..]));
+        }
+    }
+
+    None
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "trace file is truncated")
+}
+
+fn truncated_chunk() -> io::Error {
+    invalid_data("trace file chunk is truncated")
+}
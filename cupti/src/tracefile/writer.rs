@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::*;
+use crate::activity::ActivityRecord;
+use crate::analysis::NvtxRange;
+
+/// The default size of a chunk before it is compressed.
+const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Writes a trace file.
+///
+/// Entries are buffered until the current chunk reaches the chunk size set
+/// with [`chunk_size`](Self::chunk_size), at which point the chunk is
+/// compressed, checksummed and written out. Memory use is therefore bounded by
+/// the chunk size no matter how long the capture runs.
+///
+/// [`finish`](Self::finish) must be called to write the final chunk.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    chunk: Vec<u8>,
+    entries: u32,
+    scratch: Vec<u8>,
+    compressed: Vec<u8>,
+    chunk_size: usize,
+    compression: Compression,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Create a writer and write the file header to `out`.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        let mut header = [0; FILE_HEADER_LEN];
+        header[..8].copy_from_slice(&MAGIC);
+        header[8..10].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.write_all(&header)?;
+
+        Ok(Self {
+            out,
+            chunk: Vec::new(),
+            entries: 0,
+            scratch: Vec::new(),
+            compressed: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            compression: Compression::default(),
+        })
+    }
+
+    /// Write a chunk once it reaches `size` bytes before compression. The
+    /// default is 1 MiB, and the maximum is 1 GiB.
+    ///
+    /// Larger chunks compress better, while smaller chunks lose less data if
+    /// the capture is interrupted.
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.clamp(1, MAX_CHUNK_LEN);
+        self
+    }
+
+    /// Set the compression used for chunks. The default is
+    /// [`Compression::Lz4`].
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Write the metadata for the session.
+    ///
+    /// This is usually written once at the start of the file, but may be
+    /// written again if it changes.
+    pub fn write_metadata(&mut self, metadata: &TraceMetadata) -> io::Result<()> {
+        self.write_entry(TAG_METADATA, metadata)
+    }
+
+    /// Write an activity record.
+    pub fn write_record(&mut self, record: &ActivityRecord<'_>) -> io::Result<()> {
+        self.write_entry(TAG_RECORD, record)
+    }
+
+    /// Write all of the activity records in `records`.
+    pub fn write_records<'a, I>(&mut self, records: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'a ActivityRecord<'a>>,
+    {
+        for record in records {
+            self.write_record(record)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a callback event.
+    pub fn write_callback(&mut self, event: &CallbackEvent) -> io::Result<()> {
+        self.write_entry(TAG_CALLBACK, event)
+    }
+
+    /// Write an NVTX range.
+    pub fn write_nvtx_range(&mut self, range: &NvtxRange) -> io::Result<()> {
+        self.write_entry(TAG_NVTX_RANGE, range)
+    }
+
+    /// Write the metric values of a PM sampling sample.
+    pub fn write_pm_sample(&mut self, sample: &PmSample) -> io::Result<()> {
+        self.write_entry(TAG_PM_SAMPLE, sample)
+    }
+
+    /// Write out the current chunk, even if it has not reached the chunk
+    /// size, and flush the underlying writer.
+    ///
+    /// Everything written before this call can be read back even if the
+    /// writer is never finished.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.out.flush()
+    }
+
+    /// Write the final chunk and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_entry<T: Serialize + ?Sized>(&mut self, tag: u8, value: &T) -> io::Result<()> {
+        let mut body = std::mem::take(&mut self.scratch);
+        body.clear();
+        let body = postcard::to_extend(value, body).map_err(io::Error::other)?;
+
+        // Start a new chunk rather than exceed the maximum length. The tag and
+        // length take at most 11 bytes.
+        let len = body.len() + 11;
+        if len > MAX_CHUNK_LEN {
+            self.scratch = body;
+            return Err(io::Error::other("trace file entry is larger than 1 GiB"));
+        }
+        if self.chunk.len() + len > MAX_CHUNK_LEN {
+            self.write_chunk()?;
+        }
+
+        self.chunk.push(tag);
+        write_varint(&mut self.chunk, body.len() as u64);
+        self.chunk.extend_from_slice(&body);
+        self.entries += 1;
+        self.scratch = body;
+
+        if self.chunk.len() >= self.chunk_size || self.entries == u32::MAX {
+            self.write_chunk()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.entries == 0 {
+            return Ok(());
+        }
+
+        // Entries are never added past the maximum chunk length.
+        let raw_len = self.chunk.len() as u32;
+
+        let mut compression = self.compression;
+        if compression == Compression::Lz4 {
+            self.compressed.resize(
+                lz4_flex::block::get_maximum_output_size(self.chunk.len()),
+                0,
+            );
+            let len = lz4_flex::block::compress_into(&self.chunk, &mut self.compressed)
+                .map_err(io::Error::other)?;
+            self.compressed.truncate(len);
+
+            if len >= self.chunk.len() {
+                compression = Compression::None;
+            }
+        }
+
+        let payload = match compression {
+            Compression::None => &self.chunk,
+            Compression::Lz4 => &self.compressed,
+        };
+
+        let mut header = [0; CHUNK_HEADER_LEN];
+        header[0] = compression.to_raw();
+        header[4..8].copy_from_slice(&self.entries.to_le_bytes());
+        header[8..12].copy_from_slice(&raw_len.to_le_bytes());
+        header[12..16].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[..CHUNK_CHECKED_LEN]);
+        hasher.update(payload);
+        header[16..20].copy_from_slice(&hasher.finalize().to_le_bytes());
+
+        self.out.write_all(&header)?;
+        self.out.write_all(payload)?;
+
+        self.chunk.clear();
+        self.entries = 0;
+        Ok(())
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
@@ -4,6 +4,7 @@ use std::ops::{Deref, Index};
 use std::ptr::NonNull;
 use std::sync::{Mutex, MutexGuard};
 
+#[derive(Default)]
 pub(crate) struct NonPoisonMutex<T>(Mutex<T>);
 
 impl<T> NonPoisonMutex<T> {